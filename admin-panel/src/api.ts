@@ -14,11 +14,29 @@ import type {
   BroadcastResponse,
   // Event-Driven types
   CreateBroadcastCommand,
+  RescheduleBroadcastCommand,
+  TestBroadcastRequest,
+  BroadcastApprovalRequest,
+  EditBroadcastRequest,
+  BroadcastRevisionResponse,
+  BroadcastFunnel,
+  BroadcastVariantStats,
+  TestBroadcastResponse,
+  DeadLetterMessage,
+  BroadcastPreviewResponse,
+  ReplayDeadLettersResponse,
   BroadcastCreatedResponse,
   BroadcastStatusResponse,
   BroadcastMessageRecord,
   RetryMessageCommand,
   BroadcastSummary,
+  Segment,
+  SegmentRules,
+  SaveSegmentRequest,
+  SegmentCountResponse,
+  DecisionCampaign,
+  SaveDecisionCampaignRequest,
+  DecisionNotification,
   // External API types
   ExternalUser,
   UserSurvey,
@@ -29,6 +47,8 @@ import type {
   CreateVoteRequest,
   NextSurveyResponse,
   VoteResponse,
+  // Support inbox types
+  SupportMessage,
 } from './types';
 import { JSONDataManager, DebugDataManager } from './utils/jsonUtils';
 
@@ -181,11 +201,20 @@ export const broadcastApi = {
   },
 
   // Создание рассылки
-  create: async (command: CreateBroadcastCommand): Promise<BroadcastCreatedResponse> => {
-    const response = await api.post<BroadcastCreatedResponse>('/broadcast', command);
+  // Повтор с тем же idempotencyKey вернет уже созданную рассылку
+  create: async (command: CreateBroadcastCommand, idempotencyKey?: string): Promise<BroadcastCreatedResponse> => {
+    const response = await api.post<BroadcastCreatedResponse>('/broadcast', command, {
+      headers: idempotencyKey ? { 'Idempotency-Key': idempotencyKey } : undefined,
+    });
     return response.data;
   },
   
+  // Предпросмотр персонализированной рассылки (400 — ошибка в шаблоне)
+  preview: async (command: CreateBroadcastCommand): Promise<BroadcastPreviewResponse> => {
+    const response = await api.post<BroadcastPreviewResponse>('/broadcast/preview', command);
+    return response.data;
+  },
+
   // Получение статуса рассылки
   getStatus: async (broadcastId: string): Promise<BroadcastStatusResponse | null> => {
     const response = await api.get<BroadcastStatusResponse | null>(`/broadcast/${broadcastId}/status`);
@@ -198,16 +227,93 @@ export const broadcastApi = {
     return response.data;
   },
   
+  // Воронка рассылки: доставлено, нажали «Записаться», увидели слоты, записались
+  getFunnel: async (broadcastId: string): Promise<BroadcastFunnel> => {
+    const response = await api.get<BroadcastFunnel>(`/broadcast/${broadcastId}/funnel`);
+    return response.data;
+  },
+  
+  // Статистика A/B-вариантов: доставка и конверсия каждого варианта
+  getVariantStats: async (broadcastId: string): Promise<BroadcastVariantStats[]> => {
+    const response = await api.get<BroadcastVariantStats[]>(`/broadcast/${broadcastId}/variants`);
+    return response.data;
+  },
+  
   // Повторная отправка сообщения
-  retryMessage: async (broadcastId: string, userId: number): Promise<void> => {
-    const command: RetryMessageCommand = { broadcast_id: broadcastId, user_id: userId };
+  retryMessage: async (broadcastId: string, telegramId: number): Promise<void> => {
+    const command: RetryMessageCommand = { broadcast_id: broadcastId, telegram_id: telegramId };
     await api.post(`/broadcast/${broadcastId}/retry`, command);
   },
+
+  // Сообщения, исчерпавшие попытки доставки
+  getDeadLetters: async (limit?: number): Promise<DeadLetterMessage[]> => {
+    const response = await api.get<DeadLetterMessage[]>('/dead-letters', { params: { limit } });
+    return response.data;
+  },
+
+  // Повтор сообщений из dead-letter очереди
+  replayDeadLetters: async (limit?: number): Promise<ReplayDeadLettersResponse> => {
+    const response = await api.post<ReplayDeadLettersResponse>('/dead-letters/replay', null, { params: { limit } });
+    return response.data;
+  },
   
   // Отмена рассылки
   cancel: async (broadcastId: string): Promise<void> => {
     await api.post(`/broadcast/${broadcastId}/cancel`);
   },
+
+  // Пауза рассылки
+  pause: async (broadcastId: string): Promise<void> => {
+    await api.post(`/broadcast/${broadcastId}/pause`);
+  },
+
+  // Возобновление рассылки
+  resume: async (broadcastId: string): Promise<void> => {
+    await api.post(`/broadcast/${broadcastId}/resume`);
+  },
+
+  // Перенос отложенной рассылки
+  reschedule: async (broadcastId: string, sendAt: string): Promise<void> => {
+    const command: RescheduleBroadcastCommand = { send_at: sendAt };
+    await api.put(`/broadcast/${broadcastId}/schedule`, command);
+  },
+
+  // Выпуск черновика
+  release: async (broadcastId: string): Promise<void> => {
+    await api.post(`/broadcast/${broadcastId}/release`);
+  },
+
+  // Одобрение крупной рассылки вторым ответственным
+  approve: async (broadcastId: string, approverTelegramId: number): Promise<void> => {
+    const request: BroadcastApprovalRequest = { approver_telegram_id: approverTelegramId };
+    await api.post(`/broadcast/${broadcastId}/approve`, request);
+  },
+
+  // Отклонение крупной рассылки
+  reject: async (broadcastId: string, approverTelegramId: number, reason?: string): Promise<void> => {
+    const request: BroadcastApprovalRequest = { approver_telegram_id: approverTelegramId, reason };
+    await api.post(`/broadcast/${broadcastId}/reject`, request);
+  },
+
+  // Правка текста доставленных сообщений
+  edit: async (broadcastId: string, message: string): Promise<BroadcastRevisionResponse> => {
+    const request: EditBroadcastRequest = { message };
+    const response = await api.post<BroadcastRevisionResponse>(`/broadcast/${broadcastId}/edit`, request);
+    return response.data;
+  },
+
+  // Отзыв (удаление) доставленных сообщений
+  recall: async (broadcastId: string): Promise<BroadcastRevisionResponse> => {
+    const response = await api.post<BroadcastRevisionResponse>(`/broadcast/${broadcastId}/recall`);
+    return response.data;
+  },
+
+  // Тестовая отправка сотрудникам, статистику рассылки не меняет
+  test: async (broadcastId: string, telegramIds: number[]): Promise<TestBroadcastResponse> => {
+    const request: TestBroadcastRequest = { telegram_ids: telegramIds };
+    const response = await api.post<TestBroadcastResponse>(`/broadcast/${broadcastId}/test`, request);
+    return response.data;
+  },
   
   // Legacy method for backward compatibility
   send: async (request: BroadcastRequest): Promise<BroadcastResponse> => {
@@ -216,6 +322,65 @@ export const broadcastApi = {
   },
 };
 
+// Audience segments API
+export const segmentsApi = {
+  getAll: async (): Promise<Segment[]> => {
+    const response = await api.get<Segment[]>('/segments');
+    return response.data;
+  },
+
+  // 409 — сегмент с таким именем уже есть
+  create: async (request: SaveSegmentRequest): Promise<Segment> => {
+    const response = await api.post<Segment>('/segments', request);
+    return response.data;
+  },
+
+  update: async (segmentId: number, request: SaveSegmentRequest): Promise<Segment> => {
+    const response = await api.put<Segment>(`/segments/${segmentId}`, request);
+    return response.data;
+  },
+
+  delete: async (segmentId: number): Promise<void> => {
+    await api.delete(`/segments/${segmentId}`);
+  },
+
+  // Размер сохраненного сегмента на текущий момент
+  count: async (segmentId: number): Promise<SegmentCountResponse> => {
+    const response = await api.get<SegmentCountResponse>(`/segments/${segmentId}/count`);
+    return response.data;
+  },
+
+  // Размер сегмента по правилам до сохранения
+  countRules: async (rules: SegmentRules): Promise<SegmentCountResponse> => {
+    const response = await api.post<SegmentCountResponse>('/segments/count', rules);
+    return response.data;
+  },
+};
+
+// Decision notification campaigns API
+export const decisionCampaignsApi = {
+  getAll: async (): Promise<DecisionCampaign[]> => {
+    const response = await api.get<DecisionCampaign[]>('/decision-campaigns');
+    return response.data;
+  },
+
+  // 400 — ошибка в шаблоне, 409 — кампания с таким именем уже есть
+  create: async (request: SaveDecisionCampaignRequest): Promise<DecisionCampaign> => {
+    const response = await api.post<DecisionCampaign>('/decision-campaigns', request);
+    return response.data;
+  },
+
+  update: async (campaignId: number, request: SaveDecisionCampaignRequest): Promise<DecisionCampaign> => {
+    const response = await api.put<DecisionCampaign>(`/decision-campaigns/${campaignId}`, request);
+    return response.data;
+  },
+
+  getNotifications: async (campaignId: number): Promise<DecisionNotification[]> => {
+    const response = await api.get<DecisionNotification[]>(`/decision-campaigns/${campaignId}/notifications`);
+    return response.data;
+  },
+};
+
 // External Users API
 export const externalUsersApi = {
   // Флаг для переключения между внешним API и локальным режимом
@@ -561,3 +726,11 @@ export const votesApi = {
     return response.data;
   }
 };
+
+// Support inbox API
+export const supportApi = {
+  getMessages: async (telegramId: number): Promise<SupportMessage[]> => {
+    const response = await api.get<SupportMessage[]>(`/users/${telegramId}/support-messages`);
+    return response.data;
+  }
+};
@@ -1,7 +1,7 @@
 import React, { useState, useEffect } from 'react';
-import { X, AlertCircle } from 'lucide-react';
-import { externalUsersApi } from '../api';
-import type { UserSurvey } from '../types';
+import { X, AlertCircle, MessageCircle } from 'lucide-react';
+import { externalUsersApi, supportApi } from '../api';
+import type { UserSurvey, SupportMessage } from '../types';
 import SurveyDisplay from './SurveyDisplay';
 
 interface UserProfileProps {
@@ -14,13 +14,26 @@ const UserProfile: React.FC<UserProfileProps> = ({ telegramId, isOpen, onClose }
   const [survey, setSurvey] = useState<UserSurvey | null>(null);
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [supportMessages, setSupportMessages] = useState<SupportMessage[]>([]);
 
   useEffect(() => {
     if (isOpen && telegramId) {
       loadUserSurvey();
+      loadSupportMessages();
     }
   }, [isOpen, telegramId]);
 
+  const loadSupportMessages = async () => {
+    try {
+      const messages = await supportApi.getMessages(telegramId);
+      setSupportMessages(messages);
+    } catch (err: any) {
+      // Переписка не обязательна для профиля, показываем анкету без нее
+      setSupportMessages([]);
+      console.error('Error loading support messages:', err);
+    }
+  };
+
   const loadUserSurvey = async () => {
     setLoading(true);
     setError(null);
@@ -120,6 +133,40 @@ const UserProfile: React.FC<UserProfileProps> = ({ telegramId, isOpen, onClose }
 
               {/* Анкета */}
               <SurveyDisplay survey={survey} />
+
+              {/* Переписка с поддержкой */}
+              {supportMessages.length > 0 && (
+                <div className="bg-white border border-gray-200 rounded-lg p-3 shadow-sm">
+                  <h3 className="flex items-center text-lg font-semibold text-gray-900 mb-3">
+                    <MessageCircle className="w-5 h-5 mr-2 text-blue-600" />
+                    Переписка с поддержкой
+                  </h3>
+                  <div className="space-y-2">
+                    {supportMessages.map((message) => (
+                      <div
+                        key={message.id}
+                        className={`flex ${message.direction === 'outbound' ? 'justify-end' : 'justify-start'}`}
+                      >
+                        <div
+                          className={`max-w-[80%] rounded-lg px-3 py-2 text-sm ${
+                            message.direction === 'outbound'
+                              ? 'bg-blue-50 text-blue-900'
+                              : 'bg-gray-100 text-gray-900'
+                          }`}
+                        >
+                          <div className="whitespace-pre-wrap">{message.text || 'Медиафайл без подписи'}</div>
+                          <div className="mt-1 text-xs text-gray-400">
+                            {message.direction === 'outbound' && message.staff_telegram_id
+                              ? `Сотрудник ${message.staff_telegram_id} • `
+                              : ''}
+                            {new Date(message.created_at).toLocaleString('ru-RU')}
+                          </div>
+                        </div>
+                      </div>
+                    ))}
+                  </div>
+                </div>
+              )}
             </div>
           )}
         </div>
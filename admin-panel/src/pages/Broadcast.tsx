@@ -14,8 +14,10 @@ import type {
   BookingRecord
 } from '../types';
 import UserProfile from '../components/UserProfile';
+import { useAuth } from '../contexts/AuthContext';
 
 const Broadcast: React.FC = () => {
+  const { userProfile, userRole } = useAuth();
   const [message, setMessage] = useState('');
   const [loading, setLoading] = useState(false);
   const [currentBroadcast, setCurrentBroadcast] = useState<BroadcastCreatedResponse | null>(null);
@@ -50,6 +52,7 @@ const Broadcast: React.FC = () => {
     type: 'custom' | 'signup';
     users: string[];
     message: string;
+    idempotencyKey: string; // Один ключ на подтверждение: двойной клик не создаст вторую рассылку
   } | null>(null);
 
   // Состояние для профиля пользователя
@@ -88,7 +91,7 @@ const Broadcast: React.FC = () => {
             setCurrentBroadcast(prev => prev ? { ...prev, status: status.broadcast.status } : null);
             
             // Если рассылка завершена, останавливаем polling
-            if (status.broadcast.status === 'completed' || status.broadcast.status === 'failed') {
+            if (status.broadcast.status === 'completed' || status.broadcast.status === 'failed' || status.broadcast.status === 'cancelled') {
               clearInterval(interval);
               setPollingInterval(null);
               // Обновляем историю после завершения рассылки
@@ -252,11 +255,11 @@ const Broadcast: React.FC = () => {
     }
   };
 
-  const handleRetry = async (userId: number) => {
+  const handleRetry = async (telegramId: number) => {
     if (!currentBroadcast) return;
 
     try {
-      await broadcastApi.retryMessage(currentBroadcast.broadcast_id, userId);
+      await broadcastApi.retryMessage(currentBroadcast.broadcast_id, telegramId);
       // Обновляем статус
       const status = await broadcastApi.getStatus(currentBroadcast.broadcast_id);
       if (status) {
@@ -399,6 +402,34 @@ const Broadcast: React.FC = () => {
     }
   };
 
+  // Одобрение крупной рассылки вторым ответственным
+  const handleApproveBroadcast = async (broadcastId: string) => {
+    if (!userProfile) return;
+
+    try {
+      await broadcastApi.approve(broadcastId, userProfile.telegram_id);
+      loadBroadcastHistory();
+    } catch (err: any) {
+      setError(err.response?.data || 'Ошибка при одобрении рассылки');
+      console.error('Approve broadcast error:', err);
+    }
+  };
+
+  const handleRejectBroadcast = async (broadcastId: string) => {
+    if (!userProfile) return;
+
+    const reason = window.prompt('Причина отказа (необязательно):');
+    if (reason === null) return;
+
+    try {
+      await broadcastApi.reject(broadcastId, userProfile.telegram_id, reason.trim() || undefined);
+      loadBroadcastHistory();
+    } catch (err: any) {
+      setError(err.response?.data || 'Ошибка при отклонении рассылки');
+      console.error('Reject broadcast error:', err);
+    }
+  };
+
   const handleDeleteBroadcast = async (broadcastId: string) => {
     if (!window.confirm('Вы уверены, что хотите удалить эту рассылку? Это действие нельзя отменить.')) {
       return;
@@ -476,7 +507,7 @@ const Broadcast: React.FC = () => {
 
   // Функция для показа диалога подтверждения
   const showConfirmationDialog = (type: 'custom' | 'signup', users: string[], message: string) => {
-    setPendingBroadcast({ type, users, message });
+    setPendingBroadcast({ type, users, message, idempotencyKey: crypto.randomUUID() });
     setShowConfirmDialog(true);
   };
 
@@ -519,9 +550,10 @@ const Broadcast: React.FC = () => {
         message_type: pendingBroadcast.type,
         selected_external_users: pendingBroadcast.users, // Пользователи уже включают выбранных и из слотов
         media_group: mediaFiles.length > 0 ? { media: processedMediaFiles } : undefined,
+        created_by: userProfile?.telegram_id, // Автор нужен для правила двух ответственных
       };
 
-      const response = await broadcastApi.create(command);
+      const response = await broadcastApi.create(command, pendingBroadcast.idempotencyKey);
       setCurrentBroadcast(response);
       setMessage('');
       setMediaFiles([]); // Очищаем медиафайлы после успешной отправки
@@ -1242,9 +1274,9 @@ const Broadcast: React.FC = () => {
                       {msg.retry_count}
                     </td>
                     <td className="px-6 py-4 whitespace-nowrap text-sm font-medium">
-                      {msg.status === 'failed' && (
+                      {msg.status === 'failed' && msg.telegram_id && (
                         <button
-                          onClick={() => handleRetry(msg.user_id)}
+                          onClick={() => handleRetry(msg.telegram_id!)}
                           className="text-blue-600 hover:text-blue-900"
                         >
                           Повторить
@@ -1337,6 +1369,22 @@ const Broadcast: React.FC = () => {
                          >
                            Детали
                          </button>
+                         {broadcast.status === 'pending_approval' && userRole === 1 && (
+                           <>
+                             <button
+                               onClick={() => handleApproveBroadcast(broadcast.id)}
+                               className="text-green-600 hover:text-green-900"
+                             >
+                               Одобрить
+                             </button>
+                             <button
+                               onClick={() => handleRejectBroadcast(broadcast.id)}
+                               className="text-orange-600 hover:text-orange-900"
+                             >
+                               Отклонить
+                             </button>
+                           </>
+                         )}
                          <button
                            onClick={() => handleDeleteBroadcast(broadcast.id)}
                            className="text-red-600 hover:text-red-900"
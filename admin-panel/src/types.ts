@@ -75,8 +75,137 @@ export interface CreateBroadcastCommand {
   message: string;
   message_type?: 'custom' | 'signup';
   selected_external_users?: string[]; // telegram_id выбранных внешних пользователей
+  segment_id?: number; // Сохраненный сегмент вместо списка; вычисляется в момент отправки
   selected_slot_ids?: number[]; // ID слотов для выбора пользователей
   media_group?: MediaGroup; // Группа медиафайлов для отправки
+  send_at?: string; // Отложенная отправка (ISO 8601); без него — сразу
+  keyboard?: BroadcastKeyboard; // Inline-клавиатура; для signup без нее — кнопка «Записаться»
+  parse_mode?: BroadcastParseMode; // По умолчанию plain: текст экранируется
+  split_long_text?: boolean; // Делить текст длиннее 4096 символов на несколько сообщений
+  draft?: boolean; // Черновик: не отправляется до выпуска
+  created_by?: number; // telegram_id автора; крупную рассылку одобряет другой ответственный
+  variants?: BroadcastVariant[]; // A/B-варианты B, C...; основной текст — вариант A, всего до 5
+  auto_winner?: AutoWinnerSettings; // Сначала тестовая доля, затем победитель — остальным
+}
+
+// Дополнительный вариант рассылки: свой текст или медиафайлы
+export interface BroadcastVariant {
+  message: string;
+  media_group?: MediaGroup;
+}
+
+export interface AutoWinnerSettings {
+  test_percent: number; // 1–99
+  decide_after_minutes: number;
+}
+
+// Разметка текста рассылки; подписи к медиафайлам — до 1024 символов
+export type BroadcastParseMode = 'plain' | 'html' | 'markdown_v2';
+
+// Inline-клавиатура рассылки: до 100 кнопок, до 8 в ряду, текст до 64 символов
+export type BotAction =
+  | { action: 'sign_up' }
+  | { action: 'book'; slot_id: number };
+
+export type KeyboardButton =
+  | { text: string; type: 'url'; url: string }
+  | { text: string; type: 'callback'; action: BotAction };
+
+export interface BroadcastKeyboard {
+  rows: KeyboardButton[][];
+}
+
+// Переменные шаблона рассылки: {{full_name}}, {{slot_time|скоро}}
+export type TemplateVariable = 'full_name' | 'first_name' | 'telegram_nickname' | 'slot_time' | 'slot_place' | 'survey_status';
+
+export interface BroadcastPreview {
+  telegram_id: number;
+  message: string;
+  captions: string[];
+}
+
+export interface BroadcastPreviewResponse {
+  variables: TemplateVariable[];
+  previews: BroadcastPreview[];
+}
+
+// Сегменты аудитории: условия объединяются через И, незаданные не проверяются
+export type VoteOutcome = 'approved' | 'rejected' | 'undecided';
+
+export interface SegmentRules {
+  survey_statuses?: SurveyStatus[];
+  vote_outcomes?: VoteOutcome[];
+  booked?: boolean; // true — записавшиеся, false — не записавшиеся
+  no_response_after_signup?: boolean; // получили рассылку о записи, но не записались
+  year_of_admission?: number[];
+  has_driver_license?: boolean;
+  has_printer?: boolean;
+  can_host_night?: boolean;
+  exclude_telegram_ids?: number[];
+  exclude_segment_ids?: number[];
+}
+
+export interface Segment {
+  id: number;
+  name: string;
+  rules: SegmentRules;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface SaveSegmentRequest {
+  name: string;
+  rules: SegmentRules;
+}
+
+export interface SegmentCountResponse {
+  count: number;
+  skipped_unreachable: number;
+}
+
+// Автоматические уведомления о решении ответственного
+export interface DecisionCampaign {
+  id: number;
+  name: string;
+  approval_message: string; // Приглашение записаться, уходит с кнопкой «Записаться»
+  rejection_message?: string; // Без него при отказе кандидату не пишем
+  active: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface SaveDecisionCampaignRequest {
+  name: string;
+  approval_message: string;
+  rejection_message?: string;
+  active?: boolean; // Активация кампании выключает остальные
+}
+
+export interface DecisionNotification {
+  campaign_id: number;
+  telegram_id: number;
+  decision: number; // 1 - одобрена, 0 - отклонена
+  broadcast_id?: string;
+  created_at: string;
+}
+
+export interface RescheduleBroadcastCommand {
+  send_at: string;
+}
+
+// Решение ответственного по рассылке, ожидающей одобрения
+export interface BroadcastApprovalRequest {
+  approver_telegram_id: number;
+  reason?: string; // Причина отказа, передается автору
+}
+
+// Тестовая отправка рассылки сотрудникам (только ответственные)
+export interface TestBroadcastRequest {
+  telegram_ids: number[];
+}
+
+export interface TestBroadcastResponse {
+  queued: number;
 }
 
 export interface MediaGroup {
@@ -106,6 +235,7 @@ export interface BroadcastSummary {
   created_at: string;
   started_at?: string;
   completed_at?: string;
+  scheduled_at?: string;
 }
 
 export interface BroadcastStatusResponse {
@@ -123,15 +253,66 @@ export interface BroadcastMessageRecord {
   sent_at?: string;
   retry_count: number;
   created_at: string;
+  edit_status?: MessageEditStatus; // Результат последней правки или отзыва
+  edit_error?: string;
+}
+
+export type BroadcastStatus = 'draft' | 'pending_approval' | 'scheduled' | 'pending' | 'in_progress' | 'paused' | 'completed' | 'failed' | 'cancelled';
+
+export type MessageStatus = 'pending' | 'sent' | 'failed' | 'retrying' | 'cancelled';
+
+export type MessageEditStatus = 'pending' | 'edited' | 'deleted' | 'failed';
+
+// Новый текст доставленной рассылки (или подпись, если рассылка с медиафайлами)
+export interface EditBroadcastRequest {
+  message: string;
 }
 
-export type BroadcastStatus = 'pending' | 'in_progress' | 'completed' | 'failed';
+export interface BroadcastRevisionResponse {
+  queued: number;
+}
 
-export type MessageStatus = 'pending' | 'sent' | 'failed' | 'retrying';
+export interface BroadcastFunnel {
+  broadcast_id: string;
+  delivered: number;
+  clicked: number;
+  slots_viewed: number;
+  booked: number;
+  conversion_rate: number;
+}
+
+export interface BroadcastVariantStats {
+  variant: number; // 0 — A (основной текст), 1 — B...
+  recipients: number;
+  delivered: number;
+  failed: number;
+  clicked: number;
+  booked: number;
+  conversion_rate: number;
+  is_winner: boolean;
+}
 
 export interface RetryMessageCommand {
   broadcast_id: string;
-  user_id: number;
+  telegram_id: number;
+}
+
+// Сообщение, исчерпавшее попытки доставки
+export interface DeadLetterMessage {
+  message: {
+    telegram_id: number;
+    broadcast_id: string;
+    message: string;
+    message_type?: 'custom' | 'signup';
+    media_group?: MediaGroup;
+    created_at: string;
+  };
+  error: string;
+  failed_at: string;
+}
+
+export interface ReplayDeadLettersResponse {
+  replayed: number;
 }
 
 // External API Types
@@ -371,3 +552,13 @@ export interface AuthResponse {
   user_profile?: UserProfile;
   user_role?: number;
 }
+
+// Support inbox types
+export interface SupportMessage {
+  id: number;
+  telegram_id: number;
+  direction: 'inbound' | 'outbound';
+  text?: string;
+  staff_telegram_id?: number;
+  created_at: string;
+}
@@ -2,6 +2,7 @@ use tracing::{error, info};
 use sqlx::SqlitePool;
 
 use core_logic::{BroadcastEvent, BroadcastMessage, MessageStatus, EventsWorker, RabbitMQClient};
+use core_logic::variants;
 use anyhow::Error;
 
 #[tokio::main]
@@ -25,6 +26,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("  - Database: SQLite");
     info!("  - RabbitMQ: Connected");
 
+    // Бот нужен только для уведомлений об итогах рассылок
+    let bot = std::env::var("TELOXIDE_TOKEN").ok().map(teloxide::Bot::new);
+
     // Создаем воркер для обработки событий
     let events_worker = EventsWorker::new().await?;
 
@@ -32,9 +36,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     events_worker.start_processing("event_worker", move |event| {
         let pool = pool.clone();
         let rabbitmq_client = rabbitmq_client.clone();
+        let bot = bot.clone();
         
         async move {
-            handle_broadcast_event(event, &pool, &rabbitmq_client).await
+            handle_broadcast_event(event, &pool, &rabbitmq_client, bot.as_ref()).await
         }
     }).await?;
 
@@ -45,103 +50,247 @@ async fn handle_broadcast_event(
     event: BroadcastEvent,
     pool: &SqlitePool,
     rabbitmq_client: &RabbitMQClient,
+    bot: Option<&teloxide::Bot>,
 ) -> Result<(), Error> {
     info!("=== PROCESSING BROADCAST EVENT ===");
     info!("Event type: {:?}", event);
 
     match event {
-        BroadcastEvent::BroadcastCreated { broadcast_id, message, target_users, message_type, media_group, created_at } => {
-            info!("Processing BroadcastCreated event for broadcast: {}", broadcast_id);
-            
-            // Используем переданных пользователей
-            let users = target_users;
-
-            info!("Found {} users for broadcast", users.len());
-            for user in &users {
-                info!("  - telegram_id: {}, name: {}", user.telegram_id, user.name);
-            }
-
-            // Создаем сообщения для каждого пользователя
-            for user in users {
-                info!("Creating BroadcastMessage for user {} with media_group: {:?}", user.telegram_id, media_group);
-                let message_record = BroadcastMessage {
-                    broadcast_id: broadcast_id.clone(),
-                    telegram_id: user.telegram_id,
-                    message: message.clone(),
-                    message_type: message_type.clone(),
-                    media_group: media_group.clone(),
-                    created_at,
-                };
-                info!("BroadcastMessage created: has_media_group={}", message_record.media_group.is_some());
-
-                // Создаем запись сообщения в БД
-                let message_db_record = core_logic::BroadcastMessageRecord {
-                    id: 0, // Будет заполнено БД
-                    broadcast_id: message_record.broadcast_id.clone(),
-                    telegram_id: message_record.telegram_id,
-                    status: MessageStatus::Pending,
-                    error: None,
-                    sent_at: None,
-                    retry_count: 0,
-                    message_type: message_record.message_type.clone(),
-                    created_at: chrono::Utc::now().naive_utc(),
-                };
-
-                // Сохраняем сообщение в БД
-                core_logic::db::create_broadcast_message(pool, &message_db_record).await?;
-
-                // Отправляем сообщение в RabbitMQ
-                    info!("Publishing message to RabbitMQ for telegram_id {} in broadcast {}", user.telegram_id, broadcast_id);
-                match rabbitmq_client.publish_message(&message_record).await {
-                        Ok(_) => {
-                        info!("✅ Message published to RabbitMQ successfully");
-                        }
-                        Err(e) => {
-                        let error_msg = format!("Failed to publish to RabbitMQ: {}", e);
-                        error!("{}", error_msg);
-                        
-                        // Обновляем статус на failed
-                        core_logic::db::update_broadcast_message_status(
-                            pool,
-                            &message_record.broadcast_id,
-                            message_record.telegram_id,
-                            MessageStatus::Failed,
-                            Some(error_msg),
-                        ).await?;
-                    }
-                }
-            }
-
-            // Обновляем статус рассылки
-            core_logic::db::update_broadcast_status(
-                pool,
-                &broadcast_id,
-                core_logic::BroadcastStatus::InProgress as u32,
-                0, // version
-            ).await?;
-
-            info!("✅ BroadcastCreated event processed successfully");
+        created @ BroadcastEvent::BroadcastCreated { .. } => {
+            queue_broadcast(created, None, pool, rabbitmq_client, bot).await?;
         }
         BroadcastEvent::BroadcastStarted { .. } => {
             info!("BroadcastStarted event - no action needed");
         }
-        BroadcastEvent::MessageSent { .. } => {
-            info!("MessageSent event - no action needed");
-        }
-        BroadcastEvent::MessageFailed { .. } => {
-            info!("MessageFailed event - no action needed");
+        BroadcastEvent::MessageSent { .. } | BroadcastEvent::MessageFailed { .. } => {
+            // Только пишутся в broadcast_events: сводку пересчитывает бот вместе со статусом сообщения
+            info!("Message delivery event - no action needed");
         }
         BroadcastEvent::MessageRetrying { .. } => {
             info!("MessageRetrying event - no action needed");
         }
-        BroadcastEvent::BroadcastCompleted { .. } => {
-            info!("BroadcastCompleted event - no action needed");
+        BroadcastEvent::BroadcastCompleted { broadcast_id, total_sent, total_failed, .. } => {
+            info!("🏁 BroadcastCompleted event for {}: sent {}, failed {}", broadcast_id, total_sent, total_failed);
+        }
+        BroadcastEvent::BroadcastCancelled { broadcast_id, .. } => {
+            // Сообщения, созданные уже после отмены, тоже не должны уйти
+            let cancelled = core_logic::db::cancel_pending_broadcast_messages(pool, &broadcast_id).await?;
+            info!("🛑 BroadcastCancelled event: {} pending messages cancelled", cancelled);
+        }
+        BroadcastEvent::BroadcastPaused { broadcast_id, .. } => {
+            info!("⏸️ BroadcastPaused event for {} - messages are deferred by the telegram worker", broadcast_id);
+        }
+        BroadcastEvent::BroadcastResumed { broadcast_id, .. } => {
+            info!("▶️ BroadcastResumed event for {} - no action needed", broadcast_id);
+        }
+        BroadcastEvent::BroadcastApproved { broadcast_id, approved_by, .. } => {
+            info!("✅ BroadcastApproved event for {} by {} - published by the scheduler", broadcast_id, approved_by);
+        }
+        BroadcastEvent::BroadcastRejected { broadcast_id, rejected_by, .. } => {
+            info!("🚫 BroadcastRejected event for {} by {} - no action needed", broadcast_id, rejected_by);
+        }
+        BroadcastEvent::BroadcastWinnerSelected { broadcast_id, variant, .. } => {
+            info!("🏆 BroadcastWinnerSelected event for {}: variant {}", broadcast_id, core_logic::variants::variant_label(variant));
+            match core_logic::db::get_broadcast_created_event(pool, &broadcast_id).await? {
+                Some(created) => queue_broadcast(created, Some(variant), pool, rabbitmq_client, bot).await?,
+                None => error!("❌ BroadcastCreated event for broadcast {} not found", broadcast_id),
+            }
         }
     }
 
     Ok(())
 }
 
+/// Создает сообщения рассылки и публикует их в очередь. Без победителя — первая отправка,
+/// с победителем A/B-теста — его вариант остальной аудитории
+async fn queue_broadcast(
+    created: BroadcastEvent,
+    winner: Option<usize>,
+    pool: &SqlitePool,
+    rabbitmq_client: &RabbitMQClient,
+    bot: Option<&teloxide::Bot>,
+) -> Result<(), Error> {
+    let BroadcastEvent::BroadcastCreated {
+        broadcast_id,
+        message,
+        target_users,
+        message_type,
+        media_group,
+        created_at,
+        segment_id,
+        keyboard,
+        parse_mode,
+        split_long_text,
+        variants,
+        auto_winner,
+        ..
+    } = created
+    else {
+        return Ok(());
+    };
+
+    info!("Processing BroadcastCreated event for broadcast: {}", broadcast_id);
+
+    // Рассылку могли отменить, пока событие ждало в очереди
+    if core_logic::db::get_broadcast_status(pool, &broadcast_id).await? == Some(core_logic::BroadcastStatus::Cancelled) {
+        info!("🛑 Broadcast {} is cancelled, skipping message creation", broadcast_id);
+        return Ok(());
+    }
+    if winner.is_none() {
+        core_logic::db::mark_broadcast_started(pool, &broadcast_id).await?;
+    }
+    
+    // Сегмент вычисляется в момент отправки, иначе используем переданных пользователей
+    let users = match segment_id {
+        Some(segment_id) => {
+            let Some(users) = core_logic::db::resolve_broadcast_segment(pool, &broadcast_id, segment_id).await? else {
+                error!("❌ Segment {} for broadcast {} not found", segment_id, broadcast_id);
+                if let Some(mut summary) = core_logic::db::get_broadcast_summary(pool, &broadcast_id).await? {
+                    summary.status = core_logic::BroadcastStatus::Failed;
+                    summary.completed_at = Some(chrono::Utc::now().naive_utc());
+                    core_logic::db::update_broadcast_summary(pool, &summary).await?;
+                }
+                return Ok(());
+            };
+            info!("🎯 Segment {} resolved to {} users", segment_id, users.len());
+            users
+        }
+        None => target_users,
+    };
+
+    info!("Found {} users for broadcast", users.len());
+    for user in &users {
+        info!("  - telegram_id: {}, name: {}", user.telegram_id, user.name);
+    }
 
+    // A/B-варианты: без победителя получатели делятся по хешу telegram_id
+    // (в режиме автовыбора — только тестовая доля), победитель уходит всем, кому еще не отправляли
+    let recipients: Vec<(core_logic::User, Option<usize>)> = match winner {
+        Some(winner) => {
+            let queued = core_logic::db::get_broadcast_recipient_ids(pool, &broadcast_id).await?;
+            users.into_iter().filter(|user| !queued.contains(&user.telegram_id)).map(|user| (user, Some(winner))).collect()
+        }
+        None if variants.is_empty() => users.into_iter().map(|user| (user, None)).collect(),
+        None => {
+            if let Some(settings) = &auto_winner {
+                core_logic::db::schedule_broadcast_auto_winner(pool, &broadcast_id, settings).await?;
+            }
+            let test_percent = auto_winner.map(|settings| settings.test_percent);
+            users
+                .into_iter()
+                .filter_map(|user| {
+                    variants::assign_variant(user.telegram_id, variants.len() + 1, test_percent).map(|variant| (user, Some(variant)))
+                })
+                .collect()
+        }
+    };
+    let contents: Vec<_> = (0..=variants.len())
+        .map(|index| variants::variant_content(message.clone(), media_group.clone(), variants.clone(), index))
+        .collect();
+
+    // Профили для шаблона загружаем для всех получателей сразу, а не по одному в цикле
+    let needs_profile = contents.iter().any(|(message, media_group)| {
+        core_logic::template::validate_broadcast(message, media_group.as_ref())
+            .is_ok_and(|variables| variables.iter().any(|variable| variable.needs_profile()))
+    });
+    if needs_profile {
+        let telegram_ids: Vec<i64> = recipients.iter().map(|(user, _)| user.telegram_id).collect();
+        core_logic::db::prefetch_template_profiles(pool, &telegram_ids).await;
+    }
 
+    // Создаем сообщения для каждого пользователя
+    for (user, variant) in recipients {
+        let (message, media_group) = contents[variant.unwrap_or(0)].clone();
+        info!("Creating BroadcastMessage for user {} with media_group: {:?}", user.telegram_id, media_group);
+        let message_record = BroadcastMessage {
+            broadcast_id: broadcast_id.clone(),
+            telegram_id: user.telegram_id,
+            message,
+            message_type: message_type.clone(),
+            media_group,
+            created_at,
+            keyboard: keyboard.clone(),
+            parse_mode,
+            split_long_text,
+            is_test: false,
+            action: core_logic::BroadcastMessageAction::Send,
+            telegram_message_ids: Vec::new(),
+        };
+        // Подставляем в шаблон данные конкретного получателя
+        let message_record = core_logic::db::render_broadcast_message(pool, message_record).await?;
+        info!("BroadcastMessage created: has_media_group={}", message_record.media_group.is_some());
 
+        // Создаем запись сообщения в БД
+        let message_db_record = core_logic::BroadcastMessageRecord {
+            id: 0, // Будет заполнено БД
+            broadcast_id: message_record.broadcast_id.clone(),
+            telegram_id: message_record.telegram_id,
+            status: MessageStatus::Pending,
+            error: None,
+            sent_at: None,
+            retry_count: 0,
+            message_type: message_record.message_type.clone(),
+            created_at: chrono::Utc::now().naive_utc(),
+            edit_status: None,
+            edit_error: None,
+            variant: variant.map(|variant| variant as i64),
+        };
+
+        // Сохраняем сообщение в БД; при повторной доставке BroadcastCreated получатель уже есть,
+        // и сообщение публикуется снова, только если прошлая попытка не дошла до публикации
+        if !core_logic::db::create_broadcast_message(pool, &message_db_record).await?
+            && !core_logic::db::is_broadcast_message_unqueued(pool, &broadcast_id, user.telegram_id).await?
+        {
+            info!("⏭️ Message for telegram_id {} in broadcast {} already queued, skipping", user.telegram_id, broadcast_id);
+            continue;
+        }
+
+        // Отправляем сообщение в RabbitMQ
+            info!("Publishing message to RabbitMQ for telegram_id {} in broadcast {}", user.telegram_id, broadcast_id);
+        match rabbitmq_client.publish_message(&message_record).await {
+                Ok(_) => {
+                info!("✅ Message published to RabbitMQ successfully");
+                if let Err(e) = core_logic::db::mark_broadcast_message_queued(pool, &message_record.broadcast_id, message_record.telegram_id).await {
+                    error!("Failed to mark message for telegram_id {} as queued: {}", message_record.telegram_id, e);
+                }
+                }
+                Err(e) => {
+                let error_msg = format!("Failed to publish to RabbitMQ: {}", e);
+                error!("{}", error_msg);
+                
+                // Обновляем статус на failed
+                let completed = core_logic::db::update_broadcast_message_status(
+                    pool,
+                    &message_record.broadcast_id,
+                    message_record.telegram_id,
+                    MessageStatus::Failed,
+                    Some(error_msg),
+                ).await?;
+                if let Some(summary) = completed {
+                    notify_completed(bot, pool, &summary).await;
+                }
+            }
+        }
+    }
+
+    // Рассылка без получателей или с неопубликованными сообщениями завершается здесь,
+    // остальные — воркером бота на последнем сообщении
+    if let Some(summary) = core_logic::db::update_broadcast_summary_from_messages(pool, &broadcast_id).await? {
+        notify_completed(bot, pool, &summary).await;
+    }
+
+    info!("✅ Broadcast {} messages queued", broadcast_id);
+
+    Ok(())
+}
+
+/// Отправляет итоги рассылки, если уведомления включены и задан токен бота
+async fn notify_completed(bot: Option<&teloxide::Bot>, pool: &SqlitePool, summary: &core_logic::BroadcastSummary) {
+    let Some(bot) = bot.filter(|_| telegram_bot::admin::completion_notifications_enabled()) else {
+        return;
+    };
+    match telegram_bot::admin::notify_broadcast_completed(bot, pool, summary).await {
+        Ok(notified) => info!("🏁 Broadcast {} results sent to {} responsibles", summary.id, notified),
+        Err(e) => error!("Failed to notify about completed broadcast {}: {}", summary.id, e),
+    }
+}
@@ -0,0 +1,223 @@
+use axum::{
+    body::{self, Body},
+    extract::State,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use core_logic::{IdempotencyReservation, StoredResponse};
+use sqlx::SqlitePool;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+// Ответ на повтор помечается, чтобы клиент отличал его от первого выполнения
+const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+// Как у Json-экстрактора axum по умолчанию: больше все равно не будет принято
+const MAX_IDEMPOTENT_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Middleware для изменяющих запросов с заголовком Idempotency-Key: первый запрос выполняется
+/// и его ответ сохраняется, повтор с тем же ключом получает сохраненный ответ без второго действия.
+/// Ответы 5xx не сохраняются, чтобы повтор мог выполнить запрос заново.
+/// Нужен только пул БД, поэтому слой подключается с `State<SqlitePool>`
+pub async fn idempotency_handler(
+    State(pool): State<SqlitePool>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let is_mutating = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER).filter(|_| is_mutating).cloned() else {
+        return next.run(req).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.trim().is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => key.to_string(),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Idempotency-Key должен быть непустой строкой до {} символов", MAX_IDEMPOTENCY_KEY_LEN),
+            )
+                .into_response();
+        }
+    };
+
+    // Файлы загружаются потоком, их тело не буферизуем
+    let is_multipart = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/"));
+    if is_multipart {
+        println!("⚠️ Idempotency-Key не поддерживается для multipart-запросов, ключ {} проигнорирован", key);
+        return next.run(req).await;
+    }
+
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let (parts, request_body) = req.into_parts();
+    let request_body = match body::to_bytes(request_body, MAX_IDEMPOTENT_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, format!("Не удалось прочитать тело запроса: {}", e)).into_response(),
+    };
+
+    match core_logic::db::reserve_idempotency_key(&pool, &key, &method, &path, &request_body).await {
+        Ok(IdempotencyReservation::Reserved) => {}
+        Ok(IdempotencyReservation::Completed(stored)) => {
+            println!("🔁 {} {}: повтор с Idempotency-Key {}, возвращаем сохраненный ответ", method, path, key);
+            return replay(stored);
+        }
+        Ok(IdempotencyReservation::InProgress) => {
+            return (StatusCode::CONFLICT, "Запрос с этим Idempotency-Key еще выполняется".to_string()).into_response();
+        }
+        Ok(IdempotencyReservation::Mismatch) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key уже использован с другим телом запроса".to_string(),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            println!("❌ Ошибка при проверке Idempotency-Key {}: {}", key, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response();
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(request_body))).await;
+    let (parts, response_body) = response.into_parts();
+    let response_body = match body::to_bytes(response_body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("❌ Не удалось прочитать ответ для Idempotency-Key {}: {}", key, e);
+            release(&pool, &key, &method, &path).await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read response".to_string()).into_response();
+        }
+    };
+
+    if parts.status.is_server_error() {
+        release(&pool, &key, &method, &path).await;
+    } else {
+        let stored = StoredResponse {
+            status_code: parts.status.as_u16(),
+            body: response_body.to_vec(),
+            content_type: parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        };
+        if let Err(e) = core_logic::db::complete_idempotency_key(&pool, &key, &method, &path, &stored).await {
+            println!("❌ Не удалось сохранить ответ для Idempotency-Key {}: {}", key, e);
+        }
+    }
+
+    Response::from_parts(parts, Body::from(response_body))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status_code).unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body).into_response();
+    let headers = response.headers_mut();
+    match stored.content_type.and_then(|content_type| HeaderValue::from_str(&content_type).ok()) {
+        Some(content_type) => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        None => {
+            headers.remove(header::CONTENT_TYPE);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+async fn release(pool: &SqlitePool, key: &str, method: &str, path: &str) {
+    if let Err(e) = core_logic::db::release_idempotency_key(pool, key, method, path).await {
+        println!("❌ Не удалось освободить Idempotency-Key {}: {}", key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use axum::{middleware, routing::post, Router};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tower::ServiceExt;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        core_logic::db::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    /// /broadcast отвечает номером выполнения, /flaky — 500 на первое выполнение и 200 дальше
+    fn test_router(pool: SqlitePool, calls: Arc<AtomicUsize>) -> Router {
+        let broadcast_calls = calls.clone();
+        Router::new()
+            .route(
+                "/broadcast",
+                post(move || {
+                    let calls = broadcast_calls.clone();
+                    async move { format!("call {}", calls.fetch_add(1, Ordering::SeqCst) + 1) }
+                }),
+            )
+            .route(
+                "/flaky",
+                post(move || {
+                    let calls = calls.clone();
+                    async move {
+                        match calls.fetch_add(1, Ordering::SeqCst) {
+                            0 => StatusCode::INTERNAL_SERVER_ERROR,
+                            _ => StatusCode::OK,
+                        }
+                    }
+                }),
+            )
+            .layer(middleware::from_fn_with_state(pool, idempotency_handler))
+    }
+
+    /// Статус, признак повтора и тело ответа
+    async fn send(router: &Router, path: &str, key: &str, body: &'static str) -> (StatusCode, bool, String) {
+        let request = Request::post(path)
+            .header(IDEMPOTENCY_KEY_HEADER, key)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let replayed = response.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER);
+        let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn repeated_request_gets_stored_response() {
+        let pool = test_pool().await;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = test_router(pool.clone(), calls.clone());
+        let body = r#"{"message":"Открыта запись"}"#;
+
+        assert_eq!(send(&router, "/broadcast", "key-1", body).await, (StatusCode::OK, false, "call 1".to_string()));
+        assert_eq!(send(&router, "/broadcast", "key-1", body).await, (StatusCode::OK, true, "call 1".to_string()));
+        // Тот же ключ с другим телом — ошибка клиента
+        let mismatch = send(&router, "/broadcast", "key-1", r#"{"message":"Другой текст"}"#).await;
+        assert_eq!(mismatch.0, StatusCode::UNPROCESSABLE_ENTITY);
+        // Запрос с этим ключом еще выполняется
+        core_logic::db::reserve_idempotency_key(&pool, "key-2", "POST", "/broadcast", body.as_bytes()).await.unwrap();
+        assert_eq!(send(&router, "/broadcast", "key-2", body).await.0, StatusCode::CONFLICT);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn server_error_releases_key_for_retry() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = test_router(test_pool().await, calls.clone());
+
+        assert_eq!(send(&router, "/flaky", "key-1", "{}").await.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(send(&router, "/flaky", "key-1", "{}").await, (StatusCode::OK, false, String::new()));
+        assert_eq!(send(&router, "/flaky", "key-1", "{}").await, (StatusCode::OK, true, String::new()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -17,6 +17,9 @@ use core_logic::{
     // Event-Driven structures
     CreateBroadcastCommand, BroadcastCreatedResponse, BroadcastStatusResponse,
     GetBroadcastStatusQuery, GetBroadcastMessagesQuery, RetryMessageCommand, CancelBroadcastCommand,
+    RescheduleBroadcastCommand, BroadcastStatus, DeadLetterMessage, ReplayDeadLettersResponse, BroadcastPreviewResponse,
+    TestBroadcastRequest, TestBroadcastResponse, BroadcastApprovalRequest, EditBroadcastRequest, BroadcastRevisionResponse,
+    BroadcastEvent, BroadcastMessageAction,
     // Voting system structures
     Vote, CreateVoteRequest, VoteResponse, NextSurveyResponse, SurveyVoteSummary,
     // Auth structures
@@ -27,11 +30,20 @@ use core_logic::{
 use core_logic::RabbitMQClient;
 use sqlx::SqlitePool;
 mod upload;
+mod scheduler;
+mod idempotency;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 use tower_http::cors::{CorsLayer, Any};
 use serde_json::Error as JsonError;
 
+// Сколько сообщений dead-letter очереди показывать и повторять за один запрос
+const DEFAULT_DEAD_LETTERS_LIMIT: usize = 50;
+// Для скольких получателей показывать предпросмотр персонализированной рассылки
+const BROADCAST_PREVIEW_LIMIT: usize = 5;
+// Сколько сотрудников можно указать в тестовой отправке
+const MAX_TEST_RECIPIENTS: usize = 20;
+
 // Состояние приложения
 #[derive(Clone)]
 struct AppState {
@@ -107,6 +119,9 @@ async fn main() {
     let bot_token = std::env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN not found");
     let bot = teloxide::Bot::new(bot_token);
 
+    // Планировщик отложенных рассылок
+    tokio::spawn(scheduler::run_broadcast_scheduler(pool.clone(), rabbitmq.clone()));
+
     let state = AppState { pool, rabbitmq, bot };
 
     // Настройка CORS
@@ -131,11 +146,25 @@ async fn main() {
         .route("/votes/clear-locks/{telegram_id}", post(clear_user_locks))
         // Event-Driven broadcast endpoints
         .route("/broadcast", post(create_broadcast).get(get_all_broadcasts))
+        .route("/broadcast/preview", post(preview_broadcast))
         .route("/broadcast/{id}", delete(delete_broadcast))
         .route("/broadcast/{id}/status", get(get_broadcast_status))
         .route("/broadcast/{id}/messages", get(get_broadcast_messages))
+        .route("/broadcast/{id}/funnel", get(get_broadcast_funnel))
+        .route("/broadcast/{id}/variants", get(get_broadcast_variant_stats))
         .route("/broadcast/{id}/retry", post(retry_broadcast_message))
+        .route("/dead-letters", get(get_dead_letters))
+        .route("/dead-letters/replay", post(replay_dead_letters))
         .route("/broadcast/{id}/cancel", post(cancel_broadcast))
+        .route("/broadcast/{id}/pause", post(pause_broadcast))
+        .route("/broadcast/{id}/resume", post(resume_broadcast))
+        .route("/broadcast/{id}/schedule", put(reschedule_broadcast))
+        .route("/broadcast/{id}/release", post(release_broadcast))
+        .route("/broadcast/{id}/test", post(test_broadcast))
+        .route("/broadcast/{id}/approve", post(approve_broadcast))
+        .route("/broadcast/{id}/edit", post(edit_broadcast))
+        .route("/broadcast/{id}/recall", post(recall_broadcast))
+        .route("/broadcast/{id}/reject", post(reject_broadcast))
         .route("/upload", post(upload::upload_file))
         // Voting system endpoints
         .route("/surveys/next", get(get_next_survey))
@@ -144,12 +173,24 @@ async fn main() {
         .route("/users/{id}/role", put(set_user_role))
         .route("/users/{id}/info", get(get_user_info))
         .route("/users/{id}/survey", get(get_user_survey))
+        .route("/users/{id}/support-messages", get(get_user_support_messages))
         .route("/surveys/sync", post(sync_users))
         .route("/external-users", get(get_external_users))
         .route("/selected-users", get(get_selected_users))
         .route("/no-response-users", get(get_no_response_users))
+        .route("/unreachable-users", get(get_unreachable_users))
+        .route("/deep-links", get(get_deep_link_stats).post(create_deep_link))
+        .route("/segments", get(get_segments).post(create_segment))
+        .route("/segments/count", post(count_segment))
+        .route("/segments/{id}", put(update_segment).delete(delete_segment))
+        .route("/segments/{id}/count", get(get_segment_count))
+        .route("/decision-campaigns", get(get_decision_campaigns).post(create_decision_campaign))
+        .route("/decision-campaigns/{id}", put(update_decision_campaign))
+        .route("/decision-campaigns/{id}/notifications", get(get_decision_notifications))
         .route("/broadcast-message-status", put(update_broadcast_message_status))
         .route("/auth/telegram", post(authenticate_telegram))
+        // Повтор изменяющего запроса с тем же Idempotency-Key получает сохраненный ответ
+        .layer(middleware::from_fn_with_state(state.pool.clone(), idempotency::idempotency_handler))
         .layer(cors)
         .layer(middleware::from_fn(json_error_handler))
         .with_state(state);
@@ -379,8 +420,12 @@ async fn get_all_votes(State(state): State<AppState>) -> Result<Json<Vec<Vote>>,
     )
 )]
 async fn create_user(State(state): State<AppState>, Json(payload): Json<CreateUserRequest>) -> Result<Json<User>, (StatusCode, String)> {
+    let role = payload.role;
     match core_logic::db::create_user(&state.pool, payload).await {
-        Ok(user) => Ok(Json(user)),
+        Ok(user) => {
+            refresh_command_menu(&state.bot, user.telegram_id, Some(role)).await;
+            Ok(Json(user))
+        }
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Database error: {}", e),
@@ -451,8 +496,12 @@ async fn update_user(
     Path(telegram_id): Path<i64>, 
     Json(payload): Json<UpdateUserRequest>
 ) -> Result<Json<User>, (StatusCode, String)> {
+    let role = payload.role;
     match core_logic::db::update_user(&state.pool, telegram_id, payload).await {
-        Ok(user) => Ok(Json(user)),
+        Ok(user) => {
+            refresh_command_menu(&state.bot, telegram_id, Some(role)).await;
+            Ok(Json(user))
+        }
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Database error: {}", e),
@@ -472,7 +521,10 @@ async fn delete_user(
     Path(telegram_id): Path<i64>
 ) -> Result<StatusCode, (StatusCode, String)> {
     match core_logic::db::delete_user(&state.pool, telegram_id).await {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Ok(_) => {
+            refresh_command_menu(&state.bot, telegram_id, None).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Database error: {}", e),
@@ -505,7 +557,8 @@ async fn delete_booking(
     path = "/broadcast",
     request_body = CreateBroadcastCommand,
     responses(
-        (status = 201, description = "Broadcast created successfully", body = BroadcastCreatedResponse)
+        (status = 201, description = "Broadcast created successfully", body = BroadcastCreatedResponse),
+        (status = 400, description = "Template, formatting or keyboard error")
     )
 )]
 async fn create_broadcast(
@@ -528,6 +581,37 @@ async fn create_broadcast(
     //         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get users: {}", e)))?
     // };
 
+    // Шаблон проверяем до создания: ошибка в переменных — ошибка запроса
+    if let Err(e) = core_logic::template::validate_broadcast(&payload.message, payload.media_group.as_ref()) {
+        println!("❌ Ошибка в шаблоне рассылки: {}", e);
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Err(e) = core_logic::formatting::validate_broadcast(
+        &payload.message,
+        payload.media_group.as_ref(),
+        payload.parse_mode,
+        payload.split_long_text,
+    ) {
+        println!("❌ Ошибка в разметке рассылки: {}", e);
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Some(Err(e)) = payload.keyboard.as_ref().map(|keyboard| keyboard.validate()) {
+        println!("❌ Ошибка в клавиатуре рассылки: {}", e);
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Err(e) = validate_broadcast_variants(&payload) {
+        println!("❌ Ошибка в вариантах рассылки: {}", e);
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+
+    if let Some(segment_id) = payload.segment_id {
+        let segment = core_logic::db::get_segment(&state.pool, segment_id).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        if segment.is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("Сегмент {} не найден", segment_id)));
+        }
+    }
+
     // Создаем рассылку в БД (пользователи будут обработаны внутри handle_create_broadcast)
     let (result, event) = match core_logic::db::handle_create_broadcast(&state.pool, payload.clone()).await {
         Ok((result, event)) => (result, event),
@@ -537,7 +621,21 @@ async fn create_broadcast(
         )),
     };
 
-    println!("Broadcast created with ID: {}", result.broadcast_id);
+    println!("Broadcast created with ID: {} (skipped unreachable: {})", result.broadcast_id, result.skipped_unreachable);
+
+    // Отложенную рассылку опубликует планировщик
+    if result.status == BroadcastStatus::Scheduled {
+        println!("⏰ Рассылка {} запланирована на {:?}", result.broadcast_id, payload.send_at);
+        return Ok(Json(result));
+    }
+    if result.status == BroadcastStatus::Draft {
+        println!("📝 Рассылка {} сохранена как черновик", result.broadcast_id);
+        return Ok(Json(result));
+    }
+    if result.status == BroadcastStatus::PendingApproval {
+        request_broadcast_approval(&state, &result.broadcast_id, payload.created_by).await;
+        return Ok(Json(result));
+    }
 
     // Отправляем событие в RabbitMQ
     if let Err(e) = state.rabbitmq.publish_event(&event).await {
@@ -550,6 +648,77 @@ async fn create_broadcast(
     Ok(Json(result))
 }
 
+/// Варианты проверяются так же, как основной текст; автовыбор имеет смысл только с ними
+fn validate_broadcast_variants(payload: &CreateBroadcastCommand) -> Result<(), String> {
+    if payload.variants.len() >= core_logic::variants::MAX_VARIANTS {
+        return Err(format!("Не больше {} вариантов вместе с основным текстом", core_logic::variants::MAX_VARIANTS));
+    }
+    for (index, variant) in payload.variants.iter().enumerate() {
+        let label = core_logic::variants::variant_label(index + 1);
+        core_logic::template::validate_broadcast(&variant.message, variant.media_group.as_ref())
+            .map_err(|e| format!("Вариант {}: {}", label, e))?;
+        core_logic::formatting::validate_broadcast(
+            &variant.message,
+            variant.media_group.as_ref(),
+            payload.parse_mode,
+            payload.split_long_text,
+        )
+        .map_err(|e| format!("Вариант {}: {}", label, e))?;
+    }
+    match payload.auto_winner {
+        Some(_) if payload.variants.is_empty() => Err("Автовыбор победителя требует хотя бы одного варианта".to_string()),
+        Some(settings) => settings.validate(),
+        None => Ok(()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/preview",
+    request_body = CreateBroadcastCommand,
+    responses(
+        (status = 200, description = "Broadcast text rendered for the first recipients", body = BroadcastPreviewResponse),
+        (status = 400, description = "Template, formatting, keyboard or recipient error")
+    )
+)]
+async fn preview_broadcast(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateBroadcastCommand>,
+) -> Result<Json<BroadcastPreviewResponse>, (StatusCode, String)> {
+    println!("👀 POST /broadcast/preview - предпросмотр рассылки");
+
+    if let Err(e) = core_logic::template::validate_broadcast(&payload.message, payload.media_group.as_ref()) {
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Err(e) = core_logic::formatting::validate_broadcast(
+        &payload.message,
+        payload.media_group.as_ref(),
+        payload.parse_mode,
+        payload.split_long_text,
+    ) {
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Some(Err(e)) = payload.keyboard.as_ref().map(|keyboard| keyboard.validate()) {
+        return Err((StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    if let Some(invalid) = payload
+        .selected_external_users
+        .iter()
+        .flatten()
+        .find(|telegram_id| telegram_id.parse::<i64>().is_err())
+    {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid telegram_id: {}", invalid)));
+    }
+
+    match core_logic::db::handle_preview_broadcast(&state.pool, &payload, BROADCAST_PREVIEW_LIMIT).await {
+        Ok(preview) => Ok(Json(preview)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to preview broadcast: {}", e),
+        )),
+    }
+}
+
 #[utoipa::path(
     delete,
     path = "/broadcast/{id}",
@@ -643,11 +812,71 @@ async fn get_broadcast_messages(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/broadcast/{id}/funnel",
+    responses(
+        (status = 200, description = "Delivered, clicked, slots viewed and booked recipients", body = core_logic::BroadcastFunnel),
+        (status = 404, description = "Broadcast not found")
+    )
+)]
+async fn get_broadcast_funnel(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+) -> Result<Json<core_logic::BroadcastFunnel>, (StatusCode, String)> {
+    println!("📈 GET /broadcast/{}/funnel - воронка рассылки", broadcast_id);
+
+    match core_logic::db::get_broadcast_funnel(&state.pool, &broadcast_id).await {
+        Ok(Some(funnel)) => Ok(Json(funnel)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Broadcast not found".to_string())),
+        Err(e) => {
+            println!("❌ Ошибка при получении воронки рассылки: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get broadcast funnel: {}", e),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/broadcast/{id}/variants",
+    responses(
+        (status = 200, description = "Delivery and conversion per A/B variant", body = Vec<core_logic::variants::BroadcastVariantStats>),
+        (status = 404, description = "Broadcast not found")
+    )
+)]
+async fn get_broadcast_variant_stats(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+) -> Result<Json<Vec<core_logic::variants::BroadcastVariantStats>>, (StatusCode, String)> {
+    println!("🧪 GET /broadcast/{}/variants - статистика A/B-вариантов", broadcast_id);
+
+    let summary = core_logic::db::get_broadcast_summary(&state.pool, &broadcast_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    if summary.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Broadcast not found".to_string()));
+    }
+
+    match core_logic::db::get_broadcast_variant_stats(&state.pool, &broadcast_id).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => {
+            println!("❌ Ошибка при получении статистики вариантов: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get broadcast variant stats: {}", e),
+            ))
+        }
+    }
+}
+
 #[utoipa::path(
     post,
     path = "/broadcast/{id}/retry",
     responses(
-        (status = 200, description = "Message retry initiated successfully")
+        (status = 200, description = "Message retry initiated successfully"),
+        (status = 404, description = "No failed message for this user")
     )
 )]
 async fn retry_broadcast_message(
@@ -660,433 +889,1532 @@ async fn retry_broadcast_message(
         telegram_id: payload.telegram_id,
     };
     
-    match core_logic::db::handle_retry_message(&state.pool, command).await {
-        Ok(_) => Ok(StatusCode::OK),
+    let message = core_logic::db::handle_retry_message(&state.pool, command).await.map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to retry message: {}", e),
+    ))?;
+
+    let Some(message) = message else {
+        return Err((StatusCode::NOT_FOUND, "Failed message not found".to_string()));
+    };
+
+    match state.rabbitmq.publish_message(&message).await {
+        Ok(_) => {
+            println!("🔁 Сообщение для {} снова в очереди", message.telegram_id);
+            Ok(StatusCode::OK)
+        },
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to retry message: {}", e),
+            format!("Failed to publish message: {}", e),
         )),
     }
 }
 
 #[utoipa::path(
-    post,
-    path = "/broadcast/{id}/cancel",
+    get,
+    path = "/dead-letters",
+    params(
+        ("limit" = Option<usize>, Query, description = "Сколько сообщений показать (по умолчанию 50)")
+    ),
     responses(
-        (status = 200, description = "Broadcast cancelled successfully")
+        (status = 200, description = "Messages that exhausted their delivery retries", body = [DeadLetterMessage])
     )
 )]
-async fn cancel_broadcast(
+async fn get_dead_letters(
     State(state): State<AppState>,
-    Path(broadcast_id): Path<String>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    let command = CancelBroadcastCommand { broadcast_id };
-    
-    match core_logic::db::handle_cancel_broadcast(&state.pool, command).await {
-        Ok(_) => Ok(StatusCode::OK),
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Vec<DeadLetterMessage>>, (StatusCode, String)> {
+    let limit = dead_letters_limit(&params);
+    println!("☠️ GET /dead-letters - просмотр (limit={})", limit);
+
+    match state.rabbitmq.peek_dead_letters(limit).await {
+        Ok(dead_letters) => Ok(Json(dead_letters)),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to cancel broadcast: {}", e),
+            format!("Failed to read dead-letter queue: {}", e),
         )),
     }
 }
 
-// Voting System Endpoints
-
 #[utoipa::path(
-    get,
-    path = "/surveys/next",
+    post,
+    path = "/dead-letters/replay",
     params(
-        ("telegram_id" = i64, Query, description = "Telegram ID пользователя")
+        ("limit" = Option<usize>, Query, description = "Сколько сообщений повторить (по умолчанию 50)")
     ),
     responses(
-        (status = 200, description = "Next survey retrieved successfully", body = NextSurveyResponse)
+        (status = 200, description = "Dead-lettered messages returned to the broadcast queue", body = ReplayDeadLettersResponse)
     )
 )]
-async fn get_next_survey(
+async fn replay_dead_letters(
     State(state): State<AppState>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<NextSurveyResponse>, (StatusCode, String)> {
-    let telegram_id = params.get("telegram_id")
-        .and_then(|s| s.parse::<i64>().ok())
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "telegram_id is required".to_string()))?;
-    
-    match core_logic::get_next_survey(&state.pool, telegram_id).await {
-        Ok(response) => Ok(Json(response)),
+) -> Result<Json<ReplayDeadLettersResponse>, (StatusCode, String)> {
+    let limit = dead_letters_limit(&params);
+    println!("🔁 POST /dead-letters/replay - повтор (limit={})", limit);
+
+    // Перед публикацией возвращаем сообщение в pending с новым запасом попыток
+    let pool = state.pool.clone();
+    let replayed = state.rabbitmq.replay_dead_letters(limit, |dead_letter| {
+        let pool = pool.clone();
+        async move {
+            // Правку или отзыв повторяем как есть: статус доставки у них не меняется
+            if dead_letter.message.is_test || dead_letter.message.action != BroadcastMessageAction::Send {
+                return Ok(true);
+            }
+            let reset = core_logic::db::reset_broadcast_message_for_retry(
+                &pool,
+                &dead_letter.message.broadcast_id,
+                dead_letter.message.telegram_id,
+            ).await?;
+            Ok(reset)
+        }
+    }).await;
+
+    match replayed {
+        Ok(replayed) => Ok(Json(ReplayDeadLettersResponse { replayed })),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
+            format!("Failed to replay dead letters: {}", e),
         )),
     }
 }
 
+fn dead_letters_limit(params: &std::collections::HashMap<String, String>) -> usize {
+    params.get("limit")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_DEAD_LETTERS_LIMIT)
+}
+
 #[utoipa::path(
     post,
-    path = "/surveys/{id}/vote",
+    path = "/broadcast/{id}/cancel",
     params(
-        ("id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)"),
-        ("telegram_id" = i64, Query, description = "Telegram ID голосующего")
+        ("id" = String, Path, description = "Broadcast ID")
     ),
-    request_body = CreateVoteRequest,
     responses(
-        (status = 200, description = "Vote created successfully", body = VoteResponse)
+        (status = 200, description = "Broadcast cancelled successfully"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is already finished")
     )
 )]
-async fn create_vote(
+async fn cancel_broadcast(
     State(state): State<AppState>,
-    Path(survey_id): Path<i64>,
-    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-    Json(payload): Json<CreateVoteRequest>,
-) -> Result<Json<VoteResponse>, (StatusCode, String)> {
-    println!("🗳️ Получен запрос голосования для анкеты {} от пользователя", survey_id);
-    println!("📋 Данные голоса: {:?}", payload);
-    
-    let voter_telegram_id = params.get("telegram_id")
-        .and_then(|s| s.parse::<i64>().ok())
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "telegram_id is required".to_string()))?;
-    
-    println!("👤 ID голосующего: {}", voter_telegram_id);
-    
-    // Убеждаемся, что survey_id в пути совпадает с survey_id в теле запроса
-    if payload.survey_id != survey_id {
-        println!("❌ Несоответствие survey_id: путь={}, тело={}", survey_id, payload.survey_id);
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Survey ID in path and body must match".to_string(),
-        ));
-    }
+    Path(broadcast_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("🛑 POST /broadcast/{}/cancel - отмена рассылки", broadcast_id);
+    let command = CancelBroadcastCommand { broadcast_id: broadcast_id.clone() };
     
-    match core_logic::handle_vote(&state.pool, payload, voter_telegram_id).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    // Ошибку переводим сразу: Box<dyn Error> нельзя держать через .await в обработчике
+    let event = core_logic::db::handle_cancel_broadcast(&state.pool, command).await.map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to cancel broadcast: {}", e),
+    ))?;
+
+    match event {
+        Some(event) => {
+            publish_control_event(&state, &event).await;
+            Ok(StatusCode::OK)
+        },
+        None => Err(broadcast_transition_error(&state, &broadcast_id).await),
     }
 }
 
 #[utoipa::path(
-    get,
-    path = "/surveys/{id}/summary",
+    post,
+    path = "/broadcast/{id}/pause",
     params(
-        ("id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)")
+        ("id" = String, Path, description = "Broadcast ID")
     ),
     responses(
-        (status = 200, description = "Survey summary retrieved successfully", body = SurveyVoteSummary)
+        (status = 200, description = "Broadcast paused successfully"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is not being sent")
     )
 )]
-async fn get_survey_summary(
+async fn pause_broadcast(
     State(state): State<AppState>,
-    Path(survey_id): Path<i64>,
-) -> Result<Json<SurveyVoteSummary>, (StatusCode, String)> {
-    match core_logic::get_survey_vote_summary(&state.pool, survey_id).await {
-        Ok(summary) => Ok(Json(summary)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    Path(broadcast_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("⏸️ POST /broadcast/{}/pause - пауза рассылки", broadcast_id);
+
+    let event = core_logic::db::handle_pause_broadcast(&state.pool, &broadcast_id).await.map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to pause broadcast: {}", e),
+    ))?;
+
+    match event {
+        Some(event) => {
+            publish_control_event(&state, &event).await;
+            Ok(StatusCode::OK)
+        },
+        None => Err(broadcast_transition_error(&state, &broadcast_id).await),
     }
 }
 
 #[utoipa::path(
-    put,
-    path = "/users/{id}/role",
+    post,
+    path = "/broadcast/{id}/resume",
     params(
-        ("id" = i64, Path, description = "Telegram ID пользователя")
+        ("id" = String, Path, description = "Broadcast ID")
     ),
-    request_body = i32,
     responses(
-        (status = 200, description = "User role updated successfully")
+        (status = 200, description = "Broadcast resumed successfully"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is not paused")
     )
 )]
-async fn set_user_role(
+async fn resume_broadcast(
     State(state): State<AppState>,
-    Path(telegram_id): Path<i64>,
-    Json(role): Json<i32>,
+    Path(broadcast_id): Path<String>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    if role != 0 && role != 1 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Role must be 0 (regular user) or 1 (responsible user)".to_string(),
-        ));
+    println!("▶️ POST /broadcast/{}/resume - возобновление рассылки", broadcast_id);
+
+    let event = core_logic::db::handle_resume_broadcast(&state.pool, &broadcast_id).await.map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to resume broadcast: {}", e),
+    ))?;
+
+    match event {
+        Some(event) => {
+            publish_control_event(&state, &event).await;
+            Ok(StatusCode::OK)
+        },
+        None => Err(broadcast_transition_error(&state, &broadcast_id).await),
     }
-    
-    match core_logic::set_user_role(&state.pool, telegram_id, role).await {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(e) => Err((
+}
+
+/// Публикует событие управления рассылкой; статус в БД уже изменен, поэтому ошибка не критична
+async fn publish_control_event(state: &AppState, event: &core_logic::BroadcastEvent) {
+    if let Err(e) = state.rabbitmq.publish_event(event).await {
+        println!("⚠️ Не удалось опубликовать событие рассылки: {}", e);
+    }
+}
+
+/// Ответ на недопустимую смену статуса рассылки: 404, если ее нет, иначе 409
+async fn broadcast_transition_error(state: &AppState, broadcast_id: &str) -> (StatusCode, String) {
+    match core_logic::db::get_broadcast_summary(&state.pool, broadcast_id).await {
+        Ok(Some(summary)) => (
+            StatusCode::CONFLICT,
+            format!("Broadcast is already {}", summary.status),
+        ),
+        Ok(None) => (StatusCode::NOT_FOUND, "Broadcast not found".to_string()),
+        Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Database error: {}", e),
-        )),
+        ),
     }
 }
 
 #[utoipa::path(
-    post,
-    path = "/surveys/sync",
+    put,
+    path = "/broadcast/{id}/schedule",
+    request_body = RescheduleBroadcastCommand,
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
     responses(
-        (status = 200, description = "Users synced successfully")
+        (status = 200, description = "Broadcast rescheduled successfully"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is no longer scheduled")
     )
 )]
-async fn sync_users(
+async fn reschedule_broadcast(
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    match core_logic::db::sync_users_from_external_api(&state.pool).await {
-        Ok(synced_user_ids) => {
-            let response = serde_json::json!({
-                "success": true,
-                "message": format!("Синхронизировано {} пользователей", synced_user_ids.len()),
-                "synced_count": synced_user_ids.len(),
-                "user_ids": synced_user_ids
-            });
-            Ok(Json(response))
-        }
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Ошибка синхронизации: {}", e),
-        )),
+    Path(broadcast_id): Path<String>,
+    Json(payload): Json<RescheduleBroadcastCommand>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("⏰ PUT /broadcast/{}/schedule - перенос на {}", broadcast_id, payload.send_at);
+
+    match core_logic::db::reschedule_broadcast(&state.pool, &broadcast_id, payload.send_at).await {
+        Ok(true) => Ok(StatusCode::OK),
+        Ok(false) => Err(broadcast_transition_error(&state, &broadcast_id).await),
+        Err(e) => {
+            println!("❌ Ошибка при переносе рассылки: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to reschedule broadcast: {}", e),
+            ))
+        },
     }
 }
 
 #[utoipa::path(
     post,
-    path = "/auth/telegram",
-    request_body = TelegramAuth,
+    path = "/broadcast/{id}/release",
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
     responses(
-        (status = 200, description = "Authentication result", body = AuthResponse)
+        (status = 200, description = "Draft released to the scheduler"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is not a draft")
     )
 )]
-#[axum::debug_handler]
-async fn authenticate_telegram(
+async fn release_broadcast(
     State(state): State<AppState>,
-    Json(telegram_auth): Json<TelegramAuth>,
-) -> Result<Json<AuthResponse>, (StatusCode, String)> {
-    println!("🚀 Получен запрос авторизации для пользователя ID: {}", telegram_auth.id);
-    match core_logic::authenticate_user(telegram_auth.clone()).await {
-        Ok(mut auth_response) => {
-            println!("📋 Результат авторизации: success={}, message={}", auth_response.success, auth_response.message);
-            if auth_response.success {
-                // Получаем роль пользователя из базы данных
-                match core_logic::get_user_role_from_db(&state.pool, telegram_auth.id).await {
-                    Ok(user_role) => {
-                        println!("👤 Роль пользователя {}: {:?}", telegram_auth.id, user_role);
-                        auth_response.user_role = user_role;
-                        Ok(Json(auth_response))
-                    }
-                    Err(e) => {
-                        eprintln!("❌ Ошибка получения роли пользователя: {}", e);
-                        Ok(Json(auth_response)) // Возвращаем ответ без роли
-                    }
+    Path(broadcast_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("🚀 POST /broadcast/{}/release - выпуск черновика", broadcast_id);
+
+    // Событие публикует планировщик: сразу или в сохраненное время отправки
+    match core_logic::db::release_draft_broadcast(&state.pool, &broadcast_id).await {
+        Ok(Some(BroadcastStatus::PendingApproval)) => {
+            let created_by = core_logic::db::get_broadcast_author(&state.pool, &broadcast_id).await.unwrap_or_default();
+            request_broadcast_approval(&state, &broadcast_id, created_by).await;
+            Ok(StatusCode::OK)
+        },
+        Ok(Some(_)) => {
+            println!("✅ Черновик {} передан планировщику", broadcast_id);
+            Ok(StatusCode::OK)
+        },
+        Ok(None) => Err(broadcast_transition_error(&state, &broadcast_id).await),
+        Err(e) => {
+            println!("❌ Ошибка при выпуске черновика: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to release broadcast: {}", e),
+            ))
+        },
+    }
+}
+
+/// Рассылает ответственным запрос на одобрение; ошибки Telegram не отменяют рассылку
+async fn request_broadcast_approval(state: &AppState, broadcast_id: &str, created_by: Option<i64>) {
+    let summary = match core_logic::db::get_broadcast_summary(&state.pool, broadcast_id).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => return,
+        Err(e) => {
+            println!("❌ Не удалось загрузить рассылку {} для одобрения: {}", broadcast_id, e);
+            return;
+        }
+    };
+
+    match telegram_bot::admin::notify_broadcast_approvers(&state.bot, &state.pool, &summary, created_by).await {
+        Ok(notified) => println!("🔐 Рассылка {} ждет одобрения, запрос отправлен {} ответственным", broadcast_id, notified),
+        Err(e) => println!("⚠️ Не удалось запросить одобрение рассылки {}: {}", broadcast_id, e),
+    }
+}
+
+/// Проверяет, что решение принимает ответственный; одобрить рассылку автор не может,
+/// а рассылку без автора одобрить нельзя
+async fn check_broadcast_approver(
+    state: &AppState,
+    broadcast_id: &str,
+    approver_telegram_id: i64,
+    approving: bool,
+) -> Result<Option<i64>, (StatusCode, String)> {
+    let role = core_logic::db::get_user_role(&state.pool, approver_telegram_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    if role != Some(1) {
+        return Err((StatusCode::FORBIDDEN, "Решение по рассылке принимает только ответственный".to_string()));
+    }
+
+    let author = core_logic::db::get_broadcast_author(&state.pool, broadcast_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    // Без автора правило двух ответственных не проверить, такую рассылку можно только отклонить
+    if approving && author.is_none() {
+        return Err((StatusCode::FORBIDDEN, "У рассылки не указан автор — одобрение невозможно".to_string()));
+    }
+    if approving && author == Some(approver_telegram_id) {
+        return Err((StatusCode::FORBIDDEN, "Автор не может одобрить свою рассылку".to_string()));
+    }
+    Ok(author)
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/{id}/approve",
+    request_body = BroadcastApprovalRequest,
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
+    responses(
+        (status = 200, description = "Broadcast approved and handed to the scheduler"),
+        (status = 403, description = "Approver is not responsible, is the author or the author is unknown"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is not awaiting approval")
+    )
+)]
+async fn approve_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+    Json(payload): Json<BroadcastApprovalRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("🔐 POST /broadcast/{}/approve - одобрение пользователем {}", broadcast_id, payload.approver_telegram_id);
+
+    let author = check_broadcast_approver(&state, &broadcast_id, payload.approver_telegram_id, true).await?;
+
+    match core_logic::db::handle_approve_broadcast(&state.pool, &broadcast_id, payload.approver_telegram_id).await {
+        Ok(Some(_)) => {
+            if let Some(author) = author {
+                if let Err(e) = telegram_bot::admin::notify_broadcast_decision(&state.bot, author, &broadcast_id, true, None).await {
+                    println!("⚠️ Не удалось уведомить автора рассылки {}: {}", author, e);
+                }
+            }
+            Ok(StatusCode::OK)
+        },
+        Ok(None) => Err(broadcast_transition_error(&state, &broadcast_id).await),
+        Err(e) => {
+            println!("❌ Ошибка при одобрении рассылки: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to approve broadcast: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/{id}/reject",
+    request_body = BroadcastApprovalRequest,
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
+    responses(
+        (status = 200, description = "Broadcast rejected"),
+        (status = 403, description = "Approver is not responsible"),
+        (status = 404, description = "Broadcast not found"),
+        (status = 409, description = "Broadcast is not awaiting approval")
+    )
+)]
+async fn reject_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+    Json(payload): Json<BroadcastApprovalRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("🚫 POST /broadcast/{}/reject - отклонение пользователем {}", broadcast_id, payload.approver_telegram_id);
+
+    let author = check_broadcast_approver(&state, &broadcast_id, payload.approver_telegram_id, false).await?;
+
+    match core_logic::db::handle_reject_broadcast(&state.pool, &broadcast_id, payload.approver_telegram_id, payload.reason.clone()).await {
+        Ok(Some(_)) => {
+            if let Some(author) = author {
+                let reason = payload.reason.as_deref();
+                if let Err(e) = telegram_bot::admin::notify_broadcast_decision(&state.bot, author, &broadcast_id, false, reason).await {
+                    println!("⚠️ Не удалось уведомить автора рассылки {}: {}", author, e);
                 }
-            } else {
-                println!("❌ Авторизация не удалась: {}", auth_response.message);
-                Ok(Json(auth_response))
             }
+            Ok(StatusCode::OK)
+        },
+        Ok(None) => Err(broadcast_transition_error(&state, &broadcast_id).await),
+        Err(e) => {
+            println!("❌ Ошибка при отклонении рассылки: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to reject broadcast: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/{id}/edit",
+    request_body = EditBroadcastRequest,
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
+    responses(
+        (status = 200, description = "Delivered messages queued for editing", body = BroadcastRevisionResponse),
+        (status = 400, description = "Template or formatting error"),
+        (status = 404, description = "Broadcast not found")
+    )
+)]
+async fn edit_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+    Json(payload): Json<EditBroadcastRequest>,
+) -> Result<Json<BroadcastRevisionResponse>, (StatusCode, String)> {
+    println!("✏️ POST /broadcast/{}/edit - правка доставленных сообщений", broadcast_id);
+
+    let event = core_logic::db::get_broadcast_created_event(&state.pool, &broadcast_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    let Some(BroadcastEvent::BroadcastCreated { media_group, parse_mode, split_long_text, .. }) = event else {
+        return Err((StatusCode::NOT_FOUND, "Broadcast not found".to_string()));
+    };
+
+    if let Err(e) = validate_broadcast_edit(&payload.message, media_group, parse_mode, split_long_text) {
+        println!("❌ Ошибка в правке рассылки: {}", e);
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+
+    revise_broadcast(&state, &broadcast_id, BroadcastMessageAction::Edit, Some(&payload.message)).await
+}
+
+/// Правка проверяется так же, как рассылка при создании: длинный текст рассылки с
+/// `split_long_text` снова делится на части, у медиагруппы новый текст — подпись к первому файлу
+fn validate_broadcast_edit(
+    message: &str,
+    media_group: Option<core_logic::MediaGroup>,
+    parse_mode: core_logic::formatting::BroadcastParseMode,
+    split_long_text: bool,
+) -> Result<(), String> {
+    core_logic::template::validate_broadcast(message, None).map_err(|e| e.to_string())?;
+    if core_logic::template::mask_variables(message).trim().is_empty() {
+        return Err("Текст не может быть пустым".to_string());
+    }
+    let media_group = media_group.map(|mut media_group| {
+        if let Some(item) = media_group.media.first_mut() {
+            item.caption = Some(message.to_string());
+        }
+        media_group
+    });
+    core_logic::formatting::validate_broadcast(message, media_group.as_ref(), parse_mode, split_long_text)
+        .map_err(|e| e.to_string())
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/{id}/recall",
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
+    responses(
+        (status = 200, description = "Delivered messages queued for deletion", body = BroadcastRevisionResponse),
+        (status = 404, description = "Broadcast not found")
+    )
+)]
+async fn recall_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+) -> Result<Json<BroadcastRevisionResponse>, (StatusCode, String)> {
+    println!("🗑️ POST /broadcast/{}/recall - отзыв доставленных сообщений", broadcast_id);
+    revise_broadcast(&state, &broadcast_id, BroadcastMessageAction::Delete, None).await
+}
+
+/// Ставит правку или удаление доставленных сообщений в очередь воркера бота
+async fn revise_broadcast(
+    state: &AppState,
+    broadcast_id: &str,
+    action: BroadcastMessageAction,
+    new_text: Option<&str>,
+) -> Result<Json<BroadcastRevisionResponse>, (StatusCode, String)> {
+    let messages = match core_logic::db::handle_revise_broadcast(&state.pool, broadcast_id, action, new_text).await {
+        Ok(Some(messages)) => messages,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Broadcast not found".to_string())),
+        Err(e) => {
+            println!("❌ Ошибка при подготовке правки рассылки: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ));
+        },
+    };
+
+    for message in &messages {
+        if let Err(e) = state.rabbitmq.publish_message(message).await {
+            println!("❌ Не удалось поставить правку сообщения для {}: {}", message.telegram_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to publish message: {}", e),
+            ));
+        }
+    }
+
+    println!("✅ Сообщений в очереди на правку: {}", messages.len());
+    Ok(Json(BroadcastRevisionResponse { queued: messages.len() }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcast/{id}/test",
+    request_body = TestBroadcastRequest,
+    params(
+        ("id" = String, Path, description = "Broadcast ID")
+    ),
+    responses(
+        (status = 200, description = "Test messages queued for staff", body = TestBroadcastResponse),
+        (status = 400, description = "Empty list or recipient is not staff"),
+        (status = 404, description = "Broadcast not found")
+    )
+)]
+async fn test_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+    Json(payload): Json<TestBroadcastRequest>,
+) -> Result<Json<TestBroadcastResponse>, (StatusCode, String)> {
+    println!("🧪 POST /broadcast/{}/test - тестовая отправка {:?}", broadcast_id, payload.telegram_ids);
+
+    if payload.telegram_ids.is_empty() || payload.telegram_ids.len() > MAX_TEST_RECIPIENTS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Укажите от 1 до {} получателей", MAX_TEST_RECIPIENTS),
+        ));
+    }
+    // Тест уходит только ответственным, чтобы кандидаты не получили неготовый текст
+    for telegram_id in &payload.telegram_ids {
+        let role = core_logic::db::get_user_role(&state.pool, *telegram_id).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        if role != Some(1) {
+            return Err((StatusCode::BAD_REQUEST, format!("Пользователь {} не сотрудник", telegram_id)));
+        }
+    }
+
+    let messages = match core_logic::db::get_test_broadcast_messages(&state.pool, &broadcast_id, &payload.telegram_ids).await {
+        Ok(Some(messages)) => messages,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Broadcast not found".to_string())),
+        Err(e) => {
+            println!("❌ Ошибка при подготовке тестовой отправки: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ));
+        },
+    };
+
+    for message in &messages {
+        if let Err(e) = state.rabbitmq.publish_message(message).await {
+            println!("❌ Не удалось поставить тестовое сообщение для {}: {}", message.telegram_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to publish message: {}", e),
+            ));
         }
+    }
+
+    println!("✅ Тестовых сообщений в очереди: {}", messages.len());
+    Ok(Json(TestBroadcastResponse { queued: messages.len() }))
+}
+
+// Voting System Endpoints
+
+#[utoipa::path(
+    get,
+    path = "/surveys/next",
+    params(
+        ("telegram_id" = i64, Query, description = "Telegram ID пользователя")
+    ),
+    responses(
+        (status = 200, description = "Next survey retrieved successfully", body = NextSurveyResponse)
+    )
+)]
+async fn get_next_survey(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<NextSurveyResponse>, (StatusCode, String)> {
+    let telegram_id = params.get("telegram_id")
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "telegram_id is required".to_string()))?;
+    
+    match core_logic::get_next_survey(&state.pool, telegram_id).await {
+        Ok(response) => Ok(Json(response)),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Ошибка авторизации: {}", e),
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/surveys/{id}/vote",
+    params(
+        ("id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)"),
+        ("telegram_id" = i64, Query, description = "Telegram ID голосующего")
+    ),
+    request_body = CreateVoteRequest,
+    responses(
+        (status = 200, description = "Vote created successfully", body = VoteResponse)
+    )
+)]
+async fn create_vote(
+    State(state): State<AppState>,
+    Path(survey_id): Path<i64>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+    Json(payload): Json<CreateVoteRequest>,
+) -> Result<Json<VoteResponse>, (StatusCode, String)> {
+    println!("🗳️ Получен запрос голосования для анкеты {} от пользователя", survey_id);
+    println!("📋 Данные голоса: {:?}", payload);
+    
+    let voter_telegram_id = params.get("telegram_id")
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "telegram_id is required".to_string()))?;
+    
+    println!("👤 ID голосующего: {}", voter_telegram_id);
+    
+    // Убеждаемся, что survey_id в пути совпадает с survey_id в теле запроса
+    if payload.survey_id != survey_id {
+        println!("❌ Несоответствие survey_id: путь={}, тело={}", survey_id, payload.survey_id);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Survey ID in path and body must match".to_string(),
+        ));
+    }
+    
+    match core_logic::handle_vote(&state.pool, payload, voter_telegram_id).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/surveys/{id}/summary",
+    params(
+        ("id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)")
+    ),
+    responses(
+        (status = 200, description = "Survey summary retrieved successfully", body = SurveyVoteSummary)
+    )
+)]
+async fn get_survey_summary(
+    State(state): State<AppState>,
+    Path(survey_id): Path<i64>,
+) -> Result<Json<SurveyVoteSummary>, (StatusCode, String)> {
+    match core_logic::get_survey_vote_summary(&state.pool, survey_id).await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
         )),
     }
 }
 
-// Additional Vote Management Endpoints
-
 #[utoipa::path(
-    put,
-    path = "/votes/{id}",
-    params(
-        ("id" = i64, Path, description = "Vote ID")
-    ),
-    request_body = UpdateVoteRequest,
+    put,
+    path = "/users/{id}/role",
+    params(
+        ("id" = i64, Path, description = "Telegram ID пользователя")
+    ),
+    request_body = i32,
+    responses(
+        (status = 200, description = "User role updated successfully")
+    )
+)]
+async fn set_user_role(
+    State(state): State<AppState>,
+    Path(telegram_id): Path<i64>,
+    Json(role): Json<i32>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if role != 0 && role != 1 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Role must be 0 (regular user) or 1 (responsible user)".to_string(),
+        ));
+    }
+    
+    match core_logic::set_user_role(&state.pool, telegram_id, role).await {
+        Ok(_) => {
+            refresh_command_menu(&state.bot, telegram_id, Some(role)).await;
+            Ok(StatusCode::OK)
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+// Админ-команды в меню бота видны только администраторам
+async fn refresh_command_menu(bot: &teloxide::Bot, telegram_id: i64, role: Option<i32>) {
+    if let Err(e) = telegram_bot::admin::update_command_menu(bot, telegram_id, role).await {
+        println!("⚠️ Не удалось обновить меню команд пользователя {}: {}", telegram_id, e);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/surveys/sync",
+    responses(
+        (status = 200, description = "Users synced successfully")
+    )
+)]
+async fn sync_users(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    match core_logic::db::sync_users_from_external_api(&state.pool).await {
+        Ok(synced_user_ids) => {
+            let response = serde_json::json!({
+                "success": true,
+                "message": format!("Синхронизировано {} пользователей", synced_user_ids.len()),
+                "synced_count": synced_user_ids.len(),
+                "user_ids": synced_user_ids
+            });
+            Ok(Json(response))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Ошибка синхронизации: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/telegram",
+    request_body = TelegramAuth,
+    responses(
+        (status = 200, description = "Authentication result", body = AuthResponse)
+    )
+)]
+#[axum::debug_handler]
+async fn authenticate_telegram(
+    State(state): State<AppState>,
+    Json(telegram_auth): Json<TelegramAuth>,
+) -> Result<Json<AuthResponse>, (StatusCode, String)> {
+    println!("🚀 Получен запрос авторизации для пользователя ID: {}", telegram_auth.id);
+    match core_logic::authenticate_user(telegram_auth.clone()).await {
+        Ok(mut auth_response) => {
+            println!("📋 Результат авторизации: success={}, message={}", auth_response.success, auth_response.message);
+            if auth_response.success {
+                // Получаем роль пользователя из базы данных
+                match core_logic::get_user_role_from_db(&state.pool, telegram_auth.id).await {
+                    Ok(user_role) => {
+                        println!("👤 Роль пользователя {}: {:?}", telegram_auth.id, user_role);
+                        auth_response.user_role = user_role;
+                        Ok(Json(auth_response))
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Ошибка получения роли пользователя: {}", e);
+                        Ok(Json(auth_response)) // Возвращаем ответ без роли
+                    }
+                }
+            } else {
+                println!("❌ Авторизация не удалась: {}", auth_response.message);
+                Ok(Json(auth_response))
+            }
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Ошибка авторизации: {}", e),
+        )),
+    }
+}
+
+// Additional Vote Management Endpoints
+
+#[utoipa::path(
+    put,
+    path = "/votes/{id}",
+    params(
+        ("id" = i64, Path, description = "Vote ID")
+    ),
+    request_body = UpdateVoteRequest,
+    responses(
+        (status = 200, description = "Vote updated successfully", body = Vote)
+    )
+)]
+async fn update_vote(
+    State(state): State<AppState>,
+    Path(vote_id): Path<i64>,
+    Json(payload): Json<UpdateVoteRequest>,
+) -> Result<Json<Vote>, (StatusCode, String)> {
+    match core_logic::db::update_vote(&state.pool, vote_id, payload).await {
+        Ok(vote) => Ok(Json(vote)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/votes/{id}",
+    params(
+        ("id" = i64, Path, description = "Vote ID")
+    ),
+    responses(
+        (status = 204, description = "Vote deleted successfully")
+    )
+)]
+async fn delete_vote(
+    State(state): State<AppState>,
+    Path(vote_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match core_logic::db::delete_vote(&state.pool, vote_id).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/votes/survey/{survey_id}",
+    params(
+        ("survey_id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)")
+    ),
+    responses(
+        (status = 200, description = "Votes for survey retrieved successfully", body = [Vote])
+    )
+)]
+async fn get_votes_by_survey(
+    State(state): State<AppState>,
+    Path(survey_id): Path<i64>,
+) -> Result<Json<Vec<Vote>>, (StatusCode, String)> {
+    match core_logic::db::get_votes_by_survey(&state.pool, survey_id).await {
+        Ok(votes) => Ok(Json(votes)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/votes/clear-locks/{telegram_id}",
+    params(
+        ("telegram_id" = i64, Path, description = "Telegram ID пользователя")
+    ),
+    responses(
+        (status = 200, description = "User locks cleared successfully", body = u64)
+    )
+)]
+async fn clear_user_locks(
+    State(state): State<AppState>,
+    Path(telegram_id): Path<i64>,
+) -> Result<Json<u64>, (StatusCode, String)> {
+    match core_logic::clear_user_locks(&state.pool, telegram_id).await {
+        Ok(cleared_count) => Ok(Json(cleared_count)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/info",
+    params(
+        ("id" = i64, Path, description = "Telegram ID пользователя")
+    ),
+    responses(
+        (status = 200, description = "User info retrieved successfully", body = User),
+        (status = 404, description = "User not found")
+    )
+)]
+async fn get_user_info(
+    State(state): State<AppState>,
+    Path(telegram_id): Path<i64>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    match core_logic::get_user_by_telegram_id(&state.pool, telegram_id).await {
+        Ok(Some(user_info)) => Ok(Json(user_info)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "User not found".to_string())),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/survey",
+    responses(
+        (status = 200, description = "Get user survey data from external API", body = serde_json::Value),
+        (status = 404, description = "User survey not found"),
+        (status = 500, description = "External API error")
+    )
+)]
+async fn get_user_survey(
+    Path(telegram_id): Path<i64>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    println!("📋 GET /users/{}/survey - получение данных пользователя из внешнего API", telegram_id);
+    
+    match core_logic::db::get_user_survey_from_external_api(telegram_id).await {
+        Ok(Some(survey_data)) => {
+            println!("✅ Получены данные пользователя {} из внешнего API", telegram_id);
+            Ok(Json(survey_data))
+        },
+        Ok(None) => {
+            println!("❌ Данные пользователя {} не найдены во внешнем API", telegram_id);
+            Err((StatusCode::NOT_FOUND, "User survey not found".to_string()))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении данных пользователя {} из внешнего API: {}", telegram_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("External API error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/support-messages",
+    responses(
+        (status = 200, description = "Get the candidate's conversation with the support inbox", body = [core_logic::SupportMessage])
+    )
+)]
+async fn get_user_support_messages(
+    State(state): State<AppState>,
+    Path(telegram_id): Path<i64>,
+) -> Result<Json<Vec<core_logic::SupportMessage>>, (StatusCode, String)> {
+    println!("📋 GET /users/{}/support-messages - получение переписки с поддержкой", telegram_id);
+
+    match core_logic::db::get_support_messages(&state.pool, telegram_id).await {
+        Ok(messages) => {
+            println!("✅ Получено {} сообщений переписки пользователя {}", messages.len(), telegram_id);
+            Ok(Json(messages))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении переписки пользователя {}: {}", telegram_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/external-users",
+    responses(
+        (status = 200, description = "Get users with completed surveys from external API", body = [serde_json::Value])
+    )
+)]
+async fn get_external_users() -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    println!("📋 GET /external-users - получение пользователей с завершенными анкетами");
+    
+    match core_logic::db::get_all_users_from_external_api().await {
+        Ok(users) => {
+            println!("✅ Получено {} пользователей с внешнего API", users.len());
+            Ok(Json(users))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении пользователей: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("External API error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/selected-users",
+    responses(
+        (status = 200, description = "Get users with selected surveys (approved by responsible users)", body = [serde_json::Value])
+    )
+)]
+async fn get_selected_users(
+    State(state): State<AppState>
+) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    println!("📋 GET /selected-users - получение отобранных пользователей");
+    
+    match core_logic::db::get_selected_users(&state.pool).await {
+        Ok(users) => {
+            println!("✅ Получено {} отобранных пользователей", users.len());
+            Ok(Json(users))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении отобранных пользователей: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/no-response-users",
+    responses(
+        (status = 200, description = "Get users who received signup broadcast but didn't book a slot", body = [serde_json::Value])
+    )
+)]
+async fn get_no_response_users(
+    State(state): State<AppState>
+) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    println!("📋 GET /no-response-users - получение пользователей без записи после рассылки");
+    
+    match core_logic::db::get_no_response_users_detailed(&state.pool).await {
+        Ok(users) => {
+            println!("✅ Получено {} пользователей без записи", users.len());
+            Ok(Json(users))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении пользователей без записи: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/unreachable-users",
+    responses(
+        (status = 200, description = "Users excluded from broadcasts because the bot cannot reach them", body = [core_logic::UnreachableUser])
+    )
+)]
+async fn get_unreachable_users(
+    State(state): State<AppState>
+) -> Result<Json<Vec<core_logic::UnreachableUser>>, (StatusCode, String)> {
+    println!("📋 GET /unreachable-users - получение недоступных пользователей");
+
+    match core_logic::db::get_unreachable_users(&state.pool).await {
+        Ok(users) => {
+            println!("✅ Получено {} недоступных пользователей", users.len());
+            Ok(Json(users))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении недоступных пользователей: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/deep-links",
+    request_body = core_logic::CreateDeepLinkRequest,
+    responses(
+        (status = 200, description = "Deep link created", body = core_logic::DeepLinkResponse),
+        (status = 400, description = "Invalid payload or source"),
+        (status = 409, description = "Payload already exists")
+    )
+)]
+async fn create_deep_link(
+    State(state): State<AppState>,
+    Json(request): Json<core_logic::CreateDeepLinkRequest>,
+) -> Result<Json<core_logic::DeepLinkResponse>, (StatusCode, String)> {
+    use teloxide::requests::Requester;
+
+    println!("🔗 POST /deep-links - создание ссылки для источника {}", request.source);
+
+    if request.source.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Источник не может быть пустым".to_string()));
+    }
+
+    let flow = request.flow.unwrap_or_default();
+    let payload = match request.payload {
+        Some(payload) => payload.trim().to_string(),
+        None => format!("{}-{}", flow, &uuid::Uuid::new_v4().simple().to_string()[..8]),
+    };
+    if !core_logic::DeepLink::is_valid_payload(&payload) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Payload должен содержать до {} символов A-Z, a-z, 0-9, _ и -", core_logic::DeepLink::MAX_PAYLOAD_LENGTH),
+        ));
+    }
+
+    let link = core_logic::DeepLink {
+        payload,
+        source: request.source.trim().to_string(),
+        flow,
+        broadcast_id: request.broadcast_id,
+    };
+
+    // Имя бота нужно для ссылки вида t.me/<bot>?start=<payload>
+    let me = state.bot.get_me().await.map_err(|e| {
+        println!("❌ Не удалось получить имя бота: {}", e);
+        (StatusCode::BAD_GATEWAY, format!("Telegram API error: {}", e))
+    })?;
+
+    match core_logic::db::create_deep_link(&state.pool, &link).await {
+        Ok(true) => {
+            println!("✅ Ссылка {} создана", link.payload);
+            Ok(Json(core_logic::DeepLinkResponse {
+                url: format!("https://t.me/{}?start={}", me.username(), link.payload),
+                payload: link.payload,
+                source: link.source,
+                flow: link.flow,
+                broadcast_id: link.broadcast_id,
+            }))
+        },
+        Ok(false) => Err((StatusCode::CONFLICT, format!("Payload {} уже существует", link.payload))),
+        Err(e) => {
+            println!("❌ Ошибка при создании ссылки: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/deep-links",
     responses(
-        (status = 200, description = "Vote updated successfully", body = Vote)
+        (status = 200, description = "Visitors, bookings and conversion per /start payload", body = [core_logic::DeepLinkStats])
     )
 )]
-async fn update_vote(
-    State(state): State<AppState>,
-    Path(vote_id): Path<i64>,
-    Json(payload): Json<UpdateVoteRequest>,
-) -> Result<Json<Vote>, (StatusCode, String)> {
-    match core_logic::db::update_vote(&state.pool, vote_id, payload).await {
-        Ok(vote) => Ok(Json(vote)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
+async fn get_deep_link_stats(
+    State(state): State<AppState>
+) -> Result<Json<Vec<core_logic::DeepLinkStats>>, (StatusCode, String)> {
+    println!("📋 GET /deep-links - получение конверсии по ссылкам");
+
+    match core_logic::db::get_deep_link_stats(&state.pool).await {
+        Ok(stats) => {
+            println!("✅ Получена статистика по {} ссылкам", stats.len());
+            Ok(Json(stats))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении статистики ссылок: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+
+/// Проверяет имя сегмента и сегменты в исключениях
+async fn validate_segment_request(
+    state: &AppState,
+    segment_id: Option<i64>,
+    request: &core_logic::SaveSegmentRequest,
+) -> Result<(), (StatusCode, String)> {
+    if request.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Имя сегмента не может быть пустым".to_string()));
+    }
+    let invalid = core_logic::db::find_invalid_excluded_segment(&state.pool, segment_id, &request.rules).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    match invalid {
+        Some(excluded_id) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Сегмент {} нельзя исключить: он не найден или совпадает с текущим", excluded_id),
         )),
+        None => Ok(()),
     }
 }
 
 #[utoipa::path(
-    delete,
-    path = "/votes/{id}",
-    params(
-        ("id" = i64, Path, description = "Vote ID")
-    ),
+    get,
+    path = "/segments",
     responses(
-        (status = 204, description = "Vote deleted successfully")
+        (status = 200, description = "Saved audience segments", body = [core_logic::Segment])
     )
 )]
-async fn delete_vote(
+async fn get_segments(
+    State(state): State<AppState>
+) -> Result<Json<Vec<core_logic::Segment>>, (StatusCode, String)> {
+    println!("📋 GET /segments - получение сегментов");
+
+    match core_logic::db::get_all_segments(&state.pool).await {
+        Ok(segments) => {
+            println!("✅ Получено {} сегментов", segments.len());
+            Ok(Json(segments))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при получении сегментов: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/segments",
+    request_body = core_logic::SaveSegmentRequest,
+    responses(
+        (status = 200, description = "Segment created", body = core_logic::Segment),
+        (status = 400, description = "Empty name or invalid excluded segment"),
+        (status = 409, description = "Segment name already exists")
+    )
+)]
+async fn create_segment(
     State(state): State<AppState>,
-    Path(vote_id): Path<i64>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    match core_logic::db::delete_vote(&state.pool, vote_id).await {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    Json(mut request): Json<core_logic::SaveSegmentRequest>,
+) -> Result<Json<core_logic::Segment>, (StatusCode, String)> {
+    println!("🎯 POST /segments - создание сегмента {}", request.name);
+
+    validate_segment_request(&state, None, &request).await?;
+    request.name = request.name.trim().to_string();
+
+    match core_logic::db::create_segment(&state.pool, &request).await {
+        Ok(Some(segment)) => {
+            println!("✅ Сегмент {} создан с ID {}", segment.name, segment.id);
+            Ok(Json(segment))
+        },
+        Ok(None) => Err((StatusCode::CONFLICT, format!("Сегмент {} уже существует", request.name))),
+        Err(e) => {
+            println!("❌ Ошибка при создании сегмента: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
     }
 }
 
 #[utoipa::path(
-    get,
-    path = "/votes/survey/{survey_id}",
+    put,
+    path = "/segments/{id}",
+    request_body = core_logic::SaveSegmentRequest,
     params(
-        ("survey_id" = i64, Path, description = "Survey ID (Telegram ID владельца анкеты)")
+        ("id" = i64, Path, description = "Segment ID")
     ),
     responses(
-        (status = 200, description = "Votes for survey retrieved successfully", body = [Vote])
+        (status = 200, description = "Segment updated", body = core_logic::Segment),
+        (status = 400, description = "Empty name or invalid excluded segment"),
+        (status = 404, description = "Segment not found"),
+        (status = 409, description = "Segment name already exists")
     )
 )]
-async fn get_votes_by_survey(
+async fn update_segment(
     State(state): State<AppState>,
-    Path(survey_id): Path<i64>,
-) -> Result<Json<Vec<Vote>>, (StatusCode, String)> {
-    match core_logic::db::get_votes_by_survey(&state.pool, survey_id).await {
-        Ok(votes) => Ok(Json(votes)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    Path(segment_id): Path<i64>,
+    Json(mut request): Json<core_logic::SaveSegmentRequest>,
+) -> Result<Json<core_logic::Segment>, (StatusCode, String)> {
+    println!("🎯 PUT /segments/{} - обновление сегмента", segment_id);
+
+    validate_segment_request(&state, Some(segment_id), &request).await?;
+    request.name = request.name.trim().to_string();
+
+    let segment = core_logic::db::get_segment(&state.pool, segment_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    if segment.is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("Сегмент {} не найден", segment_id)));
+    }
+
+    match core_logic::db::update_segment(&state.pool, segment_id, &request).await {
+        Ok(Some(segment)) => {
+            println!("✅ Сегмент {} обновлен", segment_id);
+            Ok(Json(segment))
+        },
+        Ok(None) => Err((StatusCode::CONFLICT, format!("Сегмент {} уже существует", request.name))),
+        Err(e) => {
+            println!("❌ Ошибка при обновлении сегмента: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
     }
 }
 
 #[utoipa::path(
-    post,
-    path = "/votes/clear-locks/{telegram_id}",
+    delete,
+    path = "/segments/{id}",
     params(
-        ("telegram_id" = i64, Path, description = "Telegram ID пользователя")
+        ("id" = i64, Path, description = "Segment ID")
     ),
     responses(
-        (status = 200, description = "User locks cleared successfully", body = u64)
+        (status = 204, description = "Segment deleted"),
+        (status = 404, description = "Segment not found")
     )
 )]
-async fn clear_user_locks(
+async fn delete_segment(
     State(state): State<AppState>,
-    Path(telegram_id): Path<i64>,
-) -> Result<Json<u64>, (StatusCode, String)> {
-    match core_logic::clear_user_locks(&state.pool, telegram_id).await {
-        Ok(cleared_count) => Ok(Json(cleared_count)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    Path(segment_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    println!("🗑️ DELETE /segments/{} - удаление сегмента", segment_id);
+
+    match core_logic::db::delete_segment(&state.pool, segment_id).await {
+        Ok(true) => {
+            println!("✅ Сегмент {} удален", segment_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(false) => Err((StatusCode::NOT_FOUND, format!("Сегмент {} не найден", segment_id))),
+        Err(e) => {
+            println!("❌ Ошибка при удалении сегмента: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
     }
 }
 
 #[utoipa::path(
     get,
-    path = "/users/{id}/info",
+    path = "/segments/{id}/count",
     params(
-        ("id" = i64, Path, description = "Telegram ID пользователя")
+        ("id" = i64, Path, description = "Segment ID")
     ),
     responses(
-        (status = 200, description = "User info retrieved successfully", body = User),
-        (status = 404, description = "User not found")
+        (status = 200, description = "How many users the segment matches right now", body = core_logic::SegmentCountResponse),
+        (status = 404, description = "Segment not found")
     )
 )]
-async fn get_user_info(
+async fn get_segment_count(
     State(state): State<AppState>,
-    Path(telegram_id): Path<i64>,
-) -> Result<Json<User>, (StatusCode, String)> {
-    match core_logic::get_user_by_telegram_id(&state.pool, telegram_id).await {
-        Ok(Some(user_info)) => Ok(Json(user_info)),
-        Ok(None) => Err((StatusCode::NOT_FOUND, "User not found".to_string())),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Database error: {}", e),
-        )),
+    Path(segment_id): Path<i64>,
+) -> Result<Json<core_logic::SegmentCountResponse>, (StatusCode, String)> {
+    println!("🔢 GET /segments/{}/count - размер сегмента", segment_id);
+
+    let segment = core_logic::db::get_segment(&state.pool, segment_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Сегмент {} не найден", segment_id)))?;
+
+    count_segment_rules(&state, &segment.rules).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/segments/count",
+    request_body = core_logic::SegmentRules,
+    responses(
+        (status = 200, description = "How many users the rules match right now", body = core_logic::SegmentCountResponse)
+    )
+)]
+async fn count_segment(
+    State(state): State<AppState>,
+    Json(rules): Json<core_logic::SegmentRules>,
+) -> Result<Json<core_logic::SegmentCountResponse>, (StatusCode, String)> {
+    println!("🔢 POST /segments/count - размер несохраненного сегмента");
+
+    count_segment_rules(&state, &rules).await
+}
+
+async fn count_segment_rules(
+    state: &AppState,
+    rules: &core_logic::SegmentRules,
+) -> Result<Json<core_logic::SegmentCountResponse>, (StatusCode, String)> {
+    match core_logic::db::count_segment(&state.pool, rules).await {
+        Ok(count) => {
+            println!("✅ В сегменте {} пользователей (недоступных: {})", count.count, count.skipped_unreachable);
+            Ok(Json(count))
+        },
+        Err(e) => {
+            println!("❌ Ошибка при вычислении сегмента: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            ))
+        },
+    }
+}
+
+/// Проверяет имя кампании и шаблоны уведомлений
+fn validate_decision_campaign_request(request: &core_logic::SaveDecisionCampaignRequest) -> Result<(), (StatusCode, String)> {
+    if request.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Имя кампании не может быть пустым".to_string()));
+    }
+    if request.approval_message.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Текст приглашения не может быть пустым".to_string()));
+    }
+    let templates = std::iter::once(("приглашение", request.approval_message.as_str()))
+        .chain(request.rejection_message.as_deref().map(|message| ("отказ", message)));
+    for (kind, message) in templates {
+        if let Err(e) = core_logic::template::validate_broadcast(message, None) {
+            println!("❌ Ошибка в шаблоне кампании ({}): {}", kind, e);
+            return Err((StatusCode::BAD_REQUEST, format!("{}: {}", kind, e)));
+        }
+        // Уведомления отправляются обычным текстом
+        if let Err(e) = core_logic::formatting::validate_broadcast(message, None, Default::default(), false) {
+            return Err((StatusCode::BAD_REQUEST, format!("{}: {}", kind, e)));
+        }
     }
+    Ok(())
 }
 
 #[utoipa::path(
     get,
-    path = "/users/{id}/survey",
+    path = "/decision-campaigns",
     responses(
-        (status = 200, description = "Get user survey data from external API", body = serde_json::Value),
-        (status = 404, description = "User survey not found"),
-        (status = 500, description = "External API error")
+        (status = 200, description = "Automatic decision notification campaigns", body = [core_logic::DecisionCampaign])
     )
 )]
-async fn get_user_survey(
-    Path(telegram_id): Path<i64>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    println!("📋 GET /users/{}/survey - получение данных пользователя из внешнего API", telegram_id);
-    
-    match core_logic::db::get_user_survey_from_external_api(telegram_id).await {
-        Ok(Some(survey_data)) => {
-            println!("✅ Получены данные пользователя {} из внешнего API", telegram_id);
-            Ok(Json(survey_data))
-        },
-        Ok(None) => {
-            println!("❌ Данные пользователя {} не найдены во внешнем API", telegram_id);
-            Err((StatusCode::NOT_FOUND, "User survey not found".to_string()))
+async fn get_decision_campaigns(
+    State(state): State<AppState>
+) -> Result<Json<Vec<core_logic::DecisionCampaign>>, (StatusCode, String)> {
+    println!("📋 GET /decision-campaigns - получение кампаний уведомлений");
+
+    match core_logic::db::get_decision_campaigns(&state.pool).await {
+        Ok(campaigns) => {
+            println!("✅ Получено {} кампаний", campaigns.len());
+            Ok(Json(campaigns))
         },
         Err(e) => {
-            println!("❌ Ошибка при получении данных пользователя {} из внешнего API: {}", telegram_id, e);
+            println!("❌ Ошибка при получении кампаний: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("External API error: {}", e),
+                format!("Database error: {}", e),
             ))
         },
     }
 }
 
 #[utoipa::path(
-    get,
-    path = "/external-users",
+    post,
+    path = "/decision-campaigns",
+    request_body = core_logic::SaveDecisionCampaignRequest,
     responses(
-        (status = 200, description = "Get users with completed surveys from external API", body = [serde_json::Value])
+        (status = 200, description = "Campaign created", body = core_logic::DecisionCampaign),
+        (status = 400, description = "Empty name or template error"),
+        (status = 409, description = "Campaign name already exists")
     )
 )]
-async fn get_external_users() -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
-    println!("📋 GET /external-users - получение пользователей с завершенными анкетами");
-    
-    match core_logic::db::get_all_users_from_external_api().await {
-        Ok(users) => {
-            println!("✅ Получено {} пользователей с внешнего API", users.len());
-            Ok(Json(users))
+async fn create_decision_campaign(
+    State(state): State<AppState>,
+    Json(mut request): Json<core_logic::SaveDecisionCampaignRequest>,
+) -> Result<Json<core_logic::DecisionCampaign>, (StatusCode, String)> {
+    println!("📨 POST /decision-campaigns - создание кампании {}", request.name);
+
+    validate_decision_campaign_request(&request)?;
+    request.name = request.name.trim().to_string();
+
+    match core_logic::db::create_decision_campaign(&state.pool, &request).await {
+        Ok(Some(campaign)) => {
+            println!("✅ Кампания {} создана с ID {} (активна: {})", campaign.name, campaign.id, campaign.active);
+            Ok(Json(campaign))
         },
+        Ok(None) => Err((StatusCode::CONFLICT, format!("Кампания {} уже существует", request.name))),
         Err(e) => {
-            println!("❌ Ошибка при получении пользователей: {}", e);
+            println!("❌ Ошибка при создании кампании: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("External API error: {}", e),
+                format!("Database error: {}", e),
             ))
         },
     }
 }
 
 #[utoipa::path(
-    get,
-    path = "/selected-users",
+    put,
+    path = "/decision-campaigns/{id}",
+    request_body = core_logic::SaveDecisionCampaignRequest,
+    params(
+        ("id" = i64, Path, description = "Campaign ID")
+    ),
     responses(
-        (status = 200, description = "Get users with selected surveys (approved by responsible users)", body = [serde_json::Value])
+        (status = 200, description = "Campaign updated", body = core_logic::DecisionCampaign),
+        (status = 400, description = "Empty name or template error"),
+        (status = 404, description = "Campaign not found"),
+        (status = 409, description = "Campaign name already exists")
     )
 )]
-async fn get_selected_users(
-    State(state): State<AppState>
-) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
-    println!("📋 GET /selected-users - получение отобранных пользователей");
-    
-    match core_logic::db::get_selected_users(&state.pool).await {
-        Ok(users) => {
-            println!("✅ Получено {} отобранных пользователей", users.len());
-            Ok(Json(users))
+async fn update_decision_campaign(
+    State(state): State<AppState>,
+    Path(campaign_id): Path<i64>,
+    Json(mut request): Json<core_logic::SaveDecisionCampaignRequest>,
+) -> Result<Json<core_logic::DecisionCampaign>, (StatusCode, String)> {
+    println!("📨 PUT /decision-campaigns/{} - обновление кампании", campaign_id);
+
+    validate_decision_campaign_request(&request)?;
+    request.name = request.name.trim().to_string();
+
+    let campaign = core_logic::db::get_decision_campaign(&state.pool, campaign_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    if campaign.is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("Кампания {} не найдена", campaign_id)));
+    }
+
+    match core_logic::db::update_decision_campaign(&state.pool, campaign_id, &request).await {
+        Ok(Some(campaign)) => {
+            println!("✅ Кампания {} обновлена (активна: {})", campaign_id, campaign.active);
+            Ok(Json(campaign))
         },
+        Ok(None) => Err((StatusCode::CONFLICT, format!("Кампания {} уже существует", request.name))),
         Err(e) => {
-            println!("❌ Ошибка при получении отобранных пользователей: {}", e);
+            println!("❌ Ошибка при обновлении кампании: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Database error: {}", e),
@@ -1097,23 +2425,27 @@ async fn get_selected_users(
 
 #[utoipa::path(
     get,
-    path = "/no-response-users",
+    path = "/decision-campaigns/{id}/notifications",
+    params(
+        ("id" = i64, Path, description = "Campaign ID")
+    ),
     responses(
-        (status = 200, description = "Get users who received signup broadcast but didn't book a slot", body = [serde_json::Value])
+        (status = 200, description = "Notifications sent by the campaign", body = [core_logic::DecisionNotification])
     )
 )]
-async fn get_no_response_users(
-    State(state): State<AppState>
-) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
-    println!("📋 GET /no-response-users - получение пользователей без записи после рассылки");
-    
-    match core_logic::db::get_no_response_users_detailed(&state.pool).await {
-        Ok(users) => {
-            println!("✅ Получено {} пользователей без записи", users.len());
-            Ok(Json(users))
+async fn get_decision_notifications(
+    State(state): State<AppState>,
+    Path(campaign_id): Path<i64>,
+) -> Result<Json<Vec<core_logic::DecisionNotification>>, (StatusCode, String)> {
+    println!("📋 GET /decision-campaigns/{}/notifications - отправленные уведомления", campaign_id);
+
+    match core_logic::db::get_decision_notifications(&state.pool, campaign_id).await {
+        Ok(notifications) => {
+            println!("✅ Получено {} уведомлений", notifications.len());
+            Ok(Json(notifications))
         },
         Err(e) => {
-            println!("❌ Ошибка при получении пользователей без записи: {}", e);
+            println!("❌ Ошибка при получении уведомлений: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Database error: {}", e),
@@ -1122,7 +2454,6 @@ async fn get_no_response_users(
     }
 }
 
-
 #[derive(serde::Deserialize, utoipa::ToSchema)]
 struct UpdateMessageStatusRequest {
     telegram_id: i64,
@@ -1177,3 +2508,37 @@ async fn update_broadcast_message_status(
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_logic::formatting::BroadcastParseMode;
+    use core_logic::{MediaGroup, MediaItem};
+
+    #[test]
+    fn long_edit_of_split_broadcast_is_accepted() {
+        let long_text = "Собеседование переносится на завтра. ".repeat(200);
+        assert!(long_text.chars().count() > core_logic::formatting::MAX_TEXT_LENGTH);
+
+        assert_eq!(validate_broadcast_edit(&long_text, None, BroadcastParseMode::Plain, true), Ok(()));
+        assert!(validate_broadcast_edit(&long_text, None, BroadcastParseMode::Plain, false).is_err());
+    }
+
+    #[test]
+    fn edit_of_media_broadcast_keeps_caption_limit() {
+        let media_group = MediaGroup {
+            media: vec![MediaItem {
+                media_type: "photo".to_string(),
+                file_id: Some("photo-1".to_string()),
+                file_path: None,
+                caption: Some("Старая подпись".to_string()),
+            }],
+        };
+        let caption = "Новая подпись ".repeat(100);
+        assert!(caption.chars().count() > core_logic::formatting::MAX_CAPTION_LENGTH);
+
+        assert!(validate_broadcast_edit(&caption, Some(media_group.clone()), BroadcastParseMode::Plain, true).is_err());
+        assert_eq!(validate_broadcast_edit("Новая подпись", Some(media_group), BroadcastParseMode::Plain, true), Ok(()));
+        assert!(validate_broadcast_edit("  ", None, BroadcastParseMode::Plain, true).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::time::Duration;
+use core_logic::{BroadcastEvent, BroadcastStatus, RabbitMQClient};
+use sqlx::SqlitePool;
+
+// Как часто планировщик проверяет отложенные рассылки
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Публикует BroadcastCreated для отложенных рассылок, время которых наступило,
+/// и BroadcastWinnerSelected для A/B-тестов, которым пора выбрать победителя.
+/// Состояние хранится в БД, поэтому рассылки переживают перезапуск api_server
+pub async fn run_broadcast_scheduler(pool: SqlitePool, rabbitmq: Arc<RabbitMQClient>) {
+    println!("⏰ Планировщик отложенных рассылок запущен");
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = dispatch_due_broadcasts(&pool, &rabbitmq).await {
+            println!("❌ Ошибка планировщика рассылок: {}", e);
+        }
+        if let Err(e) = dispatch_due_winners(&pool, &rabbitmq).await {
+            println!("❌ Ошибка выбора победителей A/B-тестов: {}", e);
+        }
+    }
+}
+
+async fn dispatch_due_broadcasts(pool: &SqlitePool, rabbitmq: &RabbitMQClient) -> Result<(), sqlx::Error> {
+    let due = core_logic::db::get_due_scheduled_broadcasts(pool, chrono::Utc::now()).await?;
+
+    for broadcast_id in due {
+        let Some(event) = core_logic::db::get_broadcast_created_event(pool, &broadcast_id).await? else {
+            println!("⚠️ Событие BroadcastCreated для рассылки {} не найдено", broadcast_id);
+            if let Some(mut summary) = core_logic::db::get_broadcast_summary(pool, &broadcast_id).await? {
+                summary.status = BroadcastStatus::Failed;
+                summary.completed_at = Some(chrono::Utc::now().naive_utc());
+                core_logic::db::update_broadcast_summary(pool, &summary).await?;
+            }
+            continue;
+        };
+
+        // Рассылку могли отменить или уже забрать между выборкой и публикацией
+        if !core_logic::db::claim_scheduled_broadcast(pool, &broadcast_id).await? {
+            continue;
+        }
+
+        match rabbitmq.publish_event(&event).await {
+            Ok(_) => println!("📤 Отложенная рассылка {} отправлена в очередь", broadcast_id),
+            Err(e) => {
+                println!("❌ Не удалось опубликовать отложенную рассылку {}: {}", broadcast_id, e);
+                core_logic::db::release_scheduled_broadcast(pool, &broadcast_id).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn dispatch_due_winners(pool: &SqlitePool, rabbitmq: &RabbitMQClient) -> Result<(), sqlx::Error> {
+    let due = core_logic::db::get_due_broadcast_winners(pool, chrono::Utc::now()).await?;
+
+    for broadcast_id in due {
+        // Победитель выбирается один раз, даже если планировщиков несколько;
+        // при ошибке публикации выбор снимается, как и у отложенных рассылок
+        let Some(event) = core_logic::db::select_broadcast_winner(pool, &broadcast_id).await? else {
+            continue;
+        };
+
+        match rabbitmq.publish_event(&event).await {
+            Ok(_) => println!("📤 Победитель A/B-теста рассылки {} отправлен в очередь", broadcast_id),
+            Err(e) => {
+                println!("❌ Не удалось опубликовать победителя рассылки {}: {}", broadcast_id, e);
+                if let BroadcastEvent::BroadcastWinnerSelected { variant, .. } = event {
+                    core_logic::db::release_broadcast_winner(pool, &broadcast_id, variant).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
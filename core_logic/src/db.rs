@@ -1,27 +1,47 @@
 use sqlx::{SqlitePool, Sqlite, migrate::MigrateDatabase};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use std::env;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
+use futures_util::StreamExt;
 use crate::{
     Slot, User, Record, Booking, CreateSlotRequest, CreateUserRequest, CreateBookingRequest,
     UpdateSlotRequest, UpdateUserRequest, BookingError, BookingInfo,
     // Event-Driven imports
     BroadcastEvent, BroadcastEventRecord, BroadcastSummary, BroadcastStatus, BroadcastMessageRecord, MessageStatus, BroadcastMessageType,
+    BroadcastMessage, BroadcastMessageAction, MessageEditStatus,
     CreateBroadcastCommand, BroadcastCreatedResponse, RetryMessageCommand, CancelBroadcastCommand,
     GetBroadcastStatusQuery, GetBroadcastMessagesQuery, BroadcastStatusResponse,
     // Voting system imports
     Vote, CreateVoteRequest, UpdateVoteRequest, SurveyVoteSummary, SurveyStatus, NextSurveyResponse, VoteResponse, UserSurvey,
     // Auth imports
     TelegramAuth, ExternalUserResponse, AuthResponse,
+    UnreachableUser, DeliveryErrorKind, SupportMessage, SupportDirection,
+    DeepLink, DeepLinkFlow, DeepLinkStats, BroadcastFunnel, BroadcastPreview, BroadcastPreviewResponse,
+    Segment, SegmentRules, SaveSegmentRequest, SegmentCountResponse, VoteOutcome,
+    DecisionCampaign, SaveDecisionCampaignRequest, DecisionNotification,
+    IdempotencyReservation, StoredResponse,
 };
+use crate::template::{self, MessageTemplate, TemplateContext, TemplateVariable};
+use crate::formatting::BroadcastParseMode;
+use crate::variants::{self, BroadcastVariantStats};
 
 // Константы для магических чисел
 const DEFAULT_QUERY_LIMIT: i32 = 100;
 const DEFAULT_QUERY_OFFSET: i32 = 0;
 const DEFAULT_BROADCAST_SUMMARIES_LIMIT: i32 = 50;
 const DEFAULT_BROADCAST_SUMMARIES_OFFSET: i32 = 0;
+// Рассылки на большее число получателей публикуются только после одобрения вторым ответственным
+const DEFAULT_BROADCAST_APPROVAL_THRESHOLD: i64 = 100;
+// Запись засчитывается рассылке, если «Записаться» в ней нажали за последние 7 дней — столько живет кнопка
+const BOOKING_ATTRIBUTION_WINDOW_DAYS: i64 = 7;
+// Сколько хранится ответ на запрос с Idempotency-Key
+const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+// Через сколько незавершенный запрос (например, упавший сервер) перестает блокировать ключ
+const IDEMPOTENCY_LOCK_TIMEOUT_SECS: i64 = 300;
+// Сколько профилей для шаблона рассылки запрашивается у внешнего API одновременно
+const TEMPLATE_PROFILE_CONCURRENCY: usize = 10;
 
 // Константы для системы голосования
 const MIN_VOTES_FOR_REVIEW: i64 = 3;
@@ -37,13 +57,18 @@ const SLOT_RANKING_HALF_LIFE_HOURS: f64 = 48.0;
 pub struct ApiCache {
     users: Arc<RwLock<Option<(Vec<serde_json::Value>, chrono::DateTime<chrono::Utc>)>>>,
     surveys: Arc<RwLock<HashMap<i64, (serde_json::Value, chrono::DateTime<chrono::Utc>)>>>,
+    profiles: Arc<RwLock<ProfileCache>>,
 }
 
+// Профиль или его отсутствие во внешнем API и время загрузки
+type ProfileCache = HashMap<i64, (Option<User>, chrono::DateTime<chrono::Utc>)>;
+
 impl ApiCache {
     pub fn new() -> Self {
         Self {
             users: Arc::new(RwLock::new(None)),
             surveys: Arc::new(RwLock::new(HashMap::new())),
+            profiles: Arc::new(RwLock::new(HashMap::new())),
         }
     }
     
@@ -78,6 +103,22 @@ impl ApiCache {
         let mut cache = self.surveys.write().await;
         cache.insert(telegram_id, (survey, Utc::now()));
     }
+
+    // Кеш профилей для шаблонов рассылок (10 минут); None — профиля во внешнем API нет
+    pub async fn get_profile(&self, telegram_id: i64) -> Option<Option<User>> {
+        let cache = self.profiles.read().await;
+        if let Some((profile, timestamp)) = cache.get(&telegram_id) {
+            if Utc::now().signed_duration_since(*timestamp).num_minutes() < 10 {
+                return Some(profile.clone());
+            }
+        }
+        None
+    }
+
+    pub async fn set_profile(&self, telegram_id: i64, profile: Option<User>) {
+        let mut cache = self.profiles.write().await;
+        cache.insert(telegram_id, (profile, Utc::now()));
+    }
 }
 
 // Глобальный кеш
@@ -93,6 +134,13 @@ fn get_cache() -> &'static ApiCache {
     }
 }
 
+// Один HTTP-клиент на процесс: соединения с внешним API переиспользуются
+static HTTP_CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(reqwest::Client::new)
+}
+
 pub async fn init_db() -> Result<SqlitePool, anyhow::Error> {
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     if !Sqlite::database_exists(&db_url).await.unwrap_or(false) {
@@ -102,11 +150,16 @@ pub async fn init_db() -> Result<SqlitePool, anyhow::Error> {
     let pool = SqlitePool::connect(&db_url).await?;
     
     // Применяем миграции
-    sqlx::migrate!("../migrations").run(&pool).await?;
+    run_migrations(&pool).await?;
 
     Ok(pool)
 }
 
+/// Применяет миграции к пулу (используется и в тестах с базой в памяти)
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!("../migrations").run(pool).await
+}
+
 pub async fn get_available_slots(pool: &SqlitePool) -> Result<Vec<Slot>, sqlx::Error> {
     sqlx::query_as::<_, Slot>(
         "SELECT s.id, s.time, s.place, s.max_user, 
@@ -227,11 +280,17 @@ pub async fn create_or_update_booking(pool: &SqlitePool, telegram_id: i64, slot_
     // Затем создаем новую запись
     if let Some(slot_id) = slot_id {
         // Проверяем лимит и создаем запись в одной транзакции
+        // Запись приписывается рассылке, в которой пользователь последним нажал «Записаться»
+        let attribution_since = chrono::Utc::now().naive_utc() - chrono::Duration::days(BOOKING_ATTRIBUTION_WINDOW_DAYS);
         let result = sqlx::query!(
-            "INSERT INTO records (telegram_id, slot_id) 
-             SELECT ?, ? 
-             WHERE (SELECT COUNT(*) FROM records WHERE slot_id = ?) < (SELECT max_user FROM slots WHERE id = ?)",
-            telegram_id, slot_id, slot_id, slot_id
+            "INSERT INTO records (telegram_id, slot_id, broadcast_id) 
+             SELECT ?1, ?2, (
+                 SELECT broadcast_id FROM broadcast_clicks 
+                 WHERE telegram_id = ?1 AND clicked_at >= ?3 
+                 ORDER BY clicked_at DESC LIMIT 1
+             ) 
+             WHERE (SELECT COUNT(*) FROM records WHERE slot_id = ?2) < (SELECT max_user FROM slots WHERE id = ?2)",
+            telegram_id, slot_id, attribution_since
         )
         .execute(pool)
         .await?;
@@ -430,8 +489,7 @@ pub async fn get_user_by_telegram_id(_pool: &SqlitePool, telegram_id: i64) -> Re
     let user_url = format!("{}/user/{}", api_base_url, telegram_id);
     
     // Делаем запрос к внешнему API для получения профиля пользователя
-    let client = reqwest::Client::new();
-    match client
+    match http_client()
         .get(&user_url)
         .header("X-Forwarded-For", "127.0.0.1")
         .send()
@@ -631,6 +689,12 @@ pub async fn save_broadcast_event(
         BroadcastEvent::MessageFailed { .. } => "MessageFailed",
         BroadcastEvent::MessageRetrying { .. } => "MessageRetrying",
         BroadcastEvent::BroadcastCompleted { .. } => "BroadcastCompleted",
+        BroadcastEvent::BroadcastCancelled { .. } => "BroadcastCancelled",
+        BroadcastEvent::BroadcastPaused { .. } => "BroadcastPaused",
+        BroadcastEvent::BroadcastResumed { .. } => "BroadcastResumed",
+        BroadcastEvent::BroadcastApproved { .. } => "BroadcastApproved",
+        BroadcastEvent::BroadcastRejected { .. } => "BroadcastRejected",
+        BroadcastEvent::BroadcastWinnerSelected { .. } => "BroadcastWinnerSelected",
     };
     
     let event_data = serde_json::to_string(event).map_err(|e| sqlx::Error::Protocol(format!("JSON serialization error: {}", e).into()))?;
@@ -641,6 +705,12 @@ pub async fn save_broadcast_event(
         BroadcastEvent::MessageFailed { broadcast_id, .. } => broadcast_id,
         BroadcastEvent::MessageRetrying { broadcast_id, .. } => broadcast_id,
         BroadcastEvent::BroadcastCompleted { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastCancelled { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastPaused { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastResumed { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastApproved { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastRejected { broadcast_id, .. } => broadcast_id,
+        BroadcastEvent::BroadcastWinnerSelected { broadcast_id, .. } => broadcast_id,
     };
 
     let now = chrono::Utc::now().naive_utc();
@@ -711,8 +781,8 @@ pub async fn create_broadcast_summary(
 ) -> Result<(), sqlx::Error> {
     let status_str = summary.status.to_string();
     sqlx::query!(
-        "INSERT INTO broadcast_summaries (id, message, total_users, sent_count, failed_count, pending_count, status, created_at) 
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO broadcast_summaries (id, message, total_users, sent_count, failed_count, pending_count, status, created_at, scheduled_at) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
         summary.id,
         summary.message,
         summary.total_users,
@@ -720,7 +790,8 @@ pub async fn create_broadcast_summary(
         summary.failed_count,
         summary.pending_count,
         status_str,
-        summary.created_at
+        summary.created_at,
+        summary.scheduled_at
     )
     .execute(pool)
     .await?;
@@ -751,106 +822,95 @@ pub async fn update_broadcast_summary(
     Ok(())
 }
 
-pub async fn update_broadcast_status(
-    pool: &SqlitePool,
-    broadcast_id: &str,
-    sent_count: u32,
-    failed_count: u32,
-) -> Result<(), sqlx::Error> {
-    // Получаем текущую сводку рассылки
-    let summary = get_broadcast_summary(pool, broadcast_id).await?;
-    
-    if let Some(mut current_summary) = summary {
-        // Обновляем счетчики
-        current_summary.sent_count = sent_count as i64;
-        current_summary.failed_count = failed_count as i64;
-        
-        // Получаем реальное количество pending сообщений из БД
-        let pending_count = sqlx::query!(
-            "SELECT COUNT(*) as count FROM broadcast_messages WHERE broadcast_id = ? AND status = 'pending'",
-            broadcast_id
-        )
-        .fetch_one(pool)
-        .await?
-        .count;
-        
-        current_summary.pending_count = pending_count;
-        
-        // Определяем статус на основе реального состояния сообщений
-        let (status, completed_at) = if pending_count == 0 && current_summary.total_users > 0 {
-            // Все сообщения обработаны
-            (BroadcastStatus::Completed, Some(chrono::Utc::now().naive_utc()))
-        } else if current_summary.total_users > 0 {
-            // Есть pending сообщения
-            (BroadcastStatus::InProgress, None)
-        } else {
-            // Нет пользователей
-            (BroadcastStatus::Pending, None)
-        };
-        
-        current_summary.status = status;
-        current_summary.completed_at = completed_at;
-        
-        // Обновляем сводку
-        update_broadcast_summary(pool, &current_summary).await?;
-    }
-
-    Ok(())
-}
-
+/// Проекция сводки рассылки: пересчитывает счетчики по сообщениям и завершает рассылку,
+/// когда все сообщения созданы и ни одно не ждет отправки. Возвращает сводку, если
+/// рассылку завершил именно этот вызов: событие BroadcastCompleted записывается один раз
 pub async fn update_broadcast_summary_from_messages(
     pool: &SqlitePool,
     broadcast_id: &str,
-) -> Result<(), sqlx::Error> {
-    // Получаем актуальную статистику по сообщениям
-    let sent_count = sqlx::query!(
-        "SELECT COUNT(*) as count FROM broadcast_messages WHERE broadcast_id = ? AND status = 'sent'",
+) -> Result<Option<BroadcastSummary>, sqlx::Error> {
+    let counts = sqlx::query!(
+        r#"SELECT
+            COUNT(*) as "created!: i64",
+            COALESCE(SUM(CASE WHEN status = 'sent' THEN 1 ELSE 0 END), 0) as "sent!: i64",
+            COALESCE(SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END), 0) as "failed!: i64",
+            COALESCE(SUM(CASE WHEN status IN ('pending', 'retrying') THEN 1 ELSE 0 END), 0) as "pending!: i64"
+         FROM broadcast_messages WHERE broadcast_id = ?"#,
         broadcast_id
     )
     .fetch_one(pool)
-    .await?
-    .count;
+    .await?;
 
-    let failed_count = sqlx::query!(
-        "SELECT COUNT(*) as count FROM broadcast_messages WHERE broadcast_id = ? AND status = 'failed'",
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET sent_count = ?, failed_count = ?, pending_count = ? WHERE id = ?",
+        counts.sent,
+        counts.failed,
+        counts.pending,
         broadcast_id
     )
-    .fetch_one(pool)
-    .await?
-    .count;
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    // Пока воркер событий создает сообщения, часть получателей еще не в таблице
+    if counts.pending > 0 {
+        return Ok(None);
+    }
+    let completed_at = chrono::Utc::now();
+    let completed_at_naive = completed_at.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'completed', completed_at = ? 
+         WHERE id = ? AND status = 'in_progress' AND total_users <= ?",
+        completed_at_naive,
+        broadcast_id,
+        counts.created
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
 
-    let pending_count = sqlx::query!(
-        "SELECT COUNT(*) as count FROM broadcast_messages WHERE broadcast_id = ? AND status = 'pending'",
+    let event = BroadcastEvent::BroadcastCompleted {
+        broadcast_id: broadcast_id.to_string(),
+        total_sent: counts.sent as u32,
+        total_failed: counts.failed as u32,
+        completed_at,
+    };
+    save_broadcast_event(pool, &event).await?;
+    println!("🏁 Рассылка {} завершена: отправлено {}, ошибок {}", broadcast_id, counts.sent, counts.failed);
+
+    get_broadcast_summary(pool, broadcast_id).await
+}
+
+/// Воркер событий начал создавать сообщения рассылки
+pub async fn mark_broadcast_started(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let started_at = chrono::Utc::now();
+    let started_at_naive = started_at.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'in_progress', started_at = COALESCE(started_at, ?) 
+         WHERE id = ? AND status = 'pending'",
+        started_at_naive,
         broadcast_id
     )
-    .fetch_one(pool)
-    .await?
-    .count;
+    .execute(pool)
+    .await?;
 
-    // Получаем текущую сводку
-    let summary = get_broadcast_summary(pool, broadcast_id).await?;
-    
-    if let Some(mut current_summary) = summary {
-        // Обновляем счетчики
-        current_summary.sent_count = sent_count;
-        current_summary.failed_count = failed_count;
-        current_summary.pending_count = pending_count;
-        
-        // Определяем статус на основе реального состояния
-        if pending_count == 0 && current_summary.total_users > 0 {
-            // Все сообщения обработаны
-            current_summary.status = BroadcastStatus::Completed;
-            current_summary.completed_at = Some(chrono::Utc::now().naive_utc());
-        } else if current_summary.total_users > 0 {
-            // Есть pending сообщения
-            current_summary.status = BroadcastStatus::InProgress;
-        }
-        
-        // Обновляем сводку с новыми счетчиками
-        update_broadcast_summary(pool, &current_summary).await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
     }
 
-    Ok(())
+    let event = BroadcastEvent::BroadcastStarted {
+        broadcast_id: broadcast_id.to_string(),
+        started_at,
+    };
+    save_broadcast_event(pool, &event).await?;
+    Ok(true)
 }
 
 pub async fn get_broadcast_summary(
@@ -858,7 +918,7 @@ pub async fn get_broadcast_summary(
     broadcast_id: &str,
 ) -> Result<Option<BroadcastSummary>, sqlx::Error> {
     let record = sqlx::query!(
-        "SELECT id, message, total_users, sent_count, failed_count, pending_count, status, created_at, started_at, completed_at 
+        "SELECT id, message, total_users, sent_count, failed_count, pending_count, status, created_at, started_at, completed_at, scheduled_at 
          FROM broadcast_summaries 
          WHERE id = ?",
         broadcast_id
@@ -878,6 +938,7 @@ pub async fn get_broadcast_summary(
             created_at: r.created_at,
             started_at: r.started_at,
             completed_at: r.completed_at,
+            scheduled_at: r.scheduled_at,
         })),
         None => Ok(None),
     }
@@ -892,7 +953,7 @@ pub async fn get_all_broadcast_summaries(
     let offset = offset.unwrap_or(DEFAULT_BROADCAST_SUMMARIES_OFFSET);
     
     let records = sqlx::query!(
-        "SELECT id, message, total_users, sent_count, failed_count, pending_count, status, created_at, started_at, completed_at 
+        "SELECT id, message, total_users, sent_count, failed_count, pending_count, status, created_at, started_at, completed_at, scheduled_at 
          FROM broadcast_summaries 
          ORDER BY created_at DESC 
          LIMIT ? OFFSET ?",
@@ -915,24 +976,27 @@ pub async fn get_all_broadcast_summaries(
             created_at: r.created_at,
             started_at: r.started_at,
             completed_at: r.completed_at,
+            scheduled_at: r.scheduled_at,
         })
         .collect();
 
     Ok(summaries)
 }
 
+/// Создает запись сообщения; false, если этому получателю сообщение рассылки уже создано
 pub async fn create_broadcast_message(
     pool: &SqlitePool,
     message: &BroadcastMessageRecord,
-) -> Result<(), sqlx::Error> {
+) -> Result<bool, sqlx::Error> {
     let status_str = message.status.to_string();
     let message_type_str = message.message_type.as_ref().map(|mt| match mt {
         BroadcastMessageType::Custom => "custom",
         BroadcastMessageType::SignUp => "signup",
     });
-    sqlx::query!(
-        "INSERT INTO broadcast_messages (broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at) 
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    let result = sqlx::query!(
+        "INSERT INTO broadcast_messages (broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at, variant) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) 
+         ON CONFLICT (broadcast_id, telegram_id) DO NOTHING",
         message.broadcast_id,
         message.telegram_id,
         status_str,
@@ -940,7 +1004,48 @@ pub async fn create_broadcast_message(
         message.sent_at,
         message.retry_count,
         message_type_str,
-        message.created_at
+        message.created_at,
+        message.variant
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Сообщение создано, но не опубликовано в очередь: Event Worker прервался между
+/// созданием записи и публикацией
+pub async fn is_broadcast_message_unqueued(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let unqueued = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM broadcast_messages
+            WHERE broadcast_id = ? AND telegram_id = ? AND status = 'pending' AND queued_at IS NULL
+        ) as "unqueued!: bool""#,
+        broadcast_id,
+        telegram_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(unqueued)
+}
+
+/// Отмечает, что сообщение опубликовано в очередь отправки
+pub async fn mark_broadcast_message_queued(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        "UPDATE broadcast_messages SET queued_at = ? WHERE broadcast_id = ? AND telegram_id = ?",
+        now,
+        broadcast_id,
+        telegram_id
     )
     .execute(pool)
     .await?;
@@ -976,7 +1081,7 @@ pub async fn update_broadcast_message_status(
     telegram_id: i64,
     status: MessageStatus,
     error: Option<String>,
-) -> Result<(), sqlx::Error> {
+) -> Result<Option<BroadcastSummary>, sqlx::Error> {
     let status_str = status.to_string();
     let sent_at = if status == MessageStatus::Sent {
         Some(chrono::Utc::now().naive_utc())
@@ -998,9 +1103,7 @@ pub async fn update_broadcast_message_status(
     .await?;
 
     // Обновляем сводку рассылки после изменения статуса сообщения
-    update_broadcast_summary_from_messages(pool, broadcast_id).await?;
-
-    Ok(())
+    update_broadcast_summary_from_messages(pool, broadcast_id).await
 }
 
 pub async fn get_broadcast_messages(
@@ -1019,7 +1122,7 @@ pub async fn get_broadcast_messages(
         let status_str = status.to_string();
         
         sqlx::query!(
-            "SELECT id, broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at 
+            "SELECT id, broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at, edit_status, edit_error, variant 
              FROM broadcast_messages 
              WHERE broadcast_id = ? AND status = ?
              ORDER BY created_at ASC
@@ -1046,11 +1149,14 @@ pub async fn get_broadcast_messages(
                 _ => BroadcastMessageType::Custom,
             }),
             created_at: row.created_at,
+            edit_status: row.edit_status.map(MessageEditStatus::from),
+            edit_error: row.edit_error,
+            variant: row.variant,
         })
         .collect()
     } else {
         sqlx::query!(
-            "SELECT id, broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at 
+            "SELECT id, broadcast_id, telegram_id, status, error, sent_at, retry_count, message_type, created_at, edit_status, edit_error, variant 
              FROM broadcast_messages 
              WHERE broadcast_id = ?
              ORDER BY created_at ASC
@@ -1076,6 +1182,9 @@ pub async fn get_broadcast_messages(
                 _ => BroadcastMessageType::Custom,
             }),
             created_at: row.created_at,
+            edit_status: row.edit_status.map(MessageEditStatus::from),
+            edit_error: row.edit_error,
+            variant: row.variant,
         })
         .collect()
     };
@@ -1094,43 +1203,77 @@ pub async fn handle_create_broadcast(
     // Работаем только с внешними пользователями
     let mut users = Vec::new();
     
-    // Работаем только с внешними пользователями
-    if let Some(selected_external_user_ids) = &command.selected_external_users {
+    if let Some(segment_id) = command.segment_id {
+        // Состав сегмента пересчитывается в момент отправки, здесь — оценка для сводки
+        let Some(telegram_ids) = resolve_segment(pool, segment_id).await? else {
+            return Err(format!("Segment {} not found", segment_id).into());
+        };
+        println!("🎯 Сегмент {}: {} пользователей", segment_id, telegram_ids.len());
+        users.extend(telegram_ids.into_iter().map(broadcast_recipient));
+    } else if let Some(selected_external_user_ids) = &command.selected_external_users {
         // Внешние пользователи - это telegram_id
         println!("Внешние пользователи выбраны: {:?}", selected_external_user_ids);
         
         // Создаем пользователей только с telegram_id
         let external_users = selected_external_user_ids.iter().map(|telegram_id| {
-            let user = User {
-                telegram_id: telegram_id.parse::<i64>().unwrap_or(0),
-                name: format!("User {}", telegram_id),
-                telegram_nickname: None,
-                phone_number: None,
-                full_name: None,
-            };
+            let user = broadcast_recipient(telegram_id.parse::<i64>().unwrap_or(0));
             println!("Создан пользователь: telegram_id={}", user.telegram_id);
             user
         }).collect::<Vec<_>>();
         
         users.extend(external_users);
     } else {
-        println!("ОШИБКА: selected_external_users должен быть указан!");
+        println!("ОШИБКА: selected_external_users или segment_id должен быть указан!");
         return Err("No external users specified".into());
     }
+
+    // Исключаем пользователей, которым доставка невозможна
+    let unreachable = get_unreachable_telegram_ids(pool).await?;
+    let selected_count = users.len();
+    users.retain(|user| !unreachable.contains(&user.telegram_id));
+    let skipped_unreachable = (selected_count - users.len()) as i64;
+    // Повторы в списке получателей не должны давать второе сообщение
+    let mut seen = std::collections::HashSet::new();
+    users.retain(|user| seen.insert(user.telegram_id));
+    if skipped_unreachable > 0 {
+        println!("⏭️ Пропущено недоступных пользователей: {}", skipped_unreachable);
+    }
     
     // Создаем событие
     let event = BroadcastEvent::BroadcastCreated {
         broadcast_id: broadcast_id.clone(),
         message: command.message.clone(),
-        target_users: users.clone(),
+        // Получателей сегмента Event Worker вычислит сам
+        target_users: if command.segment_id.is_some() { Vec::new() } else { users.clone() },
         message_type: command.message_type.clone(),
         media_group: command.media_group.clone(),
         created_at: chrono::Utc::now(),
+        segment_id: command.segment_id,
+        keyboard: command.keyboard.clone(),
+        parse_mode: command.parse_mode,
+        split_long_text: command.split_long_text,
+        created_by: command.created_by,
+        variants: command.variants.clone(),
+        auto_winner: command.auto_winner,
     };
     
     // Сохраняем событие
     save_broadcast_event(pool, &event).await?;
     
+    // Отложенная рассылка ждет планировщика, событие опубликуется в send_at.
+    // Черновик ждет выпуска и сохраняет время отправки до него
+    let scheduled_at = command.send_at.filter(|send_at| *send_at > chrono::Utc::now());
+    let status = if command.draft {
+        BroadcastStatus::Draft
+    } else if users.len() as i64 > broadcast_approval_threshold() {
+        println!("🔐 Рассылка {} на {} получателей ждет одобрения", broadcast_id, users.len());
+        BroadcastStatus::PendingApproval
+    } else if scheduled_at.is_some() {
+        BroadcastStatus::Scheduled
+    } else {
+        BroadcastStatus::Pending
+    };
+
     // Создаем read model
     let summary = BroadcastSummary {
         id: broadcast_id.clone(),
@@ -1139,10 +1282,11 @@ pub async fn handle_create_broadcast(
         sent_count: 0,
         failed_count: 0,
         pending_count: users.len() as i64,
-        status: BroadcastStatus::Pending,
+        status: status.clone(),
         created_at: chrono::Utc::now().naive_utc(),
         started_at: None,
         completed_at: None,
+        scheduled_at: scheduled_at.map(|send_at| send_at.naive_utc()),
     };
     
     create_broadcast_summary(pool, &summary).await?;
@@ -1152,1066 +1296,2946 @@ pub async fn handle_create_broadcast(
     
     Ok((BroadcastCreatedResponse {
         broadcast_id,
-        status: BroadcastStatus::Pending,
+        status,
+        skipped_unreachable,
     }, event))
 }
 
-pub async fn handle_retry_message(
-    pool: &SqlitePool,
-    command: RetryMessageCommand,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Получаем сообщение
-    let messages = get_broadcast_messages(pool, &command.broadcast_id, Some(MessageStatus::Failed), Some(1), Some(0)).await?;
-    
-    if let Some(mut message) = messages.into_iter().next() {
-        message.status = MessageStatus::Retrying;
-        message.retry_count += 1;
-        
-        update_broadcast_message(pool, &message).await?;
-        
-        // Создаем событие повторной попытки
-        let event = BroadcastEvent::MessageRetrying {
-            broadcast_id: command.broadcast_id,
-            telegram_id: command.telegram_id,
-            retry_count: message.retry_count as u32,
-            retry_at: chrono::Utc::now(),
-        };
-        
-        save_broadcast_event(pool, &event).await?;
-    }
-    
-    Ok(())
+/// Порог одобрения из BROADCAST_APPROVAL_THRESHOLD
+pub fn broadcast_approval_threshold() -> i64 {
+    env::var("BROADCAST_APPROVAL_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_BROADCAST_APPROVAL_THRESHOLD)
 }
 
-pub async fn handle_cancel_broadcast(
-    pool: &SqlitePool,
-    command: CancelBroadcastCommand,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Обновляем статус в read model
-    if let Some(mut summary) = get_broadcast_summary(pool, &command.broadcast_id).await? {
-        summary.status = BroadcastStatus::Failed;
-        summary.completed_at = Some(chrono::Utc::now().naive_utc());
-        
-        update_broadcast_summary(pool, &summary).await?;
+/// Получатель рассылки: из внешнего API известен только telegram_id
+fn broadcast_recipient(telegram_id: i64) -> User {
+    User {
+        telegram_id,
+        name: format!("User {}", telegram_id),
+        telegram_nickname: None,
+        phone_number: None,
+        full_name: None,
     }
-    
-    Ok(())
 }
 
-// Query Handlers
-
-pub async fn handle_get_broadcast_status(
+/// Повторно отправляет недоставленное сообщение пользователю с новым запасом попыток.
+/// Возвращает сообщение для публикации в очередь; None, если неудачного сообщения нет
+pub async fn handle_retry_message(
     pool: &SqlitePool,
-    query: GetBroadcastStatusQuery,
-) -> Result<Option<BroadcastStatusResponse>, Box<dyn std::error::Error>> {
-    let summary = get_broadcast_summary(pool, &query.broadcast_id).await?;
-    
-    match summary {
-        Some(broadcast) => {
-            let messages = get_broadcast_messages(pool, &query.broadcast_id, None, Some(DEFAULT_QUERY_LIMIT), Some(DEFAULT_QUERY_OFFSET)).await?;
-            
-            Ok(Some(BroadcastStatusResponse {
-                broadcast,
-                messages,
-            }))
-        }
-        None => Ok(None),
+    command: RetryMessageCommand,
+) -> Result<Option<BroadcastMessage>, Box<dyn std::error::Error>> {
+    let Some(message) = get_broadcast_message_payload(pool, &command.broadcast_id, command.telegram_id).await? else {
+        return Ok(None);
+    };
+
+    // Сброс обнуляет счетчик, поэтому запоминаем, сколько попыток было сделано до него
+    let retry_count = sqlx::query_scalar!(
+        "SELECT retry_count FROM broadcast_messages WHERE broadcast_id = ? AND telegram_id = ?",
+        command.broadcast_id,
+        command.telegram_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_default();
+
+    if !reset_broadcast_message_for_retry(pool, &command.broadcast_id, command.telegram_id).await? {
+        return Ok(None);
     }
-}
 
-pub async fn handle_get_broadcast_messages(
-    pool: &SqlitePool,
-    query: GetBroadcastMessagesQuery,
-) -> Result<Vec<BroadcastMessageRecord>, Box<dyn std::error::Error>> {
-    let messages = get_broadcast_messages(
-        pool,
-        &query.broadcast_id,
-        query.status,
-        query.limit,
-        query.offset,
-    ).await?;
-    
-    Ok(messages)
+    // Создаем событие повторной попытки
+    let event = BroadcastEvent::MessageRetrying {
+        broadcast_id: command.broadcast_id,
+        telegram_id: command.telegram_id,
+        retry_count: retry_count as u32,
+        retry_at: chrono::Utc::now(),
+    };
+    save_broadcast_event(pool, &event).await?;
+
+    Ok(Some(message))
 }
 
-pub async fn delete_broadcast(
+/// Учитывает автоматическую повторную попытку и возвращает новый retry_count
+pub async fn record_broadcast_message_retry(
     pool: &SqlitePool,
     broadcast_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Удаляем сообщения рассылки
-    sqlx::query!(
-        "DELETE FROM broadcast_messages WHERE broadcast_id = ?",
-        broadcast_id
+    telegram_id: i64,
+    error: &str,
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        "UPDATE broadcast_messages SET status = 'retrying', error = ?, retry_count = retry_count + 1 
+         WHERE broadcast_id = ? AND telegram_id = ? 
+         RETURNING retry_count",
+        error,
+        broadcast_id,
+        telegram_id
     )
-    .execute(pool)
+    .fetch_optional(pool)
     .await?;
 
-    // Удаляем события рассылки
-    sqlx::query!(
-        "DELETE FROM broadcast_events WHERE broadcast_id = ?",
-        broadcast_id
-    )
-    .execute(pool)
-    .await?;
+    Ok(record.map(|r| r.retry_count).unwrap_or_default())
+}
 
-    // Удаляем сводку рассылки
-    sqlx::query!(
-        "DELETE FROM broadcast_summaries WHERE id = ?",
-        broadcast_id
+/// Возвращает недоставленное сообщение в pending со сброшенным счетчиком попыток
+pub async fn reset_broadcast_message_for_retry(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE broadcast_messages SET status = 'pending', error = NULL, retry_count = 0 
+         WHERE broadcast_id = ? AND telegram_id = ? AND status = 'failed'",
+        broadcast_id,
+        telegram_id
     )
     .execute(pool)
     .await?;
 
-    Ok(())
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    update_broadcast_summary_from_messages(pool, broadcast_id).await?;
+    Ok(true)
 }
 
-// Voting System Functions
+/// Восстанавливает сообщение для очереди из события BroadcastCreated с подставленным шаблоном
+pub async fn get_broadcast_message_payload(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<Option<BroadcastMessage>, sqlx::Error> {
+    match get_broadcast_message_template(pool, broadcast_id, telegram_id).await? {
+        Some(message) => Ok(Some(render_broadcast_message(pool, message).await?)),
+        None => Ok(None),
+    }
+}
 
-/// Получает роль пользователя
-pub async fn get_user_role(pool: &SqlitePool, telegram_id: i64) -> Result<Option<i32>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT role FROM user_roles WHERE telegram_id = ?",
+/// Сообщение рассылки из события BroadcastCreated без подстановки шаблона
+async fn get_broadcast_message_template(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<Option<BroadcastMessage>, sqlx::Error> {
+    let Some(BroadcastEvent::BroadcastCreated {
+        message,
+        message_type,
+        media_group,
+        created_at,
+        keyboard,
+        parse_mode,
+        split_long_text,
+        variants,
+        ..
+    }) = get_broadcast_created_event(pool, broadcast_id).await?
+    else {
+        return Ok(None);
+    };
+
+    // Повтор и правка должны прийти получателю в его A/B-варианте
+    let variant = sqlx::query_scalar!(
+        "SELECT variant FROM broadcast_messages WHERE broadcast_id = ? AND telegram_id = ?",
+        broadcast_id,
         telegram_id
     )
     .fetch_optional(pool)
-    .await?;
-    
-    Ok(result.map(|r| r.role as i32))
-}
+    .await?
+    .flatten();
+    let (message, media_group) = variants::variant_content(message, media_group, variants, variant.unwrap_or(0) as usize);
 
-/// Создает или обновляет роль пользователя
-pub async fn set_user_role(pool: &SqlitePool, telegram_id: i64, role: i32) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        "INSERT OR REPLACE INTO user_roles (telegram_id, role, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+    let message = BroadcastMessage {
         telegram_id,
-        role
-    )
-    .execute(pool)
-    .await?;
-    
-    Ok(())
+        message,
+        broadcast_id: broadcast_id.to_string(),
+        message_type,
+        media_group,
+        created_at,
+        keyboard,
+        parse_mode,
+        split_long_text,
+        is_test: false,
+        action: BroadcastMessageAction::Send,
+        telegram_message_ids: Vec::new(),
+    };
+
+    Ok(Some(message))
 }
 
+/// Собирает данные получателя для шаблона; загружается только то, что в нем используется.
+/// Ошибки внешнего API не прерывают рассылку: поле останется пустым и сработает запасной текст
+pub async fn get_template_context(
+    pool: &SqlitePool,
+    telegram_id: i64,
+    variables: &[TemplateVariable],
+) -> Result<TemplateContext, sqlx::Error> {
+    let mut context = TemplateContext::default();
 
-/// Получает следующую анкету для обычного пользователя (атомарный захват)
-pub async fn get_next_survey_for_regular_user(pool: &SqlitePool, voter_telegram_id: i64) -> Result<Option<i64>, sqlx::Error> {
-    // Сначала находим подходящую анкету
-    let survey_id = sqlx::query!(
-        r#"
-        SELECT s.survey_id
-        FROM (
-            SELECT DISTINCT survey_id, created_at FROM votes 
-            ORDER BY created_at ASC
-        ) s
-        WHERE s.survey_id NOT IN (
-            SELECT survey_id FROM votes WHERE voter_telegram_id = ?
-        )
-        AND (
-            SELECT COUNT(*) FROM votes v 
-            WHERE v.survey_id = s.survey_id
-        ) < ?
-        AND NOT EXISTS (
-            SELECT 1 FROM votes v 
-            JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
-            WHERE v.survey_id = s.survey_id AND ur.role = 1
-        )
-        ORDER BY s.created_at ASC
-        LIMIT 1
-        "#,
-        voter_telegram_id,
-        MIN_VOTES_FOR_REVIEW
-    )
-    .fetch_optional(pool)
-    .await?;
-    
-    if let Some(survey) = survey_id {
-        // Атомарно захватываем анкету, создавая временный голос "В обработке"
-        let result = sqlx::query!(
-            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
-            survey.survey_id,
-            voter_telegram_id
-        )
-        .execute(pool)
-        .await;
-        
-        match result {
-            Ok(_) => Ok(Some(survey.survey_id)),
-            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
-                // Другой пользователь уже захватил эту анкету
-                Ok(None)
+    if variables.iter().any(|variable| variable.needs_profile()) {
+        match get_template_profile(pool, telegram_id).await {
+            Ok(Some(user)) => {
+                context.full_name = user.full_name;
+                context.telegram_nickname = user.telegram_nickname;
             }
-            Err(e) => Err(e),
+            Ok(None) => {}
+            Err(e) => eprintln!("⚠️ Профиль {} для шаблона недоступен: {}", telegram_id, e),
         }
-    } else {
-        Ok(None)
     }
+
+    if variables.iter().any(|variable| variable.needs_booking()) {
+        if let Some(booking) = get_booking_by_telegram_id(pool, telegram_id).await? {
+            context.slot_time = Some(booking.time);
+            context.slot_place = Some(booking.place);
+        }
+    }
+
+    if variables.contains(&TemplateVariable::SurveyStatus) {
+        context.survey_status = Some(get_survey_vote_summary(pool, telegram_id).await?.status);
+    }
+
+    Ok(context)
 }
 
-/// Получает следующую анкету для ответственного пользователя (атомарный захват)
-pub async fn get_next_survey_for_responsible_user(pool: &SqlitePool, voter_telegram_id: i64) -> Result<Option<i64>, sqlx::Error> {
-    // Сначала находим анкету, готовую для проверки
-    let survey_id = sqlx::query!(
-        r#"
-        SELECT s.survey_id
-        FROM (
-            SELECT DISTINCT survey_id, created_at FROM votes 
-            ORDER BY created_at ASC
-        ) s
-        WHERE (
-            SELECT COUNT(*) FROM votes v 
-            WHERE v.survey_id = s.survey_id
-        ) >= ?
-        AND NOT EXISTS (
-            SELECT 1 FROM votes v 
-            JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
-            WHERE v.survey_id = s.survey_id AND ur.role = 1
-        )
-        ORDER BY s.created_at ASC
-        LIMIT 1
-        "#,
-        MIN_VOTES_FOR_REVIEW
-    )
-    .fetch_optional(pool)
-    .await?;
-    
-    if let Some(survey) = survey_id {
-        // Атомарно захватываем анкету
-        let result = sqlx::query!(
-            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
-            survey.survey_id,
-            voter_telegram_id
-        )
-        .execute(pool)
-        .await;
-        
-        match result {
-            Ok(_) => Ok(Some(survey.survey_id)),
-            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
-                // Другой ответственный уже захватил эту анкету
-                Ok(None)
+/// Профиль получателя для шаблона: сначала из кеша, заполненного prefetch_template_profiles
+async fn get_template_profile(pool: &SqlitePool, telegram_id: i64) -> Result<Option<User>, sqlx::Error> {
+    let cache = get_cache();
+    if let Some(profile) = cache.get_profile(telegram_id).await {
+        return Ok(profile);
+    }
+    let profile = get_user_by_telegram_id(pool, telegram_id).await?;
+    cache.set_profile(telegram_id, profile.clone()).await;
+    Ok(profile)
+}
+
+/// Загружает профили всех получателей рассылки параллельно до подстановки шаблона,
+/// чтобы не ходить во внешний API последовательно для каждого. Ошибки не кешируются:
+/// такой профиль будет запрошен еще раз при подстановке
+pub async fn prefetch_template_profiles(pool: &SqlitePool, telegram_ids: &[i64]) {
+    let cache = get_cache();
+    let mut missing = Vec::new();
+    for telegram_id in telegram_ids {
+        if cache.get_profile(*telegram_id).await.is_none() && !missing.contains(telegram_id) {
+            missing.push(*telegram_id);
+        }
+    }
+    if missing.is_empty() {
+        return;
+    }
+
+    println!("👥 Загружаем {} профилей для шаблона рассылки", missing.len());
+    futures_util::stream::iter(missing)
+        .map(|telegram_id| async move { (telegram_id, get_user_by_telegram_id(pool, telegram_id).await) })
+        .buffer_unordered(TEMPLATE_PROFILE_CONCURRENCY)
+        .for_each(|(telegram_id, profile)| async move {
+            match profile {
+                Ok(profile) => cache.set_profile(telegram_id, profile).await,
+                Err(e) => eprintln!("⚠️ Профиль {} для шаблона недоступен: {}", telegram_id, e),
             }
-            Err(e) => Err(e),
+        })
+        .await;
+}
+
+/// Подставляет данные получателя в текст и подписи сообщения рассылки
+pub async fn render_broadcast_message(
+    pool: &SqlitePool,
+    mut message: BroadcastMessage,
+) -> Result<BroadcastMessage, sqlx::Error> {
+    let variables = match template::validate_broadcast(&message.message, message.media_group.as_ref()) {
+        Ok(variables) => variables,
+        Err(e) => {
+            // Шаблон проверяется при создании рассылки, сюда попадают только старые рассылки
+            eprintln!("⚠️ Шаблон рассылки {} не разобран, текст отправляется как есть: {}", message.broadcast_id, e);
+            return Ok(message);
+        }
+    };
+    if variables.is_empty() {
+        return Ok(message);
+    }
+
+    let context = get_template_context(pool, message.telegram_id, &variables).await?;
+    let parse_mode = message.parse_mode;
+    message.message = render_template_text(&message.message, &context, parse_mode);
+    if let Some(media_group) = message.media_group.as_mut() {
+        for item in media_group.media.iter_mut() {
+            item.caption = item.caption.as_deref().map(|caption| render_template_text(caption, &context, parse_mode));
         }
-    } else {
-        Ok(None)
     }
+
+    Ok(message)
 }
 
-/// Создает голос (или обновляет запись "В обработке")
-pub async fn create_vote(pool: &SqlitePool, request: CreateVoteRequest, voter_telegram_id: i64) -> Result<Vote, sqlx::Error> {
-    // Проверяем, есть ли уже запись "В обработке" для этого пользователя и анкеты
-    let existing_processing_vote = sqlx::query_as::<_, (i64,)>(
-        "SELECT id FROM votes WHERE survey_id = ? AND voter_telegram_id = ? AND comment = 'В обработке'"
+fn render_template_text(text: &str, context: &TemplateContext, parse_mode: BroadcastParseMode) -> String {
+    MessageTemplate::parse(text)
+        .map(|template| template.render(context, parse_mode))
+        .unwrap_or_else(|_| text.to_string())
+}
+
+/// Предпросмотр рассылки для первых получателей
+pub async fn handle_preview_broadcast(
+    pool: &SqlitePool,
+    command: &CreateBroadcastCommand,
+    limit: usize,
+) -> Result<BroadcastPreviewResponse, Box<dyn std::error::Error>> {
+    let variables = template::validate_broadcast(&command.message, command.media_group.as_ref())?;
+
+    let recipients: Vec<i64> = match command.segment_id {
+        Some(segment_id) => resolve_segment(pool, segment_id)
+            .await?
+            .ok_or_else(|| format!("Segment {} not found", segment_id))?,
+        None => command
+            .selected_external_users
+            .iter()
+            .flatten()
+            .map(|telegram_id| telegram_id.parse::<i64>().map_err(|_| format!("Invalid telegram_id: {}", telegram_id)))
+            .collect::<Result<_, _>>()?,
+    };
+    let recipients: Vec<i64> = recipients.into_iter().take(limit).collect();
+    if variables.iter().any(|variable| variable.needs_profile()) {
+        prefetch_template_profiles(pool, &recipients).await;
+    }
+
+    let mut previews = Vec::new();
+    for telegram_id in recipients {
+        let message = BroadcastMessage {
+            telegram_id,
+            message: command.message.clone(),
+            broadcast_id: String::new(),
+            message_type: command.message_type.clone(),
+            media_group: command.media_group.clone(),
+            created_at: chrono::Utc::now(),
+            keyboard: command.keyboard.clone(),
+            parse_mode: command.parse_mode,
+            split_long_text: command.split_long_text,
+            is_test: false,
+            action: BroadcastMessageAction::Send,
+            telegram_message_ids: Vec::new(),
+        };
+        let rendered = render_broadcast_message(pool, message).await?;
+        previews.push(BroadcastPreview {
+            telegram_id,
+            message: rendered.message,
+            captions: rendered
+                .media_group
+                .map(|group| group.media.into_iter().filter_map(|item| item.caption).collect())
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(BroadcastPreviewResponse { variables, previews })
+}
+
+/// Отменяет рассылку: неотправленные сообщения помечаются cancelled.
+/// None, если рассылка не найдена или уже завершена
+pub async fn handle_cancel_broadcast(
+    pool: &SqlitePool,
+    command: CancelBroadcastCommand,
+) -> Result<Option<BroadcastEvent>, Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now();
+    let completed_at = now.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'cancelled', completed_at = ? 
+         WHERE id = ? AND status IN ('draft', 'pending_approval', 'scheduled', 'pending', 'in_progress', 'paused')",
+        completed_at,
+        command.broadcast_id
     )
-    .bind(request.survey_id)
-    .bind(voter_telegram_id)
-    .fetch_optional(pool)
+    .execute(pool)
     .await?;
-    
-    let vote_id = if let Some(existing) = existing_processing_vote {
-        // Обновляем существующую запись "В обработке"
-        sqlx::query!(
-            "UPDATE votes SET decision = ?, comment = ? WHERE id = ?",
-            request.decision,
-            request.comment,
-            existing.0
-        )
-        .execute(pool)
-        .await?;
-        
-        existing.0
-    } else {
-        // Создаем новую запись (если по какой-то причине записи "В обработке" нет)
-        let result = sqlx::query!(
-            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, ?, ?)",
-            request.survey_id,
-            voter_telegram_id,
-            request.decision,
-            request.comment
-        )
-        .execute(pool)
-        .await?;
-        
-        result.last_insert_rowid()
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let cancelled = cancel_pending_broadcast_messages(pool, &command.broadcast_id).await?;
+    println!("🛑 Рассылка {} отменена, не отправлено сообщений: {}", command.broadcast_id, cancelled);
+
+    let event = BroadcastEvent::BroadcastCancelled {
+        broadcast_id: command.broadcast_id,
+        cancelled_at: now,
     };
-    
-    // Получаем обновленную запись
-    let vote = sqlx::query_as::<_, Vote>(
-        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE id = ?"
+    save_broadcast_event(pool, &event).await?;
+
+    Ok(Some(event))
+}
+
+/// Ставит рассылку на паузу; сообщения остаются в очереди до возобновления.
+/// None, если рассылка не найдена или не отправляется
+pub async fn handle_pause_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<Option<BroadcastEvent>, Box<dyn std::error::Error>> {
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'paused' 
+         WHERE id = ? AND status IN ('pending', 'in_progress')",
+        broadcast_id
     )
-    .bind(vote_id)
-    .fetch_one(pool)
+    .execute(pool)
     .await?;
-    
-    Ok(vote)
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let event = BroadcastEvent::BroadcastPaused {
+        broadcast_id: broadcast_id.to_string(),
+        paused_at: chrono::Utc::now(),
+    };
+    save_broadcast_event(pool, &event).await?;
+
+    Ok(Some(event))
 }
 
-/// Получает голоса для анкеты
-pub async fn get_votes_by_survey(pool: &SqlitePool, survey_id: i64) -> Result<Vec<Vote>, sqlx::Error> {
-    let votes = sqlx::query_as::<_, Vote>(
-        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE survey_id = ? ORDER BY created_at ASC"
+/// Возобновляет рассылку после паузы. None, если рассылка не на паузе
+pub async fn handle_resume_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<Option<BroadcastEvent>, Box<dyn std::error::Error>> {
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'in_progress' WHERE id = ? AND status = 'paused'",
+        broadcast_id
     )
-    .bind(survey_id)
-    .fetch_all(pool)
+    .execute(pool)
     .await?;
-    
-    Ok(votes)
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    // Пока рассылка стояла, все сообщения могли уже обработаться
+    update_broadcast_summary_from_messages(pool, broadcast_id).await?;
+
+    let event = BroadcastEvent::BroadcastResumed {
+        broadcast_id: broadcast_id.to_string(),
+        resumed_at: chrono::Utc::now(),
+    };
+    save_broadcast_event(pool, &event).await?;
+
+    Ok(Some(event))
 }
 
-/// Получает статистику голосов для анкеты
-pub async fn get_survey_vote_summary(pool: &SqlitePool, survey_id: i64) -> Result<SurveyVoteSummary, sqlx::Error> {
-    // Получаем общую статистику голосов (исключая служебные записи)
-    let stats = sqlx::query!(
-        r#"
-        SELECT 
-            decision,
-            COUNT(*) as "count: i64"
-        FROM votes 
-        WHERE survey_id = ? 
-        AND (comment IS NULL OR (comment != 'В обработке' AND comment != 'Инициализация'))
-        GROUP BY decision
-        "#,
-        survey_id
+/// Помечает неотправленные сообщения рассылки отмененными
+pub async fn cancel_pending_broadcast_messages(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE broadcast_messages SET status = 'cancelled' 
+         WHERE broadcast_id = ? AND status IN ('pending', 'retrying')",
+        broadcast_id
     )
-    .fetch_all(pool)
+    .execute(pool)
     .await?;
-    
-    let mut approve_votes = 0;
-    let mut reject_votes = 0;
-    
-    for stat in stats {
-        if stat.decision == 1 {
-            approve_votes = stat.count.unwrap_or(0);
-        } else {
-            reject_votes = stat.count.unwrap_or(0);
-        }
-    }
-    
-    let total_votes = approve_votes + reject_votes;
-    
-    // Проверяем, есть ли голос от ответственного
-    let has_responsible_vote = sqlx::query!(
-        r#"
-        SELECT 1 as "exists: i32" FROM votes v 
-        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
-        WHERE v.survey_id = ? AND ur.role = 1
-        "#,
-        survey_id
+
+    update_broadcast_summary_from_messages(pool, broadcast_id).await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Текущий статус рассылки; воркер сообщений сверяется с ним перед отправкой
+pub async fn get_broadcast_status(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<Option<BroadcastStatus>, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT status FROM broadcast_summaries WHERE id = ?",
+        broadcast_id
     )
     .fetch_optional(pool)
-    .await?
-    .is_some();
-    
-    // Определяем статус
-    let status = if has_responsible_vote {
-        SurveyStatus::Completed
-    } else if total_votes >= MIN_VOTES_FOR_REVIEW {
-        SurveyStatus::ReadyForReview
-    } else {
-        SurveyStatus::InProgress
-    };
-    
-    Ok(SurveyVoteSummary {
-        survey_id,
-        total_votes,
-        approve_votes,
-        reject_votes,
-        status,
-        has_responsible_vote,
-    })
+    .await?;
+
+    Ok(record.map(|r| BroadcastStatus::from(r.status)))
 }
 
-/// Получает данные анкеты пользователя с внешнего API
-pub async fn get_user_survey_data(_pool: &SqlitePool, survey_id: i64) -> Result<Option<UserSurvey>, sqlx::Error> {
-    // Получаем URL внешнего API из переменных окружения
-    let api_base_url = std::env::var("EXTERNAL_API_URL")
-        .unwrap_or_else(|_| "http://localhost:3001".to_string());
-    
-    let survey_url = format!("{}/api/users/{}/survey", api_base_url, survey_id);
-    
-    // Делаем запрос к внешнему API
-    let client = reqwest::Client::new();
-    match client
-        .get(&survey_url)
-        .header("X-Forwarded-For", "127.0.0.1")
-        .send()
-        .await {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<UserSurvey>().await {
-                    Ok(survey_data) => Ok(Some(survey_data)),
-                    Err(e) => {
-                        eprintln!("Ошибка парсинга JSON анкеты {}: {}", survey_id, e);
-                        Ok(None)
-                    }
-                }
-            } else {
-                eprintln!("Ошибка получения анкеты {}: HTTP {}", survey_id, response.status());
-                Ok(None)
-            }
-        }
-        Err(e) => {
-            eprintln!("Ошибка запроса к внешнему API для анкеты {}: {}", survey_id, e);
-            Ok(None)
-        }
-    }
+/// Переносит отложенную рассылку или черновик; false, если рассылка уже отправлена или отменена
+pub async fn reschedule_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    send_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let scheduled_at = send_at.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET scheduled_at = ? WHERE id = ? AND status IN ('scheduled', 'draft', 'pending_approval')",
+        scheduled_at,
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
 }
 
-/// Получает список пользователей с внешнего API и сохраняет их в базе данных
-pub async fn sync_users_from_external_api(pool: &SqlitePool) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
-    let api_base_url = std::env::var("EXTERNAL_API_URL")
-        .unwrap_or_else(|_| "http://localhost:3001".to_string());
-    
-    let users_url = format!("{}/api/users/completed", api_base_url);
-    
-    // Делаем запрос к внешнему API для получения списка пользователей
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&users_url)
-        .header("X-Forwarded-For", "127.0.0.1")
-        .send()
-        .await?;
-    
-    if !response.status().is_success() {
-        return Err(format!("Ошибка получения пользователей: HTTP {}", response.status()).into());
-    }
-    
-    let users: Vec<serde_json::Value> = response.json().await?;
-    let mut synced_user_ids = Vec::new();
-    
-    for user in users {
-        if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
-            // Проверяем, есть ли уже голос за этого пользователя
-            let existing_vote = sqlx::query!(
-                "SELECT 1 as \"exists: i32\" FROM votes WHERE survey_id = ? LIMIT 1",
-                telegram_id
-            )
-            .fetch_optional(pool)
-            .await?;
-            
-            // Если голоса еще нет, создаем запись-заглушку для инициализации
-            if existing_vote.is_none() {
-                // Создаем временную запись для инициализации анкеты
-                let _ = sqlx::query!(
-                    "INSERT OR IGNORE INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, 0, -1, 'Инициализация')",
-                    telegram_id
-                )
-                .execute(pool)
-                .await;
-                
-                synced_user_ids.push(telegram_id);
-            }
-        }
+/// Выпускает черновик: его публикует планировщик в назначенное время, а без него — сразу.
+/// Крупная рассылка сначала ждет одобрения. Возвращает новый статус; None, если рассылка не черновик
+pub async fn release_draft_broadcast(pool: &SqlitePool, broadcast_id: &str) -> Result<Option<BroadcastStatus>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let threshold = broadcast_approval_threshold();
+    let record = sqlx::query!(
+        "UPDATE broadcast_summaries 
+         SET status = CASE WHEN total_users > ?1 THEN 'pending_approval' ELSE 'scheduled' END, 
+             scheduled_at = CASE WHEN total_users > ?1 THEN scheduled_at ELSE COALESCE(scheduled_at, ?2) END 
+         WHERE id = ?3 AND status = 'draft' 
+         RETURNING status",
+        threshold,
+        now,
+        broadcast_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| BroadcastStatus::from(r.status)))
+}
+
+/// Автор рассылки из события BroadcastCreated
+pub async fn get_broadcast_author(pool: &SqlitePool, broadcast_id: &str) -> Result<Option<i64>, sqlx::Error> {
+    match get_broadcast_created_event(pool, broadcast_id).await? {
+        Some(BroadcastEvent::BroadcastCreated { created_by, .. }) => Ok(created_by),
+        _ => Ok(None),
     }
-    
-    Ok(synced_user_ids)
 }
 
-/// Получает следующую анкету для голосования
-pub async fn get_next_survey(pool: &SqlitePool, voter_telegram_id: i64) -> Result<NextSurveyResponse, sqlx::Error> {
-    
-    // Получаем роль пользователя
-    let user_role = get_user_role(pool, voter_telegram_id).await?.unwrap_or(0);
-    
-    // Получаем всех пользователей с внешнего API
-    let all_users = match get_all_users_from_external_api().await {
-        Ok(users) => {
-            users
-        },
-        Err(e) => {
-            tracing::error!("Ошибка получения пользователей с внешнего API: {}", e);
-            return Ok(NextSurveyResponse {
-                survey_id: None,
-                survey_data: None,
-                votes: None,
-                user_role,
-            });
-        }
-    };
-    
-    // Получаем голоса пользователя из БД
-    let user_votes = sqlx::query!(
-        "SELECT survey_id FROM votes WHERE voter_telegram_id = ?",
-        voter_telegram_id
+/// Одобряет рассылку: ее публикует планировщик в назначенное время, а без него — сразу.
+/// None, если рассылка не ждет одобрения
+pub async fn handle_approve_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    approved_by: i64,
+) -> Result<Option<BroadcastEvent>, sqlx::Error> {
+    let now = chrono::Utc::now();
+    let scheduled_at = now.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'scheduled', scheduled_at = COALESCE(scheduled_at, ?) 
+         WHERE id = ? AND status = 'pending_approval'",
+        scheduled_at,
+        broadcast_id
     )
-    .fetch_all(pool)
+    .execute(pool)
     .await?;
-    
-    let voted_survey_ids: std::collections::HashSet<i64> = user_votes
-        .into_iter()
-        .map(|v| v.survey_id)
-        .collect();
-    
-    
-    // Проверяем, есть ли у пользователя активные записи "В обработке"
-    let existing_processing = sqlx::query!(
-        "SELECT COUNT(*) as count FROM votes WHERE voter_telegram_id = ? AND comment = 'В обработке'",
-        voter_telegram_id
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let event = BroadcastEvent::BroadcastApproved {
+        broadcast_id: broadcast_id.to_string(),
+        approved_by,
+        approved_at: now,
+    };
+    save_broadcast_event(pool, &event).await?;
+    println!("✅ Рассылка {} одобрена пользователем {}", broadcast_id, approved_by);
+
+    Ok(Some(event))
+}
+
+/// Отклоняет рассылку, ожидающую одобрения; она завершается со статусом cancelled.
+/// None, если рассылка не ждет одобрения
+pub async fn handle_reject_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    rejected_by: i64,
+    reason: Option<String>,
+) -> Result<Option<BroadcastEvent>, sqlx::Error> {
+    let now = chrono::Utc::now();
+    let completed_at = now.naive_utc();
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'cancelled', completed_at = ? 
+         WHERE id = ? AND status = 'pending_approval'",
+        completed_at,
+        broadcast_id
     )
-    .fetch_one(pool)
+    .execute(pool)
     .await?;
-    
-    if existing_processing.count > 0 {
-        let cleared = clear_user_locks(pool, voter_telegram_id).await?;
-        println!("✅ Очищено {} записей 'В обработке'", cleared);
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
     }
-    
-    let next_survey_id = if user_role == 1 {
-        // Ответственный пользователь - ищем анкеты с >= MIN_VOTES_FOR_REVIEW голосами, но без голоса ответственного
-        println!("🔍 Ищем анкету для ответственного пользователя");
-        find_survey_for_responsible_user(pool, &all_users, &voted_survey_ids).await?
-    } else {
-        // Обычный пользователь - ищем анкеты с приоритизацией (ближе к MIN_VOTES_FOR_REVIEW голосам)
-        println!("🔍 Ищем анкету для обычного пользователя");
-        find_survey_for_regular_user(pool, &all_users, &voted_survey_ids).await?
+
+    let event = BroadcastEvent::BroadcastRejected {
+        broadcast_id: broadcast_id.to_string(),
+        rejected_by,
+        reason,
+        rejected_at: now,
     };
-    
-    println!("📋 Найденная анкета: {:?}", next_survey_id);
-    
-    if let Some(survey_id) = next_survey_id {
-        // Сначала очищаем все существующие записи "В обработке" этого пользователя
-        println!("🧹 Очищаем старые записи 'В обработке' для пользователя {}", voter_telegram_id);
-        let cleared_count = clear_user_locks(pool, voter_telegram_id).await?;
-        if cleared_count > 0 {
-            println!("✅ Очищено {} старых записей 'В обработке'", cleared_count);
-        }
-        
-        // Создаем новую запись "В обработке" для блокировки анкеты
-        println!("🔒 Создаем запись 'В обработке' для анкеты {} пользователя {}", survey_id, voter_telegram_id);
-        let lock_result = sqlx::query!(
-            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
-            survey_id,
-            voter_telegram_id
-        )
-        .execute(pool)
-        .await;
-        
-        match lock_result {
-            Ok(_) => {
-                println!("✅ Запись 'В обработке' создана для анкеты {}", survey_id);
-            },
-            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
-                println!("❌ Анкета {} уже захвачена другим пользователем", survey_id);
-                // Анкета уже захвачена, возвращаем пустой ответ
-                return Ok(NextSurveyResponse {
-                    survey_id: None,
-                    survey_data: None,
-                    votes: None,
-                    user_role,
-                });
-            },
-            Err(e) => {
-                println!("❌ Ошибка создания записи 'В обработке': {}", e);
-                return Err(e);
+    save_broadcast_event(pool, &event).await?;
+    println!("🚫 Рассылка {} отклонена пользователем {}", broadcast_id, rejected_by);
+
+    Ok(Some(event))
+}
+
+/// Тестовые сообщения рассылки для сотрудников: шаблон подставляется с их данными,
+/// медиафайлы и клавиатура те же. None, если рассылка не найдена
+pub async fn get_test_broadcast_messages(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_ids: &[i64],
+) -> Result<Option<Vec<BroadcastMessage>>, sqlx::Error> {
+    let mut messages = Vec::with_capacity(telegram_ids.len());
+    for telegram_id in telegram_ids {
+        let Some(mut message) = get_broadcast_message_payload(pool, broadcast_id, *telegram_id).await? else {
+            return Ok(None);
+        };
+        message.is_test = true;
+        messages.push(message);
+    }
+    Ok(Some(messages))
+}
+
+/// id уже доставленных частей сообщения: повторная попытка продолжает с недоставленной части
+pub async fn get_broadcast_message_telegram_ids(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+) -> Result<Vec<i32>, sqlx::Error> {
+    let message_ids = sqlx::query_scalar!(
+        "SELECT telegram_message_ids FROM broadcast_messages WHERE broadcast_id = ? AND telegram_id = ?",
+        broadcast_id,
+        telegram_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    Ok(message_ids.and_then(|ids| serde_json::from_str(&ids).ok()).unwrap_or_default())
+}
+
+/// Сохраняет id отправленных в Telegram сообщений, чтобы рассылку можно было исправить или отозвать
+pub async fn set_broadcast_message_telegram_ids(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+    message_ids: &[i32],
+) -> Result<(), sqlx::Error> {
+    let message_ids = serde_json::to_string(message_ids).map_err(|e| sqlx::Error::Protocol(format!("JSON serialization error: {}", e)))?;
+    sqlx::query!(
+        "UPDATE broadcast_messages SET telegram_message_ids = ? WHERE broadcast_id = ? AND telegram_id = ?",
+        message_ids,
+        broadcast_id,
+        telegram_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Ставит доставленные сообщения рассылки на правку или удаление. Для правки текст
+/// подставляется заново для каждого получателя. None, если рассылка не найдена
+pub async fn handle_revise_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    action: BroadcastMessageAction,
+    new_text: Option<&str>,
+) -> Result<Option<Vec<BroadcastMessage>>, sqlx::Error> {
+    if get_broadcast_created_event(pool, broadcast_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    // Удаленные сообщения больше не правим
+    let delivered = sqlx::query!(
+        "SELECT telegram_id, telegram_message_ids as \"telegram_message_ids!: String\" FROM broadcast_messages 
+         WHERE broadcast_id = ? AND status = 'sent' AND telegram_message_ids IS NOT NULL 
+           AND (edit_status IS NULL OR edit_status != 'deleted')",
+        broadcast_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut messages = Vec::with_capacity(delivered.len());
+    for row in delivered {
+        let Some(mut message) = get_broadcast_message_template(pool, broadcast_id, row.telegram_id).await? else {
+            continue;
+        };
+        if let Some(text) = new_text {
+            // У рассылки с медиафайлами текст — подпись к первому файлу
+            match message.media_group.as_mut().and_then(|group| group.media.first_mut()) {
+                Some(item) => item.caption = Some(text.to_string()),
+                None => message.message = text.to_string(),
             }
         }
-        
-        println!("📋 Получаем данные анкеты {} с внешнего API...", survey_id);
-        // Получаем анкету с внешнего API
-        let survey_data = match get_user_survey_from_external_api(survey_id).await {
-            Ok(data) => {
-                println!("✅ Получены данные анкеты с внешнего API");
-                data
-            },
-            Err(e) => {
-                println!("❌ Ошибка получения анкеты с внешнего API: {}", e);
-                tracing::error!("Ошибка получения анкеты с внешнего API: {}", e);
-                None
-            }
-        };
-        
-        println!("📊 Получаем голоса для анкеты {}...", survey_id);
-        // Получаем голоса
-        let votes = get_votes_by_survey(pool, survey_id).await?;
-        println!("✅ Получено {} голосов", votes.len());
-        
-        Ok(NextSurveyResponse {
-            survey_id: Some(survey_id),
-            survey_data,
-            votes: Some(votes),
-            user_role,
-        })
-    } else {
-        println!("❌ Анкета не найдена, возвращаем null");
-        Ok(NextSurveyResponse {
-            survey_id: None,
-            survey_data: None,
-            votes: None,
-            user_role,
-        })
+        message.action = action;
+        message.telegram_message_ids = serde_json::from_str(&row.telegram_message_ids).unwrap_or_default();
+        if action == BroadcastMessageAction::Edit {
+            message = render_broadcast_message(pool, message).await?;
+        }
+
+        update_broadcast_message_edit_status(pool, broadcast_id, row.telegram_id, MessageEditStatus::Pending, None).await?;
+        messages.push(message);
+    }
+
+    println!("✏️ Рассылка {}: {} сообщений поставлено на {:?}", broadcast_id, messages.len(), action);
+    Ok(Some(messages))
+}
+
+/// Записывает результат правки или удаления доставленного сообщения
+pub async fn update_broadcast_message_edit_status(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    telegram_id: i64,
+    status: MessageEditStatus,
+    error: Option<String>,
+) -> Result<(), sqlx::Error> {
+    let status = status.to_string();
+    sqlx::query!(
+        "UPDATE broadcast_messages SET edit_status = ?, edit_error = ? WHERE broadcast_id = ? AND telegram_id = ?",
+        status,
+        error,
+        broadcast_id,
+        telegram_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Отложенные рассылки, время отправки которых наступило
+pub async fn get_due_scheduled_broadcasts(
+    pool: &SqlitePool,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let now = now.naive_utc();
+    let records = sqlx::query!(
+        "SELECT id FROM broadcast_summaries 
+         WHERE status = 'scheduled' AND scheduled_at <= ? 
+         ORDER BY scheduled_at ASC",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records.into_iter().filter_map(|r| r.id).collect())
+}
+
+/// Переводит отложенную рассылку в pending перед публикацией события.
+/// Условие на статус не дает отправить рассылку, отмененную в тот же момент
+pub async fn claim_scheduled_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'pending' WHERE id = ? AND status = 'scheduled'",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Возвращает рассылку в очередь планировщика, если событие не удалось опубликовать
+pub async fn release_scheduled_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'scheduled' WHERE id = ? AND status = 'pending'",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Сохраненное событие BroadcastCreated рассылки
+pub async fn get_broadcast_created_event(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<Option<BroadcastEvent>, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT event_data FROM broadcast_events 
+         WHERE broadcast_id = ? AND event_type IN ('BroadcastCreated', 'BroadcastCreatedSignUp') 
+         ORDER BY created_at ASC 
+         LIMIT 1",
+        broadcast_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    record
+        .map(|r| serde_json::from_str(&r.event_data))
+        .transpose()
+        .map_err(|e| sqlx::Error::Protocol(format!("JSON deserialization error: {}", e)))
+}
+
+// Query Handlers
+
+pub async fn handle_get_broadcast_status(
+    pool: &SqlitePool,
+    query: GetBroadcastStatusQuery,
+) -> Result<Option<BroadcastStatusResponse>, Box<dyn std::error::Error>> {
+    let summary = get_broadcast_summary(pool, &query.broadcast_id).await?;
+    
+    match summary {
+        Some(broadcast) => {
+            let messages = get_broadcast_messages(pool, &query.broadcast_id, None, Some(DEFAULT_QUERY_LIMIT), Some(DEFAULT_QUERY_OFFSET)).await?;
+            
+            Ok(Some(BroadcastStatusResponse {
+                broadcast,
+                messages,
+            }))
+        }
+        None => Ok(None),
     }
 }
 
-/// Находит анкету для обычного пользователя с приоритизацией
-async fn find_survey_for_regular_user(
+pub async fn handle_get_broadcast_messages(
+    pool: &SqlitePool,
+    query: GetBroadcastMessagesQuery,
+) -> Result<Vec<BroadcastMessageRecord>, Box<dyn std::error::Error>> {
+    let messages = get_broadcast_messages(
+        pool,
+        &query.broadcast_id,
+        query.status,
+        query.limit,
+        query.offset,
+    ).await?;
+    
+    Ok(messages)
+}
+
+pub async fn delete_broadcast(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Удаляем сообщения рассылки
+    sqlx::query!(
+        "DELETE FROM broadcast_messages WHERE broadcast_id = ?",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    // Удаляем события рассылки
+    sqlx::query!(
+        "DELETE FROM broadcast_events WHERE broadcast_id = ?",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    // Удаляем сводку рассылки
+    sqlx::query!(
+        "DELETE FROM broadcast_summaries WHERE id = ?",
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Voting System Functions
+
+/// Получает роль пользователя
+pub async fn get_user_role(pool: &SqlitePool, telegram_id: i64) -> Result<Option<i32>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT role FROM user_roles WHERE telegram_id = ?",
+        telegram_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(result.map(|r| r.role as i32))
+}
+
+/// Создает или обновляет роль пользователя
+pub async fn set_user_role(pool: &SqlitePool, telegram_id: i64, role: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT OR REPLACE INTO user_roles (telegram_id, role, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+        telegram_id,
+        role
+    )
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+
+/// Получает следующую анкету для обычного пользователя (атомарный захват)
+pub async fn get_next_survey_for_regular_user(pool: &SqlitePool, voter_telegram_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    // Сначала находим подходящую анкету
+    let survey_id = sqlx::query!(
+        r#"
+        SELECT s.survey_id
+        FROM (
+            SELECT DISTINCT survey_id, created_at FROM votes 
+            ORDER BY created_at ASC
+        ) s
+        WHERE s.survey_id NOT IN (
+            SELECT survey_id FROM votes WHERE voter_telegram_id = ?
+        )
+        AND (
+            SELECT COUNT(*) FROM votes v 
+            WHERE v.survey_id = s.survey_id
+        ) < ?
+        AND NOT EXISTS (
+            SELECT 1 FROM votes v 
+            JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+            WHERE v.survey_id = s.survey_id AND ur.role = 1
+        )
+        ORDER BY s.created_at ASC
+        LIMIT 1
+        "#,
+        voter_telegram_id,
+        MIN_VOTES_FOR_REVIEW
+    )
+    .fetch_optional(pool)
+    .await?;
+    
+    if let Some(survey) = survey_id {
+        // Атомарно захватываем анкету, создавая временный голос "В обработке"
+        let result = sqlx::query!(
+            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
+            survey.survey_id,
+            voter_telegram_id
+        )
+        .execute(pool)
+        .await;
+        
+        match result {
+            Ok(_) => Ok(Some(survey.survey_id)),
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                // Другой пользователь уже захватил эту анкету
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Получает следующую анкету для ответственного пользователя (атомарный захват)
+pub async fn get_next_survey_for_responsible_user(pool: &SqlitePool, voter_telegram_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    // Сначала находим анкету, готовую для проверки
+    let survey_id = sqlx::query!(
+        r#"
+        SELECT s.survey_id
+        FROM (
+            SELECT DISTINCT survey_id, created_at FROM votes 
+            ORDER BY created_at ASC
+        ) s
+        WHERE (
+            SELECT COUNT(*) FROM votes v 
+            WHERE v.survey_id = s.survey_id
+        ) >= ?
+        AND NOT EXISTS (
+            SELECT 1 FROM votes v 
+            JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+            WHERE v.survey_id = s.survey_id AND ur.role = 1
+        )
+        ORDER BY s.created_at ASC
+        LIMIT 1
+        "#,
+        MIN_VOTES_FOR_REVIEW
+    )
+    .fetch_optional(pool)
+    .await?;
+    
+    if let Some(survey) = survey_id {
+        // Атомарно захватываем анкету
+        let result = sqlx::query!(
+            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
+            survey.survey_id,
+            voter_telegram_id
+        )
+        .execute(pool)
+        .await;
+        
+        match result {
+            Ok(_) => Ok(Some(survey.survey_id)),
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                // Другой ответственный уже захватил эту анкету
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Создает голос (или обновляет запись "В обработке")
+pub async fn create_vote(pool: &SqlitePool, request: CreateVoteRequest, voter_telegram_id: i64) -> Result<Vote, sqlx::Error> {
+    // Проверяем, есть ли уже запись "В обработке" для этого пользователя и анкеты
+    let existing_processing_vote = sqlx::query_as::<_, (i64,)>(
+        "SELECT id FROM votes WHERE survey_id = ? AND voter_telegram_id = ? AND comment = 'В обработке'"
+    )
+    .bind(request.survey_id)
+    .bind(voter_telegram_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    let vote_id = if let Some(existing) = existing_processing_vote {
+        // Обновляем существующую запись "В обработке"
+        sqlx::query!(
+            "UPDATE votes SET decision = ?, comment = ? WHERE id = ?",
+            request.decision,
+            request.comment,
+            existing.0
+        )
+        .execute(pool)
+        .await?;
+        
+        existing.0
+    } else {
+        // Создаем новую запись (если по какой-то причине записи "В обработке" нет)
+        let result = sqlx::query!(
+            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, ?, ?)",
+            request.survey_id,
+            voter_telegram_id,
+            request.decision,
+            request.comment
+        )
+        .execute(pool)
+        .await?;
+        
+        result.last_insert_rowid()
+    };
+    
+    // Получаем обновленную запись
+    let vote = sqlx::query_as::<_, Vote>(
+        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE id = ?"
+    )
+    .bind(vote_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(vote)
+}
+
+/// Получает голоса для анкеты
+pub async fn get_votes_by_survey(pool: &SqlitePool, survey_id: i64) -> Result<Vec<Vote>, sqlx::Error> {
+    let votes = sqlx::query_as::<_, Vote>(
+        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE survey_id = ? ORDER BY created_at ASC"
+    )
+    .bind(survey_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(votes)
+}
+
+/// Получает статистику голосов для анкеты
+pub async fn get_survey_vote_summary(pool: &SqlitePool, survey_id: i64) -> Result<SurveyVoteSummary, sqlx::Error> {
+    // Получаем общую статистику голосов (исключая служебные записи)
+    let stats = sqlx::query!(
+        r#"
+        SELECT 
+            decision,
+            COUNT(*) as "count: i64"
+        FROM votes 
+        WHERE survey_id = ? 
+        AND (comment IS NULL OR (comment != 'В обработке' AND comment != 'Инициализация'))
+        GROUP BY decision
+        "#,
+        survey_id
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    let mut approve_votes = 0;
+    let mut reject_votes = 0;
+    
+    for stat in stats {
+        if stat.decision == 1 {
+            approve_votes = stat.count.unwrap_or(0);
+        } else {
+            reject_votes = stat.count.unwrap_or(0);
+        }
+    }
+    
+    let total_votes = approve_votes + reject_votes;
+    
+    // Проверяем, есть ли голос от ответственного
+    let has_responsible_vote = sqlx::query!(
+        r#"
+        SELECT 1 as "exists: i32" FROM votes v 
+        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+        WHERE v.survey_id = ? AND ur.role = 1
+        "#,
+        survey_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some();
+    
+    // Определяем статус
+    let status = if has_responsible_vote {
+        SurveyStatus::Completed
+    } else if total_votes >= MIN_VOTES_FOR_REVIEW {
+        SurveyStatus::ReadyForReview
+    } else {
+        SurveyStatus::InProgress
+    };
+    
+    Ok(SurveyVoteSummary {
+        survey_id,
+        total_votes,
+        approve_votes,
+        reject_votes,
+        status,
+        has_responsible_vote,
+    })
+}
+
+/// Получает данные анкеты пользователя с внешнего API
+pub async fn get_user_survey_data(_pool: &SqlitePool, survey_id: i64) -> Result<Option<UserSurvey>, sqlx::Error> {
+    // Получаем URL внешнего API из переменных окружения
+    let api_base_url = std::env::var("EXTERNAL_API_URL")
+        .unwrap_or_else(|_| "http://localhost:3001".to_string());
+    
+    let survey_url = format!("{}/api/users/{}/survey", api_base_url, survey_id);
+    
+    // Делаем запрос к внешнему API
+    let client = reqwest::Client::new();
+    match client
+        .get(&survey_url)
+        .header("X-Forwarded-For", "127.0.0.1")
+        .send()
+        .await {
+        Ok(response) => {
+            if response.status().is_success() {
+                match response.json::<UserSurvey>().await {
+                    Ok(survey_data) => Ok(Some(survey_data)),
+                    Err(e) => {
+                        eprintln!("Ошибка парсинга JSON анкеты {}: {}", survey_id, e);
+                        Ok(None)
+                    }
+                }
+            } else {
+                eprintln!("Ошибка получения анкеты {}: HTTP {}", survey_id, response.status());
+                Ok(None)
+            }
+        }
+        Err(e) => {
+            eprintln!("Ошибка запроса к внешнему API для анкеты {}: {}", survey_id, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Получает список пользователей с внешнего API и сохраняет их в базе данных
+pub async fn sync_users_from_external_api(pool: &SqlitePool) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let api_base_url = std::env::var("EXTERNAL_API_URL")
+        .unwrap_or_else(|_| "http://localhost:3001".to_string());
+    
+    let users_url = format!("{}/api/users/completed", api_base_url);
+    
+    // Делаем запрос к внешнему API для получения списка пользователей
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&users_url)
+        .header("X-Forwarded-For", "127.0.0.1")
+        .send()
+        .await?;
+    
+    if !response.status().is_success() {
+        return Err(format!("Ошибка получения пользователей: HTTP {}", response.status()).into());
+    }
+    
+    let users: Vec<serde_json::Value> = response.json().await?;
+    let mut synced_user_ids = Vec::new();
+    
+    for user in users {
+        if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
+            // Проверяем, есть ли уже голос за этого пользователя
+            let existing_vote = sqlx::query!(
+                "SELECT 1 as \"exists: i32\" FROM votes WHERE survey_id = ? LIMIT 1",
+                telegram_id
+            )
+            .fetch_optional(pool)
+            .await?;
+            
+            // Если голоса еще нет, создаем запись-заглушку для инициализации
+            if existing_vote.is_none() {
+                // Создаем временную запись для инициализации анкеты
+                let _ = sqlx::query!(
+                    "INSERT OR IGNORE INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, 0, -1, 'Инициализация')",
+                    telegram_id
+                )
+                .execute(pool)
+                .await;
+                
+                synced_user_ids.push(telegram_id);
+            }
+        }
+    }
+    
+    Ok(synced_user_ids)
+}
+
+/// Получает следующую анкету для голосования
+pub async fn get_next_survey(pool: &SqlitePool, voter_telegram_id: i64) -> Result<NextSurveyResponse, sqlx::Error> {
+    
+    // Получаем роль пользователя
+    let user_role = get_user_role(pool, voter_telegram_id).await?.unwrap_or(0);
+    
+    // Получаем всех пользователей с внешнего API
+    let all_users = match get_all_users_from_external_api().await {
+        Ok(users) => {
+            users
+        },
+        Err(e) => {
+            tracing::error!("Ошибка получения пользователей с внешнего API: {}", e);
+            return Ok(NextSurveyResponse {
+                survey_id: None,
+                survey_data: None,
+                votes: None,
+                user_role,
+            });
+        }
+    };
+    
+    // Получаем голоса пользователя из БД
+    let user_votes = sqlx::query!(
+        "SELECT survey_id FROM votes WHERE voter_telegram_id = ?",
+        voter_telegram_id
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    let voted_survey_ids: std::collections::HashSet<i64> = user_votes
+        .into_iter()
+        .map(|v| v.survey_id)
+        .collect();
+    
+    
+    // Проверяем, есть ли у пользователя активные записи "В обработке"
+    let existing_processing = sqlx::query!(
+        "SELECT COUNT(*) as count FROM votes WHERE voter_telegram_id = ? AND comment = 'В обработке'",
+        voter_telegram_id
+    )
+    .fetch_one(pool)
+    .await?;
+    
+    if existing_processing.count > 0 {
+        let cleared = clear_user_locks(pool, voter_telegram_id).await?;
+        println!("✅ Очищено {} записей 'В обработке'", cleared);
+    }
+    
+    let next_survey_id = if user_role == 1 {
+        // Ответственный пользователь - ищем анкеты с >= MIN_VOTES_FOR_REVIEW голосами, но без голоса ответственного
+        println!("🔍 Ищем анкету для ответственного пользователя");
+        find_survey_for_responsible_user(pool, &all_users, &voted_survey_ids).await?
+    } else {
+        // Обычный пользователь - ищем анкеты с приоритизацией (ближе к MIN_VOTES_FOR_REVIEW голосам)
+        println!("🔍 Ищем анкету для обычного пользователя");
+        find_survey_for_regular_user(pool, &all_users, &voted_survey_ids).await?
+    };
+    
+    println!("📋 Найденная анкета: {:?}", next_survey_id);
+    
+    if let Some(survey_id) = next_survey_id {
+        // Сначала очищаем все существующие записи "В обработке" этого пользователя
+        println!("🧹 Очищаем старые записи 'В обработке' для пользователя {}", voter_telegram_id);
+        let cleared_count = clear_user_locks(pool, voter_telegram_id).await?;
+        if cleared_count > 0 {
+            println!("✅ Очищено {} старых записей 'В обработке'", cleared_count);
+        }
+        
+        // Создаем новую запись "В обработке" для блокировки анкеты
+        println!("🔒 Создаем запись 'В обработке' для анкеты {} пользователя {}", survey_id, voter_telegram_id);
+        let lock_result = sqlx::query!(
+            "INSERT INTO votes (survey_id, voter_telegram_id, decision, comment) VALUES (?, ?, 0, 'В обработке')",
+            survey_id,
+            voter_telegram_id
+        )
+        .execute(pool)
+        .await;
+        
+        match lock_result {
+            Ok(_) => {
+                println!("✅ Запись 'В обработке' создана для анкеты {}", survey_id);
+            },
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                println!("❌ Анкета {} уже захвачена другим пользователем", survey_id);
+                // Анкета уже захвачена, возвращаем пустой ответ
+                return Ok(NextSurveyResponse {
+                    survey_id: None,
+                    survey_data: None,
+                    votes: None,
+                    user_role,
+                });
+            },
+            Err(e) => {
+                println!("❌ Ошибка создания записи 'В обработке': {}", e);
+                return Err(e);
+            }
+        }
+        
+        println!("📋 Получаем данные анкеты {} с внешнего API...", survey_id);
+        // Получаем анкету с внешнего API
+        let survey_data = match get_user_survey_from_external_api(survey_id).await {
+            Ok(data) => {
+                println!("✅ Получены данные анкеты с внешнего API");
+                data
+            },
+            Err(e) => {
+                println!("❌ Ошибка получения анкеты с внешнего API: {}", e);
+                tracing::error!("Ошибка получения анкеты с внешнего API: {}", e);
+                None
+            }
+        };
+        
+        println!("📊 Получаем голоса для анкеты {}...", survey_id);
+        // Получаем голоса
+        let votes = get_votes_by_survey(pool, survey_id).await?;
+        println!("✅ Получено {} голосов", votes.len());
+        
+        Ok(NextSurveyResponse {
+            survey_id: Some(survey_id),
+            survey_data,
+            votes: Some(votes),
+            user_role,
+        })
+    } else {
+        println!("❌ Анкета не найдена, возвращаем null");
+        Ok(NextSurveyResponse {
+            survey_id: None,
+            survey_data: None,
+            votes: None,
+            user_role,
+        })
+    }
+}
+
+/// Находит анкету для обычного пользователя с приоритизацией
+async fn find_survey_for_regular_user(
+    pool: &SqlitePool,
+    all_users: &[serde_json::Value],
+    voted_survey_ids: &std::collections::HashSet<i64>,
+) -> Result<Option<i64>, sqlx::Error> {
+    println!("🔍 find_survey_for_regular_user: {} пользователей, {} уже проголосовано", 
+             all_users.len(), voted_survey_ids.len());
+    
+    // Извлекаем telegram_id из всех пользователей
+    let user_telegram_ids: Vec<i64> = all_users
+        .iter()
+        .filter_map(|user| user.get("telegram_id").and_then(|v| v.as_i64()))
+        .filter(|&id| !voted_survey_ids.contains(&id))
+        .collect();
+    
+    if user_telegram_ids.is_empty() {
+        println!("❌ Нет доступных пользователей для голосования");
+        return Ok(None);
+    }
+    
+    // Получаем количество голосов для всех кандидатов одним запросом
+    // Считаем только реальные голоса (не "В обработке") + проверяем есть ли голоса "В обработке"
+    let placeholders = user_telegram_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let query = format!(
+        "SELECT survey_id, 
+         COUNT(*) as total_count,
+         COUNT(CASE WHEN comment IS NULL OR (comment != 'В обработке' AND comment != 'Инициализация') THEN 1 END) as real_count,
+         COUNT(CASE WHEN comment = 'В обработке' THEN 1 END) as processing_count
+         FROM votes WHERE survey_id IN ({}) GROUP BY survey_id",
+        placeholders
+    );
+    
+    let mut query_builder = sqlx::query_as::<_, (i64, i64, i64, i64)>(&query);
+    for telegram_id in &user_telegram_ids {
+        query_builder = query_builder.bind(telegram_id);
+    }
+    
+    let vote_data: std::collections::HashMap<i64, (i64, i64, i64)> = query_builder
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(survey_id, total, real, processing)| (survey_id, (total, real, processing)))
+        .collect();
+    
+    // Создаем список кандидатов с количеством голосов
+    let mut candidates = Vec::new();
+    
+    for telegram_id in user_telegram_ids {
+        let (_total_count, real_count, processing_count) = vote_data.get(&telegram_id).copied().unwrap_or((0, 0, 0));
+        
+        // Анкета доступна обычным пользователям если:
+        // 1. Реальных голосов меньше MIN_VOTES_FOR_REVIEW
+        // 2. И нет активных голосов "В обработке" (чтобы избежать конфликтов)
+        if real_count < MIN_VOTES_FOR_REVIEW && processing_count == 0 {
+            candidates.push((telegram_id, real_count));
+        }
+    }
+    
+    // Сортируем по приоритету: ближе к MIN_VOTES_FOR_REVIEW голосам = выше приоритет
+    candidates.sort_by(|a, b| {
+        let distance_a = (MIN_VOTES_FOR_REVIEW - a.1).abs();
+        let distance_b = (MIN_VOTES_FOR_REVIEW - b.1).abs();
+        distance_a.cmp(&distance_b)
+    });
+    
+    // Возвращаем пользователя с наивысшим приоритетом
+    let result = candidates.first().map(|(telegram_id, _)| *telegram_id);
+    println!("🎯 find_survey_for_regular_user: найдено {} кандидатов, выбран: {:?}", 
+             candidates.len(), result);
+    Ok(result)
+}
+
+/// Находит анкету для ответственного пользователя
+async fn find_survey_for_responsible_user(
+    pool: &SqlitePool,
+    all_users: &[serde_json::Value],
+    _voted_survey_ids: &std::collections::HashSet<i64>,
+) -> Result<Option<i64>, sqlx::Error> {
+    println!("🔍 find_survey_for_responsible_user: проверяем {} пользователей", all_users.len());
+    
+    // Проверим, сколько анкет уже обработано ответственными
+    let responsible_processed = sqlx::query!(
+        "SELECT COUNT(DISTINCT survey_id) as count FROM votes v 
+         JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id 
+         WHERE ur.role = 1"
+    )
+    .fetch_one(pool)
+    .await?;
+    
+    println!("📊 Ответственные пользователи уже обработали {} анкет", responsible_processed.count);
+    
+    // Покажем, кто является ответственным
+    let responsible_users = sqlx::query!(
+        "SELECT telegram_id FROM user_roles WHERE role = 1"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    println!("👥 Ответственные пользователи: {:?}", 
+             responsible_users.iter().map(|r| r.telegram_id).collect::<Vec<_>>());
+    
+    // Извлекаем telegram_id из всех пользователей
+    let user_telegram_ids: Vec<i64> = all_users
+        .iter()
+        .filter_map(|user| user.get("telegram_id").and_then(|v| v.as_i64()))
+        .collect();
+    
+    if user_telegram_ids.is_empty() {
+        println!("❌ Нет доступных пользователей");
+        return Ok(None);
+    }
+    
+    // Получаем количество голосов для всех кандидатов одним запросом
+    let placeholders = user_telegram_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let vote_query = format!(
+        "SELECT survey_id, COUNT(*) as count FROM votes WHERE survey_id IN ({}) GROUP BY survey_id",
+        placeholders
+    );
+    
+    let mut vote_query_builder = sqlx::query_as::<_, (i64, i64)>(&vote_query);
+    for telegram_id in &user_telegram_ids {
+        vote_query_builder = vote_query_builder.bind(telegram_id);
+    }
+    
+    let vote_counts: std::collections::HashMap<i64, i64> = vote_query_builder
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+    
+    // Получаем голоса ответственных для всех кандидатов одним запросом
+    let responsible_vote_query = format!(
+        "SELECT v.survey_id FROM votes v 
+         JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id 
+         WHERE v.survey_id IN ({}) AND ur.role = 1",
+        placeholders
+    );
+    
+    let mut responsible_query_builder = sqlx::query_as::<_, (i64,)>(&responsible_vote_query);
+    for telegram_id in &user_telegram_ids {
+        responsible_query_builder = responsible_query_builder.bind(telegram_id);
+    }
+    
+    let responsible_votes: std::collections::HashSet<i64> = responsible_query_builder
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(survey_id,)| survey_id)
+        .collect();
+    
+    // Ищем первую подходящую анкету
+    for telegram_id in user_telegram_ids {
+        let vote_count = vote_counts.get(&telegram_id).copied().unwrap_or(0);
+        
+        println!("🔍 Анкета {}: {} голосов (требуется >= {})", telegram_id, vote_count, MIN_VOTES_FOR_REVIEW);
+        
+        if vote_count >= MIN_VOTES_FOR_REVIEW {
+            let has_responsible_vote = responsible_votes.contains(&telegram_id);
+            println!("🔍 Анкета {}: есть голос от ответственного: {}", telegram_id, has_responsible_vote);
+            
+            if !has_responsible_vote {
+                println!("✅ find_survey_for_responsible_user: найдена анкета {} с {} голосами", 
+                         telegram_id, vote_count);
+                return Ok(Some(telegram_id));
+            } else {
+                println!("❌ Анкета {} уже имеет голос от ответственного", telegram_id);
+            }
+        } else {
+            println!("❌ Анкета {} имеет недостаточно голосов: {} < {}", telegram_id, vote_count, MIN_VOTES_FOR_REVIEW);
+        }
+    }
+    println!("❌ find_survey_for_responsible_user: не найдено подходящих анкет");
+    Ok(None)
+}
+
+/// Обрабатывает голосование
+pub async fn handle_vote(pool: &SqlitePool, request: CreateVoteRequest, voter_telegram_id: i64) -> Result<VoteResponse, sqlx::Error> {
+    // Создаем голос
+    let _vote = create_vote(pool, request.clone(), voter_telegram_id).await?;
+
+    // Голос ответственного — окончательное решение, о нем пишем кандидату
+    if get_user_role(pool, voter_telegram_id).await? == Some(1) {
+        if let Err(e) = notify_decision(pool, request.survey_id, request.decision).await {
+            println!("❌ Не удалось поставить уведомление о решении для {}: {}", request.survey_id, e);
+        }
+    }
+    
+    // Получаем следующую анкету
+    let next_survey = get_next_survey(pool, voter_telegram_id).await?;
+    
+    Ok(VoteResponse {
+        success: true,
+        message: "Голос успешно сохранен".to_string(),
+        next_survey: Some(next_survey),
+    })
+}
+
+// Authentication Functions
+
+/// Проверяет авторизацию через Telegram и получает профиль пользователя
+pub async fn authenticate_user(telegram_auth: TelegramAuth) -> Result<AuthResponse, String> {
+    // TODO: Добавить проверку подписи Telegram (hash verification)
+    // Пока что просто проверяем, что данные пришли
+    
+    let api_base_url = std::env::var("USER_API_URL")
+        .unwrap_or_else(|_| "https://api.ingroupsts.ru".to_string());
+        
+    
+    let user_url = format!("{}/user/{}", api_base_url, telegram_auth.id);
+    
+    println!("🔍 Попытка авторизации пользователя ID: {}", telegram_auth.id);
+    println!("🌐 URL запроса: {}", user_url);
+    
+    // Делаем запрос к внешнему API для получения профиля пользователя
+    match http_client()
+        .get(&user_url)
+        .header("X-Forwarded-For", "127.0.0.1")
+        .send()
+        .await {
+        Ok(response) => {
+            if response.status().is_success() {
+                match response.json::<ExternalUserResponse>().await {
+                    Ok(user_data) => {
+                        // Пользователь найден в системе
+                        println!("✅ Пользователь {} успешно авторизован", telegram_auth.id);
+                        Ok(AuthResponse {
+                            success: true,
+                            message: "Авторизация успешна".to_string(),
+                            user_profile: Some(user_data.user_profile),
+                            user_role: None, // Будет получена из БД
+                        })
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Ошибка парсинга профиля пользователя {}: {}", telegram_auth.id, e);
+                        Ok(AuthResponse {
+                            success: false,
+                            message: "Ошибка получения данных пользователя".to_string(),
+                            user_profile: None,
+                            user_role: None,
+                        })
+                    }
+                }
+            } else {
+                // Пользователь не найден в системе
+                println!("❌ Пользователь {} не найден в системе (статус: {})", telegram_auth.id, response.status());
+                Ok(AuthResponse {
+                    success: false,
+                    message: "Пользователь не найден в системе".to_string(),
+                    user_profile: None,
+                    user_role: None,
+                })
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ Ошибка запроса к внешнему API для пользователя {}: {}", telegram_auth.id, e);
+            Ok(AuthResponse {
+                success: false,
+                message: "Ошибка подключения к серверу".to_string(),
+                user_profile: None,
+                user_role: None,
+            })
+        }
+    }
+}
+
+/// Получает роль пользователя из базы данных
+pub async fn get_user_role_from_db(pool: &SqlitePool, telegram_id: i64) -> Result<Option<i32>, sqlx::Error> {
+    get_user_role(pool, telegram_id).await
+}
+
+/// Обновляет голос
+pub async fn update_vote(pool: &SqlitePool, vote_id: i64, vote: UpdateVoteRequest) -> Result<Vote, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE votes SET decision = ?, comment = ? WHERE id = ?",
+        vote.decision,
+        vote.comment,
+        vote_id
+    )
+    .execute(pool)
+    .await?;
+    
+    let updated_vote = sqlx::query_as::<_, Vote>(
+        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE id = ?"
+    )
+    .bind(vote_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(updated_vote)
+}
+
+/// Удаляет голос
+pub async fn delete_vote(pool: &SqlitePool, vote_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM votes WHERE id = ?", vote_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+/// Очищает блокировки пользователя (удаляет голоса со статусом "В обработке")
+pub async fn clear_user_locks(pool: &SqlitePool, telegram_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM votes WHERE voter_telegram_id = ? AND comment = ?",
+        telegram_id,
+        "В обработке"
+    )
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+/// Получает пользователей с отобранными анкетами (одобренными ответственными)
+pub async fn get_selected_users(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    // Получаем всех пользователей с внешнего API
+    let all_users = get_all_users_from_external_api().await
+        .map_err(|e| sqlx::Error::Protocol(format!("External API error: {}", e)))?;
+    
+    // Получаем анкеты с положительными голосами от ответственных
+    let selected_surveys = sqlx::query!(
+        r#"
+        SELECT DISTINCT v.survey_id
+        FROM votes v
+        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+        WHERE ur.role = 1 AND v.decision = 1
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    let selected_survey_ids: std::collections::HashSet<i64> = selected_surveys
+        .into_iter()
+        .map(|s| s.survey_id)
+        .collect();
+    
+    // Фильтруем пользователей, оставляя только тех, чьи анкеты одобрены
+    let selected_users: Vec<serde_json::Value> = all_users
+        .into_iter()
+        .filter(|user| {
+            if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
+                selected_survey_ids.contains(&telegram_id)
+            } else {
+                false
+            }
+        })
+        .collect();
+    
+    Ok(selected_users)
+}
+
+/// Получает пользователей, которые не выполнили запись после рассылки о записи
+pub async fn get_no_response_users(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    // Получаем всех пользователей с внешнего API
+    let all_users = get_all_users_from_external_api().await
+        .map_err(|e| sqlx::Error::Protocol(format!("External API error: {}", e)))?;
+    
+    let signup_telegram_ids = get_signup_recipient_ids(pool).await?;
+    let booked_telegram_ids = get_booked_telegram_ids(pool).await?;
+    
+    // Фильтруем пользователей: получили рассылку о записи, но не записались
+    let no_response_users: Vec<serde_json::Value> = all_users
+        .into_iter()
+        .filter(|user| {
+            if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
+                // Получил рассылку о записи И не записался
+                signup_telegram_ids.contains(&telegram_id) && !booked_telegram_ids.contains(&telegram_id)
+            } else {
+                false
+            }
+        })
+        .collect();
+    
+    Ok(no_response_users)
+}
+
+/// Получает пользователей, которым была отправлена рассылка о записи (signup).
+/// Включаем как успешно отправленные, так и неудачные сообщения
+async fn get_signup_recipient_ids(pool: &SqlitePool) -> Result<std::collections::HashSet<i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT telegram_id
+        FROM broadcast_messages bm
+        JOIN broadcast_summaries bs ON bm.broadcast_id = bs.id
+        WHERE bm.message_type = 'signup' 
+        AND bs.status IN ('completed', 'in_progress')
+        AND bm.status IN ('sent', 'failed')
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.telegram_id).collect())
+}
+
+/// Получает пользователей, которые уже записались на слоты
+async fn get_booked_telegram_ids(pool: &SqlitePool) -> Result<std::collections::HashSet<i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT telegram_id
+        FROM records
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.telegram_id).collect())
+}
+
+/// Получает детальную информацию о пользователях без записи с информацией о статусе сообщений
+pub async fn get_no_response_users_detailed(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    // Получаем пользователей, которые получили рассылку о записи, но не записались
+    let no_response_users = sqlx::query!(
+        r#"
+        SELECT DISTINCT 
+            bm.telegram_id,
+            bm.status as message_status,
+            bm.error,
+            bm.sent_at,
+            bm.retry_count,
+            bs.created_at as broadcast_created_at
+        FROM broadcast_messages bm
+        JOIN broadcast_summaries bs ON bm.broadcast_id = bs.id
+        WHERE bm.message_type = 'signup' 
+        AND bs.status IN ('completed', 'in_progress', 'pending')
+        AND bm.status IN ('sent', 'failed')
+        AND bm.telegram_id NOT IN (
+            SELECT DISTINCT telegram_id 
+            FROM records
+        )
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    println!("🔍 DEBUG: Found {} no-response users", no_response_users.len());
+    
+    // Преобразуем в JSON формат
+    let result: Vec<serde_json::Value> = no_response_users
+        .into_iter()
+        .map(|user| {
+            serde_json::json!({
+                "telegram_id": user.telegram_id,
+                "message_info": {
+                    "status": user.message_status,
+                    "error": user.error,
+                    "sent_at": user.sent_at,
+                    "retry_count": user.retry_count,
+                    "broadcast_created_at": user.broadcast_created_at
+                }
+            })
+        })
+        .collect();
+    
+    Ok(result)
+}
+
+/// Обновляет статус сообщения рассылки
+pub async fn update_broadcast_message_status_new(
+    pool: &SqlitePool,
+    telegram_id: i64,
+    message_type: &str,
+    new_status: &str
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE broadcast_messages 
+        SET status = $1
+        WHERE telegram_id = $2 AND message_type = $3
+        "#,
+        new_status,
+        telegram_id,
+        message_type
+    )
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Dialogue Storage Functions
+
+/// Получает сохраненное состояние диалога (JSON)
+pub async fn get_dialogue_state(pool: &SqlitePool, chat_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT state FROM dialogues WHERE chat_id = ?",
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.map(|r| r.state))
+}
+
+/// Создает или обновляет состояние диалога
+pub async fn save_dialogue_state(pool: &SqlitePool, chat_id: i64, state: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT OR REPLACE INTO dialogues (chat_id, state, updated_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+        chat_id,
+        state
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Удаляет состояние диалога. Возвращает false, если диалога не было.
+pub async fn delete_dialogue_state(pool: &SqlitePool, chat_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM dialogues WHERE chat_id = ?",
+        chat_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Unreachable Users Functions
+
+/// Помечает пользователя недоступным для рассылок
+pub async fn mark_user_unreachable(
+    pool: &SqlitePool,
+    telegram_id: i64,
+    reason: DeliveryErrorKind,
+    error: Option<String>,
+) -> Result<(), sqlx::Error> {
+    let reason = reason.to_string();
+    sqlx::query!(
+        "INSERT OR REPLACE INTO unreachable_users (telegram_id, reason, error, marked_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)",
+        telegram_id,
+        reason,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Возвращает пользователя в рассылки. Возвращает false, если он не был помечен.
+pub async fn clear_user_unreachable(pool: &SqlitePool, telegram_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM unreachable_users WHERE telegram_id = ?",
+        telegram_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Получает ID всех недоступных пользователей
+pub async fn get_unreachable_telegram_ids(pool: &SqlitePool) -> Result<std::collections::HashSet<i64>, sqlx::Error> {
+    let rows = sqlx::query!("SELECT telegram_id FROM unreachable_users")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.into_iter().map(|row| row.telegram_id).collect())
+}
+
+/// Получает список недоступных пользователей
+pub async fn get_unreachable_users(pool: &SqlitePool) -> Result<Vec<UnreachableUser>, sqlx::Error> {
+    sqlx::query_as!(
+        UnreachableUser,
+        r#"
+        SELECT telegram_id as "telegram_id!", reason, error, marked_at
+        FROM unreachable_users
+        ORDER BY marked_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Support Inbox Functions
+
+/// Получает тему форума для переписки с кандидатом
+pub async fn get_support_thread(pool: &SqlitePool, telegram_id: i64) -> Result<Option<i32>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT thread_id FROM support_threads WHERE telegram_id = ?",
+        telegram_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.thread_id as i32))
+}
+
+/// Получает кандидата по теме форума
+pub async fn get_support_thread_owner(pool: &SqlitePool, thread_id: i32) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT telegram_id FROM support_threads WHERE thread_id = ?",
+        thread_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|row| row.telegram_id))
+}
+
+/// Сохраняет тему форума для кандидата
+pub async fn save_support_thread(pool: &SqlitePool, telegram_id: i64, thread_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT OR REPLACE INTO support_threads (telegram_id, thread_id) VALUES (?, ?)",
+        telegram_id,
+        thread_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Забывает тему форума кандидата, например удаленную в чате сотрудников
+pub async fn delete_support_thread(pool: &SqlitePool, telegram_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM support_threads WHERE telegram_id = ?", telegram_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Сохраняет сообщение переписки с поддержкой
+pub async fn save_support_message(
+    pool: &SqlitePool,
+    telegram_id: i64,
+    direction: SupportDirection,
+    text: Option<&str>,
+    staff_telegram_id: Option<i64>,
+    staff_message_id: Option<i32>,
+) -> Result<(), sqlx::Error> {
+    let direction = direction.to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO support_messages (telegram_id, direction, text, staff_telegram_id, staff_message_id)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        telegram_id,
+        direction,
+        text,
+        staff_telegram_id,
+        staff_message_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Получает кандидата по пересланному в чат сотрудников сообщению
+pub async fn get_support_message_owner(pool: &SqlitePool, staff_message_id: i32) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT telegram_id FROM support_messages WHERE staff_message_id = ? AND direction = 'inbound'",
+        staff_message_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.telegram_id))
+}
+
+/// Получает всю переписку кандидата с поддержкой
+pub async fn get_support_messages(pool: &SqlitePool, telegram_id: i64) -> Result<Vec<SupportMessage>, sqlx::Error> {
+    sqlx::query_as!(
+        SupportMessage,
+        r#"
+        SELECT id as "id!", telegram_id, direction, text, staff_telegram_id, created_at
+        FROM support_messages
+        WHERE telegram_id = ?
+        ORDER BY created_at, id
+        "#,
+        telegram_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Deep Link Functions
+
+/// Регистрирует deep link. Возвращает false, если такой payload уже есть.
+pub async fn create_deep_link(pool: &SqlitePool, link: &DeepLink) -> Result<bool, sqlx::Error> {
+    let flow = link.flow.to_string();
+    let result = sqlx::query!(
+        "INSERT OR IGNORE INTO deep_links (payload, source, flow, broadcast_id) VALUES (?, ?, ?, ?)",
+        link.payload,
+        link.source,
+        flow,
+        link.broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Получает deep link по payload
+pub async fn get_deep_link(pool: &SqlitePool, payload: &str) -> Result<Option<DeepLink>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT payload, source, flow, broadcast_id FROM deep_links WHERE payload = ?",
+        payload
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| DeepLink {
+        payload: row.payload.unwrap_or_default(),
+        source: row.source,
+        flow: DeepLinkFlow::from(row.flow),
+        broadcast_id: row.broadcast_id,
+    }))
+}
+
+/// Записывает переход пользователя по deep link'у: посетитель считается один раз,
+/// а время обновляется, чтобы запись приписывалась последней ссылке
+pub async fn record_deep_link_visit(pool: &SqlitePool, payload: &str, telegram_id: i64) -> Result<(), sqlx::Error> {
+    // Миллисекунды различают переходы по разным ссылкам в одну секунду
+    sqlx::query!(
+        "INSERT INTO deep_link_visits (payload, telegram_id, visited_at) VALUES (?, ?, strftime('%Y-%m-%d %H:%M:%f', 'now')) 
+         ON CONFLICT (payload, telegram_id) DO UPDATE SET visited_at = excluded.visited_at",
+        payload,
+        telegram_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Конверсия по каждому payload: переходы и записи на слот. Запись приписывается
+/// последней ссылке, по которой пользователь перешел до нее
+pub async fn get_deep_link_stats(pool: &SqlitePool) -> Result<Vec<DeepLinkStats>, sqlx::Error> {
+    sqlx::query_as::<_, DeepLinkStats>(
+        r#"
+        WITH visits AS (
+            SELECT
+                v.payload,
+                v.telegram_id,
+                EXISTS (
+                    SELECT 1 FROM records r
+                    WHERE r.telegram_id = v.telegram_id
+                      AND r.slot_id IS NOT NULL
+                      AND r.created_at >= datetime(v.visited_at)
+                      AND NOT EXISTS (
+                          SELECT 1 FROM deep_link_visits later
+                          WHERE later.telegram_id = v.telegram_id
+                            AND later.visited_at > v.visited_at
+                            AND datetime(later.visited_at) <= r.created_at
+                      )
+                ) AS booked
+            FROM deep_link_visits v
+        )
+        SELECT
+            l.payload AS payload,
+            l.source AS source,
+            l.flow AS flow,
+            l.broadcast_id AS broadcast_id,
+            COUNT(vs.telegram_id) AS visitors,
+            COALESCE(SUM(vs.booked), 0) AS bookings,
+            CASE WHEN COUNT(vs.telegram_id) = 0 THEN 0.0
+                 ELSE CAST(COALESCE(SUM(vs.booked), 0) AS REAL) / COUNT(vs.telegram_id)
+            END AS conversion_rate
+        FROM deep_links l
+        LEFT JOIN visits vs ON vs.payload = l.payload
+        GROUP BY l.payload
+        ORDER BY visitors DESC, l.payload
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Broadcast Funnel Functions
+
+/// Записывает нажатие кнопки «Записаться» в рассылке; повторное нажатие обновляет время
+pub async fn record_broadcast_click(pool: &SqlitePool, broadcast_id: &str, telegram_id: i64) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO broadcast_clicks (broadcast_id, telegram_id, clicked_at) VALUES (?1, ?2, ?3) 
+         ON CONFLICT (broadcast_id, telegram_id) DO UPDATE SET clicked_at = ?3",
+        broadcast_id,
+        telegram_id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Отмечает, что после нажатия пользователю показали список слотов
+pub async fn mark_broadcast_slots_viewed(pool: &SqlitePool, broadcast_id: &str, telegram_id: i64) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        "UPDATE broadcast_clicks SET slots_viewed_at = COALESCE(slots_viewed_at, ?) 
+         WHERE broadcast_id = ? AND telegram_id = ?",
+        now,
+        broadcast_id,
+        telegram_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Воронка рассылки: доставлено, нажали «Записаться», увидели слоты, записались
+pub async fn get_broadcast_funnel(pool: &SqlitePool, broadcast_id: &str) -> Result<Option<BroadcastFunnel>, sqlx::Error> {
+    sqlx::query_as::<_, BroadcastFunnel>(
+        r#"
+        WITH funnel AS (
+            SELECT
+                s.id AS broadcast_id,
+                (SELECT COUNT(*) FROM broadcast_messages m WHERE m.broadcast_id = s.id AND m.status = 'sent') AS delivered,
+                (SELECT COUNT(*) FROM broadcast_clicks c WHERE c.broadcast_id = s.id) AS clicked,
+                (SELECT COUNT(*) FROM broadcast_clicks c WHERE c.broadcast_id = s.id AND c.slots_viewed_at IS NOT NULL) AS slots_viewed,
+                (SELECT COUNT(*) FROM records r WHERE r.broadcast_id = s.id AND r.slot_id IS NOT NULL) AS booked
+            FROM broadcast_summaries s
+            WHERE s.id = ?
+        )
+        SELECT
+            broadcast_id,
+            delivered,
+            clicked,
+            slots_viewed,
+            booked,
+            CASE WHEN delivered = 0 THEN 0.0 ELSE CAST(booked AS REAL) / delivered END AS conversion_rate
+        FROM funnel
+        "#,
+    )
+    .bind(broadcast_id)
+    .fetch_optional(pool)
+    .await
+}
+
+// A/B Variant Functions
+
+/// Запоминает, когда выбрать победителя A/B-теста; вызывается при отправке тестовой доли
+pub async fn schedule_broadcast_auto_winner(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    settings: &variants::AutoWinnerSettings,
+) -> Result<(), sqlx::Error> {
+    let decide_at = chrono::Utc::now().naive_utc() + chrono::Duration::minutes(settings.decide_after_minutes as i64);
+    let test_percent = settings.test_percent as i64;
+    sqlx::query!(
+        "INSERT OR IGNORE INTO broadcast_auto_winners (broadcast_id, test_percent, decide_at) VALUES (?, ?, ?)",
+        broadcast_id,
+        test_percent,
+        decide_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Рассылки, у которых пора выбрать победителя; приостановленные и отмененные ждут
+pub async fn get_due_broadcast_winners(pool: &SqlitePool, now: DateTime<Utc>) -> Result<Vec<String>, sqlx::Error> {
+    let now = now.naive_utc();
+    let rows = sqlx::query!(
+        "SELECT w.broadcast_id as \"broadcast_id!: String\" FROM broadcast_auto_winners w 
+         JOIN broadcast_summaries s ON s.id = w.broadcast_id 
+         WHERE w.winner_variant IS NULL AND w.decide_at <= ? AND s.status IN ('in_progress', 'completed') 
+         ORDER BY w.decide_at",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.broadcast_id).collect())
+}
+
+/// Доставка и конверсия по A/B-вариантам рассылки
+pub async fn get_broadcast_variant_stats(pool: &SqlitePool, broadcast_id: &str) -> Result<Vec<BroadcastVariantStats>, sqlx::Error> {
+    sqlx::query_as::<_, BroadcastVariantStats>(
+        r#"
+        WITH variants AS (
+            SELECT
+                m.variant AS variant,
+                COUNT(*) AS recipients,
+                COALESCE(SUM(m.status = 'sent'), 0) AS delivered,
+                COALESCE(SUM(m.status = 'failed'), 0) AS failed,
+                COALESCE(SUM(c.telegram_id IS NOT NULL), 0) AS clicked,
+                COALESCE(SUM(EXISTS (
+                    SELECT 1 FROM records r
+                    WHERE r.broadcast_id = m.broadcast_id AND r.telegram_id = m.telegram_id AND r.slot_id IS NOT NULL
+                )), 0) AS booked,
+                COALESCE(MAX(w.winner_variant = m.variant), 0) AS is_winner
+            FROM broadcast_messages m
+            LEFT JOIN broadcast_clicks c ON c.broadcast_id = m.broadcast_id AND c.telegram_id = m.telegram_id
+            LEFT JOIN broadcast_auto_winners w ON w.broadcast_id = m.broadcast_id
+            WHERE m.broadcast_id = ? AND m.variant IS NOT NULL
+            GROUP BY m.variant
+        )
+        SELECT
+            variant,
+            recipients,
+            delivered,
+            failed,
+            clicked,
+            booked,
+            CASE WHEN delivered = 0 THEN 0.0 ELSE CAST(booked AS REAL) / delivered END AS conversion_rate,
+            is_winner
+        FROM variants
+        ORDER BY variant
+        "#,
+    )
+    .bind(broadcast_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Выбирает победителя A/B-теста по статистике тестовой доли. Возвращает событие для
+/// отправки победителя остальной аудитории; None, если победитель уже выбран
+pub async fn select_broadcast_winner(pool: &SqlitePool, broadcast_id: &str) -> Result<Option<BroadcastEvent>, sqlx::Error> {
+    let stats = get_broadcast_variant_stats(pool, broadcast_id).await?;
+    let winner = variants::pick_winner(&stats);
+    let winner_variant = winner as i64;
+    let selected_at = chrono::Utc::now();
+    let selected_at_naive = selected_at.naive_utc();
+
+    let result = sqlx::query!(
+        "UPDATE broadcast_auto_winners SET winner_variant = ?, decided_at = ? WHERE broadcast_id = ? AND winner_variant IS NULL",
+        winner_variant,
+        selected_at_naive,
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let event = BroadcastEvent::BroadcastWinnerSelected {
+        broadcast_id: broadcast_id.to_string(),
+        variant: winner,
+        selected_at,
+    };
+    save_broadcast_event(pool, &event).await?;
+    println!("🏆 Рассылка {}: победил вариант {}", broadcast_id, variants::variant_label(winner));
+
+    Ok(Some(event))
+}
+
+/// Снимает выбор победителя, если событие не удалось опубликовать: планировщик выберет его снова
+pub async fn release_broadcast_winner(pool: &SqlitePool, broadcast_id: &str, variant: usize) -> Result<(), sqlx::Error> {
+    let variant = variant as i64;
+    sqlx::query!(
+        "UPDATE broadcast_auto_winners SET winner_variant = NULL, decided_at = NULL WHERE broadcast_id = ? AND winner_variant = ?",
+        broadcast_id,
+        variant
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Получатели, чье сообщение рассылки уже опубликовано в очередь или больше не ждет отправки.
+/// Созданные, но не опубликованные сообщения сюда не входят: их нужно опубликовать снова
+pub async fn get_broadcast_recipient_ids(pool: &SqlitePool, broadcast_id: &str) -> Result<std::collections::HashSet<i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT telegram_id FROM broadcast_messages WHERE broadcast_id = ? AND (queued_at IS NOT NULL OR status != 'pending')",
+        broadcast_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.telegram_id).collect())
+}
+
+// Idempotency Functions
+
+/// Резервирует Idempotency-Key для запроса; просроченные ключи удаляются заодно
+pub async fn reserve_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    method: &str,
+    path: &str,
+    request_body: &[u8],
+) -> Result<IdempotencyReservation, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let expired_before = now - chrono::Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS);
+    sqlx::query!("DELETE FROM idempotency_keys WHERE created_at < ?", expired_before)
+        .execute(pool)
+        .await?;
+
+    let inserted = sqlx::query!(
+        "INSERT OR IGNORE INTO idempotency_keys (idempotency_key, method, path, request_body, created_at) VALUES (?, ?, ?, ?, ?)",
+        key,
+        method,
+        path,
+        request_body,
+        now
+    )
+    .execute(pool)
+    .await?;
+    if inserted.rows_affected() == 1 {
+        return Ok(IdempotencyReservation::Reserved);
+    }
+
+    let Some(row) = sqlx::query!(
+        r#"SELECT request_body, status_code, response_body, content_type, created_at as "created_at: chrono::NaiveDateTime"
+           FROM idempotency_keys WHERE idempotency_key = ? AND method = ? AND path = ?"#,
+        key,
+        method,
+        path
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        // Ключ удалили между вставкой и чтением — считаем его занятым, клиент повторит
+        return Ok(IdempotencyReservation::InProgress);
+    };
+
+    if row.request_body != request_body {
+        return Ok(IdempotencyReservation::Mismatch);
+    }
+    let Some(status_code) = row.status_code else {
+        // Запрос, не завершившийся за отведенное время, можно выполнить заново
+        let stale_before = now - chrono::Duration::seconds(IDEMPOTENCY_LOCK_TIMEOUT_SECS);
+        if row.created_at >= stale_before {
+            return Ok(IdempotencyReservation::InProgress);
+        }
+        let taken = sqlx::query!(
+            "UPDATE idempotency_keys SET created_at = ? WHERE idempotency_key = ? AND method = ? AND path = ? AND status_code IS NULL AND created_at < ?",
+            now,
+            key,
+            method,
+            path,
+            stale_before
+        )
+        .execute(pool)
+        .await?;
+        return Ok(if taken.rows_affected() == 1 { IdempotencyReservation::Reserved } else { IdempotencyReservation::InProgress });
+    };
+
+    Ok(IdempotencyReservation::Completed(StoredResponse {
+        status_code: status_code as u16,
+        body: row.response_body.unwrap_or_default(),
+        content_type: row.content_type,
+    }))
+}
+
+/// Сохраняет ответ на запрос с зарезервированным Idempotency-Key
+pub async fn complete_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    method: &str,
+    path: &str,
+    response: &StoredResponse,
+) -> Result<(), sqlx::Error> {
+    let status_code = response.status_code as i64;
+    sqlx::query!(
+        "UPDATE idempotency_keys SET status_code = ?, response_body = ?, content_type = ? WHERE idempotency_key = ? AND method = ? AND path = ?",
+        status_code,
+        response.body,
+        response.content_type,
+        key,
+        method,
+        path
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Освобождает ключ, если запрос не удался по вине сервера: повтор выполнит его заново
+pub async fn release_idempotency_key(pool: &SqlitePool, key: &str, method: &str, path: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE idempotency_key = ? AND method = ? AND path = ? AND status_code IS NULL",
+        key,
+        method,
+        path
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Admin Bot Functions
+
+/// Получает ID анкет, по которым уже есть решение ответственного
+pub async fn get_decided_survey_ids(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT v.survey_id
+        FROM votes v
+        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+        WHERE ur.role = 1
+        AND (v.comment IS NULL OR (v.comment != 'В обработке' AND v.comment != 'Инициализация'))
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.survey_id).collect())
+}
+
+/// Получает решение ответственного по анкете (1 - одобрена, 0 - отклонена)
+pub async fn get_responsible_decision(pool: &SqlitePool, survey_id: i64) -> Result<Option<i32>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT v.decision
+        FROM votes v
+        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+        WHERE v.survey_id = ? AND ur.role = 1
+        AND (v.comment IS NULL OR (v.comment != 'В обработке' AND v.comment != 'Инициализация'))
+        ORDER BY v.created_at DESC
+        LIMIT 1
+        "#,
+        survey_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.map(|r| r.decision as i32))
+}
+
+/// Получает запись пользователя на собеседование
+pub async fn get_booking_by_telegram_id(pool: &SqlitePool, telegram_id: i64) -> Result<Option<BookingInfo>, sqlx::Error> {
+    sqlx::query_as::<_, BookingInfo>(
+        "SELECT r.telegram_id, s.time, s.place FROM records r JOIN slots s ON r.slot_id = s.id WHERE r.telegram_id = ?"
+    )
+    .bind(telegram_id)
+    .fetch_optional(pool)
+    .await
+}
+
+// Audience Segments
+
+fn segment_from_row(
+    id: i64,
+    name: String,
+    rules: &str,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+) -> Result<Segment, sqlx::Error> {
+    let rules = serde_json::from_str(rules).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    Ok(Segment { id, name, rules, created_at, updated_at })
+}
+
+/// Сохраняет сегмент. None, если сегмент с таким именем уже есть
+pub async fn create_segment(pool: &SqlitePool, request: &SaveSegmentRequest) -> Result<Option<Segment>, sqlx::Error> {
+    let rules = serde_json::to_string(&request.rules).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    let row = sqlx::query!(
+        r#"
+        INSERT OR IGNORE INTO broadcast_segments (name, rules) VALUES (?, ?)
+        RETURNING id as "id!: i64", name, rules, created_at, updated_at
+        "#,
+        request.name,
+        rules
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| segment_from_row(row.id, row.name, &row.rules, row.created_at, row.updated_at))
+        .transpose()
+}
+
+/// Обновляет имя и правила сегмента. None, если сегмента нет или имя занято другим сегментом
+pub async fn update_segment(pool: &SqlitePool, segment_id: i64, request: &SaveSegmentRequest) -> Result<Option<Segment>, sqlx::Error> {
+    let rules = serde_json::to_string(&request.rules).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE OR IGNORE broadcast_segments
+        SET name = ?, rules = ?, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        RETURNING id as "id!: i64", name, rules, created_at, updated_at
+        "#,
+        request.name,
+        rules,
+        segment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| segment_from_row(row.id, row.name, &row.rules, row.created_at, row.updated_at))
+        .transpose()
+}
+
+pub async fn get_segment(pool: &SqlitePool, segment_id: i64) -> Result<Option<Segment>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id, name, rules, created_at, updated_at FROM broadcast_segments WHERE id = ?",
+        segment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| segment_from_row(row.id, row.name, &row.rules, row.created_at, row.updated_at))
+        .transpose()
+}
+
+pub async fn get_all_segments(pool: &SqlitePool) -> Result<Vec<Segment>, sqlx::Error> {
+    let rows = sqlx::query!(r#"SELECT id as "id!: i64", name, rules, created_at, updated_at FROM broadcast_segments ORDER BY name"#)
+        .fetch_all(pool)
+        .await?;
+
+    rows.into_iter()
+        .map(|row| segment_from_row(row.id, row.name, &row.rules, row.created_at, row.updated_at))
+        .collect()
+}
+
+pub async fn delete_segment(pool: &SqlitePool, segment_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM broadcast_segments WHERE id = ?", segment_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Ищет в исключениях сегмент, которого нет (или сам сегмент). Возвращает его ID
+pub async fn find_invalid_excluded_segment(
     pool: &SqlitePool,
-    all_users: &[serde_json::Value],
-    voted_survey_ids: &std::collections::HashSet<i64>,
+    segment_id: Option<i64>,
+    rules: &SegmentRules,
 ) -> Result<Option<i64>, sqlx::Error> {
-    println!("🔍 find_survey_for_regular_user: {} пользователей, {} уже проголосовано", 
-             all_users.len(), voted_survey_ids.len());
-    
-    // Извлекаем telegram_id из всех пользователей
-    let user_telegram_ids: Vec<i64> = all_users
-        .iter()
-        .filter_map(|user| user.get("telegram_id").and_then(|v| v.as_i64()))
-        .filter(|&id| !voted_survey_ids.contains(&id))
-        .collect();
-    
-    if user_telegram_ids.is_empty() {
-        println!("❌ Нет доступных пользователей для голосования");
-        return Ok(None);
-    }
-    
-    // Получаем количество голосов для всех кандидатов одним запросом
-    // Считаем только реальные голоса (не "В обработке") + проверяем есть ли голоса "В обработке"
-    let placeholders = user_telegram_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let query = format!(
-        "SELECT survey_id, 
-         COUNT(*) as total_count,
-         COUNT(CASE WHEN comment IS NULL OR (comment != 'В обработке' AND comment != 'Инициализация') THEN 1 END) as real_count,
-         COUNT(CASE WHEN comment = 'В обработке' THEN 1 END) as processing_count
-         FROM votes WHERE survey_id IN ({}) GROUP BY survey_id",
-        placeholders
-    );
-    
-    let mut query_builder = sqlx::query_as::<_, (i64, i64, i64, i64)>(&query);
-    for telegram_id in &user_telegram_ids {
-        query_builder = query_builder.bind(telegram_id);
-    }
-    
-    let vote_data: std::collections::HashMap<i64, (i64, i64, i64)> = query_builder
-        .fetch_all(pool)
-        .await?
-        .into_iter()
-        .map(|(survey_id, total, real, processing)| (survey_id, (total, real, processing)))
-        .collect();
-    
-    // Создаем список кандидатов с количеством голосов
-    let mut candidates = Vec::new();
-    
-    for telegram_id in user_telegram_ids {
-        let (_total_count, real_count, processing_count) = vote_data.get(&telegram_id).copied().unwrap_or((0, 0, 0));
-        
-        // Анкета доступна обычным пользователям если:
-        // 1. Реальных голосов меньше MIN_VOTES_FOR_REVIEW
-        // 2. И нет активных голосов "В обработке" (чтобы избежать конфликтов)
-        if real_count < MIN_VOTES_FOR_REVIEW && processing_count == 0 {
-            candidates.push((telegram_id, real_count));
+    for excluded_id in &rules.exclude_segment_ids {
+        if Some(*excluded_id) == segment_id || get_segment(pool, *excluded_id).await?.is_none() {
+            return Ok(Some(*excluded_id));
         }
     }
-    
-    // Сортируем по приоритету: ближе к MIN_VOTES_FOR_REVIEW голосам = выше приоритет
-    candidates.sort_by(|a, b| {
-        let distance_a = (MIN_VOTES_FOR_REVIEW - a.1).abs();
-        let distance_b = (MIN_VOTES_FOR_REVIEW - b.1).abs();
-        distance_a.cmp(&distance_b)
-    });
-    
-    // Возвращаем пользователя с наивысшим приоритетом
-    let result = candidates.first().map(|(telegram_id, _)| *telegram_id);
-    println!("🎯 find_survey_for_regular_user: найдено {} кандидатов, выбран: {:?}", 
-             candidates.len(), result);
-    Ok(result)
+    Ok(None)
 }
 
-/// Находит анкету для ответственного пользователя
-async fn find_survey_for_responsible_user(
-    pool: &SqlitePool,
-    all_users: &[serde_json::Value],
-    _voted_survey_ids: &std::collections::HashSet<i64>,
-) -> Result<Option<i64>, sqlx::Error> {
-    println!("🔍 find_survey_for_responsible_user: проверяем {} пользователей", all_users.len());
-    
-    // Проверим, сколько анкет уже обработано ответственными
-    let responsible_processed = sqlx::query!(
-        "SELECT COUNT(DISTINCT survey_id) as count FROM votes v 
-         JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id 
-         WHERE ur.role = 1"
-    )
-    .fetch_one(pool)
-    .await?;
-    
-    println!("📊 Ответственные пользователи уже обработали {} анкет", responsible_processed.count);
-    
-    // Покажем, кто является ответственным
-    let responsible_users = sqlx::query!(
-        "SELECT telegram_id FROM user_roles WHERE role = 1"
+/// Статус анкеты и решение ответственного по всем анкетам с голосами
+/// (по тем же правилам, что get_survey_vote_summary и get_selected_users)
+async fn get_survey_states(pool: &SqlitePool) -> Result<HashMap<i64, (SurveyStatus, VoteOutcome)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            v.survey_id as "survey_id!: i64",
+            SUM(CASE WHEN v.comment IS NULL OR (v.comment != 'В обработке' AND v.comment != 'Инициализация') THEN 1 ELSE 0 END) as "votes!: i64",
+            MAX(CASE WHEN ur.role = 1 THEN 1 ELSE 0 END) as "has_responsible_vote!: i64",
+            MAX(CASE WHEN ur.role = 1 AND v.decision = 1 THEN 1 ELSE 0 END) as "approved!: i64",
+            MAX(CASE WHEN ur.role = 1 AND v.decision = 0
+                AND (v.comment IS NULL OR (v.comment != 'В обработке' AND v.comment != 'Инициализация')) THEN 1 ELSE 0 END) as "rejected!: i64"
+        FROM votes v
+        LEFT JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
+        GROUP BY v.survey_id
+        "#
     )
     .fetch_all(pool)
     .await?;
-    
-    println!("👥 Ответственные пользователи: {:?}", 
-             responsible_users.iter().map(|r| r.telegram_id).collect::<Vec<_>>());
-    
-    // Извлекаем telegram_id из всех пользователей
-    let user_telegram_ids: Vec<i64> = all_users
-        .iter()
-        .filter_map(|user| user.get("telegram_id").and_then(|v| v.as_i64()))
-        .collect();
-    
-    if user_telegram_ids.is_empty() {
-        println!("❌ Нет доступных пользователей");
-        return Ok(None);
-    }
-    
-    // Получаем количество голосов для всех кандидатов одним запросом
-    let placeholders = user_telegram_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let vote_query = format!(
-        "SELECT survey_id, COUNT(*) as count FROM votes WHERE survey_id IN ({}) GROUP BY survey_id",
-        placeholders
-    );
-    
-    let mut vote_query_builder = sqlx::query_as::<_, (i64, i64)>(&vote_query);
-    for telegram_id in &user_telegram_ids {
-        vote_query_builder = vote_query_builder.bind(telegram_id);
-    }
-    
-    let vote_counts: std::collections::HashMap<i64, i64> = vote_query_builder
-        .fetch_all(pool)
-        .await?
-        .into_iter()
-        .collect();
-    
-    // Получаем голоса ответственных для всех кандидатов одним запросом
-    let responsible_vote_query = format!(
-        "SELECT v.survey_id FROM votes v 
-         JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id 
-         WHERE v.survey_id IN ({}) AND ur.role = 1",
-        placeholders
-    );
-    
-    let mut responsible_query_builder = sqlx::query_as::<_, (i64,)>(&responsible_vote_query);
-    for telegram_id in &user_telegram_ids {
-        responsible_query_builder = responsible_query_builder.bind(telegram_id);
-    }
-    
-    let responsible_votes: std::collections::HashSet<i64> = responsible_query_builder
-        .fetch_all(pool)
-        .await?
+
+    Ok(rows
         .into_iter()
-        .map(|(survey_id,)| survey_id)
-        .collect();
-    
-    // Ищем первую подходящую анкету
-    for telegram_id in user_telegram_ids {
-        let vote_count = vote_counts.get(&telegram_id).copied().unwrap_or(0);
-        
-        println!("🔍 Анкета {}: {} голосов (требуется >= {})", telegram_id, vote_count, MIN_VOTES_FOR_REVIEW);
-        
-        if vote_count >= MIN_VOTES_FOR_REVIEW {
-            let has_responsible_vote = responsible_votes.contains(&telegram_id);
-            println!("🔍 Анкета {}: есть голос от ответственного: {}", telegram_id, has_responsible_vote);
-            
-            if !has_responsible_vote {
-                println!("✅ find_survey_for_responsible_user: найдена анкета {} с {} голосами", 
-                         telegram_id, vote_count);
-                return Ok(Some(telegram_id));
+        .map(|row| {
+            let status = if row.has_responsible_vote > 0 {
+                SurveyStatus::Completed
+            } else if row.votes >= MIN_VOTES_FOR_REVIEW {
+                SurveyStatus::ReadyForReview
             } else {
-                println!("❌ Анкета {} уже имеет голос от ответственного", telegram_id);
-            }
-        } else {
-            println!("❌ Анкета {} имеет недостаточно голосов: {} < {}", telegram_id, vote_count, MIN_VOTES_FOR_REVIEW);
-        }
-    }
-    println!("❌ find_survey_for_responsible_user: не найдено подходящих анкет");
-    Ok(None)
+                SurveyStatus::InProgress
+            };
+            let outcome = if row.approved > 0 {
+                VoteOutcome::Approved
+            } else if row.rejected > 0 {
+                VoteOutcome::Rejected
+            } else {
+                VoteOutcome::Undecided
+            };
+            (row.survey_id, (status, outcome))
+        })
+        .collect())
 }
 
-/// Обрабатывает голосование
-pub async fn handle_vote(pool: &SqlitePool, request: CreateVoteRequest, voter_telegram_id: i64) -> Result<VoteResponse, sqlx::Error> {
-    // Создаем голос
-    let _vote = create_vote(pool, request.clone(), voter_telegram_id).await?;
-    
-    // Получаем следующую анкету
-    let next_survey = get_next_survey(pool, voter_telegram_id).await?;
-    
-    Ok(VoteResponse {
-        success: true,
-        message: "Голос успешно сохранен".to_string(),
-        next_survey: Some(next_survey),
-    })
+/// Данные для вычисления сегментов, загружаются один раз на запрос
+struct SegmentData {
+    users: Vec<serde_json::Value>,
+    survey_states: HashMap<i64, (SurveyStatus, VoteOutcome)>,
+    booked: std::collections::HashSet<i64>,
+    signup_recipients: std::collections::HashSet<i64>,
+    segments: HashMap<i64, SegmentRules>,
 }
 
-// Authentication Functions
+impl SegmentData {
+    async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let users = get_all_users_from_external_api().await
+            .map_err(|e| sqlx::Error::Protocol(format!("External API error: {}", e)))?;
+        let segments = get_all_segments(pool)
+            .await?
+            .into_iter()
+            .map(|segment| (segment.id, segment.rules))
+            .collect();
 
-/// Проверяет авторизацию через Telegram и получает профиль пользователя
-pub async fn authenticate_user(telegram_auth: TelegramAuth) -> Result<AuthResponse, String> {
-    // TODO: Добавить проверку подписи Telegram (hash verification)
-    // Пока что просто проверяем, что данные пришли
-    
-    let api_base_url = std::env::var("USER_API_URL")
-        .unwrap_or_else(|_| "https://api.ingroupsts.ru".to_string());
-        
-    
-    let user_url = format!("{}/user/{}", api_base_url, telegram_auth.id);
-    
-    println!("🔍 Попытка авторизации пользователя ID: {}", telegram_auth.id);
-    println!("🌐 URL запроса: {}", user_url);
-    
-    // Делаем запрос к внешнему API для получения профиля пользователя
-    let client = reqwest::Client::new();
-    match client
-        .get(&user_url)
-        .header("X-Forwarded-For", "127.0.0.1")
-        .send()
-        .await {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<ExternalUserResponse>().await {
-                    Ok(user_data) => {
-                        // Пользователь найден в системе
-                        println!("✅ Пользователь {} успешно авторизован", telegram_auth.id);
-                        Ok(AuthResponse {
-                            success: true,
-                            message: "Авторизация успешна".to_string(),
-                            user_profile: Some(user_data.user_profile),
-                            user_role: None, // Будет получена из БД
-                        })
-                    }
-                    Err(e) => {
-                        eprintln!("❌ Ошибка парсинга профиля пользователя {}: {}", telegram_auth.id, e);
-                        Ok(AuthResponse {
-                            success: false,
-                            message: "Ошибка получения данных пользователя".to_string(),
-                            user_profile: None,
-                            user_role: None,
-                        })
-                    }
-                }
-            } else {
-                // Пользователь не найден в системе
-                println!("❌ Пользователь {} не найден в системе (статус: {})", telegram_auth.id, response.status());
-                Ok(AuthResponse {
-                    success: false,
-                    message: "Пользователь не найден в системе".to_string(),
-                    user_profile: None,
-                    user_role: None,
-                })
-            }
-        }
-        Err(e) => {
-            eprintln!("❌ Ошибка запроса к внешнему API для пользователя {}: {}", telegram_auth.id, e);
-            Ok(AuthResponse {
-                success: false,
-                message: "Ошибка подключения к серверу".to_string(),
-                user_profile: None,
-                user_role: None,
+        Ok(SegmentData {
+            users,
+            survey_states: get_survey_states(pool).await?,
+            booked: get_booked_telegram_ids(pool).await?,
+            signup_recipients: get_signup_recipient_ids(pool).await?,
+            segments,
+        })
+    }
+
+    /// telegram_id пользователей, подходящих под правила
+    fn resolve(&self, rules: &SegmentRules) -> Vec<i64> {
+        let mut telegram_ids: Vec<i64> = self
+            .users
+            .iter()
+            .filter_map(|user| {
+                let telegram_id = user.get("telegram_id").and_then(|v| v.as_i64())?;
+                self.matches(rules, telegram_id, user, &mut Vec::new()).then_some(telegram_id)
+            })
+            .collect();
+        telegram_ids.sort_unstable();
+        telegram_ids.dedup();
+        telegram_ids
+    }
+
+    /// `chain` — сегменты, через исключения которых мы сюда пришли: защита от циклов
+    fn matches(&self, rules: &SegmentRules, telegram_id: i64, user: &serde_json::Value, chain: &mut Vec<i64>) -> bool {
+        let (status, outcome) = self
+            .survey_states
+            .get(&telegram_id)
+            .cloned()
+            .unwrap_or((SurveyStatus::InProgress, VoteOutcome::Undecided));
+        let booked = self.booked.contains(&telegram_id);
+        let no_response = self.signup_recipients.contains(&telegram_id) && !booked;
+
+        let matches = rules.survey_statuses.as_ref().is_none_or(|statuses| statuses.contains(&status))
+            && rules.vote_outcomes.as_ref().is_none_or(|outcomes| outcomes.contains(&outcome))
+            && rules.booked.is_none_or(|expected| expected == booked)
+            && rules.no_response_after_signup.is_none_or(|expected| expected == no_response)
+            && rules.year_of_admission.as_ref().is_none_or(|years| {
+                user.get("year_of_admission")
+                    .and_then(|v| v.as_i64())
+                    .is_some_and(|year| years.iter().any(|expected| i64::from(*expected) == year))
             })
+            && profile_flag_matches(user, "has_driver_license", rules.has_driver_license)
+            && profile_flag_matches(user, "has_printer", rules.has_printer)
+            && profile_flag_matches(user, "can_host_night", rules.can_host_night)
+            && !rules.exclude_telegram_ids.contains(&telegram_id);
+        if !matches {
+            return false;
+        }
+
+        for excluded_id in &rules.exclude_segment_ids {
+            if chain.contains(excluded_id) {
+                continue;
+            }
+            let Some(excluded_rules) = self.segments.get(excluded_id) else {
+                continue;
+            };
+            chain.push(*excluded_id);
+            let excluded = self.matches(excluded_rules, telegram_id, user, chain);
+            chain.pop();
+            if excluded {
+                return false;
+            }
         }
+        true
     }
 }
 
-/// Получает роль пользователя из базы данных
-pub async fn get_user_role_from_db(pool: &SqlitePool, telegram_id: i64) -> Result<Option<i32>, sqlx::Error> {
-    get_user_role(pool, telegram_id).await
+/// Флаг профиля: во внешнем API хранится как bool или как 0/1.
+/// Пользователь без поля под условие не подходит
+fn profile_flag_matches(user: &serde_json::Value, field: &str, expected: Option<bool>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let value = match user.get(field) {
+        Some(serde_json::Value::Bool(value)) => Some(*value),
+        Some(value) => value.as_i64().map(|value| value != 0),
+        None => None,
+    };
+    value == Some(expected)
 }
 
-/// Обновляет голос
-pub async fn update_vote(pool: &SqlitePool, vote_id: i64, vote: UpdateVoteRequest) -> Result<Vote, sqlx::Error> {
+/// Вычисляет состав сегмента по текущим данным (без учета недоступных пользователей)
+pub async fn resolve_segment_rules(pool: &SqlitePool, rules: &SegmentRules) -> Result<Vec<i64>, sqlx::Error> {
+    Ok(SegmentData::load(pool).await?.resolve(rules))
+}
+
+/// Вычисляет состав сохраненного сегмента. None, если сегмента нет
+pub async fn resolve_segment(pool: &SqlitePool, segment_id: i64) -> Result<Option<Vec<i64>>, sqlx::Error> {
+    let Some(segment) = get_segment(pool, segment_id).await? else {
+        return Ok(None);
+    };
+    resolve_segment_rules(pool, &segment.rules).await.map(Some)
+}
+
+/// Сколько пользователей сейчас получит рассылку по правилам сегмента
+pub async fn count_segment(pool: &SqlitePool, rules: &SegmentRules) -> Result<SegmentCountResponse, sqlx::Error> {
+    let telegram_ids = resolve_segment_rules(pool, rules).await?;
+    let unreachable = get_unreachable_telegram_ids(pool).await?;
+    let count = telegram_ids.iter().filter(|telegram_id| !unreachable.contains(telegram_id)).count();
+
+    Ok(SegmentCountResponse {
+        count,
+        skipped_unreachable: telegram_ids.len() - count,
+    })
+}
+
+/// Получатели рассылки по сегменту на момент отправки: недоступные исключаются,
+/// сводка рассылки получает итоговое число получателей. None, если сегмент удален
+pub async fn resolve_broadcast_segment(
+    pool: &SqlitePool,
+    broadcast_id: &str,
+    segment_id: i64,
+) -> Result<Option<Vec<User>>, sqlx::Error> {
+    let Some(mut telegram_ids) = resolve_segment(pool, segment_id).await? else {
+        return Ok(None);
+    };
+    let unreachable = get_unreachable_telegram_ids(pool).await?;
+    telegram_ids.retain(|telegram_id| !unreachable.contains(telegram_id));
+
+    let total_users = telegram_ids.len() as i64;
     sqlx::query!(
-        "UPDATE votes SET decision = ?, comment = ? WHERE id = ?",
-        vote.decision,
-        vote.comment,
-        vote_id
+        "UPDATE broadcast_summaries SET total_users = ?, pending_count = ? WHERE id = ?",
+        total_users,
+        total_users,
+        broadcast_id
     )
     .execute(pool)
     .await?;
-    
-    let updated_vote = sqlx::query_as::<_, Vote>(
-        "SELECT id, survey_id, voter_telegram_id, decision, comment, created_at FROM votes WHERE id = ?"
-    )
-    .bind(vote_id)
-    .fetch_one(pool)
-    .await?;
-    
-    Ok(updated_vote)
-}
 
-/// Удаляет голос
-pub async fn delete_vote(pool: &SqlitePool, vote_id: i64) -> Result<(), sqlx::Error> {
-    sqlx::query!("DELETE FROM votes WHERE id = ?", vote_id)
-        .execute(pool)
-        .await?;
-    
-    Ok(())
+    Ok(Some(telegram_ids.into_iter().map(broadcast_recipient).collect()))
 }
 
-/// Очищает блокировки пользователя (удаляет голоса со статусом "В обработке")
-pub async fn clear_user_locks(pool: &SqlitePool, telegram_id: i64) -> Result<u64, sqlx::Error> {
-    let result = sqlx::query!(
-        "DELETE FROM votes WHERE voter_telegram_id = ? AND comment = ?",
-        telegram_id,
-        "В обработке"
+// Decision Notifications
+
+/// Создает кампанию уведомлений. None, если кампания с таким именем уже есть
+pub async fn create_decision_campaign(
+    pool: &SqlitePool,
+    request: &SaveDecisionCampaignRequest,
+) -> Result<Option<DecisionCampaign>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    if request.active {
+        sqlx::query!("UPDATE decision_campaigns SET active = FALSE WHERE active")
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let campaign = sqlx::query_as!(
+        DecisionCampaign,
+        r#"
+        INSERT OR IGNORE INTO decision_campaigns (name, approval_message, rejection_message, active)
+        VALUES (?, ?, ?, ?)
+        RETURNING id as "id!: i64", name, approval_message, rejection_message, active as "active: bool", created_at, updated_at
+        "#,
+        request.name,
+        request.approval_message,
+        request.rejection_message,
+        request.active
     )
-    .execute(pool)
+    .fetch_optional(&mut *tx)
     .await?;
-    
-    Ok(result.rows_affected())
+
+    // Имя занято: отменяем и выключение других кампаний
+    if campaign.is_some() {
+        tx.commit().await?;
+    }
+    Ok(campaign)
 }
 
-/// Получает пользователей с отобранными анкетами (одобренными ответственными)
-pub async fn get_selected_users(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
-    // Получаем всех пользователей с внешнего API
-    let all_users = get_all_users_from_external_api().await
-        .map_err(|e| sqlx::Error::Protocol(format!("External API error: {}", e)))?;
-    
-    // Получаем анкеты с положительными голосами от ответственных
-    let selected_surveys = sqlx::query!(
+/// Обновляет кампанию. None, если кампании нет или имя занято другой кампанией
+pub async fn update_decision_campaign(
+    pool: &SqlitePool,
+    campaign_id: i64,
+    request: &SaveDecisionCampaignRequest,
+) -> Result<Option<DecisionCampaign>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    if request.active {
+        sqlx::query!("UPDATE decision_campaigns SET active = FALSE WHERE active AND id != ?", campaign_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let campaign = sqlx::query_as!(
+        DecisionCampaign,
         r#"
-        SELECT DISTINCT v.survey_id
-        FROM votes v
-        JOIN user_roles ur ON v.voter_telegram_id = ur.telegram_id
-        WHERE ur.role = 1 AND v.decision = 1
-        "#
+        UPDATE OR IGNORE decision_campaigns
+        SET name = ?, approval_message = ?, rejection_message = ?, active = ?, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        RETURNING id as "id!: i64", name, approval_message, rejection_message, active as "active: bool", created_at, updated_at
+        "#,
+        request.name,
+        request.approval_message,
+        request.rejection_message,
+        request.active,
+        campaign_id
     )
-    .fetch_all(pool)
+    .fetch_optional(&mut *tx)
     .await?;
-    
-    let selected_survey_ids: std::collections::HashSet<i64> = selected_surveys
-        .into_iter()
-        .map(|s| s.survey_id)
-        .collect();
-    
-    // Фильтруем пользователей, оставляя только тех, чьи анкеты одобрены
-    let selected_users: Vec<serde_json::Value> = all_users
-        .into_iter()
-        .filter(|user| {
-            if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
-                selected_survey_ids.contains(&telegram_id)
-            } else {
-                false
-            }
-        })
-        .collect();
-    
-    Ok(selected_users)
+
+    if campaign.is_some() {
+        tx.commit().await?;
+    }
+    Ok(campaign)
 }
 
-/// Получает пользователей, которые не выполнили запись после рассылки о записи
-pub async fn get_no_response_users(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
-    // Получаем всех пользователей с внешнего API
-    let all_users = get_all_users_from_external_api().await
-        .map_err(|e| sqlx::Error::Protocol(format!("External API error: {}", e)))?;
-    
-    // Получаем пользователей, которым была отправлена рассылка о записи (signup)
-    // Включаем как успешно отправленные, так и неудачные сообщения
-    let signup_recipients = sqlx::query!(
+pub async fn get_decision_campaign(pool: &SqlitePool, campaign_id: i64) -> Result<Option<DecisionCampaign>, sqlx::Error> {
+    sqlx::query_as!(
+        DecisionCampaign,
         r#"
-        SELECT DISTINCT telegram_id
-        FROM broadcast_messages bm
-        JOIN broadcast_summaries bs ON bm.broadcast_id = bs.id
-        WHERE bm.message_type = 'signup' 
-        AND bs.status IN ('completed', 'in_progress')
-        AND bm.status IN ('sent', 'failed')
-        "#
+        SELECT id as "id!: i64", name, approval_message, rejection_message, active as "active: bool", created_at, updated_at
+        FROM decision_campaigns WHERE id = ?
+        "#,
+        campaign_id
     )
-    .fetch_all(pool)
-    .await?;
-    
-    let signup_telegram_ids: std::collections::HashSet<i64> = signup_recipients
-        .into_iter()
-        .map(|r| r.telegram_id)
-        .collect();
-    
-    // Получаем пользователей, которые уже записались на слоты
-    let booked_users = sqlx::query!(
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_decision_campaigns(pool: &SqlitePool) -> Result<Vec<DecisionCampaign>, sqlx::Error> {
+    sqlx::query_as!(
+        DecisionCampaign,
         r#"
-        SELECT DISTINCT telegram_id
-        FROM records
+        SELECT id as "id!: i64", name, approval_message, rejection_message, active as "active: bool", created_at, updated_at
+        FROM decision_campaigns ORDER BY created_at DESC
         "#
     )
     .fetch_all(pool)
-    .await?;
-    
-    let booked_telegram_ids: std::collections::HashSet<i64> = booked_users
-        .into_iter()
-        .map(|r| r.telegram_id)
-        .collect();
-    
-    // Фильтруем пользователей: получили рассылку о записи, но не записались
-    let no_response_users: Vec<serde_json::Value> = all_users
-        .into_iter()
-        .filter(|user| {
-            if let Some(telegram_id) = user.get("telegram_id").and_then(|v| v.as_i64()) {
-                // Получил рассылку о записи И не записался
-                signup_telegram_ids.contains(&telegram_id) && !booked_telegram_ids.contains(&telegram_id)
-            } else {
-                false
-            }
-        })
-        .collect();
-    
-    Ok(no_response_users)
+    .await
 }
 
-/// Получает детальную информацию о пользователях без записи с информацией о статусе сообщений
-pub async fn get_no_response_users_detailed(pool: &SqlitePool) -> Result<Vec<serde_json::Value>, sqlx::Error> {
-    // Получаем пользователей, которые получили рассылку о записи, но не записались
-    let no_response_users = sqlx::query!(
+pub async fn get_active_decision_campaign(pool: &SqlitePool) -> Result<Option<DecisionCampaign>, sqlx::Error> {
+    sqlx::query_as!(
+        DecisionCampaign,
         r#"
-        SELECT DISTINCT 
-            bm.telegram_id,
-            bm.status as message_status,
-            bm.error,
-            bm.sent_at,
-            bm.retry_count,
-            bs.created_at as broadcast_created_at
-        FROM broadcast_messages bm
-        JOIN broadcast_summaries bs ON bm.broadcast_id = bs.id
-        WHERE bm.message_type = 'signup' 
-        AND bs.status IN ('completed', 'in_progress', 'pending')
-        AND bm.status IN ('sent', 'failed')
-        AND bm.telegram_id NOT IN (
-            SELECT DISTINCT telegram_id 
-            FROM records
-        )
+        SELECT id as "id!: i64", name, approval_message, rejection_message, active as "active: bool", created_at, updated_at
+        FROM decision_campaigns WHERE active LIMIT 1
         "#
     )
-    .fetch_all(pool)
-    .await?;
-    
-    println!("🔍 DEBUG: Found {} no-response users", no_response_users.len());
-    
-    // Преобразуем в JSON формат
-    let result: Vec<serde_json::Value> = no_response_users
-        .into_iter()
-        .map(|user| {
-            serde_json::json!({
-                "telegram_id": user.telegram_id,
-                "message_info": {
-                    "status": user.message_status,
-                    "error": user.error,
-                    "sent_at": user.sent_at,
-                    "retry_count": user.retry_count,
-                    "broadcast_created_at": user.broadcast_created_at
-                }
-            })
-        })
-        .collect();
-    
-    Ok(result)
+    .fetch_optional(pool)
+    .await
 }
 
-/// Обновляет статус сообщения рассылки
-pub async fn update_broadcast_message_status_new(
-    pool: &SqlitePool,
-    telegram_id: i64,
-    message_type: &str,
-    new_status: &str
-) -> Result<u64, sqlx::Error> {
-    let result = sqlx::query!(
+pub async fn get_decision_notifications(pool: &SqlitePool, campaign_id: i64) -> Result<Vec<DecisionNotification>, sqlx::Error> {
+    sqlx::query_as!(
+        DecisionNotification,
         r#"
-        UPDATE broadcast_messages 
-        SET status = $1
-        WHERE telegram_id = $2 AND message_type = $3
+        SELECT campaign_id, telegram_id, decision, broadcast_id, created_at
+        FROM decision_notifications WHERE campaign_id = ?
+        ORDER BY created_at DESC
         "#,
-        new_status,
+        campaign_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Ставит кандидату уведомление о решении ответственного по шаблону активной кампании.
+/// Решение окончательное: кандидат получает одно уведомление за кампанию, повторный голос
+/// (в том числе с другим исходом) ничего не отправляет.
+/// Рассылку публикует планировщик api_server, поэтому голос можно принять в любом сервисе.
+/// Возвращает ID созданной рассылки
+pub async fn notify_decision(pool: &SqlitePool, telegram_id: i64, decision: i32) -> Result<Option<String>, sqlx::Error> {
+    let Some(campaign) = get_active_decision_campaign(pool).await? else {
+        return Ok(None);
+    };
+    let (message, message_type) = if decision == 1 {
+        (campaign.approval_message, BroadcastMessageType::SignUp)
+    } else {
+        match campaign.rejection_message {
+            Some(message) => (message, BroadcastMessageType::Custom),
+            None => return Ok(None),
+        }
+    };
+
+    let inserted = sqlx::query!(
+        "INSERT OR IGNORE INTO decision_notifications (campaign_id, telegram_id, decision) VALUES (?, ?, ?)",
+        campaign.id,
         telegram_id,
-        message_type
+        decision
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        println!("⏭️ Уведомление о решении для {} уже поставлено в кампании {}", telegram_id, campaign.id);
+        return Ok(None);
+    }
+
+    let command = CreateBroadcastCommand {
+        message,
+        message_type: Some(message_type),
+        selected_external_users: Some(vec![telegram_id.to_string()]),
+        segment_id: None,
+        media_group: None,
+        send_at: None,
+        keyboard: None,
+        parse_mode: BroadcastParseMode::Plain,
+        split_long_text: false,
+        draft: false,
+        created_by: None,
+        variants: Vec::new(),
+        auto_winner: None,
+    };
+    let created = match handle_create_broadcast(pool, command).await.map_err(|e| e.to_string()) {
+        Ok((created, _)) => created,
+        Err(e) => {
+            // Снимаем отметку, чтобы следующий голос ответственного повторил попытку
+            sqlx::query!(
+                "DELETE FROM decision_notifications WHERE campaign_id = ? AND telegram_id = ? AND broadcast_id IS NULL",
+                campaign.id,
+                telegram_id
+            )
+            .execute(pool)
+            .await?;
+            return Err(sqlx::Error::Protocol(format!("Failed to create broadcast: {}", e)));
+        }
+    };
+    schedule_broadcast_now(pool, &created.broadcast_id).await?;
+
+    sqlx::query!(
+        "UPDATE decision_notifications SET broadcast_id = ? WHERE campaign_id = ? AND telegram_id = ?",
+        created.broadcast_id,
+        campaign.id,
+        telegram_id
     )
     .execute(pool)
     .await?;
-    
-    Ok(result.rows_affected())
+
+    println!("📨 Уведомление о решении для {} поставлено в рассылку {}", telegram_id, created.broadcast_id);
+    Ok(Some(created.broadcast_id))
+}
+
+/// Передает только что созданную рассылку планировщику для немедленной отправки
+async fn schedule_broadcast_now(pool: &SqlitePool, broadcast_id: &str) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query!(
+        "UPDATE broadcast_summaries SET status = 'scheduled', scheduled_at = ? WHERE id = ? AND status = 'pending'",
+        now,
+        broadcast_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,558 @@
+//! Разметка текстов рассылок: режимы Telegram (HTML, MarkdownV2), проверка сущностей,
+//! экранирование, ограничения длины и разбиение длинных текстов на несколько сообщений.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::template::{self, char_position};
+use crate::MediaGroup;
+
+// Ограничения Telegram на длину после разбора разметки (в единицах UTF-16)
+pub const MAX_TEXT_LENGTH: usize = 4096;
+pub const MAX_CAPTION_LENGTH: usize = 1024;
+
+// Теги, которые понимает Telegram в режиме HTML
+const HTML_TAGS: [&str; 16] = [
+    "b", "strong", "i", "em", "u", "ins", "s", "strike", "del", "span", "tg-spoiler", "a", "code", "pre", "blockquote",
+    "tg-emoji",
+];
+const HTML_NAMED_ENTITIES: [&str; 4] = ["lt", "gt", "amp", "quot"];
+// Символы MarkdownV2, которые вне разметки нужно экранировать обратной косой чертой
+const MARKDOWN_V2_RESERVED: &str = "_*[]()~`>#+-=|{}.!";
+
+/// Режим разметки текста рассылки
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastParseMode {
+    /// Обычный текст: экранируется и отправляется как HTML
+    #[default]
+    Plain,
+    Html,
+    MarkdownV2,
+}
+
+/// Ошибка разметки; позиция — номер символа от начала текста
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ошибка разметки (символ {}): {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Экранирует значение для вставки в текст с указанной разметкой
+pub fn escape(text: &str, parse_mode: BroadcastParseMode) -> String {
+    match parse_mode {
+        BroadcastParseMode::Plain => text.to_string(),
+        BroadcastParseMode::Html => escape_html(text),
+        BroadcastParseMode::MarkdownV2 => escape_markdown_v2(text),
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn escape_markdown_v2(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\\' || MARKDOWN_V2_RESERVED.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Место, где текст можно разрезать: после пробела или переноса строки вне сущностей
+struct Break {
+    offset: usize,
+    visible: usize,
+    newline: bool,
+}
+
+/// Результат разбора: видимая длина, точки разреза и первый символ сверх лимита
+struct Analysis {
+    visible: usize,
+    breaks: Vec<Break>,
+    overflow_at: Option<usize>,
+}
+
+struct Analyzer<'a> {
+    text: &'a str,
+    limit: usize,
+    analysis: Analysis,
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(text: &'a str, limit: usize) -> Self {
+        Analyzer {
+            text,
+            limit,
+            analysis: Analysis { visible: 0, breaks: Vec::new(), overflow_at: None },
+        }
+    }
+
+    /// Учитывает видимый символ; на верхнем уровне пробелы становятся точками разреза
+    fn visible(&mut self, offset: usize, c: char, top_level: bool) {
+        self.analysis.visible += c.len_utf16();
+        if self.analysis.visible > self.limit && self.analysis.overflow_at.is_none() {
+            self.analysis.overflow_at = Some(offset);
+        }
+        if top_level && (c == ' ' || c == '\n') {
+            self.analysis.breaks.push(Break {
+                offset: offset + c.len_utf8(),
+                visible: self.analysis.visible,
+                newline: c == '\n',
+            });
+        }
+    }
+
+    fn error(&self, offset: usize, message: impl Into<String>) -> FormatError {
+        FormatError {
+            position: char_position(self.text, offset),
+            message: message.into(),
+        }
+    }
+}
+
+fn analyze(text: &str, parse_mode: BroadcastParseMode, limit: usize) -> Result<Analysis, FormatError> {
+    let mut analyzer = Analyzer::new(text, limit);
+    match parse_mode {
+        BroadcastParseMode::Plain => {
+            for (offset, c) in text.char_indices() {
+                analyzer.visible(offset, c, true);
+            }
+        }
+        BroadcastParseMode::Html => analyze_html(&mut analyzer)?,
+        BroadcastParseMode::MarkdownV2 => analyze_markdown_v2(&mut analyzer)?,
+    }
+    Ok(analyzer.analysis)
+}
+
+fn analyze_html(analyzer: &mut Analyzer) -> Result<(), FormatError> {
+    let text = analyzer.text;
+    let mut open_tags: Vec<(String, usize)> = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '<' => {
+                let Some(length) = text[offset..].find('>') else {
+                    return Err(analyzer.error(offset, "не закрыт тег, ожидается >"));
+                };
+                let body = &text[offset + 1..offset + length];
+                while chars.peek().is_some_and(|(next, _)| *next <= offset + length) {
+                    chars.next();
+                }
+
+                if let Some(name) = body.strip_prefix('/') {
+                    let name = name.trim().to_lowercase();
+                    match open_tags.pop() {
+                        Some((open, _)) if open == name => {}
+                        Some((open, _)) => {
+                            return Err(analyzer.error(offset, format!("</{}> закрывает незакрытый тег <{}>", name, open)));
+                        }
+                        None => return Err(analyzer.error(offset, format!("лишний закрывающий тег </{}>", name))),
+                    }
+                    continue;
+                }
+
+                let name = body.split_whitespace().next().unwrap_or_default().to_lowercase();
+                if !HTML_TAGS.contains(&name.as_str()) {
+                    return Err(analyzer.error(
+                        offset,
+                        format!("тег <{}> не поддерживается, доступны: {}", name, HTML_TAGS.join(", ")),
+                    ));
+                }
+                if name == "a" && !body.contains("href=") {
+                    return Err(analyzer.error(offset, "у ссылки <a> нет href"));
+                }
+                if name == "span" && !body.contains("tg-spoiler") {
+                    return Err(analyzer.error(offset, "<span> поддерживается только с class=\"tg-spoiler\""));
+                }
+                open_tags.push((name, offset));
+            }
+            '&' => {
+                let entity = text[offset + 1..].split(';').next().filter(|_| text[offset + 1..].contains(';'));
+                let valid = entity.is_some_and(|entity| {
+                    HTML_NAMED_ENTITIES.contains(&entity)
+                        || entity.strip_prefix("#x").is_some_and(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                        || entity.strip_prefix('#').is_some_and(|dec| !dec.is_empty() && dec.chars().all(|c| c.is_ascii_digit()))
+                });
+                let Some(entity) = entity.filter(|_| valid) else {
+                    return Err(analyzer.error(offset, "символ & нужно заменить на &amp;"));
+                };
+                let end = offset + 1 + entity.len();
+                while chars.peek().is_some_and(|(next, _)| *next <= end) {
+                    chars.next();
+                }
+                analyzer.visible(offset, c, false);
+            }
+            '>' => return Err(analyzer.error(offset, "символ > нужно заменить на &gt;")),
+            _ => analyzer.visible(offset, c, open_tags.is_empty()),
+        }
+    }
+
+    match open_tags.pop() {
+        Some((name, offset)) => Err(analyzer.error(offset, format!("не закрыт тег <{}>", name))),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MarkdownEntity {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    LinkText,
+}
+
+impl MarkdownEntity {
+    fn marker(&self) -> &'static str {
+        match self {
+            MarkdownEntity::Bold => "*",
+            MarkdownEntity::Italic => "_",
+            MarkdownEntity::Underline => "__",
+            MarkdownEntity::Strikethrough => "~",
+            MarkdownEntity::Spoiler => "||",
+            MarkdownEntity::LinkText => "[",
+        }
+    }
+}
+
+fn analyze_markdown_v2(analyzer: &mut Analyzer) -> Result<(), FormatError> {
+    let text = analyzer.text;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let char_at = |index: usize| chars.get(index).map(|(_, c)| *c);
+    let mut open: Vec<(MarkdownEntity, usize)> = Vec::new();
+    let mut index = 0;
+
+    // Открывает сущность или закрывает ее, если она последняя открытая
+    let toggle = |open: &mut Vec<(MarkdownEntity, usize)>, entity: MarkdownEntity, offset: usize| -> Result<(), FormatError> {
+        match open.last() {
+            Some((last, _)) if *last == entity => {
+                open.pop();
+                Ok(())
+            }
+            _ if open.iter().any(|(opened, _)| *opened == entity) => Err(FormatError {
+                position: char_position(text, offset),
+                message: format!("сущности «{}» пересекаются с вложенными, закройте их по порядку", entity.marker()),
+            }),
+            _ => {
+                open.push((entity, offset));
+                Ok(())
+            }
+        }
+    };
+
+    while let Some((offset, c)) = chars.get(index).copied() {
+        let top_level = open.is_empty();
+        match c {
+            '\\' => {
+                let Some(escaped) = char_at(index + 1).filter(|escaped| (*escaped as u32) < 127) else {
+                    return Err(analyzer.error(offset, "после \\ ожидается экранируемый символ"));
+                };
+                analyzer.visible(chars[index + 1].0, escaped, false);
+                index += 2;
+            }
+            '`' => {
+                let is_pre = char_at(index + 1) == Some('`') && char_at(index + 2) == Some('`');
+                let marker_len = if is_pre { 3 } else { 1 };
+                let mut cursor = index + marker_len;
+                // Язык блока кода на первой строке не виден
+                if is_pre {
+                    while char_at(cursor).is_some_and(|c| c != '\n' && c != '`') {
+                        cursor += 1;
+                    }
+                }
+                loop {
+                    match char_at(cursor) {
+                        None => return Err(analyzer.error(offset, format!("не закрыт блок кода {}", "`".repeat(marker_len)))),
+                        Some('\\') => {
+                            if let Some(escaped) = char_at(cursor + 1) {
+                                analyzer.visible(chars[cursor + 1].0, escaped, false);
+                            }
+                            cursor += 2;
+                        }
+                        Some('`') if !is_pre || (char_at(cursor + 1) == Some('`') && char_at(cursor + 2) == Some('`')) => {
+                            index = cursor + marker_len;
+                            break;
+                        }
+                        Some(c) => {
+                            analyzer.visible(chars[cursor].0, c, false);
+                            cursor += 1;
+                        }
+                    }
+                }
+            }
+            '*' => {
+                toggle(&mut open, MarkdownEntity::Bold, offset)?;
+                index += 1;
+            }
+            '_' if char_at(index + 1) == Some('_') => {
+                toggle(&mut open, MarkdownEntity::Underline, offset)?;
+                index += 2;
+            }
+            '_' => {
+                toggle(&mut open, MarkdownEntity::Italic, offset)?;
+                index += 1;
+            }
+            '~' => {
+                toggle(&mut open, MarkdownEntity::Strikethrough, offset)?;
+                index += 1;
+            }
+            '|' if char_at(index + 1) == Some('|') => {
+                toggle(&mut open, MarkdownEntity::Spoiler, offset)?;
+                index += 2;
+            }
+            '[' => {
+                open.push((MarkdownEntity::LinkText, offset));
+                index += 1;
+            }
+            // Кастомный эмодзи: ![👍](tg://emoji?id=...)
+            '!' if char_at(index + 1) == Some('[') => {
+                open.push((MarkdownEntity::LinkText, offset));
+                index += 2;
+            }
+            ']' => {
+                if !matches!(open.last(), Some((MarkdownEntity::LinkText, _))) {
+                    return Err(analyzer.error(offset, "символ «]» нужно экранировать: \\]"));
+                }
+                open.pop();
+                if char_at(index + 1) != Some('(') {
+                    return Err(analyzer.error(offset, "после [текста] ожидается (ссылка)"));
+                }
+                let mut cursor = index + 2;
+                loop {
+                    match char_at(cursor) {
+                        None => return Err(analyzer.error(offset, "не закрыта ссылка, ожидается )")),
+                        Some('\\') => cursor += 2,
+                        Some(')') => break,
+                        Some(_) => cursor += 1,
+                    }
+                }
+                index = cursor + 1;
+            }
+            // Цитата: > в начале строки
+            '>' if index == 0 || char_at(index - 1) == Some('\n') => {
+                index += 1;
+            }
+            c if MARKDOWN_V2_RESERVED.contains(c) => {
+                return Err(analyzer.error(offset, format!("символ «{}» нужно экранировать: \\{}", c, c)));
+            }
+            c => {
+                analyzer.visible(offset, c, top_level);
+                index += 1;
+            }
+        }
+    }
+
+    match open.pop() {
+        Some((entity, offset)) => Err(analyzer.error(offset, format!("не закрыта сущность «{}»", entity.marker()))),
+        None => Ok(()),
+    }
+}
+
+/// Проверяет разметку и длину текста
+pub fn validate_text(text: &str, parse_mode: BroadcastParseMode, limit: usize) -> Result<(), FormatError> {
+    let analysis = analyze(text, parse_mode, limit)?;
+    match analysis.overflow_at {
+        Some(offset) => Err(FormatError {
+            position: char_position(text, offset),
+            message: format!("текст длиннее {} символов ({})", limit, analysis.visible),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Делит длинный текст на части не длиннее лимита. Режет по переносам строк, затем по пробелам,
+/// и только вне сущностей разметки, чтобы каждая часть оставалась корректной
+pub fn split_text(text: &str, parse_mode: BroadcastParseMode, limit: usize) -> Result<Vec<String>, FormatError> {
+    let analysis = analyze(text, parse_mode, limit)?;
+    if analysis.overflow_at.is_none() {
+        return Ok(vec![text.to_string()]);
+    }
+
+    let mut parts = Vec::new();
+    let (mut start, mut start_visible) = (0, 0);
+    while analysis.visible - start_visible > limit {
+        let mut fitting = analysis
+            .breaks
+            .iter()
+            .filter(|point| point.offset > start && point.visible - start_visible <= limit);
+        let Some(point) = fitting.clone().rfind(|point| point.newline).or_else(|| fitting.next_back()) else {
+            return Err(FormatError {
+                position: char_position(text, start),
+                message: format!("не удалось разбить текст: нет пробела вне разметки в пределах {} символов", limit),
+            });
+        };
+        let part = text[start..point.offset].trim();
+        if !part.is_empty() {
+            parts.push(part.to_string());
+        }
+        start = point.offset;
+        start_visible = point.visible;
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+
+    Ok(parts)
+}
+
+/// Проверяет разметку и длину рассылки при создании. Переменные шаблона заменяются
+/// заглушками той же длины, поэтому позиции ошибок совпадают с исходным текстом.
+/// С медиагруппой отправляются только подписи, без нее — только текст
+pub fn validate_broadcast(
+    message: &str,
+    media_group: Option<&MediaGroup>,
+    parse_mode: BroadcastParseMode,
+    split_long_text: bool,
+) -> Result<(), FormatError> {
+    validate_content(message, media_group, parse_mode, split_long_text, template::mask_variables)
+}
+
+/// Проверяет текст, уже подставленный для получателя: значения переменных бывают длиннее
+/// заглушек, с которыми рассылка проверялась при создании
+pub fn validate_rendered(
+    message: &str,
+    media_group: Option<&MediaGroup>,
+    parse_mode: BroadcastParseMode,
+    split_long_text: bool,
+) -> Result<(), FormatError> {
+    validate_content(message, media_group, parse_mode, split_long_text, str::to_string)
+}
+
+fn validate_content(
+    message: &str,
+    media_group: Option<&MediaGroup>,
+    parse_mode: BroadcastParseMode,
+    split_long_text: bool,
+    prepare: fn(&str) -> String,
+) -> Result<(), FormatError> {
+    let Some(media_group) = media_group else {
+        let text = prepare(message);
+        if text.trim().is_empty() {
+            return Err(FormatError { position: 0, message: "пустой текст сообщения".to_string() });
+        }
+        return if split_long_text {
+            split_text(&text, parse_mode, MAX_TEXT_LENGTH).map(|_| ())
+        } else {
+            validate_text(&text, parse_mode, MAX_TEXT_LENGTH)
+        };
+    };
+
+    for (index, item) in media_group.media.iter().enumerate() {
+        let Some(caption) = &item.caption else {
+            continue;
+        };
+        validate_text(&prepare(caption), parse_mode, MAX_CAPTION_LENGTH).map_err(|e| FormatError {
+            position: e.position,
+            message: format!("подпись к файлу {}: {}", index + 1, e.message),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str, parse_mode: BroadcastParseMode) -> FormatError {
+        validate_text(text, parse_mode, MAX_TEXT_LENGTH).unwrap_err()
+    }
+
+    #[test]
+    fn rejects_unbalanced_html_tags() {
+        let unclosed = error("Привет, <b>мир", BroadcastParseMode::Html);
+        assert_eq!((unclosed.position, unclosed.message.as_str()), (8, "не закрыт тег <b>"));
+
+        let crossed = error("<b>жирный <i>курсив</b></i>", BroadcastParseMode::Html);
+        assert_eq!(crossed.position, 19);
+        assert!(crossed.message.contains("</b> закрывает незакрытый тег <i>"));
+
+        assert!(error("текст</b>", BroadcastParseMode::Html).message.contains("лишний закрывающий тег"));
+        assert!(error("<script>x</script>", BroadcastParseMode::Html).message.contains("не поддерживается"));
+        assert_eq!(validate_text("<b>жирный <i>курсив</i></b>", BroadcastParseMode::Html, MAX_TEXT_LENGTH), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unbalanced_markdown_entities() {
+        assert_eq!(error("Важно: *жирный", BroadcastParseMode::MarkdownV2).position, 7);
+        assert!(error("*a _b* c_", BroadcastParseMode::MarkdownV2).message.contains("пересекаются"));
+        assert!(error("[ссылка", BroadcastParseMode::MarkdownV2).message.contains("не закрыта сущность «[»"));
+        assert!(error("Итого: 5.", BroadcastParseMode::MarkdownV2).message.contains("\\."));
+    }
+
+    #[test]
+    fn escaped_text_passes_validation_and_counts_visible_characters() {
+        let raw = "1 + 1 = 2. <b> & [скобки] (да)!";
+        assert_eq!(escape_html("a<b>&c"), "a&lt;b&gt;&amp;c");
+        assert_eq!(escape_markdown_v2("1+1=2."), "1\\+1\\=2\\.");
+        assert_eq!(escape(raw, BroadcastParseMode::Plain), raw);
+        for parse_mode in [BroadcastParseMode::Html, BroadcastParseMode::MarkdownV2] {
+            assert_eq!(validate_text(&escape(raw, parse_mode), parse_mode, MAX_TEXT_LENGTH), Ok(()));
+        }
+
+        // Экранированный символ занимает одну позицию из лимита
+        assert_eq!(validate_text(&"&amp;".repeat(MAX_CAPTION_LENGTH), BroadcastParseMode::Html, MAX_CAPTION_LENGTH), Ok(()));
+        assert!(validate_text(&"\\.".repeat(MAX_CAPTION_LENGTH + 1), BroadcastParseMode::MarkdownV2, MAX_CAPTION_LENGTH).is_err());
+        assert!(validate_text("a & b", BroadcastParseMode::Html, MAX_TEXT_LENGTH).is_err());
+    }
+
+    #[test]
+    fn limits_are_inclusive_and_counted_in_utf16() {
+        let text = "я".repeat(MAX_TEXT_LENGTH);
+        assert_eq!(validate_text(&text, BroadcastParseMode::Plain, MAX_TEXT_LENGTH), Ok(()));
+        assert_eq!(split_text(&text, BroadcastParseMode::Plain, MAX_TEXT_LENGTH), Ok(vec![text.clone()]));
+        assert_eq!(error(&format!("{}я", text), BroadcastParseMode::Plain).position, MAX_TEXT_LENGTH);
+
+        // Эмодзи вне BMP занимает две единицы UTF-16
+        let caption = "😀".repeat(MAX_CAPTION_LENGTH / 2);
+        assert_eq!(validate_text(&caption, BroadcastParseMode::Plain, MAX_CAPTION_LENGTH), Ok(()));
+        assert!(validate_text(&format!("{}a", caption), BroadcastParseMode::Plain, MAX_CAPTION_LENGTH).is_err());
+    }
+
+    #[test]
+    fn splits_exactly_at_limit_without_breaking_entities() {
+        // Пробел — последний символ, который помещается в первую часть
+        let text = format!("{} {}", "a".repeat(MAX_TEXT_LENGTH - 1), "b".repeat(10));
+        let parts = split_text(&text, BroadcastParseMode::Plain, MAX_TEXT_LENGTH).unwrap();
+        assert_eq!(parts, vec!["a".repeat(MAX_TEXT_LENGTH - 1), "b".repeat(10)]);
+
+        // Пробелы внутри <b> не точки разреза: жирный текст целиком уходит во вторую часть
+        let bold = format!("<b>{}</b>", "жирный ".repeat(20).trim_end());
+        let text = format!("{} {}", "a".repeat(MAX_TEXT_LENGTH - 100), bold);
+        let parts = split_text(&text, BroadcastParseMode::Html, MAX_TEXT_LENGTH).unwrap();
+        assert_eq!(parts, vec!["a".repeat(MAX_TEXT_LENGTH - 100), bold]);
+        for part in &parts {
+            assert_eq!(validate_text(part, BroadcastParseMode::Html, MAX_TEXT_LENGTH), Ok(()));
+        }
+
+        let caption = format!("*{}*", "слово ".repeat(200));
+        assert!(split_text(&caption, BroadcastParseMode::MarkdownV2, MAX_CAPTION_LENGTH)
+            .unwrap_err()
+            .message
+            .contains("нет пробела вне разметки"));
+    }
+
+    #[test]
+    fn rendered_text_is_checked_without_masking() {
+        let message = format!("{} {{{{full_name}}}}", "a".repeat(MAX_TEXT_LENGTH - 20));
+        assert_eq!(validate_broadcast(&message, None, BroadcastParseMode::Plain, false), Ok(()));
+
+        let rendered = format!("{} Константинопольский Константин", "a".repeat(MAX_TEXT_LENGTH - 20));
+        assert!(validate_rendered(&rendered, None, BroadcastParseMode::Plain, false).is_err());
+        assert_eq!(validate_rendered(&rendered, None, BroadcastParseMode::Plain, true), Ok(()));
+    }
+}
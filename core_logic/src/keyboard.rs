@@ -0,0 +1,203 @@
+//! Inline-клавиатуры рассылок: URL-кнопки и кнопки действий бота в несколько рядов.
+//! Клавиатура хранится в событии BroadcastCreated и едет в каждом сообщении очереди.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Ограничения Telegram на inline-клавиатуру
+const MAX_BUTTONS: usize = 100;
+const MAX_BUTTONS_PER_ROW: usize = 8;
+const MAX_BUTTON_TEXT_LENGTH: usize = 64;
+const MAX_URL_LENGTH: usize = 2048;
+const ALLOWED_URL_SCHEMES: [&str; 3] = ["https://", "http://", "tg://"];
+
+/// Действие бота, которое выполняет кнопка рассылки
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BotAction {
+    /// Открыть выбор слота
+    SignUp,
+    /// Сразу предложить конкретный слот
+    Book { slot_id: i64 },
+}
+
+/// Что делает кнопка
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ButtonKind {
+    Url { url: String },
+    Callback { action: BotAction },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct KeyboardButton {
+    pub text: String,
+    #[serde(flatten)]
+    pub kind: ButtonKind,
+}
+
+impl KeyboardButton {
+    pub fn callback(text: &str, action: BotAction) -> Self {
+        KeyboardButton {
+            text: text.to_string(),
+            kind: ButtonKind::Callback { action },
+        }
+    }
+}
+
+/// Клавиатура рассылки: ряды кнопок сверху вниз
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct BroadcastKeyboard {
+    pub rows: Vec<Vec<KeyboardButton>>,
+}
+
+/// Ошибка проверки клавиатуры; ряд и кнопка нумеруются с единицы
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardError {
+    pub row: Option<usize>,
+    pub button: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.row, self.button) {
+            (Some(row), Some(button)) => write!(f, "Ошибка в клавиатуре (ряд {}, кнопка {}): {}", row, button, self.message),
+            (Some(row), None) => write!(f, "Ошибка в клавиатуре (ряд {}): {}", row, self.message),
+            _ => write!(f, "Ошибка в клавиатуре: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for KeyboardError {}
+
+impl BroadcastKeyboard {
+    /// Кнопка «Записаться» рассылок типа SignUp без своей клавиатуры
+    pub fn sign_up(text: &str) -> Self {
+        BroadcastKeyboard {
+            rows: vec![vec![KeyboardButton::callback(text, BotAction::SignUp)]],
+        }
+    }
+
+    /// Проверяет клавиатуру на ограничения Telegram
+    pub fn validate(&self) -> Result<(), KeyboardError> {
+        let error = |row: Option<usize>, button: Option<usize>, message: String| KeyboardError { row, button, message };
+
+        if self.rows.is_empty() {
+            return Err(error(None, None, "нет ни одного ряда".to_string()));
+        }
+        let total: usize = self.rows.iter().map(Vec::len).sum();
+        if total > MAX_BUTTONS {
+            return Err(error(None, None, format!("кнопок {}, допускается не больше {}", total, MAX_BUTTONS)));
+        }
+
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let row_number = Some(row_index + 1);
+            if row.is_empty() {
+                return Err(error(row_number, None, "пустой ряд".to_string()));
+            }
+            if row.len() > MAX_BUTTONS_PER_ROW {
+                return Err(error(
+                    row_number,
+                    None,
+                    format!("кнопок в ряду {}, допускается не больше {}", row.len(), MAX_BUTTONS_PER_ROW),
+                ));
+            }
+
+            for (button_index, button) in row.iter().enumerate() {
+                let button_number = Some(button_index + 1);
+                let text_length = button.text.trim().chars().count();
+                if text_length == 0 {
+                    return Err(error(row_number, button_number, "пустой текст кнопки".to_string()));
+                }
+                if text_length > MAX_BUTTON_TEXT_LENGTH {
+                    return Err(error(
+                        row_number,
+                        button_number,
+                        format!("текст длиннее {} символов", MAX_BUTTON_TEXT_LENGTH),
+                    ));
+                }
+                if let ButtonKind::Url { url } = &button.kind {
+                    if url.len() > MAX_URL_LENGTH || !ALLOWED_URL_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+                        return Err(error(
+                            row_number,
+                            button_number,
+                            format!("ссылка должна начинаться с {} и быть не длиннее {} символов", ALLOWED_URL_SCHEMES.join(", "), MAX_URL_LENGTH),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url_button(url: &str) -> KeyboardButton {
+        KeyboardButton {
+            text: "Сайт".to_string(),
+            kind: ButtonKind::Url { url: url.to_string() },
+        }
+    }
+
+    fn keyboard(rows: Vec<Vec<KeyboardButton>>) -> BroadcastKeyboard {
+        BroadcastKeyboard { rows }
+    }
+
+    #[test]
+    fn accepts_rows_and_buttons_up_to_telegram_limits() {
+        let button = KeyboardButton::callback("Записаться", BotAction::SignUp);
+        let full_row = vec![button.clone(); MAX_BUTTONS_PER_ROW];
+
+        assert_eq!(BroadcastKeyboard::sign_up("Записаться").validate(), Ok(()));
+        assert_eq!(keyboard(vec![full_row.clone()]).validate(), Ok(()));
+        let rows = MAX_BUTTONS / MAX_BUTTONS_PER_ROW;
+        let mut at_limit = vec![full_row.clone(); rows];
+        at_limit.push(vec![button.clone(); MAX_BUTTONS - rows * MAX_BUTTONS_PER_ROW]);
+        assert_eq!(keyboard(at_limit.clone()).validate(), Ok(()));
+
+        at_limit.push(vec![button.clone()]);
+        assert_eq!(keyboard(at_limit).validate().unwrap_err().row, None);
+    }
+
+    #[test]
+    fn rejects_empty_and_overfull_rows_with_their_number() {
+        let button = KeyboardButton::callback("Слот", BotAction::Book { slot_id: 7 });
+
+        assert!(keyboard(Vec::new()).validate().is_err());
+        let error = keyboard(vec![vec![button.clone()], Vec::new()]).validate().unwrap_err();
+        assert_eq!((error.row, error.button), (Some(2), None));
+        let error = keyboard(vec![vec![button; MAX_BUTTONS_PER_ROW + 1]]).validate().unwrap_err();
+        assert_eq!((error.row, error.button), (Some(1), None));
+    }
+
+    #[test]
+    fn limits_button_text_in_characters() {
+        let text = |length: usize| KeyboardButton::callback(&"я".repeat(length), BotAction::SignUp);
+
+        assert_eq!(keyboard(vec![vec![text(MAX_BUTTON_TEXT_LENGTH)]]).validate(), Ok(()));
+        let error = keyboard(vec![vec![text(1), text(MAX_BUTTON_TEXT_LENGTH + 1)]]).validate().unwrap_err();
+        assert_eq!((error.row, error.button), (Some(1), Some(2)));
+        assert!(keyboard(vec![vec![KeyboardButton::callback("  ", BotAction::SignUp)]]).validate().is_err());
+    }
+
+    #[test]
+    fn accepts_only_allowed_url_schemes_and_lengths() {
+        for url in ["https://example.com", "http://example.com/a?b=c", "tg://resolve?domain=test_bot"] {
+            assert_eq!(keyboard(vec![vec![url_button(url)]]).validate(), Ok(()), "{}", url);
+        }
+        for url in ["javascript:alert(1)", "example.com", "ftp://example.com", ""] {
+            assert!(keyboard(vec![vec![url_button(url)]]).validate().is_err(), "{}", url);
+        }
+
+        let base = "https://example.com/";
+        let longest = format!("{}{}", base, "a".repeat(MAX_URL_LENGTH - base.len()));
+        assert_eq!(keyboard(vec![vec![url_button(&longest)]]).validate(), Ok(()));
+        let error = keyboard(vec![vec![url_button(&format!("{}a", longest))]]).validate().unwrap_err();
+        assert_eq!((error.row, error.button), (Some(1), Some(1)));
+    }
+}
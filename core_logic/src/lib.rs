@@ -1,5 +1,9 @@
 pub mod db;
 pub mod rabbitmq;
+pub mod template;
+pub mod keyboard;
+pub mod formatting;
+pub mod variants;
 
 pub use db::{
     get_available_slots,
@@ -23,7 +27,7 @@ pub use db::{
     // Event Store functions
     save_broadcast_event, get_broadcast_events, is_event_processed,
     // Read Model functions
-    create_broadcast_summary, update_broadcast_summary, update_broadcast_status, update_broadcast_summary_from_messages, get_broadcast_summary, get_all_broadcast_summaries,
+    create_broadcast_summary, update_broadcast_summary, update_broadcast_summary_from_messages, get_broadcast_summary, get_all_broadcast_summaries,
     create_broadcast_message, update_broadcast_message, update_broadcast_message_status, get_broadcast_messages,
     // Command handlers
     handle_create_broadcast, handle_retry_message, handle_cancel_broadcast,
@@ -38,7 +42,7 @@ pub use db::{
     authenticate_user, get_user_role_from_db,
 };
 
-pub use rabbitmq::{RabbitMQClient, EventsWorker, MessagesWorker};
+pub use rabbitmq::{RabbitMQClient, EventsWorker, MessagesWorker, MessageOutcome};
 
 use chrono::{DateTime, Utc, NaiveDateTime};
 use serde::{Deserialize, Serialize};
@@ -218,7 +222,7 @@ pub struct BroadcastRequest {
     pub include_users_without_telegram: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct BroadcastMessage {
     pub telegram_id: i64,
     pub message: String,
@@ -226,6 +230,40 @@ pub struct BroadcastMessage {
     pub message_type: Option<BroadcastMessageType>,
     pub media_group: Option<MediaGroup>,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub keyboard: Option<keyboard::BroadcastKeyboard>,
+    #[serde(default)]
+    pub parse_mode: formatting::BroadcastParseMode,
+    #[serde(default)]
+    pub split_long_text: bool,
+    /// Тестовая отправка сотруднику: статусы и статистика рассылки не меняются
+    #[serde(default)]
+    pub is_test: bool,
+    #[serde(default)]
+    pub action: BroadcastMessageAction,
+    /// Доставленные сообщения для правки или удаления
+    #[serde(default)]
+    pub telegram_message_ids: Vec<i32>,
+}
+
+/// Что воркер делает с сообщением рассылки
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastMessageAction {
+    #[default]
+    Send,
+    /// Заменить текст или подпись доставленного сообщения
+    Edit,
+    /// Удалить доставленное сообщение у получателя
+    Delete,
+}
+
+/// Сообщение рассылки, исчерпавшее попытки доставки (лежит в dead-letter очереди)
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeadLetterMessage {
+    pub message: BroadcastMessage,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -249,6 +287,23 @@ pub enum BroadcastEvent {
         message_type: Option<BroadcastMessageType>,
         media_group: Option<MediaGroup>,
         created_at: DateTime<Utc>,
+        /// Сегмент, который Event Worker вычисляет при отправке; target_users тогда пуст
+        #[serde(default)]
+        segment_id: Option<i64>,
+        #[serde(default)]
+        keyboard: Option<keyboard::BroadcastKeyboard>,
+        #[serde(default)]
+        parse_mode: formatting::BroadcastParseMode,
+        #[serde(default)]
+        split_long_text: bool,
+        /// Автор рассылки; одобрить крупную рассылку может только другой ответственный
+        #[serde(default)]
+        created_by: Option<i64>,
+        /// A/B-варианты в дополнение к основному тексту (варианту A)
+        #[serde(default)]
+        variants: Vec<variants::BroadcastVariant>,
+        #[serde(default)]
+        auto_winner: Option<variants::AutoWinnerSettings>,
     },
     BroadcastStarted {
         broadcast_id: String,
@@ -277,6 +332,35 @@ pub enum BroadcastEvent {
         total_failed: u32,
         completed_at: DateTime<Utc>,
     },
+    BroadcastCancelled {
+        broadcast_id: String,
+        cancelled_at: DateTime<Utc>,
+    },
+    BroadcastPaused {
+        broadcast_id: String,
+        paused_at: DateTime<Utc>,
+    },
+    BroadcastResumed {
+        broadcast_id: String,
+        resumed_at: DateTime<Utc>,
+    },
+    BroadcastApproved {
+        broadcast_id: String,
+        approved_by: i64,
+        approved_at: DateTime<Utc>,
+    },
+    BroadcastRejected {
+        broadcast_id: String,
+        rejected_by: i64,
+        reason: Option<String>,
+        rejected_at: DateTime<Utc>,
+    },
+    /// Автовыбор A/B-теста: победивший вариант уходит остальной аудитории
+    BroadcastWinnerSelected {
+        broadcast_id: String,
+        variant: usize,
+        selected_at: DateTime<Utc>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -302,6 +386,8 @@ pub struct BroadcastSummary {
     pub created_at: NaiveDateTime,
     pub started_at: Option<NaiveDateTime>,
     pub completed_at: Option<NaiveDateTime>,
+    /// Время отложенной отправки (UTC)
+    pub scheduled_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
@@ -311,6 +397,21 @@ pub enum BroadcastStatus {
     InProgress,
     Completed,
     Failed,
+    Scheduled,
+    Paused,
+    Cancelled,
+    /// Черновик: не отправляется, пока его не выпустят
+    Draft,
+    /// Крупная рассылка ждет одобрения вторым ответственным
+    #[serde(rename = "pending_approval")]
+    PendingApproval,
+}
+
+impl BroadcastStatus {
+    /// Рассылка завершена и больше не меняет статус
+    pub fn is_final(&self) -> bool {
+        matches!(self, BroadcastStatus::Completed | BroadcastStatus::Failed | BroadcastStatus::Cancelled)
+    }
 }
 
 impl std::fmt::Display for BroadcastStatus {
@@ -320,6 +421,11 @@ impl std::fmt::Display for BroadcastStatus {
             BroadcastStatus::InProgress => write!(f, "in_progress"),
             BroadcastStatus::Completed => write!(f, "completed"),
             BroadcastStatus::Failed => write!(f, "failed"),
+            BroadcastStatus::Scheduled => write!(f, "scheduled"),
+            BroadcastStatus::Paused => write!(f, "paused"),
+            BroadcastStatus::Cancelled => write!(f, "cancelled"),
+            BroadcastStatus::Draft => write!(f, "draft"),
+            BroadcastStatus::PendingApproval => write!(f, "pending_approval"),
         }
     }
 }
@@ -331,6 +437,11 @@ impl From<String> for BroadcastStatus {
             "in_progress" => BroadcastStatus::InProgress,
             "completed" => BroadcastStatus::Completed,
             "failed" => BroadcastStatus::Failed,
+            "scheduled" => BroadcastStatus::Scheduled,
+            "paused" => BroadcastStatus::Paused,
+            "cancelled" => BroadcastStatus::Cancelled,
+            "draft" => BroadcastStatus::Draft,
+            "pending_approval" => BroadcastStatus::PendingApproval,
             _ => BroadcastStatus::Pending,
         }
     }
@@ -347,6 +458,11 @@ pub struct BroadcastMessageRecord {
     pub retry_count: i64,
     pub message_type: Option<BroadcastMessageType>,
     pub created_at: NaiveDateTime,
+    /// Результат последней правки или отзыва; None, если сообщение не правилось
+    pub edit_status: Option<MessageEditStatus>,
+    pub edit_error: Option<String>,
+    /// A/B-вариант получателя: 0 — A; None, если вариантов нет
+    pub variant: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
@@ -356,6 +472,7 @@ pub enum MessageStatus {
     Sent,
     Failed,
     Retrying,
+    Cancelled,
 }
 
 impl std::fmt::Display for MessageStatus {
@@ -365,6 +482,7 @@ impl std::fmt::Display for MessageStatus {
             MessageStatus::Sent => write!(f, "sent"),
             MessageStatus::Failed => write!(f, "failed"),
             MessageStatus::Retrying => write!(f, "retrying"),
+            MessageStatus::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -376,18 +494,338 @@ impl From<String> for MessageStatus {
             "sent" => MessageStatus::Sent,
             "failed" => MessageStatus::Failed,
             "retrying" => MessageStatus::Retrying,
+            "cancelled" => MessageStatus::Cancelled,
             _ => MessageStatus::Pending,
         }
     }
 }
 
+/// Статус правки или удаления доставленного сообщения
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEditStatus {
+    Pending,
+    Edited,
+    Deleted,
+    Failed,
+}
+
+impl std::fmt::Display for MessageEditStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageEditStatus::Pending => write!(f, "pending"),
+            MessageEditStatus::Edited => write!(f, "edited"),
+            MessageEditStatus::Deleted => write!(f, "deleted"),
+            MessageEditStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl From<String> for MessageEditStatus {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "edited" => MessageEditStatus::Edited,
+            "deleted" => MessageEditStatus::Deleted,
+            "failed" => MessageEditStatus::Failed,
+            _ => MessageEditStatus::Pending,
+        }
+    }
+}
+
+/// Класс ошибки доставки сообщения в Telegram
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryErrorKind {
+    /// Пользователь заблокировал бота
+    Blocked,
+    /// Чат не найден (пользователь не писал боту или удален)
+    ChatNotFound,
+    /// Аккаунт пользователя удален
+    Deactivated,
+    /// Временная ошибка (сеть, ошибка Telegram), можно повторить
+    Transient,
+}
+
+impl DeliveryErrorKind {
+    /// Ошибка означает, что пользователь недоступен до тех пор, пока сам не напишет боту
+    pub fn is_permanent(&self) -> bool {
+        !matches!(self, DeliveryErrorKind::Transient)
+    }
+}
+
+impl std::fmt::Display for DeliveryErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryErrorKind::Blocked => write!(f, "blocked"),
+            DeliveryErrorKind::ChatNotFound => write!(f, "chat_not_found"),
+            DeliveryErrorKind::Deactivated => write!(f, "deactivated"),
+            DeliveryErrorKind::Transient => write!(f, "transient"),
+        }
+    }
+}
+
+/// Пользователь, исключенный из рассылок
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct UnreachableUser {
+    pub telegram_id: i64,
+    pub reason: String,
+    pub error: Option<String>,
+    #[schema(value_type = String)]
+    pub marked_at: NaiveDateTime,
+}
+
+/// Направление сообщения в переписке с поддержкой
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SupportDirection {
+    /// Сообщение кандидата
+    Inbound,
+    /// Ответ сотрудника
+    Outbound,
+}
+
+impl std::fmt::Display for SupportDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SupportDirection::Inbound => write!(f, "inbound"),
+            SupportDirection::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+/// Сообщение из переписки кандидата с поддержкой
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct SupportMessage {
+    pub id: i64,
+    pub telegram_id: i64,
+    pub direction: String,
+    pub text: Option<String>,
+    pub staff_telegram_id: Option<i64>,
+    #[schema(value_type = String)]
+    pub created_at: NaiveDateTime,
+}
+
+/// Сценарий, который открывает deep link /start <payload>
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DeepLinkFlow {
+    /// Приветствие с кнопкой записи
+    #[default]
+    Welcome,
+    /// Сразу выбор слота
+    SignUp,
+    /// Контакты
+    Contact,
+}
+
+impl std::fmt::Display for DeepLinkFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeepLinkFlow::Welcome => write!(f, "welcome"),
+            DeepLinkFlow::SignUp => write!(f, "signup"),
+            DeepLinkFlow::Contact => write!(f, "contact"),
+        }
+    }
+}
+
+impl From<String> for DeepLinkFlow {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "signup" => DeepLinkFlow::SignUp,
+            "contact" => DeepLinkFlow::Contact,
+            _ => DeepLinkFlow::Welcome,
+        }
+    }
+}
+
+/// Зарегистрированный deep link
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeepLink {
+    pub payload: String,
+    pub source: String,
+    pub flow: DeepLinkFlow,
+    pub broadcast_id: Option<String>,
+}
+
+impl DeepLink {
+    /// Telegram допускает в параметре start до 64 символов A-Z, a-z, 0-9, _ и -
+    pub const MAX_PAYLOAD_LENGTH: usize = 64;
+
+    pub fn is_valid_payload(payload: &str) -> bool {
+        !payload.is_empty()
+            && payload.len() <= Self::MAX_PAYLOAD_LENGTH
+            && payload.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateDeepLinkRequest {
+    pub source: String,
+    pub flow: Option<DeepLinkFlow>,
+    pub broadcast_id: Option<String>,
+    /// Свой payload; если не указан, генерируется автоматически
+    pub payload: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeepLinkResponse {
+    pub payload: String,
+    pub source: String,
+    pub flow: DeepLinkFlow,
+    pub broadcast_id: Option<String>,
+    pub url: String,
+}
+
+/// Конверсия по deep link'у: сколько пользователей перешло и сколько из них записалось
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct DeepLinkStats {
+    pub payload: String,
+    pub source: Option<String>,
+    pub flow: Option<String>,
+    pub broadcast_id: Option<String>,
+    pub visitors: i64,
+    pub bookings: i64,
+    pub conversion_rate: f64,
+}
+
+/// Воронка рассылки с кнопкой «Записаться»: получатели на каждом шаге
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastFunnel {
+    pub broadcast_id: String,
+    /// Доставлено сообщений
+    pub delivered: i64,
+    /// Нажали «Записаться»
+    pub clicked: i64,
+    /// Увидели список слотов
+    pub slots_viewed: i64,
+    /// Записались на слот после нажатия
+    pub booked: i64,
+    /// Доля записавшихся среди получивших рассылку
+    pub conversion_rate: f64,
+}
+
+// Сегменты аудитории
+
+/// Итог рассмотрения анкеты ответственным
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteOutcome {
+    Approved,
+    Rejected,
+    /// Решения ответственного еще нет
+    Undecided,
+}
+
+/// Правила сегмента. Заданные условия объединяются через И, незаданные не проверяются.
+/// Пользователи без telegram_id в сегмент не попадают
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SegmentRules {
+    /// Статус рассмотрения анкеты: подходит любой из перечисленных
+    pub survey_statuses: Option<Vec<SurveyStatus>>,
+    /// Итог решения ответственного: подходит любой из перечисленных
+    pub vote_outcomes: Option<Vec<VoteOutcome>>,
+    /// true — только записавшиеся на слот, false — только не записавшиеся
+    pub booked: Option<bool>,
+    /// Получили рассылку о записи, но так и не записались
+    pub no_response_after_signup: Option<bool>,
+    /// Год поступления из профиля: подходит любой из перечисленных
+    pub year_of_admission: Option<Vec<i32>>,
+    pub has_driver_license: Option<bool>,
+    pub has_printer: Option<bool>,
+    pub can_host_night: Option<bool>,
+    /// Исключаемые пользователи
+    pub exclude_telegram_ids: Vec<i64>,
+    /// Исключаемые сегменты: их участники не попадают в рассылку
+    pub exclude_segment_ids: Vec<i64>,
+}
+
+/// Сохраненный сегмент аудитории
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Segment {
+    pub id: i64,
+    pub name: String,
+    pub rules: SegmentRules,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SaveSegmentRequest {
+    pub name: String,
+    pub rules: SegmentRules,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SegmentCountResponse {
+    /// Сколько пользователей получит рассылку сейчас
+    pub count: usize,
+    /// Сколько подходящих пользователей пропущено как недоступные
+    pub skipped_unreachable: usize,
+}
+
+// Уведомления о решении ответственного
+
+/// Кампания автоматических уведомлений: шаблоны писем кандидатам об одобрении и отказе
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DecisionCampaign {
+    pub id: i64,
+    pub name: String,
+    /// Приглашение записаться, отправляется с кнопкой «Записаться»
+    pub approval_message: String,
+    /// Отказ; None — при отклонении кандидату не пишем
+    pub rejection_message: Option<String>,
+    /// Уведомления отправляет только активная кампания
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SaveDecisionCampaignRequest {
+    pub name: String,
+    pub approval_message: String,
+    pub rejection_message: Option<String>,
+    /// Активация кампании выключает остальные
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Уведомление о решении, отправленное кандидату в рамках кампании
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DecisionNotification {
+    pub campaign_id: i64,
+    pub telegram_id: i64,
+    pub decision: i64,                     // 1 - одобрена, 0 - отклонена
+    pub broadcast_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
 // Command Structures
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct CreateBroadcastCommand {
     pub message: String,
     pub message_type: Option<BroadcastMessageType>,
     pub selected_external_users: Option<Vec<String>>, // telegram_id выбранных внешних пользователей
+    #[serde(default)]
+    pub segment_id: Option<i64>, // Сохраненный сегмент вместо явного списка; вычисляется в момент отправки
     pub media_group: Option<MediaGroup>, // Группа медиафайлов для отправки
+    #[serde(default)]
+    pub send_at: Option<DateTime<Utc>>, // Отложенная отправка; None или прошедшее время — сразу
+    #[serde(default)]
+    pub keyboard: Option<keyboard::BroadcastKeyboard>, // Inline-клавиатура; для SignUp без нее — кнопка «Записаться»
+    #[serde(default)]
+    pub parse_mode: formatting::BroadcastParseMode, // plain (экранируется), html или markdown_v2
+    #[serde(default)]
+    pub split_long_text: bool, // Делить текст длиннее 4096 символов на несколько сообщений вместо ошибки
+    #[serde(default)]
+    pub draft: bool, // Черновик: не отправляется до POST /broadcast/{id}/release
+    #[serde(default)]
+    pub created_by: Option<i64>, // telegram_id автора; крупную рассылку одобряет другой ответственный
+    #[serde(default)]
+    pub variants: Vec<variants::BroadcastVariant>, // A/B-варианты; основной текст — вариант A
+    #[serde(default)]
+    pub auto_winner: Option<variants::AutoWinnerSettings>, // Сначала тестовая доля, затем победитель — остальным
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
@@ -421,6 +859,43 @@ pub struct CancelBroadcastCommand {
     pub broadcast_id: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RescheduleBroadcastCommand {
+    pub send_at: DateTime<Utc>,
+}
+
+/// Решение ответственного по рассылке, ожидающей одобрения
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastApprovalRequest {
+    pub approver_telegram_id: i64,
+    #[serde(default)]
+    pub reason: Option<String>, // Причина отказа, передается автору
+}
+
+/// Новый текст доставленной рассылки (или подпись, если рассылка с медиафайлами)
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EditBroadcastRequest {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastRevisionResponse {
+    /// Сколько доставленных сообщений поставлено в очередь на правку или удаление
+    pub queued: usize,
+}
+
+/// Тестовая отправка рассылки сотрудникам
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TestBroadcastRequest {
+    pub telegram_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TestBroadcastResponse {
+    /// Сколько тестовых сообщений поставлено в очередь
+    pub queued: usize,
+}
+
 // Query Structures
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetBroadcastStatusQuery {
@@ -440,6 +915,30 @@ pub struct GetBroadcastMessagesQuery {
 pub struct BroadcastCreatedResponse {
     pub broadcast_id: String,
     pub status: BroadcastStatus,
+    /// Сколько выбранных пользователей пропущено как недоступные
+    pub skipped_unreachable: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastPreview {
+    pub telegram_id: i64,
+    /// Текст с подставленными данными получателя
+    pub message: String,
+    /// Подписи к медиафайлам после подстановки
+    pub captions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastPreviewResponse {
+    /// Переменные, найденные в тексте и подписях
+    pub variables: Vec<template::TemplateVariable>,
+    pub previews: Vec<BroadcastPreview>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReplayDeadLettersResponse {
+    /// Сколько сообщений возвращено в очередь рассылки
+    pub replayed: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -497,7 +996,7 @@ pub struct SurveyVoteSummary {
     pub has_responsible_vote: bool,        // Есть ли голос от ответственного
 }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
 pub enum SurveyStatus {
     InProgress,                            // Меньше 5 голосов
     ReadyForReview,                       // 5+ голосов, нет голоса от ответственного
@@ -585,3 +1084,26 @@ pub struct FileUploadResponse {
     pub file_id: Option<String>,
     pub file_type: Option<String>,
 }
+
+// Идемпотентность изменяющих запросов API
+
+/// Ответ, сохраненный для Idempotency-Key
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status_code: u16,
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+/// Итог резервирования Idempotency-Key перед выполнением запроса
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyReservation {
+    /// Ключ новый: запрос выполняется, ответ нужно сохранить
+    Reserved,
+    /// Запрос с этим ключом еще выполняется
+    InProgress,
+    /// Ключ уже использован с другим телом запроса
+    Mismatch,
+    /// Запрос уже выполнен: повтор получает сохраненный ответ
+    Completed(StoredResponse),
+}
@@ -1,6 +1,6 @@
 use lapin::{
-    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions},
-    types::FieldTable, Channel, Connection, ConnectionProperties, Consumer,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions},
+    types::{AMQPValue, FieldTable}, Channel, Connection, ConnectionProperties, Consumer,
 };
 use serde_json;
 use std::time::Duration;
@@ -8,18 +8,57 @@ use tracing::{error, info};
 use futures_util::StreamExt;
 use std::sync::Arc;
 
-use crate::{BroadcastEvent, BroadcastMessage};
+use crate::{BroadcastEvent, BroadcastMessage, DeadLetterMessage};
 use anyhow::Error;
 
 // Константы для очередей и exchange'ов
 pub const BROADCAST_QUEUE_NAME: &str = "telegram_broadcast";
 pub const BROADCAST_EXCHANGE_NAME: &str = "telegram_broadcast_exchange";
+pub const BROADCAST_ROUTING_KEY: &str = "broadcast";
+// Очереди отложенных сообщений: по истечении TTL сообщение возвращается в BROADCAST_EXCHANGE_NAME.
+// RabbitMQ истекает только сообщение в голове очереди, поэтому у каждой очереди свой фиксированный
+// x-message-ttl: долгий повтор не задерживает короткие. Имя очереди — префикс и задержка в секундах
+pub const BROADCAST_DELAY_QUEUE_NAME: &str = "telegram_broadcast_delay";
+// Уровни задержки, секунды; задержка округляется вверх до ближайшего уровня
+const DELAY_TIERS_SECS: [u64; 10] = [1, 2, 5, 10, 20, 30, 60, 120, 300, 600];
+// Сообщения, исчерпавшие попытки доставки; разбираются вручную через API
+pub const DEAD_LETTER_QUEUE_NAME: &str = "telegram_broadcast_dead_letter";
 pub const EVENTS_QUEUE_NAME: &str = "broadcast_events";
 pub const EVENTS_EXCHANGE_NAME: &str = "broadcast_events_exchange";
 
+/// Уровень задержки в секундах: ближайший не меньше задержки, но не больше последнего
+fn delay_tier(delay: Duration) -> u64 {
+    DELAY_TIERS_SECS
+        .into_iter()
+        .find(|tier| Duration::from_secs(*tier) >= delay)
+        .unwrap_or(DELAY_TIERS_SECS[DELAY_TIERS_SECS.len() - 1])
+}
+
+fn delay_queue_name(delay_secs: u64) -> String {
+    format!("{}_{}s", BROADCAST_DELAY_QUEUE_NAME, delay_secs)
+}
+
+/// Аргументы очереди задержки: возврат в основной exchange и TTL уровня
+fn delay_queue_args(delay_secs: Option<u64>) -> FieldTable {
+    let mut args = FieldTable::default();
+    args.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(BROADCAST_EXCHANGE_NAME.into()),
+    );
+    args.insert(
+        "x-dead-letter-routing-key".into(),
+        AMQPValue::LongString(BROADCAST_ROUTING_KEY.into()),
+    );
+    if let Some(delay_secs) = delay_secs {
+        args.insert("x-message-ttl".into(), AMQPValue::LongLongInt((delay_secs * 1000) as i64));
+    }
+    args
+}
+
 /// Клиент для работы с RabbitMQ
 #[derive(Clone)]
 pub struct RabbitMQClient {
+    connection: Arc<Connection>,
     channel: Arc<Channel>,
 }
 
@@ -61,12 +100,39 @@ impl RabbitMQClient {
             .queue_bind(
                 BROADCAST_QUEUE_NAME,
                 BROADCAST_EXCHANGE_NAME,
-                "broadcast",
+                BROADCAST_ROUTING_KEY,
                 lapin::options::QueueBindOptions::default(),
                 lapin::types::FieldTable::default(),
             )
             .await?;
 
+        // Очереди задержки без consumer'ов: истекшие сообщения уходят обратно в основной exchange.
+        // Общая очередь без TTL остается, чтобы дослать сообщения, отложенные до перехода на уровни
+        channel
+            .queue_declare(
+                BROADCAST_DELAY_QUEUE_NAME,
+                lapin::options::QueueDeclareOptions::default(),
+                delay_queue_args(None),
+            )
+            .await?;
+        for delay_secs in DELAY_TIERS_SECS {
+            channel
+                .queue_declare(
+                    &delay_queue_name(delay_secs),
+                    lapin::options::QueueDeclareOptions::default(),
+                    delay_queue_args(Some(delay_secs)),
+                )
+                .await?;
+        }
+
+        channel
+            .queue_declare(
+                DEAD_LETTER_QUEUE_NAME,
+                lapin::options::QueueDeclareOptions::default(),
+                lapin::types::FieldTable::default(),
+            )
+            .await?;
+
         // Объявляем exchange и очередь для событий
         channel
             .exchange_declare(
@@ -96,7 +162,7 @@ impl RabbitMQClient {
             .await?;
 
         info!("Connected to RabbitMQ successfully");
-        Ok(RabbitMQClient { channel: Arc::new(channel) })
+        Ok(RabbitMQClient { connection: Arc::new(conn), channel: Arc::new(channel) })
     }
 
     /// Публикует событие в очередь событий
@@ -130,7 +196,7 @@ impl RabbitMQClient {
         self.channel
             .basic_publish(
                 BROADCAST_EXCHANGE_NAME,
-                "broadcast",
+                BROADCAST_ROUTING_KEY,
                 BasicPublishOptions::default(),
                 &message_json,
                 lapin::BasicProperties::default(),
@@ -142,6 +208,142 @@ impl RabbitMQClient {
         Ok(())
     }
 
+    /// Публикует сообщение с задержкой через очередь отложенных сообщений ближайшего
+    /// уровня не меньше задержки
+    pub async fn publish_message_delayed(
+        &self,
+        message: &BroadcastMessage,
+        delay: Duration,
+    ) -> Result<(), Error> {
+        let message_json = serde_json::to_vec(message)?;
+        let delay_secs = delay_tier(delay);
+
+        self.channel
+            .basic_publish(
+                "",
+                &delay_queue_name(delay_secs),
+                BasicPublishOptions::default(),
+                &message_json,
+                lapin::BasicProperties::default(),
+            )
+            .await?;
+
+        info!("⏳ Message delayed for {:?} (tier {} s): telegram_id={}, broadcast_id={}",
+              delay, delay_secs, message.telegram_id, message.broadcast_id);
+        Ok(())
+    }
+
+    /// Кладет сообщение в dead-letter очередь
+    pub async fn publish_dead_letter(
+        &self,
+        dead_letter: &DeadLetterMessage,
+    ) -> Result<(), Error> {
+        let payload = serde_json::to_vec(dead_letter)?;
+
+        self.channel
+            .basic_publish(
+                "",
+                DEAD_LETTER_QUEUE_NAME,
+                BasicPublishOptions::default(),
+                &payload,
+                lapin::BasicProperties::default(),
+            )
+            .await?;
+
+        info!("☠️ Message dead-lettered: telegram_id={}, broadcast_id={}",
+              dead_letter.message.telegram_id, dead_letter.message.broadcast_id);
+        Ok(())
+    }
+
+    /// Возвращает первые сообщения dead-letter очереди, не забирая их
+    pub async fn peek_dead_letters(&self, limit: usize) -> Result<Vec<DeadLetterMessage>, Error> {
+        // Отдельный канал: неподтвержденные сообщения вернутся в очередь при nack
+        let channel = self.connection.create_channel().await?;
+        let mut dead_letters = Vec::new();
+        let mut last_tag = None;
+
+        while dead_letters.len() < limit {
+            let Some(message) = channel
+                .basic_get(DEAD_LETTER_QUEUE_NAME, BasicGetOptions::default())
+                .await?
+            else {
+                break;
+            };
+            last_tag = Some(message.delivery.delivery_tag);
+            match serde_json::from_slice(&message.delivery.data) {
+                Ok(dead_letter) => dead_letters.push(dead_letter),
+                Err(e) => error!("Failed to parse dead letter: {}", e),
+            }
+        }
+
+        if let Some(tag) = last_tag {
+            channel
+                .basic_nack(tag, BasicNackOptions { multiple: true, requeue: true })
+                .await?;
+        }
+        channel.close(0, "peek finished").await?;
+
+        Ok(dead_letters)
+    }
+
+    /// Переносит сообщения из dead-letter очереди обратно в очередь рассылки.
+    /// `before_replay` вызывается перед публикацией каждого сообщения (например, чтобы сбросить статус в БД);
+    /// если он вернул false, сообщение повторять не нужно и оно убирается из очереди
+    pub async fn replay_dead_letters<F, Fut>(&self, limit: usize, before_replay: F) -> Result<usize, Error>
+    where
+        F: Fn(DeadLetterMessage) -> Fut,
+        Fut: std::future::Future<Output = Result<bool, Error>>,
+    {
+        let channel = self.connection.create_channel().await?;
+        let mut replayed = 0;
+
+        while replayed < limit {
+            let Some(message) = channel
+                .basic_get(DEAD_LETTER_QUEUE_NAME, BasicGetOptions::default())
+                .await?
+            else {
+                break;
+            };
+            let delivery_tag = message.delivery.delivery_tag;
+
+            let dead_letter: DeadLetterMessage = match serde_json::from_slice(&message.delivery.data) {
+                Ok(dead_letter) => dead_letter,
+                Err(e) => {
+                    // Нечитаемое сообщение повторить нельзя: в очереди оно заблокировало бы остальные
+                    error!("Failed to parse dead letter, dropping it: {}", e);
+                    channel.basic_ack(delivery_tag, BasicAckOptions::default()).await?;
+                    continue;
+                }
+            };
+
+            let message = dead_letter.message.clone();
+            let result = match before_replay(dead_letter).await {
+                Ok(true) => self.publish_message(&message).await,
+                Ok(false) => {
+                    // Сообщение уже доставлено или повторено, второй раз не отправляем
+                    info!("⏭️ Dead letter for user {} of broadcast {} is no longer failed, dropping it",
+                          message.telegram_id, message.broadcast_id);
+                    channel.basic_ack(delivery_tag, BasicAckOptions::default()).await?;
+                    continue;
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                channel
+                    .basic_nack(delivery_tag, BasicNackOptions { multiple: false, requeue: true })
+                    .await?;
+                return Err(e);
+            }
+
+            channel.basic_ack(delivery_tag, BasicAckOptions::default()).await?;
+            replayed += 1;
+        }
+        channel.close(0, "replay finished").await?;
+
+        info!("🔁 Replayed {} dead-lettered messages", replayed);
+        Ok(replayed)
+    }
+
     /// Создает consumer для событий
     pub async fn create_events_consumer(
         &self,
@@ -164,10 +366,11 @@ impl RabbitMQClient {
     pub async fn create_messages_consumer(
         &self,
         consumer_tag: &str,
+        prefetch_count: u16,
     ) -> Result<Consumer, Error> {
-        // Настраиваем QoS
+        // Настраиваем QoS: не больше сообщений, чем обрабатываем параллельно
         self.channel
-            .basic_qos(1, BasicQosOptions::default())
+            .basic_qos(prefetch_count, BasicQosOptions::default())
             .await?;
 
         let consumer = self.channel
@@ -191,6 +394,14 @@ impl RabbitMQClient {
         Ok(())
     }
 
+    /// Возвращает сообщение брокеру без подтверждения; с requeue оно будет доставлено снова
+    pub async fn nack_message(&self, delivery_tag: u64, requeue: bool) -> Result<(), Error> {
+        self.channel
+            .basic_nack(delivery_tag, BasicNackOptions { multiple: false, requeue })
+            .await?;
+        Ok(())
+    }
+
     /// Получает канал для прямого доступа (если нужен)
     pub fn get_channel(&self) -> &Channel {
         &self.channel
@@ -285,25 +496,43 @@ impl EventsWorker {
     }
 }
 
+/// Результат обработки сообщения рассылки
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageOutcome {
+    /// Сообщение обработано (отправлено, окончательно не доставлено или отменено)
+    Processed,
+    /// Повторить позже (ответ Telegram, временная ошибка или пауза рассылки): сообщение вернется в очередь после задержки
+    RetryAfter(Duration),
+    /// Попытки исчерпаны: сообщение уходит в dead-letter очередь с текстом ошибки
+    DeadLetter(String),
+}
+
 /// Воркер для обработки сообщений
 pub struct MessagesWorker {
     client: RabbitMQClient,
+    concurrency: usize,
 }
 
 impl MessagesWorker {
     pub async fn new() -> Result<Self, Error> {
         let client = RabbitMQClient::new().await?;
-        Ok(MessagesWorker { client })
+        Ok(MessagesWorker { client, concurrency: 1 })
+    }
+
+    /// Сколько сообщений обрабатывать параллельно
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.clamp(1, u16::MAX as usize);
+        self
     }
 
     pub async fn start_processing<F, Fut>(&self, consumer_tag: &str, handler: F) -> Result<(), Error>
     where
         F: Fn(BroadcastMessage) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = Result<(), Error>> + Send + 'static,
+        Fut: std::future::Future<Output = Result<MessageOutcome, Error>> + Send + 'static,
     {
-        let consumer = self.client.create_messages_consumer(consumer_tag).await?;
+        let consumer = self.client.create_messages_consumer(consumer_tag, self.concurrency as u16).await?;
         
-        info!("🚀 Messages worker started with tag: {}", consumer_tag);
+        info!("🚀 Messages worker started with tag: {}, concurrency: {}", consumer_tag, self.concurrency);
         info!("Waiting for broadcast messages...");
 
         self.process_messages(consumer, handler).await?;
@@ -312,67 +541,134 @@ impl MessagesWorker {
 
     async fn process_messages<F, Fut>(
         &self,
-        mut consumer: Consumer,
+        consumer: Consumer,
         handler: F,
     ) -> Result<(), Error>
     where
         F: Fn(BroadcastMessage) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = Result<(), Error>> + Send + 'static,
+        Fut: std::future::Future<Output = Result<MessageOutcome, Error>> + Send + 'static,
     {
         info!("🎯 Starting messages processing loop");
 
-        while let Some(delivery) = consumer.next().await {
-            let delivery = match delivery {
-                Ok(delivery) => {
-                    info!("✅ Message received, tag: {}", delivery.delivery_tag);
-                    delivery
-                }
-                Err(e) => {
-                    error!("❌ Failed to receive message: {}", e);
-                    continue;
-                }
-            };
+        // Темп отправки задает лимитер в обработчике, здесь только ограничиваем параллельность
+        consumer
+            .for_each_concurrent(self.concurrency, |delivery| async {
+                let delivery = match delivery {
+                    Ok(delivery) => {
+                        info!("✅ Message received, tag: {}", delivery.delivery_tag);
+                        delivery
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to receive message: {}", e);
+                        return;
+                    }
+                };
+
+                let delivery_tag = delivery.delivery_tag;
+
+                // Парсим сообщение
+                let message: BroadcastMessage = match serde_json::from_slice(&delivery.data) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        error!("Failed to parse message: {}", e);
+                        if let Err(e) = self.client.ack_message(delivery_tag).await {
+                            error!("Failed to ack message: {}", e);
+                        }
+                        return;
+                    }
+                };
 
-            let delivery_tag = delivery.delivery_tag;
+                info!("=== PROCESSING BROADCAST MESSAGE ===");
+                info!("Telegram ID: {}", message.telegram_id);
+                info!("Broadcast ID: {}", message.broadcast_id);
 
-            // Парсим сообщение
-            let message: BroadcastMessage = match serde_json::from_slice(&delivery.data) {
-                Ok(message) => message,
-                Err(e) => {
-                    error!("Failed to parse message: {}", e);
-                    if let Err(e) = self.client.ack_message(delivery_tag).await {
-                        error!("Failed to ack message: {}", e);
+                // Обрабатываем сообщение
+                let dead_letter_error = match handler(message.clone()).await {
+                    Ok(MessageOutcome::Processed) => {
+                        info!("✅ Message processed successfully");
+                        None
+                    }
+                    Ok(MessageOutcome::RetryAfter(delay)) => {
+                        // Не удалось отложить — возвращаем сообщение в очередь, а не теряем его
+                        if let Err(e) = self.client.publish_message_delayed(&message, delay).await {
+                            error!("❌ Failed to delay message for user {}: {}", message.telegram_id, e);
+                            if let Err(e) = self.client.nack_message(delivery_tag, true).await {
+                                error!("❌ Failed to requeue message: {}", e);
+                            }
+                            return;
+                        }
+                        None
+                    }
+                    Ok(MessageOutcome::DeadLetter(error)) => Some(error),
+                    Err(e) => {
+                        // Ошибку обработчика не теряем: сообщение можно будет повторить из dead-letter очереди
+                        error!("❌ Failed to process message: {}", e);
+                        Some(e.to_string())
+                    }
+                };
+
+                // Не удалось положить в dead-letter очередь — тоже возвращаем сообщение в очередь
+                if let Some(error) = dead_letter_error {
+                    let telegram_id = message.telegram_id;
+                    if let Err(e) = self.dead_letter(message, error).await {
+                        error!("❌ Failed to dead-letter message for user {}: {}", telegram_id, e);
+                        if let Err(e) = self.client.nack_message(delivery_tag, true).await {
+                            error!("❌ Failed to requeue message: {}", e);
+                        }
+                        return;
                     }
-                    continue;
                 }
-            };
-
-            info!("=== PROCESSING BROADCAST MESSAGE ===");
-            info!("Telegram ID: {}", message.telegram_id);
-            info!("Broadcast ID: {}", message.broadcast_id);
 
-            // Обрабатываем сообщение
-            match handler(message).await {
-                Ok(_) => {
-                    info!("✅ Message processed successfully");
-                }
-                Err(e) => {
-                    error!("❌ Failed to process message: {}", e);
+                // Подтверждаем обработку
+                if let Err(e) = self.client.ack_message(delivery_tag).await {
+                    error!("❌ Failed to ack message: {}", e);
+                } else {
+                    info!("✅ Message acknowledged successfully");
                 }
-            }
-
-            // Подтверждаем обработку
-            if let Err(e) = self.client.ack_message(delivery_tag).await {
-                error!("❌ Failed to ack message: {}", e);
-            } else {
-                info!("✅ Message acknowledged successfully");
-            }
-
-            // Небольшая задержка
-            tokio::time::sleep(Duration::from_millis(100)).await;
-        }
+            })
+            .await;
 
         info!("🛑 Messages processing loop ended");
         Ok(())
     }
+
+    async fn dead_letter(&self, message: BroadcastMessage, error: String) -> Result<(), Error> {
+        let dead_letter = DeadLetterMessage {
+            message,
+            error,
+            failed_at: chrono::Utc::now(),
+        };
+        self.client.publish_dead_letter(&dead_letter).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_is_rounded_up_to_next_tier() {
+        assert_eq!(delay_tier(Duration::from_millis(1)), 1);
+        assert_eq!(delay_tier(Duration::from_secs(5)), 5);
+        assert_eq!(delay_tier(Duration::from_millis(5001)), 10);
+        assert_eq!(delay_tier(Duration::from_secs(40)), 60);
+        assert_eq!(delay_tier(Duration::from_secs(3600)), 600);
+    }
+
+    #[test]
+    fn short_retry_after_long_one_uses_its_own_queue() {
+        // Долгий повтор, за ним короткий RetryAfter и перепроверка паузы: у каждого своя очередь,
+        // поэтому короткие не ждут, пока истечет долгий
+        let long = delay_queue_name(delay_tier(Duration::from_secs(600)));
+        let short = delay_queue_name(delay_tier(Duration::from_secs(3)));
+        let paused = delay_queue_name(delay_tier(Duration::from_secs(30)));
+        assert_eq!(long, "telegram_broadcast_delay_600s");
+        assert_eq!(short, "telegram_broadcast_delay_5s");
+        assert_eq!(paused, "telegram_broadcast_delay_30s");
+
+        let ttl = |delay_secs| delay_queue_args(Some(delay_secs)).inner().get("x-message-ttl").cloned();
+        assert_eq!(ttl(5), Some(AMQPValue::LongLongInt(5_000)));
+        assert_eq!(ttl(600), Some(AMQPValue::LongLongInt(600_000)));
+        assert!(delay_queue_args(None).inner().get("x-message-ttl").is_none());
+    }
 }
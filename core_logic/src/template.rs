@@ -0,0 +1,371 @@
+//! Шаблоны рассылок: `{{full_name}}`, `{{slot_time|скоро}}` и т.п.
+//! Подстановка выполняется для каждого получателя отдельно.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::formatting::{self, BroadcastParseMode};
+use crate::{MediaGroup, SurveyStatus};
+
+const OPEN_TAG: &str = "{{";
+const CLOSE_TAG: &str = "}}";
+const FALLBACK_SEPARATOR: char = '|';
+// Время слотов хранится в UTC, кандидатам показываем московское
+const MSK_OFFSET_HOURS: i64 = 3;
+const SLOT_TIME_FORMAT: &str = "%d.%m.%Y %H:%M";
+
+/// Переменная шаблона
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateVariable {
+    /// ФИО из внешнего профиля
+    FullName,
+    /// Имя: второе слово ФИО (или первое, если слово одно)
+    FirstName,
+    /// Ник в Telegram из внешнего профиля, без @
+    TelegramNickname,
+    /// Время забронированного слота (МСК)
+    SlotTime,
+    /// Место забронированного слота
+    SlotPlace,
+    /// Статус рассмотрения анкеты
+    SurveyStatus,
+}
+
+impl TemplateVariable {
+    pub const ALL: [TemplateVariable; 6] = [
+        TemplateVariable::FullName,
+        TemplateVariable::FirstName,
+        TemplateVariable::TelegramNickname,
+        TemplateVariable::SlotTime,
+        TemplateVariable::SlotPlace,
+        TemplateVariable::SurveyStatus,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateVariable::FullName => "full_name",
+            TemplateVariable::FirstName => "first_name",
+            TemplateVariable::TelegramNickname => "telegram_nickname",
+            TemplateVariable::SlotTime => "slot_time",
+            TemplateVariable::SlotPlace => "slot_place",
+            TemplateVariable::SurveyStatus => "survey_status",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|variable| variable.name() == name)
+    }
+
+    /// Нужен ли профиль из внешнего API
+    pub fn needs_profile(&self) -> bool {
+        matches!(self, TemplateVariable::FullName | TemplateVariable::FirstName | TemplateVariable::TelegramNickname)
+    }
+
+    /// Нужна ли бронь слота
+    pub fn needs_booking(&self) -> bool {
+        matches!(self, TemplateVariable::SlotTime | TemplateVariable::SlotPlace)
+    }
+}
+
+impl std::fmt::Display for TemplateVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Ошибка разбора шаблона; позиция — номер символа от начала текста
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ошибка в шаблоне (символ {}): {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Variable {
+        variable: TemplateVariable,
+        fallback: Option<String>,
+    },
+}
+
+/// Разобранный шаблон сообщения
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTemplate {
+    parts: Vec<Part>,
+}
+
+impl MessageTemplate {
+    /// Разбирает шаблон. Неизвестные переменные и незакрытые скобки — ошибка
+    pub fn parse(text: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        let mut offset = 0;
+
+        while let Some(start) = rest.find(OPEN_TAG) {
+            Self::check_no_close_tag(text, offset, offset + start)?;
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let body_start = start + OPEN_TAG.len();
+            let Some(body_len) = rest[body_start..].find(CLOSE_TAG) else {
+                return Err(TemplateError {
+                    position: char_position(text, offset + start),
+                    message: "не закрыта переменная, ожидается }}".to_string(),
+                });
+            };
+            let body = &rest[body_start..body_start + body_len];
+            if body.contains(OPEN_TAG) {
+                return Err(TemplateError {
+                    position: char_position(text, offset + start),
+                    message: "переменные нельзя вкладывать друг в друга".to_string(),
+                });
+            }
+
+            let (name, fallback) = match body.split_once(FALLBACK_SEPARATOR) {
+                Some((name, fallback)) => (name.trim(), Some(fallback.trim().to_string())),
+                None => (body.trim(), None),
+            };
+            let Some(variable) = TemplateVariable::from_name(name) else {
+                let known: Vec<&str> = TemplateVariable::ALL.iter().map(|v| v.name()).collect();
+                return Err(TemplateError {
+                    position: char_position(text, offset + start),
+                    message: format!("неизвестная переменная «{}», доступны: {}", name, known.join(", ")),
+                });
+            };
+            parts.push(Part::Variable { variable, fallback });
+
+            let consumed = body_start + body_len + CLOSE_TAG.len();
+            rest = &rest[consumed..];
+            offset += consumed;
+        }
+
+        Self::check_no_close_tag(text, offset, text.len())?;
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(MessageTemplate { parts })
+    }
+
+    /// Проверяет, что в тексте между переменными нет одиночных }}
+    fn check_no_close_tag(text: &str, from: usize, to: usize) -> Result<(), TemplateError> {
+        match text[from..to].find(CLOSE_TAG) {
+            Some(position) => Err(TemplateError {
+                position: char_position(text, from + position),
+                message: "лишние }} без открывающих {{".to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Переменные шаблона без повторов, в порядке появления
+    pub fn variables(&self) -> Vec<TemplateVariable> {
+        let mut variables = Vec::new();
+        for part in &self.parts {
+            if let Part::Variable { variable, .. } = part {
+                if !variables.contains(variable) {
+                    variables.push(*variable);
+                }
+            }
+        }
+        variables
+    }
+
+    /// Шаблон без переменных: текст одинаков для всех получателей
+    pub fn is_static(&self) -> bool {
+        self.parts.iter().all(|part| matches!(part, Part::Text(_)))
+    }
+
+    /// Подставляет данные получателя. Отсутствующее значение заменяется запасным
+    /// текстом из `{{переменная|запасной текст}}`, а без него — пустой строкой.
+    /// Значения экранируются под разметку, запасной текст вставляется как есть
+    pub fn render(&self, context: &TemplateContext, parse_mode: BroadcastParseMode) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Variable { variable, fallback } => {
+                    match context.value(*variable) {
+                        Some(value) => rendered.push_str(&formatting::escape(&value, parse_mode)),
+                        None => rendered.push_str(fallback.as_deref().unwrap_or_default()),
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// Проверяет текст рассылки и подписи к медиафайлам, возвращает все используемые переменные
+pub fn validate_broadcast(message: &str, media_group: Option<&MediaGroup>) -> Result<Vec<TemplateVariable>, TemplateError> {
+    let mut variables = MessageTemplate::parse(message)?.variables();
+    let captions = media_group
+        .into_iter()
+        .flat_map(|group| group.media.iter())
+        .enumerate()
+        .filter_map(|(index, item)| item.caption.as_deref().map(|caption| (index, caption)));
+    for (index, caption) in captions {
+        let template = MessageTemplate::parse(caption).map_err(|e| TemplateError {
+            position: e.position,
+            message: format!("подпись к файлу {}: {}", index + 1, e.message),
+        })?;
+        for variable in template.variables() {
+            if !variables.contains(&variable) {
+                variables.push(variable);
+            }
+        }
+    }
+    Ok(variables)
+}
+
+/// Заменяет переменные заглушками той же длины в символах, чтобы проверить разметку
+/// текста без подстановки и сохранить позиции ошибок
+pub fn mask_variables(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN_TAG) {
+        let Some(body_len) = rest[start..].find(CLOSE_TAG) else {
+            break;
+        };
+        let end = start + body_len + CLOSE_TAG.len();
+        masked.push_str(&rest[..start]);
+        masked.push_str(&"x".repeat(rest[start..end].chars().count()));
+        rest = &rest[end..];
+    }
+    masked.push_str(rest);
+    masked
+}
+
+/// Данные получателя для подстановки в шаблон
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    pub full_name: Option<String>,
+    pub telegram_nickname: Option<String>,
+    pub slot_time: Option<DateTime<Utc>>,
+    pub slot_place: Option<String>,
+    pub survey_status: Option<SurveyStatus>,
+}
+
+impl TemplateContext {
+    fn value(&self, variable: TemplateVariable) -> Option<String> {
+        let value = match variable {
+            TemplateVariable::FullName => self.full_name.clone(),
+            TemplateVariable::FirstName => self.full_name.as_deref().and_then(first_name),
+            TemplateVariable::TelegramNickname => {
+                self.telegram_nickname.as_deref().map(|nickname| nickname.trim_start_matches('@').to_string())
+            }
+            TemplateVariable::SlotTime => self.slot_time.map(|time| {
+                (time + chrono::Duration::hours(MSK_OFFSET_HOURS)).format(SLOT_TIME_FORMAT).to_string()
+            }),
+            TemplateVariable::SlotPlace => self.slot_place.clone(),
+            TemplateVariable::SurveyStatus => self.survey_status.as_ref().map(|status| survey_status_text(status).to_string()),
+        };
+        value.filter(|value| !value.trim().is_empty())
+    }
+}
+
+/// Имя из ФИО «Фамилия Имя Отчество»
+fn first_name(full_name: &str) -> Option<String> {
+    let words: Vec<&str> = full_name.split_whitespace().collect();
+    match words.as_slice() {
+        [] => None,
+        [single] => Some(single.to_string()),
+        [_, name, ..] => Some(name.to_string()),
+    }
+}
+
+fn survey_status_text(status: &SurveyStatus) -> &'static str {
+    match status {
+        SurveyStatus::InProgress => "на рассмотрении",
+        SurveyStatus::ReadyForReview => "ожидает решения",
+        SurveyStatus::Completed => "рассмотрена",
+    }
+}
+
+/// Номер символа по смещению в байтах (для сообщений об ошибках)
+pub(crate) fn char_position(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            full_name: Some("Иванов Иван Иванович".to_string()),
+            telegram_nickname: Some("@ivan".to_string()),
+            ..TemplateContext::default()
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_variable_with_known_list() {
+        let error = MessageTemplate::parse("Привет, {{ name }}!").unwrap_err();
+
+        assert_eq!(error.position, 8);
+        assert!(error.message.contains("«name»"));
+        assert!(error.message.contains("full_name"));
+    }
+
+    #[test]
+    fn reports_error_positions_in_characters() {
+        assert_eq!(MessageTemplate::parse("Ждем {{slot_time").unwrap_err().position, 5);
+        assert_eq!(MessageTemplate::parse("Ждем }} вас").unwrap_err().position, 5);
+        assert_eq!(MessageTemplate::parse("Ок {{full_name {{slot_time}}").unwrap_err().position, 3);
+
+        let error = validate_broadcast("Текст", Some(&MediaGroup {
+            media: vec![crate::MediaItem {
+                media_type: "photo".to_string(),
+                file_id: Some("file".to_string()),
+                file_path: None,
+                caption: Some("Файл {{x}}".to_string()),
+            }],
+        }))
+        .unwrap_err();
+        assert_eq!(error.position, 5);
+        assert!(error.message.starts_with("подпись к файлу 1"));
+    }
+
+    #[test]
+    fn escapes_values_but_not_fallbacks() {
+        let template = MessageTemplate::parse("<b>{{full_name}}</b> {{slot_place|<i>уточним</i>}}").unwrap();
+        let context = TemplateContext {
+            full_name: Some("A & <B>".to_string()),
+            ..TemplateContext::default()
+        };
+
+        assert_eq!(template.render(&context, BroadcastParseMode::Html), "<b>A &amp; &lt;B&gt;</b> <i>уточним</i>");
+        assert_eq!(
+            MessageTemplate::parse("{{telegram_nickname}}").unwrap().render(
+                &TemplateContext { telegram_nickname: Some("@a_b".to_string()), ..TemplateContext::default() },
+                BroadcastParseMode::MarkdownV2,
+            ),
+            "a\\_b",
+        );
+    }
+
+    #[test]
+    fn renders_first_name_and_empty_values() {
+        let template = MessageTemplate::parse("{{first_name}}, {{telegram_nickname}}; {{slot_time}}.").unwrap();
+
+        assert_eq!(template.variables(), vec![
+            TemplateVariable::FirstName,
+            TemplateVariable::TelegramNickname,
+            TemplateVariable::SlotTime,
+        ]);
+        assert_eq!(template.render(&context(), BroadcastParseMode::Plain), "Иван, ivan; .");
+    }
+}
@@ -0,0 +1,170 @@
+//! A/B-варианты рассылок: основной текст — вариант A, `variants` добавляют B, C...
+//! Получатели делятся по хешу telegram_id, поэтому один человек всегда получает один вариант.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::MediaGroup;
+
+// Вместе с основным текстом — не больше пяти вариантов (A–E)
+pub const MAX_VARIANTS: usize = 5;
+// Доля тестовой группы в режиме автовыбора победителя, проценты
+const MIN_TEST_PERCENT: u8 = 1;
+const MAX_TEST_PERCENT: u8 = 99;
+// FNV-1a: стабилен между запусками и версиями Rust, в отличие от DefaultHasher
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const PERCENT_BUCKETS: u64 = 100;
+
+/// Дополнительный вариант рассылки: свой текст или медиафайлы, остальное общее
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct BroadcastVariant {
+    pub message: String,
+    #[serde(default)]
+    pub media_group: Option<MediaGroup>,
+}
+
+/// Автовыбор победителя: сначала варианты получает тестовая доля аудитории,
+/// через `decide_after_minutes` лучший вариант уходит остальным
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq)]
+pub struct AutoWinnerSettings {
+    pub test_percent: u8,
+    pub decide_after_minutes: u32,
+}
+
+impl AutoWinnerSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_TEST_PERCENT..=MAX_TEST_PERCENT).contains(&self.test_percent) {
+            return Err(format!("Тестовая доля должна быть от {} до {}%", MIN_TEST_PERCENT, MAX_TEST_PERCENT));
+        }
+        if self.decide_after_minutes == 0 {
+            return Err("Время до выбора победителя должно быть больше нуля".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Статистика варианта: доставка и конверсия его получателей
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastVariantStats {
+    /// Номер варианта: 0 — A (основной текст), 1 — B...
+    pub variant: i64,
+    pub recipients: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    /// Нажали «Записаться»
+    pub clicked: i64,
+    /// Записались после нажатия
+    pub booked: i64,
+    /// Доля записавшихся среди получивших вариант
+    pub conversion_rate: f64,
+    /// Вариант выбран победителем в режиме автовыбора
+    pub is_winner: bool,
+}
+
+/// Буква варианта для логов и уведомлений
+pub fn variant_label(index: usize) -> char {
+    (b'A' + index as u8) as char
+}
+
+fn audience_hash(telegram_id: i64) -> u64 {
+    telegram_id.to_le_bytes().iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Вариант получателя или None, если в режиме автовыбора он ждет победителя.
+/// Тестовая группа и вариант берутся из разных частей хеша, чтобы не зависеть друг от друга
+pub fn assign_variant(telegram_id: i64, variant_count: usize, test_percent: Option<u8>) -> Option<usize> {
+    let hash = audience_hash(telegram_id);
+    if let Some(test_percent) = test_percent {
+        if hash % PERCENT_BUCKETS >= test_percent as u64 {
+            return None;
+        }
+    }
+    Some(((hash / PERCENT_BUCKETS) % variant_count.max(1) as u64) as usize)
+}
+
+/// Текст и медиафайлы варианта: 0 — основной текст рассылки, дальше — `variants` по порядку
+pub fn variant_content(
+    message: String,
+    media_group: Option<MediaGroup>,
+    variants: Vec<BroadcastVariant>,
+    index: usize,
+) -> (String, Option<MediaGroup>) {
+    match index.checked_sub(1).and_then(|position| variants.into_iter().nth(position)) {
+        Some(variant) => (variant.message, variant.media_group),
+        None => (message, media_group),
+    }
+}
+
+/// Лучший вариант: по доле записей, затем по доле нажатий; при равенстве — более ранний
+pub fn pick_winner(stats: &[BroadcastVariantStats]) -> usize {
+    let rate = |count: i64, delivered: i64| if delivered == 0 { 0.0 } else { count as f64 / delivered as f64 };
+    stats
+        .iter()
+        .fold(None::<&BroadcastVariantStats>, |best, candidate| match best {
+            Some(best)
+                if (rate(best.booked, best.delivered), rate(best.clicked, best.delivered))
+                    >= (rate(candidate.booked, candidate.delivered), rate(candidate.clicked, candidate.delivered)) =>
+            {
+                Some(best)
+            }
+            _ => Some(candidate),
+        })
+        .map(|winner| winner.variant as usize)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(variant: i64, delivered: i64, clicked: i64, booked: i64) -> BroadcastVariantStats {
+        BroadcastVariantStats {
+            variant,
+            recipients: delivered,
+            delivered,
+            failed: 0,
+            clicked,
+            booked,
+            conversion_rate: 0.0,
+            is_winner: false,
+        }
+    }
+
+    #[test]
+    fn variant_depends_only_on_telegram_id_and_splits_evenly() {
+        let assigned: Vec<_> = (0..1000).map(|id| assign_variant(id, 2, None)).collect();
+        assert_eq!(assigned, (0..1000).map(|id| assign_variant(id, 2, None)).collect::<Vec<_>>());
+        let variant_b = assigned.iter().filter(|variant| **variant == Some(1)).count();
+        assert!((400..=600).contains(&variant_b), "variant B size {}", variant_b);
+        assert!(assigned.iter().all(|variant| matches!(variant, Some(0) | Some(1))));
+    }
+
+    #[test]
+    fn test_group_boundary_follows_hash_bucket() {
+        for telegram_id in [1, 2001, 123_456_789, -42] {
+            let bucket = (audience_hash(telegram_id) % PERCENT_BUCKETS) as u8;
+            assert_eq!(assign_variant(telegram_id, 2, Some(bucket)), None);
+            assert!(assign_variant(telegram_id, 2, Some(bucket + 1)).is_some());
+            // Вариант в тестовой группе тот же, что и без автовыбора
+            assert_eq!(assign_variant(telegram_id, 2, Some(100)), assign_variant(telegram_id, 2, None));
+        }
+        let settings = |test_percent| AutoWinnerSettings { test_percent, decide_after_minutes: 30 };
+        assert!(settings(0).validate().is_err() && settings(100).validate().is_err());
+        assert!(settings(1).validate().is_ok() && settings(99).validate().is_ok());
+    }
+
+    #[test]
+    fn winner_is_chosen_by_bookings_then_clicks_then_order() {
+        assert_eq!(pick_winner(&[stats(0, 10, 9, 1), stats(1, 10, 2, 2)]), 1);
+        assert_eq!(pick_winner(&[stats(0, 10, 3, 1), stats(1, 10, 5, 1)]), 1);
+        assert_eq!(pick_winner(&[stats(0, 10, 5, 1), stats(1, 20, 10, 2), stats(2, 10, 5, 1)]), 0);
+    }
+
+    #[test]
+    fn winner_falls_back_to_first_variant_without_stats() {
+        assert_eq!(pick_winner(&[]), 0);
+        assert_eq!(pick_winner(&[stats(0, 0, 0, 0), stats(1, 0, 0, 0)]), 0);
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::Utc;
+use core_logic::{
+    BroadcastEvent, BroadcastMessageRecord, BroadcastMessageType, BroadcastStatus, CreateBroadcastCommand,
+    IdempotencyReservation, MessageStatus, SaveDecisionCampaignRequest, StoredResponse,
+};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+const USER_ID: i64 = 2001;
+
+/// SQLite в памяти с примененными миграциями
+async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    core_logic::db::run_migrations(&pool).await.unwrap();
+    pool
+}
+
+/// Запись сообщения рассылки для получателя
+fn recipient_record(broadcast_id: &str, telegram_id: i64, status: MessageStatus, variant: Option<i64>) -> BroadcastMessageRecord {
+    BroadcastMessageRecord {
+        id: 0,
+        broadcast_id: broadcast_id.to_string(),
+        telegram_id,
+        status,
+        error: None,
+        sent_at: None,
+        retry_count: 0,
+        message_type: Some(BroadcastMessageType::Custom),
+        created_at: Utc::now().naive_utc(),
+        edit_status: None,
+        edit_error: None,
+        variant,
+    }
+}
+
+async fn create_decision_campaign(pool: &SqlitePool, rejection_message: Option<&str>) {
+    let campaign = SaveDecisionCampaignRequest {
+        name: "Осенний набор".to_string(),
+        approval_message: "{{first_name|Привет}}, твоя анкета одобрена!".to_string(),
+        rejection_message: rejection_message.map(str::to_string),
+        active: true,
+    };
+    core_logic::db::create_decision_campaign(pool, &campaign).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn approval_queues_single_sign_up_invitation() {
+    let pool = test_pool().await;
+    create_decision_campaign(&pool, None).await;
+
+    let broadcast_id = core_logic::db::notify_decision(&pool, USER_ID, 1).await.unwrap().unwrap();
+    // Повторное одобрение ничего не отправляет, отказ без шаблона — тоже
+    assert!(core_logic::db::notify_decision(&pool, USER_ID, 1).await.unwrap().is_none());
+    assert!(core_logic::db::notify_decision(&pool, USER_ID, 0).await.unwrap().is_none());
+
+    let summary = core_logic::db::get_broadcast_summary(&pool, &broadcast_id).await.unwrap().unwrap();
+    assert_eq!(summary.status, BroadcastStatus::Scheduled);
+    let due = core_logic::db::get_due_scheduled_broadcasts(&pool, Utc::now()).await.unwrap();
+    assert_eq!(due, vec![broadcast_id.clone()]);
+    let event = core_logic::db::get_broadcast_created_event(&pool, &broadcast_id).await.unwrap().unwrap();
+    let BroadcastEvent::BroadcastCreated { message_type, target_users, .. } = event else {
+        panic!("unexpected event");
+    };
+    assert_eq!(message_type, Some(BroadcastMessageType::SignUp));
+    assert_eq!(target_users.iter().map(|user| user.telegram_id).collect::<Vec<_>>(), vec![USER_ID]);
+}
+
+#[tokio::test]
+async fn changed_decision_does_not_send_second_notification() {
+    let pool = test_pool().await;
+    create_decision_campaign(&pool, Some("К сожалению, анкета отклонена")).await;
+
+    let broadcast_id = core_logic::db::notify_decision(&pool, USER_ID, 0).await.unwrap().unwrap();
+    assert!(core_logic::db::notify_decision(&pool, USER_ID, 1).await.unwrap().is_none());
+
+    let campaign = core_logic::db::get_active_decision_campaign(&pool).await.unwrap().unwrap();
+    let notifications = core_logic::db::get_decision_notifications(&pool, campaign.id).await.unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!((notifications[0].decision, notifications[0].broadcast_id.clone()), (0, Some(broadcast_id.clone())));
+    assert_eq!(core_logic::db::get_due_scheduled_broadcasts(&pool, Utc::now()).await.unwrap(), vec![broadcast_id]);
+}
+
+#[tokio::test]
+async fn auto_winner_picks_variant_with_more_sign_ups() {
+    const VARIANT_B_USER_ID: i64 = 2002;
+    let pool = test_pool().await;
+    let command: CreateBroadcastCommand = serde_json::from_value(serde_json::json!({
+        "message": "Открыта запись на собеседования",
+        "message_type": "custom",
+        "selected_external_users": [USER_ID.to_string(), VARIANT_B_USER_ID.to_string()],
+        "media_group": null,
+        "variants": [{ "message": "Осталось мало мест — запишись" }],
+        "auto_winner": { "test_percent": 50, "decide_after_minutes": 30 },
+    }))
+    .unwrap();
+    let (created, event) = core_logic::db::handle_create_broadcast(&pool, command).await.unwrap();
+    let broadcast_id = created.broadcast_id;
+    let BroadcastEvent::BroadcastCreated { auto_winner: Some(settings), .. } = event else {
+        panic!("unexpected event");
+    };
+    core_logic::db::mark_broadcast_started(&pool, &broadcast_id).await.unwrap();
+    core_logic::db::schedule_broadcast_auto_winner(&pool, &broadcast_id, &settings).await.unwrap();
+    for (telegram_id, variant) in [(USER_ID, 0), (VARIANT_B_USER_ID, 1)] {
+        let record = BroadcastMessageRecord {
+            sent_at: Some(Utc::now().naive_utc()),
+            ..recipient_record(&broadcast_id, telegram_id, MessageStatus::Sent, Some(variant))
+        };
+        core_logic::db::create_broadcast_message(&pool, &record).await.unwrap();
+    }
+    core_logic::db::record_broadcast_click(&pool, &broadcast_id, VARIANT_B_USER_ID).await.unwrap();
+
+    // Повтор приходит получателю в его варианте
+    let payload = core_logic::db::get_broadcast_message_payload(&pool, &broadcast_id, VARIANT_B_USER_ID).await.unwrap().unwrap();
+    assert_eq!(payload.message, "Осталось мало мест — запишись");
+
+    assert!(core_logic::db::get_due_broadcast_winners(&pool, Utc::now()).await.unwrap().is_empty());
+    let decide_at = Utc::now() + chrono::Duration::minutes(31);
+    assert_eq!(core_logic::db::get_due_broadcast_winners(&pool, decide_at).await.unwrap(), vec![broadcast_id.clone()]);
+    let winner = core_logic::db::select_broadcast_winner(&pool, &broadcast_id).await.unwrap();
+    assert!(matches!(winner, Some(BroadcastEvent::BroadcastWinnerSelected { variant: 1, .. })));
+    // Событие не опубликовано: выбор снимается, и планировщик выбирает победителя снова
+    core_logic::db::release_broadcast_winner(&pool, &broadcast_id, 1).await.unwrap();
+    assert_eq!(core_logic::db::get_due_broadcast_winners(&pool, decide_at).await.unwrap(), vec![broadcast_id.clone()]);
+    let winner = core_logic::db::select_broadcast_winner(&pool, &broadcast_id).await.unwrap();
+    assert!(matches!(winner, Some(BroadcastEvent::BroadcastWinnerSelected { variant: 1, .. })));
+    assert!(core_logic::db::select_broadcast_winner(&pool, &broadcast_id).await.unwrap().is_none());
+
+    let stats = core_logic::db::get_broadcast_variant_stats(&pool, &broadcast_id).await.unwrap();
+    assert_eq!(stats.iter().map(|stats| (stats.variant, stats.clicked, stats.is_winner)).collect::<Vec<_>>(), vec![(0, 0, false), (1, 1, true)]);
+}
+
+#[tokio::test]
+async fn repeated_create_delivers_broadcast_once() {
+    const KEY: &str = "create-broadcast-1";
+    let pool = test_pool().await;
+    let body = r#"{"message":"Открыта запись"}"#.as_bytes();
+    let reserve = |body: &'static [u8]| {
+        let pool = pool.clone();
+        async move { core_logic::db::reserve_idempotency_key(&pool, KEY, "POST", "/broadcast", body).await.unwrap() }
+    };
+
+    // Пока первый запрос выполняется, повтор получает отказ, после — сохраненный ответ
+    assert_eq!(reserve(body).await, IdempotencyReservation::Reserved);
+    assert_eq!(reserve(body).await, IdempotencyReservation::InProgress);
+    let stored = StoredResponse {
+        status_code: 200,
+        body: br#"{"broadcast_id":"test-broadcast"}"#.to_vec(),
+        content_type: Some("application/json".to_string()),
+    };
+    core_logic::db::complete_idempotency_key(&pool, KEY, "POST", "/broadcast", &stored).await.unwrap();
+    assert_eq!(reserve(body).await, IdempotencyReservation::Completed(stored));
+    assert_eq!(reserve(r#"{"message":"Другой текст"}"#.as_bytes()).await, IdempotencyReservation::Mismatch);
+
+    // Повторы в списке получателей и повторная доставка BroadcastCreated не дают второго сообщения
+    let command: CreateBroadcastCommand = serde_json::from_value(serde_json::json!({
+        "message": "Открыта запись на собеседования",
+        "message_type": "custom",
+        "selected_external_users": [USER_ID.to_string(), USER_ID.to_string()],
+        "media_group": null,
+    }))
+    .unwrap();
+    let (created, event) = core_logic::db::handle_create_broadcast(&pool, command).await.unwrap();
+    let BroadcastEvent::BroadcastCreated { target_users, .. } = event else {
+        panic!("unexpected event");
+    };
+    assert_eq!(target_users.len(), 1);
+    let record = recipient_record(&created.broadcast_id, USER_ID, MessageStatus::Pending, None);
+    assert!(core_logic::db::create_broadcast_message(&pool, &record).await.unwrap());
+    assert!(!core_logic::db::create_broadcast_message(&pool, &record).await.unwrap());
+    // Сообщение без отметки о публикации публикуется снова, после отметки — нет
+    assert!(core_logic::db::is_broadcast_message_unqueued(&pool, &created.broadcast_id, USER_ID).await.unwrap());
+    assert!(core_logic::db::get_broadcast_recipient_ids(&pool, &created.broadcast_id).await.unwrap().is_empty());
+    core_logic::db::mark_broadcast_message_queued(&pool, &created.broadcast_id, USER_ID).await.unwrap();
+    assert!(!core_logic::db::is_broadcast_message_unqueued(&pool, &created.broadcast_id, USER_ID).await.unwrap());
+    assert!(core_logic::db::get_broadcast_recipient_ids(&pool, &created.broadcast_id).await.unwrap().contains(&USER_ID));
+    let messages = core_logic::db::get_broadcast_messages(&pool, &created.broadcast_id, None, None, None).await.unwrap();
+    assert_eq!(messages.len(), 1);
+}
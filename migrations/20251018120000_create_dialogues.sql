@@ -0,0 +1,6 @@
+-- Состояние многошаговых диалогов бота (teloxide dialogue storage)
+CREATE TABLE IF NOT EXISTS dialogues (
+    chat_id INTEGER PRIMARY KEY,                   -- ID чата Telegram
+    state TEXT NOT NULL,                           -- Состояние диалога в JSON
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Пользователи, которым бот не может доставить сообщения (заблокировали бота, удалили аккаунт)
+CREATE TABLE IF NOT EXISTS unreachable_users (
+    telegram_id INTEGER PRIMARY KEY,               -- ID пользователя Telegram
+    reason TEXT NOT NULL,                          -- Причина: blocked, chat_not_found, deactivated
+    error TEXT,                                    -- Текст ошибки Telegram
+    marked_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
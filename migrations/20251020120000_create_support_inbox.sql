@@ -0,0 +1,21 @@
+-- Обращения кандидатов в поддержку через бота
+-- Тема форума в чате сотрудников для каждого кандидата
+CREATE TABLE IF NOT EXISTS support_threads (
+    telegram_id INTEGER PRIMARY KEY,               -- ID кандидата в Telegram
+    thread_id INTEGER NOT NULL UNIQUE,             -- ID темы форума в чате сотрудников
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- История переписки кандидата с сотрудниками
+CREATE TABLE IF NOT EXISTS support_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,                  -- ID кандидата в Telegram
+    direction TEXT NOT NULL,                       -- inbound (от кандидата) или outbound (ответ сотрудника)
+    text TEXT,                                     -- Текст или подпись сообщения
+    staff_telegram_id INTEGER,                     -- Кто из сотрудников ответил
+    staff_message_id INTEGER,                      -- ID сообщения в чате сотрудников
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_support_messages_telegram_id ON support_messages(telegram_id);
+CREATE INDEX IF NOT EXISTS idx_support_messages_staff_message_id ON support_messages(staff_message_id);
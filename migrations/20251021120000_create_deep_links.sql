@@ -0,0 +1,18 @@
+-- Deep link'и /start <payload> для атрибуции кандидатов
+CREATE TABLE IF NOT EXISTS deep_links (
+    payload TEXT PRIMARY KEY,                      -- Параметр start в ссылке t.me/<bot>?start=<payload>
+    source TEXT NOT NULL,                          -- Источник: рассылка, пост в канале, QR-постер
+    flow TEXT NOT NULL DEFAULT 'welcome',          -- Куда ведет ссылка: welcome, signup, contact
+    broadcast_id TEXT,                             -- Рассылка, в которой размещена ссылка
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Переходы по deep link'ам: первый переход каждого пользователя по каждой ссылке
+CREATE TABLE IF NOT EXISTS deep_link_visits (
+    payload TEXT NOT NULL,
+    telegram_id INTEGER NOT NULL,
+    visited_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (payload, telegram_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_deep_link_visits_telegram_id ON deep_link_visits(telegram_id);
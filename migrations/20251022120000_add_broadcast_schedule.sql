@@ -0,0 +1,5 @@
+-- Отложенные рассылки: время отправки хранится в read model,
+-- событие BroadcastCreated публикуется планировщиком api_server
+ALTER TABLE broadcast_summaries ADD COLUMN scheduled_at DATETIME; -- Когда отправить (NULL — сразу)
+
+CREATE INDEX IF NOT EXISTS idx_broadcast_summaries_scheduled ON broadcast_summaries(status, scheduled_at);
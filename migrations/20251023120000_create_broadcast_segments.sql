@@ -0,0 +1,9 @@
+-- Сохраненные сегменты аудитории для рассылок.
+-- Правила хранятся в JSON и вычисляются на момент отправки
+CREATE TABLE IF NOT EXISTS broadcast_segments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    rules TEXT NOT NULL,                           -- SegmentRules в JSON
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
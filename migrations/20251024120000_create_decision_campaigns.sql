@@ -0,0 +1,21 @@
+-- Кампании автоматических уведомлений о решении ответственного.
+-- Активна не больше одной кампании: ее шаблоны отправляются кандидатам
+CREATE TABLE IF NOT EXISTS decision_campaigns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    approval_message TEXT NOT NULL,                -- Приглашение записаться (с кнопкой «Записаться»)
+    rejection_message TEXT,                        -- Отказ; NULL — при отклонении не пишем
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Отправленные уведомления: одно на кандидата и решение в рамках кампании
+CREATE TABLE IF NOT EXISTS decision_notifications (
+    campaign_id INTEGER NOT NULL REFERENCES decision_campaigns(id) ON DELETE CASCADE,
+    telegram_id INTEGER NOT NULL,
+    decision INTEGER NOT NULL,                     -- 1 - одобрена, 0 - отклонена
+    broadcast_id TEXT,                             -- Рассылка с уведомлением
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (campaign_id, telegram_id, decision)
+);
@@ -0,0 +1,5 @@
+-- Правка и отзыв доставленных рассылок: id сообщений в Telegram сохраняются при отправке,
+-- результат последней правки или удаления хранится отдельно от статуса доставки
+ALTER TABLE broadcast_messages ADD COLUMN telegram_message_ids TEXT; -- JSON-массив, первым — сообщение с текстом или подписью
+ALTER TABLE broadcast_messages ADD COLUMN edit_status TEXT;          -- pending, edited, deleted, failed (NULL — не правилось)
+ALTER TABLE broadcast_messages ADD COLUMN edit_error TEXT;
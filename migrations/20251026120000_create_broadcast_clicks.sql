@@ -0,0 +1,14 @@
+-- Воронка рассылок с кнопкой «Записаться»: доставлено → нажали → увидели слоты → записались
+CREATE TABLE IF NOT EXISTS broadcast_clicks (
+    broadcast_id TEXT NOT NULL,
+    telegram_id INTEGER NOT NULL,
+    clicked_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, -- Последнее нажатие кнопки
+    slots_viewed_at DATETIME,                              -- Когда после нажатия показали список слотов
+    PRIMARY KEY (broadcast_id, telegram_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_broadcast_clicks_telegram_id ON broadcast_clicks(telegram_id, clicked_at);
+
+-- Рассылка, нажатие кнопки в которой привело к записи
+ALTER TABLE records ADD COLUMN broadcast_id TEXT;
+CREATE INDEX IF NOT EXISTS idx_records_broadcast_id ON records(broadcast_id);
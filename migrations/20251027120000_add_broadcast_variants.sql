@@ -0,0 +1,13 @@
+-- A/B-варианты рассылок: вариант получателя хранится вместе с его сообщением
+ALTER TABLE broadcast_messages ADD COLUMN variant INTEGER; -- 0 — основной текст (A), 1 — B...; NULL — без вариантов
+
+-- Автовыбор победителя: после тестовой доли лучший вариант уходит остальной аудитории
+CREATE TABLE IF NOT EXISTS broadcast_auto_winners (
+    broadcast_id TEXT PRIMARY KEY,
+    test_percent INTEGER NOT NULL,  -- Доля аудитории, получающая варианты на тесте
+    decide_at DATETIME NOT NULL,    -- Когда выбрать победителя
+    winner_variant INTEGER,         -- NULL — победитель еще не выбран
+    decided_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_broadcast_auto_winners_decide_at ON broadcast_auto_winners(decide_at);
@@ -0,0 +1,15 @@
+-- Сохраненные ответы на изменяющие запросы с заголовком Idempotency-Key:
+-- повтор с тем же ключом получает тот же ответ, а не второе действие
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    idempotency_key TEXT NOT NULL,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    request_body BLOB NOT NULL,     -- Тело запроса: тот же ключ с другим телом — ошибка клиента
+    status_code INTEGER,            -- NULL — запрос еще обрабатывается
+    response_body BLOB,
+    content_type TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (idempotency_key, method, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
@@ -0,0 +1,19 @@
+-- Решение ответственного окончательное: кандидат получает одно уведомление за кампанию,
+-- последующий голос с другим исходом второго сообщения не отправляет.
+-- Первичный ключ в SQLite не меняется, поэтому таблица пересоздается; остается самое раннее уведомление
+CREATE TABLE decision_notifications_by_candidate (
+    campaign_id INTEGER NOT NULL REFERENCES decision_campaigns(id) ON DELETE CASCADE,
+    telegram_id INTEGER NOT NULL,
+    decision INTEGER NOT NULL,                     -- 1 - одобрена, 0 - отклонена
+    broadcast_id TEXT,                             -- Рассылка с уведомлением
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (campaign_id, telegram_id)
+);
+
+INSERT OR IGNORE INTO decision_notifications_by_candidate (campaign_id, telegram_id, decision, broadcast_id, created_at)
+SELECT campaign_id, telegram_id, decision, broadcast_id, created_at
+FROM decision_notifications
+ORDER BY created_at, rowid;
+
+DROP TABLE decision_notifications;
+ALTER TABLE decision_notifications_by_candidate RENAME TO decision_notifications;
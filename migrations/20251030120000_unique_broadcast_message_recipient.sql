@@ -0,0 +1,6 @@
+-- Получатель получает рассылку не больше одного раза, даже при повторной доставке BroadcastCreated.
+-- Дубликаты, созданные до индекса, удаляются: остается первое сообщение получателя
+DELETE FROM broadcast_messages
+WHERE id NOT IN (SELECT MIN(id) FROM broadcast_messages GROUP BY broadcast_id, telegram_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_broadcast_messages_recipient ON broadcast_messages(broadcast_id, telegram_id);
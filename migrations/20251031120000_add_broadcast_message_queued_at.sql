@@ -0,0 +1,6 @@
+-- Время публикации сообщения в очередь отправки. NULL у сообщения в статусе pending значит,
+-- что Event Worker прервался между созданием записи и публикацией, и сообщение нужно опубликовать снова
+ALTER TABLE broadcast_messages ADD COLUMN queued_at DATETIME;
+
+-- Сообщения, созданные до этой миграции, уже были опубликованы
+UPDATE broadcast_messages SET queued_at = created_at;
@@ -0,0 +1,531 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommandScope, ParseMode, Recipient};
+use teloxide::utils::command::BotCommands;
+use teloxide::utils::html;
+use core_logic::{BroadcastSummary, CreateSlotRequest, SurveyStatus};
+use crate::dialogue::{self, BotDialogue, BotState};
+use crate::handlers::{format_russian_date, Command};
+
+// Роль с доступом к админ-командам (ответственный)
+const ADMIN_ROLE: i32 = 1;
+
+// Смещение московского времени относительно UTC
+const MSK_OFFSET_HOURS: i64 = 3;
+// Формат ввода времени слота
+const SLOT_TIME_INPUT_FORMAT: &str = "%d.%m.%Y %H:%M";
+// Максимальная вместимость слота
+const MAX_SLOT_CAPACITY: u16 = 100;
+// Лимит длины сообщения Telegram
+const MAX_MESSAGE_LENGTH: usize = 4096;
+// Сколько символов текста рассылки показывать в запросе на одобрение
+const APPROVAL_PREVIEW_LENGTH: usize = 500;
+
+// Тексты админ-команд
+const NO_ACCESS_MESSAGE: &str = "⛔ Команда доступна только администраторам.";
+const ADMIN_ERROR_MESSAGE: &str = "⚠️ Не удалось получить данные. Попробуйте позже.";
+const STATS_TEMPLATE: &str = "📊 <b>Статистика на сегодня</b>\n\n📅 Собеседований сегодня: {TODAY_BOOKINGS}\n📝 Анкет ожидают решения: {PENDING_SURVEYS} из {TOTAL_SURVEYS}";
+const NO_UPCOMING_SLOTS_MESSAGE: &str = "📭 Предстоящих слотов нет.";
+const SLOTS_HEADER: &str = "📋 <b>Предстоящие слоты</b> (записано / мест):";
+const ADD_SLOT_TIME_PROMPT: &str = "🕒 Введите дату и время слота по Москве в формате ДД.ММ.ГГГГ ЧЧ:ММ.\n\nДля отмены — /cancel";
+const ADD_SLOT_PLACE_PROMPT: &str = "🏢 Укажите место проведения.";
+const ADD_SLOT_CAPACITY_PROMPT: &str = "👥 Сколько человек можно записать в слот?";
+const INVALID_SLOT_TIME_MESSAGE: &str = "⚠️ Не удалось распознать дату. Пример: 25.09.2025 18:30";
+const PAST_SLOT_TIME_MESSAGE: &str = "⚠️ Это время уже прошло. Введите будущую дату.";
+const INVALID_CAPACITY_TEMPLATE: &str = "⚠️ Введите целое число от 1 до {MAX_USERS}.";
+const SLOT_CREATED_TEMPLATE: &str = "✅ Слот #{SLOT_ID} создан\n\n📅 Время: {TIME}\n🏢 Место: {PLACE}\n👥 Мест: {MAX_USERS}";
+const SLOT_CREATE_ERROR_MESSAGE: &str = "⚠️ Не удалось создать слот. Попробуйте позже.";
+const CANCELLED_MESSAGE: &str = "❎ Действие отменено.";
+const FIND_USAGE_MESSAGE: &str = "Использование: /find <ник>";
+const CANDIDATE_NOT_FOUND_TEMPLATE: &str = "🔍 Кандидат @{NICKNAME} не найден.";
+const BROADCAST_STATUS_USAGE_MESSAGE: &str = "Использование: /broadcast_status <id>";
+const BROADCAST_NOT_FOUND_TEMPLATE: &str = "🔍 Рассылка {BROADCAST_ID} не найдена.";
+const BROADCAST_APPROVAL_TEMPLATE: &str = "🔐 <b>Рассылка ждет одобрения</b> <code>{BROADCAST_ID}</code>\n\n👥 Получателей: {TOTAL}\n\n{MESSAGE}\n\nОдобрить или отклонить ее может ответственный, не создававший рассылку, в админ-панели.";
+const BROADCAST_APPROVED_TEMPLATE: &str = "✅ Рассылка <code>{BROADCAST_ID}</code> одобрена и будет отправлена.";
+const BROADCAST_REJECTED_TEMPLATE: &str = "🚫 Рассылка <code>{BROADCAST_ID}</code> отклонена.";
+const BROADCAST_COMPLETED_HEADER: &str = "🏁 <b>Рассылка завершена</b>";
+
+// Плейсхолдеры
+const TODAY_BOOKINGS_PLACEHOLDER: &str = "{TODAY_BOOKINGS}";
+const PENDING_SURVEYS_PLACEHOLDER: &str = "{PENDING_SURVEYS}";
+const TOTAL_SURVEYS_PLACEHOLDER: &str = "{TOTAL_SURVEYS}";
+const SLOT_ID_PLACEHOLDER: &str = "{SLOT_ID}";
+const TIME_PLACEHOLDER: &str = "{TIME}";
+const PLACE_PLACEHOLDER: &str = "{PLACE}";
+const MAX_USERS_PLACEHOLDER: &str = "{MAX_USERS}";
+const NICKNAME_PLACEHOLDER: &str = "{NICKNAME}";
+const BROADCAST_ID_PLACEHOLDER: &str = "{BROADCAST_ID}";
+const TOTAL_PLACEHOLDER: &str = "{TOTAL}";
+const MESSAGE_PLACEHOLDER: &str = "{MESSAGE}";
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "snake_case", description = "Admin commands:")]
+pub enum AdminCommand {
+    #[command(description = "Today's bookings and pending surveys.")]
+    Stats,
+    #[command(description = "List upcoming slots with fill levels.")]
+    Slots,
+    #[command(rename = "addslot", description = "Add a slot step by step.")]
+    AddSlot,
+    #[command(description = "Find a candidate by nickname: /find <nickname>.")]
+    Find(String),
+    #[command(description = "Show broadcast progress: /broadcast_status <id>.")]
+    BroadcastStatus(String),
+    #[command(description = "Cancel the current action.")]
+    Cancel,
+}
+
+/// Проверяет, что пользователь — администратор (ответственный)
+pub async fn is_admin(pool: &SqlitePool, telegram_id: i64) -> bool {
+    match core_logic::get_user_role(pool, telegram_id).await {
+        Ok(role) => role == Some(ADMIN_ROLE),
+        Err(e) => {
+            tracing::error!("Failed to get role for user {}: {}", telegram_id, e);
+            false
+        }
+    }
+}
+
+pub async fn handle_admin_command(
+    bot: Bot,
+    msg: Message,
+    cmd: AdminCommand,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+) -> ResponseResult<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+
+    if !is_admin(&pool, user.id.0 as i64).await {
+        bot.send_message(msg.chat.id, NO_ACCESS_MESSAGE).await?;
+        return Ok(());
+    }
+
+    match cmd {
+        AdminCommand::Stats => handle_stats(&bot, &msg, &pool).await?,
+        AdminCommand::Slots => handle_slots(&bot, &msg, &pool).await?,
+        AdminCommand::AddSlot => {
+            dialogue::set_state(&dialogue, BotState::AddSlotTime).await;
+            bot.send_message(msg.chat.id, ADD_SLOT_TIME_PROMPT).await?;
+        }
+        AdminCommand::Find(nickname) => handle_find(&bot, &msg, &pool, &nickname).await?,
+        AdminCommand::BroadcastStatus(broadcast_id) => handle_broadcast_status(&bot, &msg, &pool, &broadcast_id).await?,
+        AdminCommand::Cancel => {
+            dialogue::reset_state(&dialogue).await;
+            bot.send_message(msg.chat.id, CANCELLED_MESSAGE).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// /stats: собеседования на сегодня и анкеты без решения ответственного
+async fn handle_stats(bot: &Bot, msg: &Message, pool: &SqlitePool) -> ResponseResult<()> {
+    let bookings = match core_logic::db::get_todays_bookings(pool).await {
+        Ok(bookings) => bookings,
+        Err(e) => {
+            tracing::error!("Failed to get today's bookings: {}", e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let (total_surveys, pending_surveys) = match count_pending_surveys(pool).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            tracing::error!("Failed to count pending surveys: {}", e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let mut text = STATS_TEMPLATE
+        .replace(TODAY_BOOKINGS_PLACEHOLDER, &bookings.len().to_string())
+        .replace(PENDING_SURVEYS_PLACEHOLDER, &pending_surveys.to_string())
+        .replace(TOTAL_SURVEYS_PLACEHOLDER, &total_surveys.to_string());
+
+    // Группируем собеседования по слотам
+    let mut by_slot: BTreeMap<(DateTime<Utc>, String), usize> = BTreeMap::new();
+    for booking in bookings {
+        *by_slot.entry((booking.time, booking.place)).or_default() += 1;
+    }
+    if !by_slot.is_empty() {
+        text.push('\n');
+        for ((time, place), count) in by_slot {
+            let msk_time = time + chrono::Duration::hours(MSK_OFFSET_HOURS);
+            text.push_str(&format!("\n🕒 {} | 🏢 {} — {} чел.", msk_time.format("%H:%M"), html::escape(&place), count));
+        }
+    }
+
+    bot.send_message(msg.chat.id, truncate_message(text)).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+/// Считает анкеты всего и без решения ответственного
+async fn count_pending_surveys(pool: &SqlitePool) -> anyhow::Result<(usize, usize)> {
+    let users = core_logic::db::get_all_users_from_external_api()
+        .await
+        .map_err(|e| anyhow::anyhow!("External API error: {}", e))?;
+    let decided: HashSet<i64> = core_logic::db::get_decided_survey_ids(pool).await?.into_iter().collect();
+
+    let pending = users
+        .iter()
+        .filter_map(|user| user.get("telegram_id").and_then(|v| v.as_i64()))
+        .filter(|telegram_id| !decided.contains(telegram_id))
+        .count();
+
+    Ok((users.len(), pending))
+}
+
+/// /slots: предстоящие слоты с заполненностью
+async fn handle_slots(bot: &Bot, msg: &Message, pool: &SqlitePool) -> ResponseResult<()> {
+    let slots = match core_logic::db::get_all_slots(pool).await {
+        Ok(slots) => slots,
+        Err(e) => {
+            tracing::error!("Failed to get slots: {}", e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let now = Utc::now();
+    let upcoming: Vec<_> = slots.into_iter().filter(|slot| slot.time > now).collect();
+    if upcoming.is_empty() {
+        bot.send_message(msg.chat.id, NO_UPCOMING_SLOTS_MESSAGE).await?;
+        return Ok(());
+    }
+
+    let mut text = SLOTS_HEADER.to_string();
+    for slot in upcoming {
+        let booked = slot.booked_count.unwrap_or(0);
+        let fill_icon = if booked >= slot.max_user as i64 { "🔴" } else if booked > 0 { "🟡" } else { "🟢" };
+        let msk_time = slot.time + chrono::Duration::hours(MSK_OFFSET_HOURS);
+        text.push_str(&format!(
+            "\n{} #{} | 📅 {} | 🏢 {} — {}/{}",
+            fill_icon,
+            slot.id,
+            format_russian_date(&msk_time),
+            html::escape(&slot.place),
+            booked,
+            slot.max_user
+        ));
+    }
+
+    bot.send_message(msg.chat.id, truncate_message(text)).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+/// /find: запись и статус проверки анкеты кандидата
+async fn handle_find(bot: &Bot, msg: &Message, pool: &SqlitePool, nickname: &str) -> ResponseResult<()> {
+    let nickname = nickname.trim().trim_start_matches('@');
+    if nickname.is_empty() {
+        bot.send_message(msg.chat.id, FIND_USAGE_MESSAGE).await?;
+        return Ok(());
+    }
+
+    let users = match core_logic::db::get_all_users_from_external_api().await {
+        Ok(users) => users,
+        Err(e) => {
+            tracing::error!("Failed to get users from external API: {}", e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let candidate = users.iter().find(|user| {
+        user.get("username")
+            .and_then(|v| v.as_str())
+            .is_some_and(|username| username.trim_start_matches('@').eq_ignore_ascii_case(nickname))
+    });
+    let Some(telegram_id) = candidate.and_then(|user| user.get("telegram_id")).and_then(|v| v.as_i64()) else {
+        bot.send_message(msg.chat.id, CANDIDATE_NOT_FOUND_TEMPLATE.replace(NICKNAME_PLACEHOLDER, nickname)).await?;
+        return Ok(());
+    };
+    let full_name = candidate
+        .and_then(|user| user.pointer("/data/full_name"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("—");
+
+    let details = async {
+        let booking = core_logic::db::get_booking_by_telegram_id(pool, telegram_id).await?;
+        let summary = core_logic::db::get_survey_vote_summary(pool, telegram_id).await?;
+        let decision = core_logic::db::get_responsible_decision(pool, telegram_id).await?;
+        Ok::<_, sqlx::Error>((booking, summary, decision))
+    };
+    let (booking, summary, decision) = match details.await {
+        Ok(details) => details,
+        Err(e) => {
+            tracing::error!("Failed to get candidate {} details: {}", telegram_id, e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let booking_text = match booking {
+        Some(booking) => {
+            let msk_time = booking.time + chrono::Duration::hours(MSK_OFFSET_HOURS);
+            format!("{} | 🏢 {}", format_russian_date(&msk_time), html::escape(&booking.place))
+        }
+        None => "нет записи".to_string(),
+    };
+    let status_text = match (summary.status, decision) {
+        (_, Some(1)) => "✅ одобрена ответственным",
+        (_, Some(_)) => "❌ отклонена ответственным",
+        (SurveyStatus::ReadyForReview, None) => "⏳ ждёт решения ответственного",
+        _ => "🔎 на проверке",
+    };
+
+    let text = format!(
+        "👤 <b>{}</b> (@{})\n🆔 <code>{}</code>\n\n📅 Запись: {}\n🗳 Голоса: ✅ {} / ❌ {}\n📝 Анкета: {}",
+        html::escape(full_name),
+        html::escape(nickname),
+        telegram_id,
+        booking_text,
+        summary.approve_votes,
+        summary.reject_votes,
+        status_text
+    );
+    bot.send_message(msg.chat.id, text).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+/// /broadcast_status: прогресс рассылки
+async fn handle_broadcast_status(bot: &Bot, msg: &Message, pool: &SqlitePool, broadcast_id: &str) -> ResponseResult<()> {
+    let broadcast_id = broadcast_id.trim();
+    if broadcast_id.is_empty() {
+        bot.send_message(msg.chat.id, BROADCAST_STATUS_USAGE_MESSAGE).await?;
+        return Ok(());
+    }
+
+    match core_logic::db::get_broadcast_summary(pool, broadcast_id).await {
+        Ok(Some(summary)) => {
+            bot.send_message(msg.chat.id, format_broadcast_summary(&summary))
+                .parse_mode(ParseMode::Html)
+                .await?;
+        }
+        Ok(None) => {
+            bot.send_message(msg.chat.id, BROADCAST_NOT_FOUND_TEMPLATE.replace(BROADCAST_ID_PLACEHOLDER, broadcast_id))
+                .await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed to get broadcast {}: {}", broadcast_id, e);
+            bot.send_message(msg.chat.id, ADMIN_ERROR_MESSAGE).await?;
+        }
+    }
+    Ok(())
+}
+
+fn format_broadcast_summary(summary: &BroadcastSummary) -> String {
+    let format_time = |time: Option<NaiveDateTime>| match time {
+        Some(time) => format_russian_date(&(Utc.from_utc_datetime(&time) + chrono::Duration::hours(MSK_OFFSET_HOURS))),
+        None => "—".to_string(),
+    };
+
+    format!(
+        "📨 <b>Рассылка</b> <code>{}</code>\n\nСтатус: {}\n👥 Всего: {}\n✅ Отправлено: {}\n❌ Ошибок: {}\n⏳ В очереди: {}\n\n🕒 Создана: {}\n▶️ Начата: {}\n🏁 Завершена: {}",
+        html::escape(&summary.id),
+        summary.status,
+        summary.total_users,
+        summary.sent_count,
+        summary.failed_count,
+        summary.pending_count,
+        format_time(Some(summary.created_at)),
+        format_time(summary.started_at),
+        format_time(summary.completed_at)
+    )
+}
+
+/// Шаг /addslot: время слота
+pub async fn receive_slot_time(bot: Bot, msg: Message, dialogue: BotDialogue) -> ResponseResult<()> {
+    let Some(text) = msg.text() else {
+        bot.send_message(msg.chat.id, ADD_SLOT_TIME_PROMPT).await?;
+        return Ok(());
+    };
+
+    let Ok(msk_time) = NaiveDateTime::parse_from_str(text.trim(), SLOT_TIME_INPUT_FORMAT) else {
+        bot.send_message(msg.chat.id, INVALID_SLOT_TIME_MESSAGE).await?;
+        return Ok(());
+    };
+    // Вводится московское время, храним UTC
+    let time = Utc.from_utc_datetime(&(msk_time - chrono::Duration::hours(MSK_OFFSET_HOURS)));
+    if time <= Utc::now() {
+        bot.send_message(msg.chat.id, PAST_SLOT_TIME_MESSAGE).await?;
+        return Ok(());
+    }
+
+    dialogue::set_state(&dialogue, BotState::AddSlotPlace { time }).await;
+    bot.send_message(msg.chat.id, ADD_SLOT_PLACE_PROMPT).await?;
+    Ok(())
+}
+
+/// Шаг /addslot: место проведения
+pub async fn receive_slot_place(bot: Bot, msg: Message, dialogue: BotDialogue, time: DateTime<Utc>) -> ResponseResult<()> {
+    let Some(place) = msg.text().map(str::trim).filter(|place| !place.is_empty()) else {
+        bot.send_message(msg.chat.id, ADD_SLOT_PLACE_PROMPT).await?;
+        return Ok(());
+    };
+
+    dialogue::set_state(&dialogue, BotState::AddSlotCapacity { time, place: place.to_string() }).await;
+    bot.send_message(msg.chat.id, ADD_SLOT_CAPACITY_PROMPT).await?;
+    Ok(())
+}
+
+/// Шаг /addslot: вместимость и создание слота
+pub async fn receive_slot_capacity(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    pool: Arc<SqlitePool>,
+    (time, place): (DateTime<Utc>, String),
+) -> ResponseResult<()> {
+    let max_users = msg
+        .text()
+        .and_then(|text| text.trim().parse::<u16>().ok())
+        .filter(|max_users| (1..=MAX_SLOT_CAPACITY).contains(max_users));
+    let Some(max_users) = max_users else {
+        bot.send_message(msg.chat.id, INVALID_CAPACITY_TEMPLATE.replace(MAX_USERS_PLACEHOLDER, &MAX_SLOT_CAPACITY.to_string()))
+            .await?;
+        return Ok(());
+    };
+
+    let request = CreateSlotRequest { start_time: time, place, max_users };
+    match core_logic::db::create_slot(&pool, request).await {
+        Ok(slot) => {
+            tracing::info!("✅ Slot {} created from bot by admin {}", slot.id, msg.chat.id);
+            dialogue::reset_state(&dialogue).await;
+            let msk_time = slot.time + chrono::Duration::hours(MSK_OFFSET_HOURS);
+            let text = SLOT_CREATED_TEMPLATE
+                .replace(SLOT_ID_PLACEHOLDER, &slot.id.to_string())
+                .replace(TIME_PLACEHOLDER, &format_russian_date(&msk_time))
+                .replace(PLACE_PLACEHOLDER, &slot.place)
+                .replace(MAX_USERS_PLACEHOLDER, &slot.max_user.to_string());
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed to create slot: {}", e);
+            bot.send_message(msg.chat.id, SLOT_CREATE_ERROR_MESSAGE).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Просит ответственных, кроме автора, одобрить крупную рассылку. Возвращает, скольким отправлен запрос
+pub async fn notify_broadcast_approvers(
+    bot: &Bot,
+    pool: &SqlitePool,
+    summary: &BroadcastSummary,
+    created_by: Option<i64>,
+) -> anyhow::Result<usize> {
+    let mut preview: String = summary.message.chars().take(APPROVAL_PREVIEW_LENGTH).collect();
+    if summary.message.chars().count() > APPROVAL_PREVIEW_LENGTH {
+        preview.push('…');
+    }
+    let text = BROADCAST_APPROVAL_TEMPLATE
+        .replace(BROADCAST_ID_PLACEHOLDER, &html::escape(&summary.id))
+        .replace(TOTAL_PLACEHOLDER, &summary.total_users.to_string())
+        .replace(MESSAGE_PLACEHOLDER, &html::escape(&preview));
+
+    let mut notified = 0;
+    for approver_id in core_logic::db::get_users(pool).await? {
+        if Some(approver_id) == created_by {
+            continue;
+        }
+        match bot.send_message(ChatId(approver_id), &text).parse_mode(ParseMode::Html).await {
+            Ok(_) => notified += 1,
+            // Ответственный мог еще не писать боту
+            Err(e) => tracing::warn!("⚠️ Failed to request approval from {}: {}", approver_id, e),
+        }
+    }
+
+    Ok(notified)
+}
+
+/// Сообщает автору рассылки решение второго ответственного
+pub async fn notify_broadcast_decision(
+    bot: &Bot,
+    author_id: i64,
+    broadcast_id: &str,
+    approved: bool,
+    reason: Option<&str>,
+) -> ResponseResult<()> {
+    let template = if approved { BROADCAST_APPROVED_TEMPLATE } else { BROADCAST_REJECTED_TEMPLATE };
+    let mut text = template.replace(BROADCAST_ID_PLACEHOLDER, &html::escape(broadcast_id));
+    if let Some(reason) = reason.filter(|reason| !reason.trim().is_empty()) {
+        text.push_str(&format!("\nПричина: {}", html::escape(reason.trim())));
+    }
+
+    bot.send_message(ChatId(author_id), text).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+/// Включены ли уведомления об итогах рассылок (BROADCAST_COMPLETION_NOTIFY)
+pub fn completion_notifications_enabled() -> bool {
+    std::env::var("BROADCAST_COMPLETION_NOTIFY")
+        .map(|value| matches!(value.trim(), "1" | "true"))
+        .unwrap_or(false)
+}
+
+/// Отправляет итоги завершенной рассылки автору, а если он неизвестен — всем ответственным.
+/// Рассылки на одного получателя (уведомления о решении по анкете) итогов не присылают
+pub async fn notify_broadcast_completed(bot: &Bot, pool: &SqlitePool, summary: &BroadcastSummary) -> anyhow::Result<usize> {
+    if summary.total_users <= 1 {
+        return Ok(0);
+    }
+    let text = format!("{}\n\n{}", BROADCAST_COMPLETED_HEADER, format_broadcast_summary(summary));
+    let recipients = match core_logic::db::get_broadcast_author(pool, &summary.id).await? {
+        Some(author) => vec![author],
+        None => core_logic::db::get_users(pool).await?,
+    };
+
+    let mut notified = 0;
+    for telegram_id in recipients {
+        match bot.send_message(ChatId(telegram_id), &text).parse_mode(ParseMode::Html).await {
+            Ok(_) => notified += 1,
+            Err(e) => tracing::warn!("⚠️ Failed to send broadcast results to {}: {}", telegram_id, e),
+        }
+    }
+
+    Ok(notified)
+}
+
+/// Настраивает меню команд: всем — пользовательские, администраторам — вместе с админскими
+pub async fn sync_command_menus(bot: &Bot, pool: &SqlitePool) -> anyhow::Result<()> {
+    bot.set_my_commands(Command::bot_commands()).await?;
+
+    for admin_id in core_logic::db::get_users(pool).await? {
+        if let Err(e) = update_command_menu(bot, admin_id, Some(ADMIN_ROLE)).await {
+            // Пользователь мог еще не писать боту
+            tracing::warn!("⚠️ Failed to set admin commands for {}: {}", admin_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Обновляет меню команд пользователя после смены роли
+pub async fn update_command_menu(bot: &Bot, telegram_id: i64, role: Option<i32>) -> ResponseResult<()> {
+    let scope = BotCommandScope::Chat { chat_id: Recipient::Id(ChatId(telegram_id)) };
+
+    if role == Some(ADMIN_ROLE) {
+        let mut commands = Command::bot_commands();
+        commands.extend(AdminCommand::bot_commands());
+        bot.set_my_commands(commands).scope(scope).await?;
+    } else {
+        bot.delete_my_commands().scope(scope).await?;
+    }
+
+    Ok(())
+}
+
+fn truncate_message(text: String) -> String {
+    if text.chars().count() <= MAX_MESSAGE_LENGTH {
+        return text;
+    }
+    let mut truncated: String = text.chars().take(MAX_MESSAGE_LENGTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
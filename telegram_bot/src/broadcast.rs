@@ -1,74 +1,498 @@
 use teloxide::prelude::*;
-use tracing::{error, info};
+use teloxide::{ApiError, RequestError};
+use tracing::{error, info, warn};
 use std::sync::Arc;
+use std::time::Duration;
 use sqlx::SqlitePool;
-use core_logic::{BroadcastMessage, MessageStatus, MessagesWorker};
+use core_logic::formatting::{self, BroadcastParseMode};
+use core_logic::keyboard::{BotAction, BroadcastKeyboard, ButtonKind};
+use core_logic::{
+    BroadcastEvent, BroadcastMessage, BroadcastMessageAction, BroadcastStatus, DeliveryErrorKind, MediaGroup, MessageEditStatus, MessageOutcome,
+    MessageStatus, MessagesWorker,
+};
 use anyhow::Error;
+use crate::callback_data::{CallbackAction, CallbackCodec};
+use crate::rate_limit::RateLimiter;
 
-pub async fn broadcast_worker(bot: Bot, pool: Arc<SqlitePool>) -> Result<(), Box<dyn std::error::Error>> {
+// Сколько сообщений рассылки отправляется параллельно
+const DEFAULT_BROADCAST_CONCURRENCY: usize = 8;
+// Через сколько сообщение приостановленной рассылки вернется в очередь
+const PAUSED_BROADCAST_RECHECK: Duration = Duration::from_secs(30);
+// Повторы при временных ошибках: задержка удваивается с каждой попыткой
+const MAX_DELIVERY_RETRIES: i64 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(600);
+// Кнопка рассылок SignUp без своей клавиатуры
+const SIGN_UP_BUTTON: &str = "Записаться";
+// sendMediaGroup не принимает клавиатуру: она уходит следующим сообщением с этим текстом
+const MEDIA_KEYBOARD_TEXT: &str = "👇";
+// Типы файлов, которые уходят в медиагруппе; остальные пропускаются
+const SENDABLE_MEDIA_TYPES: [&str; 5] = ["photo", "video", "document", "audio", "voice"];
+
+pub async fn broadcast_worker(bot: Bot, pool: Arc<SqlitePool>, codec: CallbackCodec) -> Result<(), Box<dyn std::error::Error>> {
     info!("Starting broadcast worker...");
 
+    let concurrency = std::env::var("BROADCAST_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_BROADCAST_CONCURRENCY);
+    let limiter = RateLimiter::from_env();
+
     // Создаем воркер для обработки сообщений
-    let worker = MessagesWorker::new().await?;
+    let worker = MessagesWorker::new().await?.with_concurrency(concurrency);
+    info!("Broadcast concurrency: {}", concurrency);
 
     // Запускаем обработку сообщений
     worker.start_processing("telegram_broadcast_worker", move |message| {
         let bot = bot.clone();
         let pool = pool.clone();
+        let codec = codec.clone();
+        let limiter = limiter.clone();
         
         async move {
-            handle_message(message, &bot, &pool).await
+            let delivery = (!message.is_test && message.action == BroadcastMessageAction::Send).then(|| message.clone());
+            match (handle_message(message, &bot, &pool, &codec, &limiter).await, delivery) {
+                // Строку помечаем failed до dead-letter, иначе повтор из dead-letter очереди ее не сбросит
+                (Err(e), Some(message)) => {
+                    let error_msg = e.to_string();
+                    if let Err(e) = record_delivery(&bot, &pool, &message, MessageStatus::Failed, Some(error_msg.clone())).await {
+                        error!("Failed to update message status to failed: {}", e);
+                    }
+                    Ok(MessageOutcome::DeadLetter(error_msg))
+                }
+                (result, _) => result,
+            }
         }
     }).await?;
 
     Ok(())
 }
 
-
-
-async fn handle_message(
+/// Отправляет одно сообщение рассылки и обновляет его статус
+pub async fn handle_message(
     message: BroadcastMessage,
     bot: &Bot,
     pool: &Arc<SqlitePool>,
-) -> Result<(), Error> {
+    codec: &CallbackCodec,
+    limiter: &RateLimiter,
+) -> Result<MessageOutcome, Error> {
+    if message.is_test {
+        return send_test_message(&message, bot, codec, limiter).await;
+    }
+    if message.action != BroadcastMessageAction::Send {
+        return revise_message(&message, bot, pool, codec, limiter).await;
+    }
+
+    // Отмененную рассылку не отправляем, приостановленную откладываем
+    match core_logic::db::get_broadcast_status(pool, &message.broadcast_id).await? {
+        Some(BroadcastStatus::Cancelled) => {
+            info!("🛑 Broadcast {} is cancelled, skipping user {}", message.broadcast_id, message.telegram_id);
+            core_logic::db::update_broadcast_message_status(
+                pool,
+                &message.broadcast_id,
+                message.telegram_id,
+                MessageStatus::Cancelled,
+                None,
+            ).await?;
+            return Ok(MessageOutcome::Processed);
+        }
+        Some(BroadcastStatus::Paused) => {
+            info!("⏸️ Broadcast {} is paused, deferring user {}", message.broadcast_id, message.telegram_id);
+            return Ok(MessageOutcome::RetryAfter(PAUSED_BROADCAST_RECHECK));
+        }
+        _ => {}
+    }
+
+    // Подставленные значения могли вывести текст за лимит Telegram: повтор тут не поможет
+    if let Err(e) = formatting::validate_rendered(&message.message, message.media_group.as_ref(), message.parse_mode, message.split_long_text) {
+        let error_msg = format!("Rendered message is invalid: {}", e);
+        error!("❌ {} for user {}", error_msg, message.telegram_id);
+        record_delivery(bot, pool, &message, MessageStatus::Failed, Some(error_msg.clone())).await?;
+        return Ok(MessageOutcome::DeadLetter(error_msg));
+    }
+
+    limiter.acquire(message.telegram_id, send_cost(&message)).await;
+
+    // Части, доставленные прошлой попыткой, второй раз не отправляем
+    let mut message_ids = core_logic::db::get_broadcast_message_telegram_ids(pool, &message.broadcast_id, message.telegram_id).await?;
+    let delivered_before = message_ids.len();
+
     // Отправляем сообщение в Telegram
-    let send_result = send_telegram_message(bot, &message).await;
+    let send_result = send_telegram_message(bot, &message, codec, &mut message_ids).await;
+
+    // id сообщений нужны, чтобы продолжить после сбоя, исправить или отозвать рассылку
+    if message_ids.len() != delivered_before
+        && let Err(e) = core_logic::db::set_broadcast_message_telegram_ids(
+            pool,
+            &message.broadcast_id,
+            message.telegram_id,
+            &message_ids,
+        ).await
+    {
+        error!("Failed to save Telegram message ids for user {}: {}", message.telegram_id, e);
+    }
 
     match send_result {
-        Ok(_) => {
+        Ok(()) => {
             info!("✅ Successfully sent message to user {}", message.telegram_id);
             
             // Обновляем статус на "sent"
-            if let Err(e) = core_logic::db::update_broadcast_message_status(
-                pool,
-                &message.broadcast_id,
-                message.telegram_id,
-                MessageStatus::Sent,
-                None,
-            ).await {
+            if let Err(e) = record_delivery(bot, pool, &message, MessageStatus::Sent, None).await {
                 error!("Failed to update message status to sent: {}", e);
             }
         }
         Err(e) => {
-            let error_msg = e.to_string();
+            // Telegram просит подождать: притормаживаем всех и возвращаем сообщение в очередь
+            if let Some(RequestError::RetryAfter(seconds)) = e.downcast_ref::<RequestError>() {
+                let delay = seconds.duration();
+                warn!("⏳ Rate limited by Telegram for user {}, retry after {:?}", message.telegram_id, delay);
+                limiter.pause(delay).await;
+                return Ok(MessageOutcome::RetryAfter(delay));
+            }
+
+            let kind = classify_error(&e);
+            let error_msg = format!("{}: {}", kind, e);
             error!("❌ Failed to send message to user {}: {}", message.telegram_id, error_msg);
+
+            // Временную ошибку повторяем с растущей задержкой, пока не исчерпан запас попыток
+            if kind == DeliveryErrorKind::Transient {
+                let retry_count = core_logic::db::record_broadcast_message_retry(
+                    pool,
+                    &message.broadcast_id,
+                    message.telegram_id,
+                    &error_msg,
+                ).await?;
+                if retry_count <= MAX_DELIVERY_RETRIES {
+                    let delay = retry_delay(retry_count);
+                    warn!("🔁 Retry {}/{} for user {} in {:?}", retry_count, MAX_DELIVERY_RETRIES, message.telegram_id, delay);
+                    return Ok(MessageOutcome::RetryAfter(delay));
+                }
+            }
+
+            // Заблокировавших бота и удаленных пользователей исключаем из следующих рассылок
+            if kind.is_permanent() {
+                match core_logic::db::mark_user_unreachable(pool, message.telegram_id, kind, Some(e.to_string())).await {
+                    Ok(()) => info!("🚫 User {} marked as unreachable ({})", message.telegram_id, kind),
+                    Err(e) => error!("Failed to mark user {} as unreachable: {}", message.telegram_id, e),
+                }
+            }
             
             // Обновляем статус на "failed"
-            if let Err(e) = core_logic::db::update_broadcast_message_status(
-                pool,
-                &message.broadcast_id,
-                message.telegram_id,
-                MessageStatus::Failed,
-                Some(error_msg),
-            ).await {
+            if let Err(e) = record_delivery(bot, pool, &message, MessageStatus::Failed, Some(error_msg.clone())).await {
                 error!("Failed to update message status to failed: {}", e);
             }
+
+            // Недоступным пользователям повторять бессмысленно, остальное разбирается вручную
+            if !kind.is_permanent() {
+                return Ok(MessageOutcome::DeadLetter(error_msg));
+            }
         }
     }
 
+    Ok(MessageOutcome::Processed)
+}
+
+/// Записывает итог доставки: статус сообщения и событие MessageSent или MessageFailed.
+/// Если это сообщение было последним, отправляет итоги рассылки ответственным
+async fn record_delivery(
+    bot: &Bot,
+    pool: &SqlitePool,
+    message: &BroadcastMessage,
+    status: MessageStatus,
+    error_msg: Option<String>,
+) -> Result<(), sqlx::Error> {
+    let event = match &error_msg {
+        Some(error) => BroadcastEvent::MessageFailed {
+            broadcast_id: message.broadcast_id.clone(),
+            telegram_id: message.telegram_id,
+            error: error.clone(),
+            failed_at: chrono::Utc::now(),
+        },
+        None => BroadcastEvent::MessageSent {
+            broadcast_id: message.broadcast_id.clone(),
+            telegram_id: message.telegram_id,
+            sent_at: chrono::Utc::now(),
+        },
+    };
+
+    core_logic::db::save_broadcast_event(pool, &event).await?;
+    let completed = core_logic::db::update_broadcast_message_status(
+        pool,
+        &message.broadcast_id,
+        message.telegram_id,
+        status,
+        error_msg,
+    ).await?;
+
+    if let Some(summary) = completed
+        && crate::admin::completion_notifications_enabled()
+    {
+        match crate::admin::notify_broadcast_completed(bot, pool, &summary).await {
+            Ok(notified) => info!("🏁 Broadcast {} completed, {} responsibles notified", summary.id, notified),
+            Err(e) => warn!("⚠️ Failed to notify about completed broadcast {}: {}", summary.id, e),
+        }
+    }
     Ok(())
 }
 
+/// Отправляет тестовое сообщение сотруднику: без повторов и без записи статусов,
+/// чтобы тест не менял статистику рассылки
+async fn send_test_message(
+    message: &BroadcastMessage,
+    bot: &Bot,
+    codec: &CallbackCodec,
+    limiter: &RateLimiter,
+) -> Result<MessageOutcome, Error> {
+    limiter.acquire(message.telegram_id, send_cost(message)).await;
+
+    match send_telegram_message(bot, message, codec, &mut Vec::new()).await {
+        Ok(()) => info!("🧪 Test message of broadcast {} sent to {}", message.broadcast_id, message.telegram_id),
+        Err(e) => warn!("🧪 Test message of broadcast {} failed for {}: {}", message.broadcast_id, message.telegram_id, e),
+    }
+    Ok(MessageOutcome::Processed)
+}
+
+/// Исправляет или удаляет доставленное сообщение и записывает результат в edit_status
+async fn revise_message(
+    message: &BroadcastMessage,
+    bot: &Bot,
+    pool: &Arc<SqlitePool>,
+    codec: &CallbackCodec,
+    limiter: &RateLimiter,
+) -> Result<MessageOutcome, Error> {
+    let cost = match message.action {
+        BroadcastMessageAction::Delete => message.telegram_message_ids.len().max(1),
+        _ if message.media_group.is_some() => 1,
+        // Каждая часть правится, удаляется или досылается отдельным запросом
+        _ => text_parts(message).map(|parts| parts.len()).unwrap_or(1).max(message.telegram_message_ids.len()),
+    };
+    limiter.acquire(message.telegram_id, cost as u32).await;
+
+    let mut message_ids = message.telegram_message_ids.clone();
+    let result = match message.action {
+        BroadcastMessageAction::Delete => delete_telegram_messages(bot, message).await.map(|_| MessageEditStatus::Deleted),
+        _ => edit_telegram_message(bot, message, codec, &mut message_ids).await.map(|_| MessageEditStatus::Edited),
+    };
+
+    // Число частей могло измениться: следующая правка или отзыв должны видеть актуальные id
+    if message_ids != message.telegram_message_ids
+        && let Err(e) = core_logic::db::set_broadcast_message_telegram_ids(
+            pool,
+            &message.broadcast_id,
+            message.telegram_id,
+            &message_ids,
+        ).await
+    {
+        error!("Failed to save Telegram message ids for user {}: {}", message.telegram_id, e);
+    }
+
+    let (status, error_msg) = match result {
+        Ok(status) => (status, None),
+        Err(e) => {
+            match e.downcast_ref::<RequestError>() {
+                Some(RequestError::RetryAfter(seconds)) => {
+                    let delay = seconds.duration();
+                    warn!("⏳ Rate limited by Telegram while revising message for {}, retry after {:?}", message.telegram_id, delay);
+                    limiter.pause(delay).await;
+                    return Ok(MessageOutcome::RetryAfter(delay));
+                }
+                // Текст не изменился: сообщение уже в нужном виде
+                Some(RequestError::Api(ApiError::MessageNotModified)) => (MessageEditStatus::Edited, None),
+                _ => {
+                    error!("❌ Failed to {:?} message of broadcast {} for user {}: {}", message.action, message.broadcast_id, message.telegram_id, e);
+                    (MessageEditStatus::Failed, Some(e.to_string()))
+                }
+            }
+        }
+    };
+
+    core_logic::db::update_broadcast_message_edit_status(pool, &message.broadcast_id, message.telegram_id, status, error_msg).await?;
+    Ok(MessageOutcome::Processed)
+}
+
+/// Меняет текст или подпись. Подпись медиагруппы находится в первом сообщении; разделенный текст
+/// делится заново: части правятся по порядку, лишние удаляются, недостающие досылаются
+async fn edit_telegram_message(
+    bot: &Bot,
+    message: &BroadcastMessage,
+    codec: &CallbackCodec,
+    message_ids: &mut Vec<i32>,
+) -> Result<(), Error> {
+    let Some(&content_id) = message_ids.first() else {
+        return Err(anyhow::anyhow!("No delivered Telegram message to edit"));
+    };
+    let chat_id = teloxide::types::ChatId(message.telegram_id);
+    let parse_mode = telegram_parse_mode(message.parse_mode);
+
+    if let Some(media_group) = &message.media_group {
+        let caption = media_group.media.first().and_then(|item| item.caption.as_deref()).unwrap_or_default();
+        bot.edit_message_caption(chat_id, teloxide::types::MessageId(content_id))
+            .caption(telegram_text(caption, message.parse_mode))
+            .parse_mode(parse_mode)
+            .await?;
+        return Ok(());
+    }
+
+    let parts = text_parts(message)?;
+    if parts.len() != message_ids.len() {
+        info!("✂️ Edited message for user {} has {} parts instead of {}", message.telegram_id, parts.len(), message_ids.len());
+    }
+    let keyboard = message_keyboard(message)
+        .map(|keyboard| inline_keyboard(&keyboard, message, codec))
+        .transpose()?;
+
+    // Лишние части удаляются с конца, id убирается только после удаления
+    while message_ids.len() > parts.len() {
+        let Some(&message_id) = message_ids.last() else { break };
+        match bot.delete_message(chat_id, teloxide::types::MessageId(message_id)).await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageToDeleteNotFound)) => {}
+            Err(e) => return Err(anyhow::Error::new(e)),
+        }
+        message_ids.pop();
+    }
+
+    // Клавиатура остается только под последней частью: без reply_markup Telegram ее снимет
+    let last_index = parts.len() - 1;
+    for (index, part) in parts.iter().enumerate() {
+        let markup = if index == last_index { keyboard.clone() } else { None };
+        match message_ids.get(index) {
+            Some(&message_id) => {
+                let mut request = bot
+                    .edit_message_text(chat_id, teloxide::types::MessageId(message_id), telegram_text(part, message.parse_mode))
+                    .parse_mode(parse_mode);
+                if let Some(markup) = markup {
+                    request = request.reply_markup(markup);
+                }
+                match request.await {
+                    // Часть не изменилась: она уже в нужном виде
+                    Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                    Err(e) => return Err(anyhow::Error::new(e)),
+                }
+            }
+            None => {
+                let mut request = bot
+                    .send_message(chat_id, telegram_text(part, message.parse_mode))
+                    .parse_mode(parse_mode);
+                if let Some(markup) = markup {
+                    request = request.reply_markup(markup);
+                }
+                message_ids.push(request.await?.id.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Части текстового сообщения: длинный текст делится, если рассылка это разрешает.
+/// Отправка и правка делят текст одинаково
+fn text_parts(message: &BroadcastMessage) -> Result<Vec<String>, Error> {
+    if message.split_long_text {
+        Ok(formatting::split_text(&message.message, message.parse_mode, formatting::MAX_TEXT_LENGTH)?)
+    } else {
+        Ok(vec![message.message.clone()])
+    }
+}
+
+/// Удаляет все сообщения рассылки у получателя: части текста, медиафайлы и клавиатуру
+async fn delete_telegram_messages(bot: &Bot, message: &BroadcastMessage) -> Result<(), Error> {
+    let chat_id = teloxide::types::ChatId(message.telegram_id);
+    for message_id in &message.telegram_message_ids {
+        match bot.delete_message(chat_id, teloxide::types::MessageId(*message_id)).await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageToDeleteNotFound)) => {}
+            Err(e) => return Err(anyhow::Error::new(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Сколько файлов медиагруппы уходит в Telegram: с file_id и поддерживаемого типа
+fn sendable_media_count(media_group: &MediaGroup) -> usize {
+    media_group
+        .media
+        .iter()
+        .filter(|item| item.file_id.is_some() && SENDABLE_MEDIA_TYPES.contains(&item.media_type.as_str()))
+        .count()
+}
+
+/// Сколько запросов к Telegram займет отправка: каждый файл медиагруппы и отдельное сообщение
+/// с клавиатурой после нее или каждая часть разделенного текста
+fn send_cost(message: &BroadcastMessage) -> u32 {
+    let requests = match &message.media_group {
+        Some(media_group) => media_group.media.len() + usize::from(message_keyboard(message).is_some()),
+        None => text_parts(message).map(|parts| parts.len()).unwrap_or(1),
+    };
+    requests.max(1) as u32
+}
+
+/// Задержка перед повторной попыткой: 5 с, 10 с, 20 с... но не больше RETRY_MAX_DELAY
+fn retry_delay(retry_count: i64) -> Duration {
+    let exponent = (retry_count - 1).clamp(0, 16) as u32;
+    RETRY_BASE_DELAY.saturating_mul(2u32.pow(exponent)).min(RETRY_MAX_DELAY)
+}
+
+/// Определяет класс ошибки отправки по ответу Telegram
+fn classify_error(error: &Error) -> DeliveryErrorKind {
+    match error.downcast_ref::<RequestError>() {
+        Some(RequestError::Api(api_error)) => match api_error {
+            ApiError::BotBlocked
+            | ApiError::BotKicked
+            | ApiError::BotKickedFromSupergroup
+            | ApiError::BotKickedFromChannel => DeliveryErrorKind::Blocked,
+            ApiError::ChatNotFound
+            | ApiError::UserNotFound
+            | ApiError::CantInitiateConversation => DeliveryErrorKind::ChatNotFound,
+            ApiError::UserDeactivated | ApiError::GroupDeactivated => DeliveryErrorKind::Deactivated,
+            _ => DeliveryErrorKind::Transient,
+        },
+        _ => DeliveryErrorKind::Transient,
+    }
+}
+
+/// Клавиатура сообщения: своя или кнопка «Записаться» для рассылок SignUp
+fn message_keyboard(message: &BroadcastMessage) -> Option<BroadcastKeyboard> {
+    match (&message.keyboard, &message.message_type) {
+        (Some(keyboard), _) => Some(keyboard.clone()),
+        (None, Some(core_logic::BroadcastMessageType::SignUp)) => Some(BroadcastKeyboard::sign_up(SIGN_UP_BUTTON)),
+        (None, _) => None,
+    }
+}
+
+/// Переводит клавиатуру рассылки в inline-клавиатуру Telegram с подписанными callback-кнопками.
+/// Кнопка «Записаться» несет id рассылки для воронки; нажатия в тестовых сообщениях не учитываются
+fn inline_keyboard(
+    keyboard: &BroadcastKeyboard,
+    message: &BroadcastMessage,
+    codec: &CallbackCodec,
+) -> Result<teloxide::types::InlineKeyboardMarkup, Error> {
+    let sign_up = match uuid::Uuid::parse_str(&message.broadcast_id) {
+        Ok(broadcast_id) if !message.is_test => CallbackAction::BroadcastSignUp(broadcast_id),
+        _ => CallbackAction::SignUp,
+    };
+
+    let mut rows = Vec::with_capacity(keyboard.rows.len());
+    for row in &keyboard.rows {
+        let mut buttons = Vec::with_capacity(row.len());
+        for button in row {
+            let kind = match &button.kind {
+                ButtonKind::Url { url } => teloxide::types::InlineKeyboardButtonKind::Url(
+                    url.parse().map_err(|e| anyhow::anyhow!("Invalid button URL '{}': {}", url, e))?,
+                ),
+                ButtonKind::Callback { action } => {
+                    let action = match action {
+                        BotAction::SignUp => sign_up.clone(),
+                        BotAction::Book { slot_id } => CallbackAction::Book(*slot_id),
+                    };
+                    teloxide::types::InlineKeyboardButtonKind::CallbackData(codec.encode(&action))
+                }
+            };
+            buttons.push(teloxide::types::InlineKeyboardButton::new(button.text.clone(), kind));
+        }
+        rows.push(buttons);
+    }
+    Ok(teloxide::types::InlineKeyboardMarkup::new(rows))
+}
+
 // Функция для создания подписи к медиафайлу
 fn create_media_caption(message: &BroadcastMessage, media_caption: &Option<String>, is_first_item: bool) -> Option<String> {
     if !is_first_item {
@@ -77,24 +501,59 @@ fn create_media_caption(message: &BroadcastMessage, media_caption: &Option<Strin
     
     // Если есть медиагруппа, текст сообщения уже объединен с подписью на фронтенде
     // Просто возвращаем подпись медиафайла
-    media_caption.clone()
+    media_caption.as_deref().map(|caption| telegram_text(caption, message.parse_mode))
+}
+
+/// Обычный текст отправляется как HTML, поэтому его нужно экранировать
+fn telegram_text(text: &str, parse_mode: BroadcastParseMode) -> String {
+    match parse_mode {
+        BroadcastParseMode::Plain => formatting::escape_html(text),
+        BroadcastParseMode::Html | BroadcastParseMode::MarkdownV2 => text.to_string(),
+    }
 }
 
+fn telegram_parse_mode(parse_mode: BroadcastParseMode) -> teloxide::types::ParseMode {
+    match parse_mode {
+        BroadcastParseMode::Plain | BroadcastParseMode::Html => teloxide::types::ParseMode::Html,
+        BroadcastParseMode::MarkdownV2 => teloxide::types::ParseMode::MarkdownV2,
+    }
+}
+
+/// Отправляет сообщение рассылки и дописывает id отправленных сообщений в `message_ids`:
+/// первым идет сообщение с текстом или подписью. Уже доставленные прошлой попыткой части
+/// (их id переданы в `message_ids`) не отправляются, при ошибке в `message_ids` остаются доставленные
 async fn send_telegram_message(
     bot: &Bot,
     message: &BroadcastMessage,
+    codec: &CallbackCodec,
+    message_ids: &mut Vec<i32>,
 ) -> Result<(), Error> {
     let telegram_id = message.telegram_id;
         info!("Sending message to Telegram user {}", telegram_id);
     info!("Message details: broadcast_id={}, message_type={:?}, has_media_group={}", 
           message.broadcast_id, message.message_type, message.media_group.is_some());
     
+    let keyboard = message_keyboard(message)
+        .map(|keyboard| inline_keyboard(&keyboard, message, codec))
+        .transpose()?;
+    let parse_mode = telegram_parse_mode(message.parse_mode);
+
     // Переменная для отслеживания отправленных медиафайлов
     let mut input_media = Vec::new();
     let mut media_files_sent = false;
+
+    // Медиагруппа доставлена прошлой попыткой; если id больше, чем файлов, доставлена и клавиатура
+    let media_group_delivered = message.media_group.is_some() && !message_ids.is_empty();
+    let media_keyboard_delivered = message
+        .media_group
+        .as_ref()
+        .is_some_and(|media_group| message_ids.len() > sendable_media_count(media_group));
+    if media_group_delivered && !media_keyboard_delivered {
+        info!("⏭️ Media group already delivered to user {}, sending the keyboard only", telegram_id);
+    }
     
     // Если есть media_group, отправляем все медиафайлы в одной группе
-    if let Some(media_group) = &message.media_group {
+    if let Some(media_group) = &message.media_group && !media_group_delivered {
         info!("Sending media group with {} files to user {}", media_group.media.len(), telegram_id);
         info!("Media group details: {:?}", media_group);
         
@@ -120,7 +579,7 @@ async fn send_telegram_message(
                         media: media_input,
                         // Подпись только к первому элементу в медиагруппе
                         caption: create_media_caption(message, &media_item.caption, is_first_item),
-                        parse_mode: None,
+                        parse_mode: Some(parse_mode),
                         caption_entities: None,
                         has_spoiler: false,
                         show_caption_above_media: false,
@@ -131,7 +590,7 @@ async fn send_telegram_message(
                         media: media_input,
                         // Подпись только к первому элементу в медиагруппе
                         caption: create_media_caption(message, &media_item.caption, is_first_item),
-                        parse_mode: None,
+                        parse_mode: Some(parse_mode),
                         caption_entities: None,
                         width: None,
                         height: None,
@@ -149,7 +608,7 @@ async fn send_telegram_message(
                         media: media_input,
                         // Подпись только к первому элементу в медиагруппе
                         caption: create_media_caption(message, &media_item.caption, is_first_item),
-                        parse_mode: None,
+                        parse_mode: Some(parse_mode),
                         caption_entities: None,
                         thumbnail: None,
                         disable_content_type_detection: None,
@@ -160,7 +619,7 @@ async fn send_telegram_message(
                         media: media_input,
                         // Подпись только к первому элементу в медиагруппе
                         caption: create_media_caption(message, &media_item.caption, is_first_item),
-                        parse_mode: None,
+                        parse_mode: Some(parse_mode),
                         caption_entities: None,
                         duration: None,
                         performer: None,
@@ -174,7 +633,7 @@ async fn send_telegram_message(
                         media: media_input,
                         // Подпись только к первому элементу в медиагруппе
                         caption: create_media_caption(message, &media_item.caption, is_first_item),
-                        parse_mode: None,
+                        parse_mode: Some(parse_mode),
                         caption_entities: None,
                         duration: None,
                         performer: None,
@@ -201,8 +660,9 @@ async fn send_telegram_message(
             ).await;
             
             match result {
-                Ok(_) => {
+                Ok(sent) => {
                     info!("✅ Media group sent successfully to user {}", telegram_id);
+                    message_ids.extend(sent.iter().map(|sent| sent.id.0));
                     media_files_sent = true;
                 }
                 Err(e) => {
@@ -223,44 +683,56 @@ async fn send_telegram_message(
     let should_send_text_message = message.media_group.is_none();
     
     if should_send_text_message {
-        let result = if let Some(core_logic::BroadcastMessageType::SignUp) = message.message_type {
-            // Для сообщений о записи создаем inline клавиатуру
-            let keyboard = teloxide::types::InlineKeyboardMarkup::new(vec![vec![
-                teloxide::types::InlineKeyboardButton::new(
-                    "Записаться",
-                    teloxide::types::InlineKeyboardButtonKind::CallbackData("sign_up".to_string()),
-                ),
-            ]]);
-            
-            bot.send_message(
-                teloxide::types::ChatId(telegram_id),
-                &message.message,
-            )
-            .reply_markup(keyboard)
-            .await
-        } else {
-            // Для обычных сообщений отправляем без клавиатуры
-            bot.send_message(
-                teloxide::types::ChatId(telegram_id),
-                &message.message,
-            ).await
-        };
-
-        match result {
-            Ok(_) => {
-                info!("✅ Message sent successfully to Telegram user {}", telegram_id);
-                Ok(())
+        // Длинный текст делится на части, клавиатура прикрепляется к последней
+        let parts = text_parts(message)?;
+        if parts.len() > 1 {
+            info!("✂️ Message for user {} split into {} parts", telegram_id, parts.len());
+        }
+        // Каждой доставленной части соответствует один id: продолжаем с первой недоставленной
+        let delivered = message_ids.len();
+        if delivered > 0 {
+            info!("⏭️ {} of {} parts already delivered to user {}, resuming", delivered, parts.len(), telegram_id);
+        }
+
+        let last_index = parts.len() - 1;
+        for (index, part) in parts.iter().enumerate().skip(delivered) {
+            let mut request = bot
+                .send_message(teloxide::types::ChatId(telegram_id), telegram_text(part, message.parse_mode))
+                .parse_mode(parse_mode);
+            if index == last_index && let Some(keyboard) = keyboard.clone() {
+                request = request.reply_markup(keyboard);
             }
-            Err(e) => {
-                error!("❌ Failed to send message to Telegram user {}: {}", telegram_id, e);
-                Err(anyhow::Error::new(e))
+
+            match request.await {
+                Ok(sent) => message_ids.push(sent.id.0),
+                Err(e) => {
+                    error!("❌ Failed to send message to Telegram user {}: {}", telegram_id, e);
+                    return Err(anyhow::Error::new(e));
+                }
             }
         }
+
+        info!("✅ Message sent successfully to Telegram user {}", telegram_id);
+        Ok(())
     } else {
         // Если есть медиагруппа и медиафайлы были отправлены, сообщение уже отправлено как подпись к первому файлу
         info!("✅ Message sent as caption to media group for user {}", telegram_id);
-        Ok(())
+
+        // Медиагруппа не принимает клавиатуру, отправляем ее отдельным сообщением
+        if media_keyboard_delivered {
+            info!("⏭️ Media group and keyboard already delivered to user {}", telegram_id);
+        } else if let Some(keyboard) = keyboard {
+            let sent = bot.send_message(teloxide::types::ChatId(telegram_id), MEDIA_KEYBOARD_TEXT)
+                .reply_markup(keyboard)
+                .await
+                .map_err(|e| {
+                    error!("❌ Failed to send keyboard after media group to user {}: {}", telegram_id, e);
+                    anyhow::Error::new(e)
+                })?;
+            message_ids.push(sent.id.0);
         }
+        Ok(())
+    }
 }
 
 // Функция для загрузки файла в Telegram
@@ -0,0 +1,295 @@
+use std::sync::Arc;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Формат: "<версия>:<действие>:<аргумент>:<истекает (unix)>:<подпись>"
+const CALLBACK_DATA_VERSION: &str = "1";
+const SEPARATOR: char = ':';
+// Длина усеченной подписи в байтах (16 hex-символов)
+const SIGNATURE_BYTES: usize = 8;
+// Ограничение Telegram на размер callback_data
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+// id рассылки (UUID) кодируется в base36, чтобы уложиться в лимит
+const BASE36_RADIX: u32 = 36;
+
+// Время жизни кнопок по умолчанию
+const DEFAULT_TTL_HOURS: i64 = 24;
+const DEFAULT_SIGN_UP_TTL_HOURS: i64 = 168;
+
+// Короткие теги действий
+const SIGN_UP_TAG: &str = "su";
+const BROADCAST_SIGN_UP_TAG: &str = "bs";
+const SHOW_MORE_SLOTS_TAG: &str = "more";
+const BACK_TO_FIRST_PAGE_TAG: &str = "back";
+const BOOK_TAG: &str = "bk";
+const CONFIRM_TAG: &str = "cf";
+const REVIEW_APPROVE_TAG: &str = "ra";
+const REVIEW_REJECT_TAG: &str = "rr";
+const REVIEW_COMMENT_TAG: &str = "rc";
+
+/// Действие, закодированное в inline-кнопке
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackAction {
+    SignUp,
+    /// «Записаться» из рассылки: нажатие учитывается в воронке рассылки
+    BroadcastSignUp(uuid::Uuid),
+    ShowMoreSlots,
+    BackToFirstPage,
+    Book(i64),
+    Confirm(i64),
+    ReviewApprove(i64),
+    ReviewReject(i64),
+    ReviewComment(i64),
+}
+
+impl CallbackAction {
+    fn tag(&self) -> &'static str {
+        match self {
+            CallbackAction::SignUp => SIGN_UP_TAG,
+            CallbackAction::BroadcastSignUp(_) => BROADCAST_SIGN_UP_TAG,
+            CallbackAction::ShowMoreSlots => SHOW_MORE_SLOTS_TAG,
+            CallbackAction::BackToFirstPage => BACK_TO_FIRST_PAGE_TAG,
+            CallbackAction::Book(_) => BOOK_TAG,
+            CallbackAction::Confirm(_) => CONFIRM_TAG,
+            CallbackAction::ReviewApprove(_) => REVIEW_APPROVE_TAG,
+            CallbackAction::ReviewReject(_) => REVIEW_REJECT_TAG,
+            CallbackAction::ReviewComment(_) => REVIEW_COMMENT_TAG,
+        }
+    }
+
+    fn argument(&self) -> Option<String> {
+        match self {
+            CallbackAction::SignUp | CallbackAction::ShowMoreSlots | CallbackAction::BackToFirstPage => None,
+            CallbackAction::BroadcastSignUp(broadcast_id) => Some(encode_base36(broadcast_id.as_u128())),
+            CallbackAction::Book(id)
+            | CallbackAction::Confirm(id)
+            | CallbackAction::ReviewApprove(id)
+            | CallbackAction::ReviewReject(id)
+            | CallbackAction::ReviewComment(id) => Some(id.to_string()),
+        }
+    }
+
+    /// Кнопка записи — обычная или из рассылки
+    fn is_sign_up(&self) -> bool {
+        matches!(self, CallbackAction::SignUp | CallbackAction::BroadcastSignUp(_))
+    }
+
+    fn from_parts(tag: &str, argument: &str) -> Result<Self, CallbackDataError> {
+        let id = || argument.parse::<i64>().map_err(|_| CallbackDataError::Malformed);
+        match tag {
+            SIGN_UP_TAG => Ok(CallbackAction::SignUp),
+            BROADCAST_SIGN_UP_TAG => u128::from_str_radix(argument, BASE36_RADIX)
+                .map(|value| CallbackAction::BroadcastSignUp(uuid::Uuid::from_u128(value)))
+                .map_err(|_| CallbackDataError::Malformed),
+            SHOW_MORE_SLOTS_TAG => Ok(CallbackAction::ShowMoreSlots),
+            BACK_TO_FIRST_PAGE_TAG => Ok(CallbackAction::BackToFirstPage),
+            BOOK_TAG => Ok(CallbackAction::Book(id()?)),
+            CONFIRM_TAG => Ok(CallbackAction::Confirm(id()?)),
+            REVIEW_APPROVE_TAG => Ok(CallbackAction::ReviewApprove(id()?)),
+            REVIEW_REJECT_TAG => Ok(CallbackAction::ReviewReject(id()?)),
+            REVIEW_COMMENT_TAG => Ok(CallbackAction::ReviewComment(id()?)),
+            _ => Err(CallbackDataError::Malformed),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CallbackDataError {
+    #[error("Некорректные данные кнопки")]
+    Malformed,
+    #[error("Неподдерживаемая версия данных кнопки: {0}")]
+    UnsupportedVersion(String),
+    #[error("Неверная подпись данных кнопки")]
+    InvalidSignature,
+    #[error("Срок действия кнопки истёк")]
+    Expired,
+}
+
+/// Кодек callback_data: версия, подпись HMAC-SHA256 и срок действия
+#[derive(Clone)]
+pub struct CallbackCodec {
+    secret: Arc<[u8]>,
+    ttl: chrono::Duration,
+    sign_up_ttl: chrono::Duration,
+}
+
+impl CallbackCodec {
+    pub fn new(secret: &[u8], ttl: chrono::Duration, sign_up_ttl: chrono::Duration) -> Self {
+        Self { secret: Arc::from(secret), ttl, sign_up_ttl }
+    }
+
+    /// Создает кодек из переменных окружения.
+    /// Без CALLBACK_SECRET подписываем токеном бота, он тоже известен только нам.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secret = std::env::var("CALLBACK_SECRET")
+            .or_else(|_| std::env::var("TELOXIDE_TOKEN"))
+            .map_err(|_| anyhow::anyhow!("CALLBACK_SECRET or TELOXIDE_TOKEN must be set"))?;
+        let ttl_hours = env_hours("CALLBACK_TTL_HOURS", DEFAULT_TTL_HOURS);
+        let sign_up_ttl_hours = env_hours("CALLBACK_SIGN_UP_TTL_HOURS", DEFAULT_SIGN_UP_TTL_HOURS);
+
+        Ok(Self::new(
+            secret.as_bytes(),
+            chrono::Duration::hours(ttl_hours),
+            chrono::Duration::hours(sign_up_ttl_hours),
+        ))
+    }
+
+    /// Кодирует действие в строку для callback_data
+    pub fn encode(&self, action: &CallbackAction) -> String {
+        // Кнопка записи живет дольше: она приходит в рассылках
+        let ttl = if action.is_sign_up() { self.sign_up_ttl } else { self.ttl };
+        let expires_at = (Utc::now() + ttl).timestamp();
+        let argument = action.argument().unwrap_or_default();
+
+        let payload = format!(
+            "{}{sep}{}{sep}{}{sep}{}",
+            CALLBACK_DATA_VERSION, action.tag(), argument, expires_at,
+            sep = SEPARATOR
+        );
+        let data = format!("{}{}{}", payload, SEPARATOR, self.sign(&payload));
+        debug_assert!(data.len() <= MAX_CALLBACK_DATA_LENGTH);
+        data
+    }
+
+    /// Проверяет версию, подпись и срок действия и возвращает действие
+    pub fn decode(&self, data: &str) -> Result<CallbackAction, CallbackDataError> {
+        let (payload, signature) = data.rsplit_once(SEPARATOR).ok_or(CallbackDataError::Malformed)?;
+        let parts: Vec<&str> = payload.split(SEPARATOR).collect();
+        let [version, tag, argument, expires_at] = parts[..] else {
+            return Err(CallbackDataError::Malformed);
+        };
+
+        if version != CALLBACK_DATA_VERSION {
+            return Err(CallbackDataError::UnsupportedVersion(version.to_string()));
+        }
+
+        let signature = decode_hex(signature).ok_or(CallbackDataError::InvalidSignature)?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_truncated_left(&signature)
+            .map_err(|_| CallbackDataError::InvalidSignature)?;
+
+        let expires_at = expires_at.parse::<i64>().map_err(|_| CallbackDataError::Malformed)?;
+        if Utc::now().timestamp() > expires_at {
+            return Err(CallbackDataError::Expired);
+        }
+
+        CallbackAction::from_parts(tag, argument)
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        signature[..SIGNATURE_BYTES].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+}
+
+fn env_hours(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(default)
+}
+
+fn encode_base36(mut value: u128) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(std::char::from_digit((value % BASE36_RADIX as u128) as u32, BASE36_RADIX).expect("digit is below radix"));
+        value /= BASE36_RADIX as u128;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != SIGNATURE_BYTES * 2 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codec(ttl: chrono::Duration) -> CallbackCodec {
+        CallbackCodec::new(b"test-secret", ttl, ttl)
+    }
+
+    #[test]
+    fn round_trips_every_action() {
+        let codec = codec(chrono::Duration::hours(1));
+        let actions = [
+            CallbackAction::SignUp,
+            CallbackAction::BroadcastSignUp(uuid::Uuid::from_u128(u128::MAX)),
+            CallbackAction::ShowMoreSlots,
+            CallbackAction::BackToFirstPage,
+            CallbackAction::Book(42),
+            CallbackAction::Confirm(-7),
+            CallbackAction::ReviewApprove(1),
+            CallbackAction::ReviewReject(2),
+            CallbackAction::ReviewComment(i64::MAX),
+        ];
+        for action in actions {
+            let data = codec.encode(&action);
+            assert!(data.len() <= MAX_CALLBACK_DATA_LENGTH, "{} is too long", data);
+            assert_eq!(codec.decode(&data), Ok(action));
+        }
+    }
+
+    #[test]
+    fn keyboard_buttons_fit_callback_data_limit() {
+        let codec = codec(chrono::Duration::hours(1));
+        let broadcast_id = uuid::Uuid::from_u128(u128::MAX);
+        for slot_id in [0, i64::MIN, i64::MAX] {
+            for action in [CallbackAction::Book(slot_id), CallbackAction::Confirm(slot_id), CallbackAction::BroadcastSignUp(broadcast_id)] {
+                assert!(codec.encode(&action).len() <= MAX_CALLBACK_DATA_LENGTH, "{:?} is too long", action);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_payload_and_foreign_signature() {
+        let codec = codec(chrono::Duration::hours(1));
+        let data = codec.encode(&CallbackAction::Book(42));
+
+        let tampered = data.replacen(":42:", ":43:", 1);
+        assert_eq!(codec.decode(&tampered), Err(CallbackDataError::InvalidSignature));
+        let foreign = CallbackCodec::new(b"other-secret", chrono::Duration::hours(1), chrono::Duration::hours(1));
+        assert_eq!(foreign.decode(&data), Err(CallbackDataError::InvalidSignature));
+        let (payload, _) = data.rsplit_once(SEPARATOR).unwrap();
+        assert_eq!(codec.decode(&format!("{}:zz", payload)), Err(CallbackDataError::InvalidSignature));
+        assert_eq!(codec.decode("garbage"), Err(CallbackDataError::Malformed));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let codec = codec(chrono::Duration::hours(1));
+        let data = codec.encode(&CallbackAction::SignUp);
+        let next_version = data.replacen(&format!("{}{}", CALLBACK_DATA_VERSION, SEPARATOR), "2:", 1);
+
+        assert_eq!(codec.decode(&next_version), Err(CallbackDataError::UnsupportedVersion("2".to_string())));
+    }
+
+    #[test]
+    fn rejects_expired_button() {
+        let codec = codec(chrono::Duration::seconds(-1));
+        let data = codec.encode(&CallbackAction::Book(42));
+
+        assert_eq!(codec.decode(&data), Err(CallbackDataError::Expired));
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::{Dialogue, Storage};
+use teloxide::types::ChatId;
+use thiserror::Error;
+
+/// Состояние диалога с пользователем
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum BotState {
+    #[default]
+    Idle,
+    /// Выбор слота: набор слотов фиксируется при входе в сценарий записи
+    ChoosingSlot { slot_ids: Vec<i64> },
+    /// Проверяющий пишет комментарий к анкете
+    AwaitingReviewComment { survey_id: i64 },
+    /// Комментарий сохранен и ждет решения по анкете
+    ReviewCommentDraft { survey_id: i64, comment: String },
+    /// Администратор создает слот: ждем время
+    AddSlotTime,
+    /// Администратор создает слот: ждем место
+    AddSlotPlace { time: DateTime<Utc> },
+    /// Администратор создает слот: ждем вместимость
+    AddSlotCapacity { time: DateTime<Utc>, place: String },
+}
+
+pub type BotDialogue = Dialogue<BotState, SqliteDialogueStorage>;
+
+#[derive(Error, Debug)]
+pub enum DialogueStorageError {
+    #[error("Ошибка базы данных: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Ошибка сериализации состояния: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Диалог не найден")]
+    DialogueNotFound,
+}
+
+/// Хранилище диалогов teloxide поверх общего пула SQLite
+pub struct SqliteDialogueStorage {
+    pool: Arc<SqlitePool>,
+}
+
+impl SqliteDialogueStorage {
+    pub fn new(pool: Arc<SqlitePool>) -> Arc<Self> {
+        Arc::new(Self { pool })
+    }
+}
+
+impl<D> Storage<D> for SqliteDialogueStorage
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = DialogueStorageError;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            if core_logic::db::delete_dialogue_state(&self.pool, chat_id.0).await? {
+                Ok(())
+            } else {
+                Err(DialogueStorageError::DialogueNotFound)
+            }
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: D) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let state = serde_json::to_string(&dialogue)?;
+            core_logic::db::save_dialogue_state(&self.pool, chat_id.0, &state).await?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            match core_logic::db::get_dialogue_state(&self.pool, chat_id.0).await? {
+                Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// Текущее состояние диалога. Ошибки хранилища логируем и считаем диалог пустым.
+pub async fn current_state(dialogue: &BotDialogue) -> BotState {
+    match dialogue.get().await {
+        Ok(state) => state.unwrap_or_default(),
+        Err(e) => {
+            tracing::error!("Failed to load dialogue for chat {}: {}", dialogue.chat_id(), e);
+            BotState::default()
+        }
+    }
+}
+
+/// Сохраняет новое состояние диалога
+pub async fn set_state(dialogue: &BotDialogue, state: BotState) {
+    if let Err(e) = dialogue.update(state).await {
+        tracing::error!("Failed to save dialogue for chat {}: {}", dialogue.chat_id(), e);
+    }
+}
+
+/// Завершает диалог
+pub async fn reset_state(dialogue: &BotDialogue) {
+    match dialogue.exit().await {
+        Ok(()) | Err(DialogueStorageError::DialogueNotFound) => {}
+        Err(e) => tracing::error!("Failed to reset dialogue for chat {}: {}", dialogue.chat_id(), e),
+    }
+}
@@ -0,0 +1,671 @@
+use std::env;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, ParseMode};
+use teloxide::utils::command::BotCommands;
+use chrono::{Utc, Datelike, TimeZone, Timelike};
+use sqlx::SqlitePool;
+use core_logic::{CreateUserRequest, DeepLink, DeepLinkFlow, Slot};
+use teloxide::dispatching::UpdateHandler;
+use crate::admin::{self, AdminCommand};
+use crate::callback_data::{CallbackAction, CallbackCodec, CallbackDataError};
+use crate::dialogue::{self, BotDialogue, BotState, SqliteDialogueStorage};
+use crate::review;
+use crate::support::{self, SupportInbox};
+
+// Константы для текстов
+const WELCOME_MESSAGE: &str = "🎉 Отлично! Ты успешно прошёл анкетирование и можешь записаться на собеседование. Выбери удобное время ниже 👇";
+const NO_SLOTS_MESSAGE_TEMPLATE: &str = "😔 К сожалению, на данный момент нет доступных слотов для записи.\n\nЕсли у вас есть вопросы, свяжитесь с <a href='https://t.me/{USERNAME}'>администратором</a>.";
+const SLOT_SELECTED_TEMPLATE: &str = "✅ Выбранный слот:\n\n📅 Время: {TIME}\n🏢 Место: {PLACE}\n\nНажмите 'Подтвердить' для завершения записи.";
+const SLOT_NOT_FOUND_MESSAGE: &str = "⚠️ Выбранный слот больше не доступен. Пожалуйста, выберите другой слот.";
+const SLOT_ERROR_MESSAGE: &str = "⚠️ Произошла ошибка при получении информации о слоте. Пожалуйста, попробуйте позже.";
+const BOOKING_CONFIRMED_TEMPLATE: &str = "🎉 Бронирование подтверждено!\n\n📅 Время: {TIME}\n🏢 Место: {PLACE}\n\n✅ Ваша запись успешно создана!\n\n💡 Для изменения времени свяжитесь с <a href='https://t.me/{USERNAME}'>администратором</a>.";
+const SLOT_FULL_TEMPLATE: &str = "❌ Слот переполнен!\n\nМаксимальное количество пользователей: {MAX_USERS}\nТекущее количество: {CURRENT_COUNT}\n\nПопробуйте выбрать другой слот или обратитесь к <a href='https://t.me/{USERNAME}'>администратору</a>.";
+const SLOT_NOT_FOUND_ERROR_MESSAGE: &str = "❌ Слот не найден. Возможно, он был удален. Попробуйте выбрать другой слот.";
+const USER_NOT_FOUND_MESSAGE: &str = "❌ Пользователь не найден. Обратитесь к <a href='https://t.me/{USERNAME}'>администратору</a>.";
+const DATABASE_ERROR_TEMPLATE: &str = "❌ Ошибка базы данных: {ERROR}\n\nПопробуйте позже или обратитесь к <a href='https://t.me/{USERNAME}'>администратору</a>.";
+const REMINDER_TEMPLATE: &str = "🔔 Напоминание о собеседовании!\n\n📅 Сегодня в {TIME}\n🏢 Место: {PLACE}\n\nУдачи на собеседовании! 🍀";
+const CONTACT_INFO_TEMPLATE: &str = "For questions, please contact: https://t.me/{USERNAME}";
+
+// Плейсхолдеры для замены
+const USERNAME_PLACEHOLDER: &str = "{USERNAME}";
+const TIME_PLACEHOLDER: &str = "{TIME}";
+const PLACE_PLACEHOLDER: &str = "{PLACE}";
+const MAX_USERS_PLACEHOLDER: &str = "{MAX_USERS}";
+const CURRENT_COUNT_PLACEHOLDER: &str = "{CURRENT_COUNT}";
+const ERROR_PLACEHOLDER: &str = "{ERROR}";
+
+// Кнопки
+const SHOW_MORE_SLOTS_BUTTON: &str = "🔄 Показать другие варианты";
+const BACK_TO_FIRST_PAGE_BUTTON: &str = "⬅️ Вернуться к основным слотам";
+const TRY_AGAIN_BUTTON: &str = "🔄 Попробовать снова";
+const CONFIRM_BUTTON: &str = "Подтвердить";
+const SIGN_UP_BUTTON: &str = "Записаться";
+
+// Заголовки
+const MORE_SLOTS_HEADER: &str = "✨ Больше вариантов для записи:\n\nВ случае, если тебе не подходит ни один из слотов, напиши, пожалуйста, прямо в этот чат — мы передадим сообщение команде.";
+const ALL_SLOTS_HEADER: &str = "📋 Все доступные слоты на данный момент:";
+
+// Страницы выбора слота
+const SLOTS_PER_PAGE: usize = 3;
+const SLOTS_IN_FLOW: i64 = 6;
+
+// Сообщения об ошибках
+const GENERIC_ERROR_MESSAGE: &str = "Sorry, something went wrong.";
+const EXPIRED_BUTTON_MESSAGE: &str = "⌛ Эта кнопка устарела. Нажмите /reschedule, чтобы получить актуальные варианты записи.";
+
+// Функция для форматирования даты в русском стиле "25 сентября 18:30"
+pub(crate) fn format_russian_date(datetime: &chrono::DateTime<Utc>) -> String {
+    let month_names = [
+        "января", "февраля", "марта", "апреля", "мая", "июня",
+        "июля", "августа", "сентября", "октября", "ноября", "декабря"
+    ];
+    
+    let day = datetime.day();
+    let month = month_names[datetime.month0() as usize];
+    let hour = datetime.hour();
+    let minute = datetime.minute();
+    
+    format!("{} {} {}:{}", day, month, hour, format!("{:02}", minute))
+}
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "These commands are supported:")]
+pub(crate) enum Command {
+    #[command(description = "Start the bot.")]
+    Start(String),
+    #[command(description = "Display this text.")]
+    Help,
+    #[command(description = "Get contact information.")]
+    Contact,
+    #[command(description = "Reschedule your interview.")]
+    Reschedule,
+    #[command(description = "Review surveys (reviewers only).")]
+    Review,
+}
+
+#[derive(Clone)]
+enum UserMessage {
+    Welcome,
+    ContactInfo(String),
+    NoSlotsAvailable(String),
+    SlotSelected { time: String, place: String },
+    SlotNotFound,
+    SlotError,
+    BookingConfirmed { time: String, place: String, username: String },
+    SlotFull { max_users: u16, current_count: u16 },
+    SlotNotFoundError,
+    UserNotFound,
+    DatabaseError(String),
+    Reminder { time: String, place: String },
+}
+
+impl UserMessage {
+    fn to_string(&self) -> String {
+        match self {
+            UserMessage::Welcome => WELCOME_MESSAGE.to_string(),
+            UserMessage::ContactInfo(username) => CONTACT_INFO_TEMPLATE.replace(USERNAME_PLACEHOLDER, username),
+            UserMessage::NoSlotsAvailable(username) => NO_SLOTS_MESSAGE_TEMPLATE.replace(USERNAME_PLACEHOLDER, username),
+            UserMessage::SlotSelected { time, place } => SLOT_SELECTED_TEMPLATE.replace(TIME_PLACEHOLDER, time).replace(PLACE_PLACEHOLDER, place),
+            UserMessage::SlotNotFound => SLOT_NOT_FOUND_MESSAGE.to_string(),
+            UserMessage::SlotError => SLOT_ERROR_MESSAGE.to_string(),
+            UserMessage::BookingConfirmed { time, place, username } => BOOKING_CONFIRMED_TEMPLATE.replace(TIME_PLACEHOLDER, time).replace(PLACE_PLACEHOLDER, place).replace(USERNAME_PLACEHOLDER, username),
+            UserMessage::SlotFull { max_users, current_count } => {
+                let username = std::env::var("CONTACT_USERNAME").unwrap_or_default();
+                SLOT_FULL_TEMPLATE.replace(MAX_USERS_PLACEHOLDER, &max_users.to_string()).replace(CURRENT_COUNT_PLACEHOLDER, &current_count.to_string()).replace(USERNAME_PLACEHOLDER, &username)
+            },
+            UserMessage::SlotNotFoundError => SLOT_NOT_FOUND_ERROR_MESSAGE.to_string(),
+            UserMessage::UserNotFound => {
+                let username = std::env::var("CONTACT_USERNAME").unwrap_or_default();
+                USER_NOT_FOUND_MESSAGE.replace(USERNAME_PLACEHOLDER, &username)
+            },
+            UserMessage::DatabaseError(error) => {
+                let username = std::env::var("CONTACT_USERNAME").unwrap_or_default();
+                DATABASE_ERROR_TEMPLATE.replace(ERROR_PLACEHOLDER, error).replace(USERNAME_PLACEHOLDER, &username)
+            },
+            UserMessage::Reminder { time, place } => REMINDER_TEMPLATE.replace(TIME_PLACEHOLDER, time).replace(PLACE_PLACEHOLDER, place),
+        }
+    }
+}
+
+async fn command_handler(
+    bot: Bot,
+    msg: Message,
+    cmd: Command,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    match cmd {
+        Command::Start(payload) => {
+            handle_start(bot, msg, payload, pool, dialogue, codec).await?;
+        }
+        Command::Help => {
+            let mut text = Command::descriptions().to_string();
+            // Администраторам показываем и админ-команды
+            if let Some(user) = msg.from.as_ref()
+                && admin::is_admin(&pool, user.id.0 as i64).await
+            {
+                text.push_str("\n\n");
+                text.push_str(&AdminCommand::descriptions().to_string());
+            }
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Command::Reschedule => {
+            send_welcome(&bot, msg.chat.id, &codec).await?;
+        }
+        Command::Contact => {
+            send_contact_info(&bot, msg.chat.id).await?;
+        }
+        Command::Review => {
+            review::handle_review_command(bot, msg, pool, codec).await?;
+        }
+    };
+    Ok(())
+}
+
+/// /start [payload]: запоминаем источник перехода и открываем сценарий ссылки
+async fn handle_start(
+    bot: Bot,
+    msg: Message,
+    payload: String,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    let payload = payload.trim();
+    let flow = if payload.is_empty() {
+        DeepLinkFlow::Welcome
+    } else if !DeepLink::is_valid_payload(payload) {
+        tracing::warn!("⚠️ Invalid /start payload: {:?}", payload);
+        DeepLinkFlow::Welcome
+    } else {
+        // Переходы учитываем только по зарегистрированным ссылкам, у остальных сценарий берем
+        // по имени payload (например, start=signup)
+        match core_logic::db::get_deep_link(&pool, payload).await {
+            Ok(Some(link)) => {
+                if let Some(user) = msg.from.as_ref()
+                    && let Err(e) = core_logic::db::record_deep_link_visit(&pool, payload, user.id.0 as i64).await
+                {
+                    tracing::error!("Failed to record deep link visit {} for user {}: {}", payload, user.id, e);
+                }
+                link.flow
+            }
+            Ok(None) => DeepLinkFlow::from(payload.to_string()),
+            Err(e) => {
+                tracing::error!("Failed to get deep link {}: {}", payload, e);
+                DeepLinkFlow::Welcome
+            }
+        }
+    };
+
+    match flow {
+        DeepLinkFlow::Welcome => {
+            send_welcome(&bot, msg.chat.id, &codec).await?;
+        }
+        DeepLinkFlow::SignUp => {
+            let (text, keyboard) = first_slots_page(&pool, &dialogue, &codec, true).await;
+            let mut request = bot.send_message(msg.chat.id, text).parse_mode(ParseMode::Html);
+            if let Some(keyboard) = keyboard {
+                request = request.reply_markup(keyboard);
+            }
+            request.await?;
+        }
+        DeepLinkFlow::Contact => {
+            send_contact_info(&bot, msg.chat.id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Приветствие с кнопкой записи
+async fn send_welcome(bot: &Bot, chat_id: ChatId, codec: &CallbackCodec) -> ResponseResult<()> {
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::new(SIGN_UP_BUTTON, InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::SignUp))),
+    ]]);
+    bot.send_message(chat_id, UserMessage::Welcome.to_string())
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+async fn send_contact_info(bot: &Bot, chat_id: ChatId) -> ResponseResult<()> {
+    let username = env::var("CONTACT_USERNAME").unwrap_or_default();
+    let message = UserMessage::ContactInfo(username);
+    bot.send_message(chat_id, message.to_string()).await?;
+    Ok(())
+}
+
+async fn callback_handler(
+    q: CallbackQuery,
+    bot: Bot,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    let Some(ref data) = q.data else {
+        return Ok(());
+    };
+
+    let action = match codec.decode(data) {
+        Ok(action) => action,
+        Err(e) => {
+            // Старые, подделанные и просроченные кнопки не выполняем
+            if e != CallbackDataError::Expired {
+                tracing::warn!("⚠️ Rejected callback data from user {}: {}", q.from.id, e);
+            }
+            bot.answer_callback_query(q.id.clone())
+                .text(EXPIRED_BUTTON_MESSAGE)
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    match action {
+        CallbackAction::SignUp => {
+            handle_sign_up(&q, bot, pool, dialogue, codec, true, None).await?;
+        }
+        CallbackAction::BroadcastSignUp(broadcast_id) => {
+            let broadcast_id = broadcast_id.to_string();
+            if let Err(e) = core_logic::db::record_broadcast_click(&pool, &broadcast_id, q.from.id.0 as i64).await {
+                tracing::error!("Failed to record click on broadcast {}: {}", broadcast_id, e);
+            }
+            handle_sign_up(&q, bot, pool, dialogue, codec, true, Some(&broadcast_id)).await?;
+        }
+        CallbackAction::BackToFirstPage => {
+            handle_sign_up(&q, bot, pool, dialogue, codec, false, None).await?;
+        }
+        CallbackAction::ShowMoreSlots => {
+            handle_show_more_slots(&q, bot, pool, dialogue, codec).await?;
+        }
+        CallbackAction::Book(slot_id) => {
+            handle_slot_selection(&q, bot, slot_id, pool, codec).await?;
+        }
+        CallbackAction::Confirm(slot_id) => {
+            handle_confirm_booking(&q, bot, slot_id, pool, dialogue, codec).await?;
+        }
+        CallbackAction::ReviewApprove(_) | CallbackAction::ReviewReject(_) | CallbackAction::ReviewComment(_) => {
+            review::handle_review_callback(&q, bot, action, pool, dialogue, codec).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Набор слотов сценария записи. При входе в сценарий выбираем лучшие слоты и фиксируем их в диалоге,
+/// чтобы первая страница и «другие варианты» не менялись между нажатиями.
+async fn flow_slot_ids(pool: &SqlitePool, dialogue: &BotDialogue, refresh: bool) -> Result<Vec<i64>, sqlx::Error> {
+    if !refresh && let BotState::ChoosingSlot { slot_ids } = dialogue::current_state(dialogue).await {
+        return Ok(slot_ids);
+    }
+
+    let slots = core_logic::db::get_best_slots_for_booking(pool, SLOTS_IN_FLOW).await?;
+    let slot_ids: Vec<i64> = slots.iter().map(|slot| slot.id).collect();
+    dialogue::set_state(dialogue, BotState::ChoosingSlot { slot_ids: slot_ids.clone() }).await;
+    Ok(slot_ids)
+}
+
+/// Загружает слоты по ID, пропуская удаленные и уже заполненные
+async fn load_available_slots(pool: &SqlitePool, slot_ids: &[i64]) -> Result<Vec<Slot>, sqlx::Error> {
+    let mut slots = Vec::with_capacity(slot_ids.len());
+    for &slot_id in slot_ids {
+        if let Some(slot) = core_logic::db::get_slot(pool, slot_id).await?
+            && slot.booked_count.unwrap_or(0) < slot.max_user as i64
+        {
+            slots.push(slot);
+        }
+    }
+    Ok(slots)
+}
+
+fn slot_button(slot: &Slot, codec: &CallbackCodec) -> Vec<InlineKeyboardButton> {
+    // Конвертируем UTC время в MSK (+3)
+    let msk_time = slot.time + chrono::Duration::hours(3);
+    let text = format!("📅 {} | 🏢 {}", 
+        format_russian_date(&msk_time), 
+        slot.place
+    );
+    vec![InlineKeyboardButton::new(
+        text,
+        InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::Book(slot.id))),
+    )]
+}
+
+async fn handle_sign_up(
+    q: &CallbackQuery,
+    bot: Bot,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+    refresh: bool,
+    broadcast_id: Option<&str>,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let Some(msg) = &q.message else {
+        return Ok(());
+    };
+
+    let (text, keyboard) = first_slots_page(&pool, &dialogue, &codec, refresh).await;
+    // Шаг воронки рассылки: пользователь увидел слоты
+    if keyboard.is_some()
+        && let Some(broadcast_id) = broadcast_id
+        && let Err(e) = core_logic::db::mark_broadcast_slots_viewed(&pool, broadcast_id, q.from.id.0 as i64).await
+    {
+        tracing::error!("Failed to mark slots viewed for broadcast {}: {}", broadcast_id, e);
+    }
+    let mut request = bot.edit_message_text(msg.chat().id, msg.id(), text).parse_mode(ParseMode::Html);
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    request.await?;
+    Ok(())
+}
+
+/// Первая страница выбора слота: текст сообщения и клавиатура (если есть что выбрать)
+async fn first_slots_page(
+    pool: &SqlitePool,
+    dialogue: &BotDialogue,
+    codec: &CallbackCodec,
+    refresh: bool,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let slot_ids = match flow_slot_ids(pool, dialogue, refresh).await {
+        Ok(slot_ids) => slot_ids,
+        Err(e) => {
+            tracing::error!("Failed to get available slots: {}", e);
+            return (GENERIC_ERROR_MESSAGE.to_string(), None);
+        }
+    };
+
+    // Показываем первую страницу зафиксированного набора
+    let first_page_ids = &slot_ids[..SLOTS_PER_PAGE.min(slot_ids.len())];
+    let first_page = match load_available_slots(pool, first_page_ids).await {
+        Ok(slots) => slots,
+        Err(e) => {
+            tracing::error!("Failed to load slots: {}", e);
+            return (GENERIC_ERROR_MESSAGE.to_string(), None);
+        }
+    };
+
+    // Все слоты первой страницы заняты — сразу показываем следующую, кнопка "Не удобно" не нужна
+    let has_more_pages = slot_ids.len() > SLOTS_PER_PAGE;
+    let (page, show_more) = if first_page.is_empty() && has_more_pages {
+        match load_available_slots(pool, &slot_ids[SLOTS_PER_PAGE..]).await {
+            Ok(slots) => (slots, false),
+            Err(e) => {
+                tracing::error!("Failed to load slots: {}", e);
+                return (GENERIC_ERROR_MESSAGE.to_string(), None);
+            }
+        }
+    } else {
+        (first_page, has_more_pages)
+    };
+
+    if page.is_empty() {
+        let username = env::var("CONTACT_USERNAME").unwrap_or_default();
+        return (UserMessage::NoSlotsAvailable(username).to_string(), None);
+    }
+
+    let mut keyboard_buttons: Vec<_> = page.iter().map(|slot| slot_button(slot, codec)).collect();
+
+    // Добавляем кнопку "Не удобно" если есть еще слоты
+    if show_more {
+        keyboard_buttons.push(vec![InlineKeyboardButton::new(
+            SHOW_MORE_SLOTS_BUTTON,
+            InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::ShowMoreSlots)),
+        )]);
+    }
+
+    (WELCOME_MESSAGE.to_string(), Some(InlineKeyboardMarkup::new(keyboard_buttons)))
+}
+
+async fn handle_show_more_slots(
+    q: &CallbackQuery,
+    bot: Bot,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let Some(msg) = &q.message else {
+        return Ok(());
+    };
+
+    let slots = match flow_slot_ids(&pool, &dialogue, false).await {
+        Ok(slot_ids) => {
+            // Показываем слоты с 4-го по 6-й, а если их нет — все слоты сценария
+            let page_ids = if slot_ids.len() > SLOTS_PER_PAGE { &slot_ids[SLOTS_PER_PAGE..] } else { &slot_ids[..] };
+            load_available_slots(&pool, page_ids).await
+                .map(|slots| (slots, slot_ids.len() > SLOTS_PER_PAGE))
+        }
+        Err(e) => Err(e),
+    };
+
+    match slots {
+        Ok((slots, is_second_page)) => {
+            let mut keyboard_buttons: Vec<_> = slots.iter().map(|slot| slot_button(slot, &codec)).collect();
+
+            // Добавляем кнопку "Назад к первым слотам"
+            keyboard_buttons.push(vec![InlineKeyboardButton::new(
+                BACK_TO_FIRST_PAGE_BUTTON,
+                InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::BackToFirstPage)),
+            )]);
+
+            let header = if is_second_page { MORE_SLOTS_HEADER } else { ALL_SLOTS_HEADER };
+            let keyboard = InlineKeyboardMarkup::new(keyboard_buttons);
+            bot.edit_message_text(msg.chat().id, msg.id(), header)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed to get available slots: {}", e);
+            bot.edit_message_text(msg.chat().id, msg.id(), GENERIC_ERROR_MESSAGE).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_slot_selection(q: &CallbackQuery, bot: Bot, slot_id: i64, pool: Arc<SqlitePool>, codec: CallbackCodec) -> ResponseResult<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    if let Some(msg) = &q.message {
+        // Получаем информацию о слоте из БД
+        match core_logic::db::get_slot(&pool, slot_id).await {
+            Ok(Some(slot)) => {
+                // Конвертируем UTC время в MSK (+3)
+                let msk_time = slot.time + chrono::Duration::hours(3);
+                let time = format_russian_date(&msk_time);
+                let place = slot.place.clone();
+                let message = UserMessage::SlotSelected { time, place };
+                let confirm_callback_data = codec.encode(&CallbackAction::Confirm(slot_id));
+                let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::new(
+                    CONFIRM_BUTTON,
+                    InlineKeyboardButtonKind::CallbackData(confirm_callback_data),
+                )]]);
+
+                bot.edit_message_text(msg.chat().id, msg.id(), message.to_string())
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            Ok(None) => {
+                let message = UserMessage::SlotNotFound;
+                bot.edit_message_text(msg.chat().id, msg.id(), message.to_string())
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+            Err(e) => {
+                tracing::error!("Failed to get slot: {}", e);
+                let message = UserMessage::SlotError;
+                bot.edit_message_text(msg.chat().id, msg.id(), message.to_string())
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_confirm_booking(
+    q: &CallbackQuery,
+    bot: Bot,
+    slot_id: i64,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    if let Ok(Some(slot)) = core_logic::db::get_slot(&pool, slot_id).await {
+        if let Some(msg) = &q.message {
+            let telegram_id = q.from.id.0 as i64;
+            let user = match core_logic::db::get_user_by_telegram_id(&pool, telegram_id).await {
+                Ok(Some(user)) => user,
+                Ok(None) => {
+                    let new_user = CreateUserRequest {
+                        telegram_id: telegram_id,
+                        role: 0, // По умолчанию обычный пользователь
+                    };
+                    match core_logic::db::create_user(&pool, new_user).await {
+                        Ok(user) => user,
+                        Err(e) => {
+                            tracing::error!("Failed to create user: {}", e);
+                            return Ok(())
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to get user by telegram id: {}", e);
+                    return Ok(())
+                }
+            };
+
+            match core_logic::db::create_or_update_booking(&pool, telegram_id, Some(slot_id)).await {
+                Ok(_) => {
+                    // Конвертируем UTC время в MSK (+3)
+                    let msk_time = slot.time + chrono::Duration::hours(3);
+                    let time = format_russian_date(&msk_time);
+                    let place = slot.place.clone();
+                    let username = env::var("CONTACT_USERNAME").unwrap_or_default();
+                    let message = UserMessage::BookingConfirmed { time, place, username };
+                    // Сценарий записи завершен
+                    dialogue::reset_state(&dialogue).await;
+                    bot.edit_message_text(msg.chat().id, msg.id(), message.to_string())
+                        .parse_mode(ParseMode::Html)
+                        .reply_markup(InlineKeyboardMarkup::new(vec![vec![]]))
+                        .await?;
+                }
+                Err(e) => {
+                    let error_message = match e {
+                        core_logic::BookingError::SlotFull { max_users, current_count } => {
+                            UserMessage::SlotFull { max_users, current_count }.to_string()
+                        }
+                        core_logic::BookingError::SlotNotFound => {
+                            UserMessage::SlotNotFoundError.to_string()
+                        }
+                        core_logic::BookingError::UserNotFound => {
+                            UserMessage::UserNotFound.to_string()
+                        }
+                        core_logic::BookingError::Database(db_error) => {
+                            UserMessage::DatabaseError(db_error.to_string()).to_string()
+                        }
+                    };
+                    
+                    bot.edit_message_text(msg.chat().id, msg.id(), error_message)
+                        .parse_mode(ParseMode::Html)
+                        .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                            InlineKeyboardButton::new(TRY_AGAIN_BUTTON, InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::SignUp)))
+                        ]]))
+                        .await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ежедневные напоминания о собеседованиях
+pub async fn notification_scheduler(bot: Bot, pool: Arc<SqlitePool>) {
+    loop {
+        let now = Utc::now();
+        let nine_am_utc = Utc.with_ymd_and_hms(now.year(), now.month(), now.day(), 9, 0, 0).unwrap();
+        let sleep_duration = if now < nine_am_utc {
+            (nine_am_utc - now).to_std()
+        } else {
+            (nine_am_utc + chrono::Duration::days(1) - now).to_std()
+        };
+
+        if let Ok(duration) = sleep_duration {
+            tokio::time::sleep(duration).await;
+        }
+
+        let bookings = match core_logic::db::get_todays_bookings(&pool).await {
+            Ok(bookings) => bookings,
+            Err(e) => {
+                tracing::error!("Failed to get today's bookings: {}", e);
+                continue;
+            }
+        };
+
+        for booking in bookings {
+            // Конвертируем UTC время в MSK (+3)
+            let msk_time = booking.time + chrono::Duration::hours(3);
+            let time = msk_time.format("%H:%M").to_string();
+            let place = booking.place.clone();
+            let message = UserMessage::Reminder { time, place };
+            if let Err(e) = bot.send_message(ChatId(booking.telegram_id), message.to_string())
+                .parse_mode(ParseMode::Html)
+                .await {
+                tracing::error!("Failed to send reminder to user {}: {}", booking.telegram_id, e);
+            }
+        }
+    }
+}
+
+/// Дерево обработчиков бота: общее для long polling и webhook
+pub fn schema() -> UpdateHandler<teloxide::RequestError> {
+    dptree::entry()
+        .inspect_async(mark_user_reachable)
+        .enter_dialogue::<Update, SqliteDialogueStorage, BotState>()
+        .branch(Update::filter_message().filter_command::<Command>().endpoint(command_handler))
+        .branch(Update::filter_message().filter_command::<AdminCommand>().endpoint(admin::handle_admin_command))
+        .branch(
+            Update::filter_message()
+                .branch(dptree::case![BotState::AwaitingReviewComment { survey_id }].endpoint(review::handle_comment_message))
+                .branch(dptree::case![BotState::AddSlotTime].endpoint(admin::receive_slot_time))
+                .branch(dptree::case![BotState::AddSlotPlace { time }].endpoint(admin::receive_slot_place))
+                .branch(dptree::case![BotState::AddSlotCapacity { time, place }].endpoint(admin::receive_slot_capacity))
+                .branch(
+                    dptree::filter(|msg: Message, inbox: SupportInbox| inbox.is_support_chat(msg.chat.id))
+                        .endpoint(support::handle_staff_reply),
+                )
+                .branch(dptree::filter(|msg: Message| msg.chat.is_private()).endpoint(support::handle_candidate_message)),
+        )
+        .branch(Update::filter_callback_query().endpoint(callback_handler))
+}
+
+/// Пользователь снова пишет боту: возвращаем его в рассылки
+async fn mark_user_reachable(update: Update, pool: Arc<SqlitePool>) {
+    let Some(user) = update.from() else {
+        return;
+    };
+    let telegram_id = user.id.0 as i64;
+
+    match core_logic::db::clear_user_unreachable(&pool, telegram_id).await {
+        Ok(true) => tracing::info!("✅ User {} is reachable again", telegram_id),
+        Ok(false) => {}
+        Err(e) => tracing::error!("Failed to clear unreachable flag for user {}: {}", telegram_id, e),
+    }
+}
+
+/// Зависимости обработчиков
+pub fn dependencies(pool: Arc<SqlitePool>, codec: CallbackCodec, inbox: SupportInbox) -> DependencyMap {
+    dptree::deps![pool.clone(), SqliteDialogueStorage::new(pool), codec, inbox]
+}
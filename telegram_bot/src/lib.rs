@@ -1 +1,9 @@
+pub mod admin;
 pub mod broadcast;
+pub mod callback_data;
+pub mod dialogue;
+pub mod handlers;
+pub mod rate_limit;
+pub mod review;
+pub mod support;
+pub mod webhook;
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+// Лимиты Telegram по умолчанию: ~30 сообщений в секунду на бота и 1 в секунду на чат.
+// Глобальный лимит берем с запасом.
+const DEFAULT_GLOBAL_RATE_PER_SEC: f64 = 25.0;
+const DEFAULT_PER_CHAT_RATE_PER_SEC: f64 = 1.0;
+// Размер "всплеска" для одного чата (медиагруппа отправляется одним запросом)
+const PER_CHAT_BURST: f64 = 1.0;
+// Когда чистить простаивающие бакеты чатов
+const MAX_IDLE_CHAT_BUCKETS: usize = 10_000;
+
+/// Token bucket: `capacity` токенов, пополняется со скоростью `rate` в секунду
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self { capacity, rate, tokens: capacity, updated_at: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Сколько ждать до появления `cost` токенов. Запрос дороже емкости бакета
+    /// ждет полного бакета и уходит в долг, иначе он не дождался бы никогда
+    fn wait_time(&self, cost: f64) -> Duration {
+        let required = cost.min(self.capacity);
+        if self.tokens >= required {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((required - self.tokens) / self.rate)
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+struct LimiterState {
+    global: TokenBucket,
+    chats: HashMap<i64, TokenBucket>,
+    // Telegram вернул RetryAfter: до этого момента не отправляем ничего
+    paused_until: Option<Instant>,
+}
+
+/// Ограничитель скорости отправки: общий лимит бота и лимит на каждый чат
+#[derive(Clone)]
+pub struct RateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+    per_chat_rate: f64,
+}
+
+impl RateLimiter {
+    pub fn new(global_rate_per_sec: f64, per_chat_rate_per_sec: f64) -> Self {
+        let now = Instant::now();
+        let state = LimiterState {
+            // Глобальный бакет вмещает секундный объем, чтобы не отправлять всё одним залпом
+            global: TokenBucket::new(global_rate_per_sec.max(1.0), global_rate_per_sec, now),
+            chats: HashMap::new(),
+            paused_until: None,
+        };
+        Self { state: Arc::new(Mutex::new(state)), per_chat_rate: per_chat_rate_per_sec }
+    }
+
+    /// Создает лимитер из TELEGRAM_GLOBAL_RATE_PER_SEC и TELEGRAM_PER_CHAT_RATE_PER_SEC
+    pub fn from_env() -> Self {
+        let global = env_rate("TELEGRAM_GLOBAL_RATE_PER_SEC", DEFAULT_GLOBAL_RATE_PER_SEC);
+        let per_chat = env_rate("TELEGRAM_PER_CHAT_RATE_PER_SEC", DEFAULT_PER_CHAT_RATE_PER_SEC);
+        Self::new(global, per_chat)
+    }
+
+    /// Ждет, пока можно будет отправить `cost` сообщений в чат
+    pub async fn acquire(&self, chat_id: i64, cost: u32) {
+        let cost = cost.max(1) as f64;
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+
+                let pause = state
+                    .paused_until
+                    .map(|until| until.saturating_duration_since(now))
+                    .unwrap_or_default();
+
+                if state.chats.len() > MAX_IDLE_CHAT_BUCKETS {
+                    state.chats.retain(|_, bucket| {
+                        bucket.refill(now);
+                        !bucket.is_full()
+                    });
+                }
+
+                let per_chat_rate = self.per_chat_rate;
+                let LimiterState { global, chats, .. } = &mut *state;
+                let chat = chats
+                    .entry(chat_id)
+                    .or_insert_with(|| TokenBucket::new(PER_CHAT_BURST, per_chat_rate, now));
+
+                global.refill(now);
+                chat.refill(now);
+
+                // Списываем токены только когда оба бакета готовы
+                let wait = pause.max(global.wait_time(cost)).max(chat.wait_time(cost));
+                if wait.is_zero() {
+                    global.tokens -= cost;
+                    chat.tokens -= cost;
+                    return;
+                }
+                wait
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Приостанавливает все отправки (после RetryAfter от Telegram)
+    pub async fn pause(&self, duration: Duration) {
+        let mut state = self.state.lock().await;
+        let until = Instant::now() + duration;
+        if state.paused_until.is_none_or(|current| current < until) {
+            state.paused_until = Some(until);
+        }
+    }
+}
+
+fn env_rate(name: &str, default: f64) -> f64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|rate| *rate > 0.0)
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAT_ID: i64 = 1;
+    // Высокие лимиты: ожидание в тестах — миллисекунды, а зависание ловит таймаут
+    const FAST_RATE_PER_SEC: f64 = 1000.0;
+    const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[tokio::test]
+    async fn costly_send_after_plain_message_completes() {
+        let limiter = RateLimiter::new(FAST_RATE_PER_SEC, FAST_RATE_PER_SEC);
+
+        limiter.acquire(CHAT_ID, 1).await;
+        let media_group = tokio::time::timeout(ACQUIRE_TIMEOUT, limiter.acquire(CHAT_ID, 5)).await;
+
+        assert!(media_group.is_ok(), "send costing more than the chat bucket never completed");
+    }
+
+    #[tokio::test]
+    async fn send_costing_more_than_global_capacity_completes() {
+        let limiter = RateLimiter::new(2.0, FAST_RATE_PER_SEC);
+
+        let acquired = tokio::time::timeout(ACQUIRE_TIMEOUT, limiter.acquire(CHAT_ID, 10)).await;
+
+        assert!(acquired.is_ok(), "send costing more than the global bucket never completed");
+    }
+
+    #[tokio::test]
+    async fn costly_send_is_paid_back_by_later_sends() {
+        let limiter = RateLimiter::new(FAST_RATE_PER_SEC, 100.0);
+
+        limiter.acquire(CHAT_ID, 5).await;
+        let started = Instant::now();
+        limiter.acquire(CHAT_ID, 1).await;
+
+        // Долг в 4 токена плюс сам запрос при 100 токенах в секунду — около 50 мс
+        assert!(started.elapsed() >= Duration::from_millis(40), "waited only {:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind, InlineKeyboardMarkup, MessageId, ParseMode};
+use teloxide::utils::html;
+use core_logic::{CreateVoteRequest, NextSurveyResponse, Vote};
+use crate::callback_data::{CallbackAction, CallbackCodec};
+use crate::dialogue::{self, BotDialogue, BotState};
+
+// Тексты режима проверки
+const NO_ACCESS_MESSAGE: &str = "⛔ Проверка анкет доступна только проверяющим.";
+const NO_SURVEYS_MESSAGE: &str = "🎉 Анкет для проверки сейчас нет. Загляните позже!";
+const REVIEW_ERROR_MESSAGE: &str = "⚠️ Не удалось получить анкету. Попробуйте позже.";
+const VOTE_ERROR_MESSAGE: &str = "⚠️ Не удалось сохранить голос. Попробуйте ещё раз.";
+const COMMENT_PROMPT_TEMPLATE: &str = "💬 Напишите комментарий к анкете #{SURVEY_ID} одним сообщением.\n\nПосле этого выберите решение в карточке анкеты.";
+const COMMENT_SAVED_TEMPLATE: &str = "✅ Комментарий к анкете #{SURVEY_ID} сохранён. Теперь выберите решение в карточке анкеты.";
+const DRAWING_PLACEHOLDER: &str = "🎨 Творческое задание доступно в админ-панели";
+const EMPTY_ANSWER: &str = "—";
+
+// Плейсхолдеры
+const SURVEY_ID_PLACEHOLDER: &str = "{SURVEY_ID}";
+
+// Кнопки
+const APPROVE_BUTTON: &str = "✅ Одобрить";
+const REJECT_BUTTON: &str = "❌ Отклонить";
+const COMMENT_BUTTON: &str = "💬 Комментарий";
+
+// Служебные комментарии, которые не являются голосами
+const PROCESSING_COMMENT: &str = "В обработке";
+const INIT_COMMENT: &str = "Инициализация";
+
+// Лимит длины сообщения Telegram
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+// Вопросы анкеты в порядке отображения (совпадают с админ-панелью)
+const SURVEY_QUESTIONS: [(&str, &str); 8] = [
+    ("q1", "1. Если бы ты был мемом, то каким?"),
+    ("q2", "2. Чем ты занимался(-ась) в школе?"),
+    ("q3", "3. Какое твое самое большое достижение в жизни, не связанное с учебой?"),
+    ("q4", "4. Охарактеризуй себя 3 словами, которые начинаются на эти буквы: Ч, У, Г"),
+    ("q5", "5. Какое качество ты бы хотел(-а) в себе развить или улучшить и почему?"),
+    ("q6", "6. Чем ты можешь вдохновить других людей?"),
+    ("q7", "7. Если бы в Вышке была студенческая организация твоей мечты — чем бы она занималась?"),
+    ("q8", "8. Как ты думаешь, что будет в Школе Актива?"),
+];
+const DRAWING_QUESTION: &str = "9. Заинтересуй проверяющего (Напиши, нарисуй, удиви в любом формате!)";
+
+/// Проверяет, есть ли пользователь в user_roles
+async fn is_reviewer(pool: &SqlitePool, telegram_id: i64) -> bool {
+    match core_logic::get_user_role(pool, telegram_id).await {
+        Ok(role) => role.is_some(),
+        Err(e) => {
+            tracing::error!("Failed to get role for user {}: {}", telegram_id, e);
+            false
+        }
+    }
+}
+
+/// Команда /review: показывает следующую анкету
+pub async fn handle_review_command(bot: Bot, msg: Message, pool: Arc<SqlitePool>, codec: CallbackCodec) -> ResponseResult<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let reviewer_id = user.id.0 as i64;
+
+    if !is_reviewer(&pool, reviewer_id).await {
+        bot.send_message(msg.chat.id, NO_ACCESS_MESSAGE).await?;
+        return Ok(());
+    }
+
+    match core_logic::get_next_survey(&pool, reviewer_id).await {
+        Ok(next_survey) => {
+            let (text, keyboard) = render_next_survey(&next_survey, &codec);
+            let mut request = bot.send_message(msg.chat.id, text).parse_mode(ParseMode::Html);
+            if let Some(keyboard) = keyboard {
+                request = request.reply_markup(keyboard);
+            }
+            request.await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed to get next survey for reviewer {}: {}", reviewer_id, e);
+            bot.send_message(msg.chat.id, REVIEW_ERROR_MESSAGE).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Обрабатывает кнопки карточки анкеты
+pub async fn handle_review_callback(
+    q: &CallbackQuery,
+    bot: Bot,
+    action: CallbackAction,
+    pool: Arc<SqlitePool>,
+    dialogue: BotDialogue,
+    codec: CallbackCodec,
+) -> ResponseResult<()> {
+    let reviewer_id = q.from.id.0 as i64;
+
+    if !is_reviewer(&pool, reviewer_id).await {
+        bot.answer_callback_query(q.id.clone()).text(NO_ACCESS_MESSAGE).await?;
+        return Ok(());
+    }
+
+    if let CallbackAction::ReviewComment(survey_id) = action {
+        bot.answer_callback_query(q.id.clone()).await?;
+        dialogue::set_state(&dialogue, BotState::AwaitingReviewComment { survey_id }).await;
+        if let Some(msg) = &q.message {
+            bot.send_message(msg.chat().id, COMMENT_PROMPT_TEMPLATE.replace(SURVEY_ID_PLACEHOLDER, &survey_id.to_string()))
+                .await?;
+        }
+        return Ok(());
+    }
+
+    let (survey_id, decision) = match action {
+        CallbackAction::ReviewApprove(survey_id) => (survey_id, 1),
+        CallbackAction::ReviewReject(survey_id) => (survey_id, 0),
+        _ => {
+            bot.answer_callback_query(q.id.clone()).await?;
+            return Ok(());
+        }
+    };
+
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let comment = take_comment(&dialogue, survey_id).await;
+    let request = CreateVoteRequest { survey_id, decision, comment };
+
+    let Some(msg) = &q.message else {
+        return Ok(());
+    };
+
+    match core_logic::handle_vote(&pool, request, reviewer_id).await {
+        Ok(response) => {
+            tracing::info!("Reviewer {} voted {} for survey {}", reviewer_id, decision, survey_id);
+            let next_survey = match response.next_survey {
+                Some(next_survey) => next_survey,
+                None => {
+                    bot.edit_message_text(msg.chat().id, msg.id(), NO_SURVEYS_MESSAGE).await?;
+                    return Ok(());
+                }
+            };
+            edit_survey_message(&bot, msg.chat().id, msg.id(), &next_survey, &codec).await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed to save vote of reviewer {} for survey {}: {}", reviewer_id, survey_id, e);
+            bot.send_message(msg.chat().id, VOTE_ERROR_MESSAGE).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Сохраняет текст комментария к анкете (состояние AwaitingReviewComment)
+pub async fn handle_comment_message(bot: Bot, msg: Message, dialogue: BotDialogue, survey_id: i64) -> ResponseResult<()> {
+    let Some(text) = msg.text() else {
+        return Ok(());
+    };
+
+    dialogue::set_state(&dialogue, BotState::ReviewCommentDraft { survey_id, comment: text.to_string() }).await;
+    bot.send_message(msg.chat.id, COMMENT_SAVED_TEMPLATE.replace(SURVEY_ID_PLACEHOLDER, &survey_id.to_string()))
+        .await?;
+
+    Ok(())
+}
+
+/// Забирает черновик комментария для анкеты и завершает диалог
+async fn take_comment(dialogue: &BotDialogue, survey_id: i64) -> Option<String> {
+    match dialogue::current_state(dialogue).await {
+        BotState::ReviewCommentDraft { survey_id: draft_survey_id, comment } => {
+            dialogue::reset_state(dialogue).await;
+            (draft_survey_id == survey_id).then_some(comment)
+        }
+        BotState::AwaitingReviewComment { .. } => {
+            dialogue::reset_state(dialogue).await;
+            None
+        }
+        _ => None,
+    }
+}
+
+async fn edit_survey_message(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    next_survey: &NextSurveyResponse,
+    codec: &CallbackCodec,
+) -> ResponseResult<()> {
+    let (text, keyboard) = render_next_survey(next_survey, codec);
+    let mut request = bot.edit_message_text(chat_id, message_id, text).parse_mode(ParseMode::Html);
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    request.await?;
+    Ok(())
+}
+
+/// Формирует текст карточки и клавиатуру голосования
+fn render_next_survey(next_survey: &NextSurveyResponse, codec: &CallbackCodec) -> (String, Option<InlineKeyboardMarkup>) {
+    let Some(survey_id) = next_survey.survey_id else {
+        return (NO_SURVEYS_MESSAGE.to_string(), None);
+    };
+
+    let keyboard = InlineKeyboardMarkup::new(vec![
+        vec![
+            InlineKeyboardButton::new(
+                APPROVE_BUTTON,
+                InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::ReviewApprove(survey_id))),
+            ),
+            InlineKeyboardButton::new(
+                REJECT_BUTTON,
+                InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::ReviewReject(survey_id))),
+            ),
+        ],
+        vec![InlineKeyboardButton::new(
+            COMMENT_BUTTON,
+            InlineKeyboardButtonKind::CallbackData(codec.encode(&CallbackAction::ReviewComment(survey_id))),
+        )],
+    ]);
+
+    (format_survey(survey_id, next_survey), Some(keyboard))
+}
+
+/// Форматирует анкету в HTML-сообщение
+fn format_survey(survey_id: i64, next_survey: &NextSurveyResponse) -> String {
+    let empty = serde_json::Value::Null;
+    let survey = next_survey.survey_data.as_ref().unwrap_or(&empty);
+    // Новый формат хранит ответы во вложенном объекте data
+    let data = survey.get("data").unwrap_or(survey);
+
+    let field = |key: &str| -> Option<String> {
+        data.get(key)
+            .or_else(|| survey.get(key))
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .map(html::escape)
+    };
+
+    let mut text = format!("📋 <b>{}</b>\n", field("full_name").unwrap_or_else(|| format!("Анкета #{}", survey_id)));
+    if let (Some(faculty), Some(group)) = (field("faculty"), field("group")) {
+        text.push_str(&format!("🎓 {} • {}\n", faculty, group));
+    }
+    if let Some(username) = field("username") {
+        text.push_str(&format!("👤 @{}\n", username));
+    }
+    if let Some(phone) = field("phone") {
+        text.push_str(&format!("📞 {}\n", phone));
+    }
+    text.push_str(&format!("🆔 <code>{}</code>\n", survey_id));
+
+    if let Some(votes) = &next_survey.votes {
+        text.push_str(&format_votes(votes, next_survey.user_role == 1));
+    }
+
+    for (key, question) in SURVEY_QUESTIONS {
+        let answer = match data.get(key) {
+            Some(serde_json::Value::String(s)) if !s.trim().is_empty() => html::escape(s),
+            Some(serde_json::Value::Array(items)) if !items.is_empty() => items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|item| format!("• {}", html::escape(item)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => EMPTY_ANSWER.to_string(),
+        };
+        text.push_str(&format!("\n<b>{}</b>\n{}\n", question, answer));
+    }
+    text.push_str(&format!("\n<b>{}</b>\n{}\n", DRAWING_QUESTION, DRAWING_PLACEHOLDER));
+
+    truncate_message(text)
+}
+
+/// Сводка по уже отданным голосам (ответственный видит комментарии)
+fn format_votes(votes: &[Vote], show_comments: bool) -> String {
+    let real_votes: Vec<&Vote> = votes
+        .iter()
+        .filter(|v| !matches!(v.comment.as_deref(), Some(PROCESSING_COMMENT) | Some(INIT_COMMENT)))
+        .collect();
+    let approve = real_votes.iter().filter(|v| v.decision == 1).count();
+    let reject = real_votes.len() - approve;
+
+    let mut text = format!("🗳 Голосов: {} (✅ {} / ❌ {})\n", real_votes.len(), approve, reject);
+    if show_comments {
+        for vote in real_votes.iter().filter(|v| v.comment.is_some()) {
+            let mark = if vote.decision == 1 { "✅" } else { "❌" };
+            text.push_str(&format!("{} <i>{}</i>\n", mark, html::escape(vote.comment.as_deref().unwrap_or_default())));
+        }
+    }
+    text
+}
+
+fn truncate_message(text: String) -> String {
+    if text.chars().count() <= MAX_MESSAGE_LENGTH {
+        return text;
+    }
+    // Обрезаем по границе строки, чтобы не разорвать HTML-теги
+    let mut truncated = String::new();
+    for line in text.lines() {
+        if truncated.chars().count() + line.chars().count() + 2 > MAX_MESSAGE_LENGTH {
+            break;
+        }
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, MessageKind, ThreadId, User};
+use teloxide::{ApiError, RequestError};
+use core_logic::SupportDirection;
+
+// Тексты поддержки
+const MESSAGE_RECEIVED_MESSAGE: &str = "📨 Сообщение передано команде. Ответ придет сюда, в этот чат.";
+const SUPPORT_UNAVAILABLE_TEMPLATE: &str = "Чтобы задать вопрос, напиши, пожалуйста, @{USERNAME}";
+const REPLY_FAILED_MESSAGE: &str = "⚠️ Не удалось доставить ответ кандидату.";
+const TOPIC_NAME_TEMPLATE: &str = "{NAME} ({TELEGRAM_ID})";
+
+// Плейсхолдеры
+const USERNAME_PLACEHOLDER: &str = "{USERNAME}";
+const NAME_PLACEHOLDER: &str = "{NAME}";
+const TELEGRAM_ID_PLACEHOLDER: &str = "{TELEGRAM_ID}";
+
+// Лимит длины названия темы форума
+const MAX_TOPIC_NAME_LENGTH: usize = 128;
+// Ответ Telegram, когда тему форума удалили
+const THREAD_NOT_FOUND_ERROR: &str = "message thread not found";
+
+/// Чат сотрудников, куда пересылаются обращения кандидатов
+#[derive(Clone)]
+pub struct SupportInbox {
+    chat_id: Option<ChatId>,
+    // Чат не форум или у бота нет прав на темы: дальше пересылаем без тем
+    topics_unavailable: Arc<AtomicBool>,
+}
+
+impl SupportInbox {
+    pub fn new(chat_id: Option<ChatId>) -> Self {
+        Self { chat_id, topics_unavailable: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Читает SUPPORT_CHAT_ID. Без него обращения не пересылаются.
+    pub fn from_env() -> Self {
+        let chat_id = std::env::var("SUPPORT_CHAT_ID")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .map(ChatId);
+        if chat_id.is_none() {
+            tracing::warn!("SUPPORT_CHAT_ID is not set, support inbox is disabled");
+        }
+        Self::new(chat_id)
+    }
+
+    pub fn is_support_chat(&self, chat_id: ChatId) -> bool {
+        self.chat_id == Some(chat_id)
+    }
+}
+
+/// Свободное сообщение кандидата: пересылаем в чат сотрудников
+pub async fn handle_candidate_message(bot: Bot, msg: Message, pool: Arc<SqlitePool>, inbox: SupportInbox) -> ResponseResult<()> {
+    // Неизвестные команды не пересылаем
+    if msg.text().is_some_and(|text| text.starts_with('/')) {
+        return Ok(());
+    }
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let telegram_id = user.id.0 as i64;
+
+    let Some(support_chat) = inbox.chat_id else {
+        let username = std::env::var("CONTACT_USERNAME").unwrap_or_default();
+        bot.send_message(msg.chat.id, SUPPORT_UNAVAILABLE_TEMPLATE.replace(USERNAME_PLACEHOLDER, &username)).await?;
+        return Ok(());
+    };
+
+    let existing_thread = match core_logic::db::get_support_thread(&pool, telegram_id).await {
+        Ok(thread) => thread,
+        Err(e) => {
+            tracing::error!("Failed to get support thread for user {}: {}", telegram_id, e);
+            None
+        }
+    };
+    let is_first_message = existing_thread.is_none();
+    let thread = match existing_thread {
+        Some(thread) => Some(thread),
+        None => create_topic(&bot, &pool, &inbox, support_chat, user).await,
+    };
+
+    let forwarded = match forward_to_staff(&bot, support_chat, &msg, thread).await {
+        // Тему удалили в чате сотрудников: забываем ее и заводим новую
+        Err(RequestError::Api(ApiError::Unknown(description)))
+            if thread.is_some() && description.contains(THREAD_NOT_FOUND_ERROR) =>
+        {
+            tracing::warn!("Support topic of user {} was deleted, creating a new one", telegram_id);
+            if let Err(e) = core_logic::db::delete_support_thread(&pool, telegram_id).await {
+                tracing::error!("Failed to delete support thread for user {}: {}", telegram_id, e);
+            }
+            let thread = create_topic(&bot, &pool, &inbox, support_chat, user).await;
+            forward_to_staff(&bot, support_chat, &msg, thread).await?
+        }
+        result => result?,
+    };
+    tracing::info!("📨 Support message from user {} forwarded to staff chat", telegram_id);
+
+    if let Err(e) = core_logic::db::save_support_message(
+        &pool,
+        telegram_id,
+        SupportDirection::Inbound,
+        msg.text().or(msg.caption()),
+        None,
+        Some(forwarded.id.0),
+    ).await {
+        tracing::error!("Failed to save support message from user {}: {}", telegram_id, e);
+    }
+
+    if is_first_message {
+        bot.send_message(msg.chat.id, MESSAGE_RECEIVED_MESSAGE).await?;
+    }
+
+    Ok(())
+}
+
+/// Ответ сотрудника в теме кандидата (или ответом на пересланное сообщение): отправляем кандидату
+pub async fn handle_staff_reply(bot: Bot, msg: Message, pool: Arc<SqlitePool>) -> ResponseResult<()> {
+    // Служебные сообщения (создание темы и т.п.) и сообщения ботов пропускаем
+    if !matches!(msg.kind, MessageKind::Common(_)) {
+        return Ok(());
+    }
+    let Some(staff) = msg.from.as_ref().filter(|user| !user.is_bot) else {
+        return Ok(());
+    };
+    if msg.text().is_some_and(|text| text.starts_with('/')) {
+        return Ok(());
+    }
+
+    let Some(candidate_id) = find_candidate(&pool, &msg).await else {
+        return Ok(());
+    };
+
+    match bot.copy_message(ChatId(candidate_id), msg.chat.id, msg.id).await {
+        Ok(_) => {
+            tracing::info!("📤 Staff {} replied to candidate {}", staff.id, candidate_id);
+            if let Err(e) = core_logic::db::save_support_message(
+                &pool,
+                candidate_id,
+                SupportDirection::Outbound,
+                msg.text().or(msg.caption()),
+                Some(staff.id.0 as i64),
+                Some(msg.id.0),
+            ).await {
+                tracing::error!("Failed to save support reply to user {}: {}", candidate_id, e);
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to deliver support reply to user {}: {}", candidate_id, e);
+            let mut reply = bot.send_message(msg.chat.id, REPLY_FAILED_MESSAGE);
+            if let Some(thread) = msg.thread_id.filter(|_| msg.is_topic_message) {
+                reply = reply.message_thread_id(thread);
+            }
+            reply.await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn forward_to_staff(bot: &Bot, support_chat: ChatId, msg: &Message, thread: Option<i32>) -> Result<Message, RequestError> {
+    let mut forward = bot.forward_message(support_chat, msg.chat.id, msg.id);
+    if let Some(thread) = thread {
+        forward = forward.message_thread_id(ThreadId(MessageId(thread)));
+    }
+    forward.await
+}
+
+/// Создает тему форума для кандидата. Если чат не форум — пересылаем без темы
+/// и больше не пытаемся создавать темы до перезапуска.
+async fn create_topic(bot: &Bot, pool: &SqlitePool, inbox: &SupportInbox, support_chat: ChatId, user: &User) -> Option<i32> {
+    if inbox.topics_unavailable.load(Ordering::Relaxed) {
+        return None;
+    }
+    let telegram_id = user.id.0 as i64;
+    let name: String = TOPIC_NAME_TEMPLATE
+        .replace(NAME_PLACEHOLDER, &user.full_name())
+        .replace(TELEGRAM_ID_PLACEHOLDER, &telegram_id.to_string())
+        .chars()
+        .take(MAX_TOPIC_NAME_LENGTH)
+        .collect();
+
+    match bot.create_forum_topic(support_chat, name).await {
+        Ok(topic) => {
+            let thread = topic.thread_id.0.0;
+            if let Err(e) = core_logic::db::save_support_thread(pool, telegram_id, thread).await {
+                tracing::error!("Failed to save support thread for user {}: {}", telegram_id, e);
+            }
+            tracing::info!("🧵 Created support topic {} for user {}", thread, telegram_id);
+            Some(thread)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to create support topic for user {}: {}", telegram_id, e);
+            // Сетевые ошибки временные, а отказ Telegram повторится на каждом сообщении
+            if matches!(e, RequestError::Api(_)) {
+                inbox.topics_unavailable.store(true, Ordering::Relaxed);
+            }
+            None
+        }
+    }
+}
+
+/// Определяет кандидата по теме форума или по сообщению, на которое ответил сотрудник
+async fn find_candidate(pool: &SqlitePool, msg: &Message) -> Option<i64> {
+    if msg.is_topic_message
+        && let Some(thread) = msg.thread_id
+    {
+        match core_logic::db::get_support_thread_owner(pool, thread.0.0).await {
+            Ok(Some(candidate_id)) => return Some(candidate_id),
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to get support thread owner: {}", e),
+        }
+    }
+
+    let reply_to = msg.reply_to_message()?;
+    match core_logic::db::get_support_message_owner(pool, reply_to.id.0).await {
+        Ok(candidate_id) => candidate_id,
+        Err(e) => {
+            tracing::error!("Failed to get support message owner: {}", e);
+            None
+        }
+    }
+}
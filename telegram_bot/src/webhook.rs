@@ -0,0 +1,60 @@
+use std::net::SocketAddr;
+use teloxide::update_listeners::webhooks::Options;
+
+// Режимы получения обновлений
+const POLLING_MODE: &str = "polling";
+const WEBHOOK_MODE: &str = "webhook";
+
+// Адрес, на котором слушает webhook-сервер, по умолчанию
+const DEFAULT_WEBHOOK_ADDRESS: &str = "0.0.0.0:8443";
+
+// Ограничения Telegram на secret_token
+const MAX_SECRET_TOKEN_LENGTH: usize = 256;
+
+/// Способ получения обновлений от Telegram
+pub enum BotMode {
+    Polling,
+    Webhook(Box<Options>),
+}
+
+impl BotMode {
+    /// Читает режим из BOT_MODE (polling по умолчанию).
+    /// Для webhook нужны WEBHOOK_URL и WEBHOOK_SECRET, адрес сервера — WEBHOOK_ADDRESS.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = std::env::var("BOT_MODE").unwrap_or_else(|_| POLLING_MODE.to_string());
+
+        match mode.to_lowercase().as_str() {
+            POLLING_MODE => Ok(BotMode::Polling),
+            WEBHOOK_MODE => {
+                let url = std::env::var("WEBHOOK_URL")
+                    .map_err(|_| anyhow::anyhow!("WEBHOOK_URL must be set in webhook mode"))?;
+                let secret = std::env::var("WEBHOOK_SECRET")
+                    .map_err(|_| anyhow::anyhow!("WEBHOOK_SECRET must be set in webhook mode"))?;
+                let address = std::env::var("WEBHOOK_ADDRESS").unwrap_or_else(|_| DEFAULT_WEBHOOK_ADDRESS.to_string());
+
+                Ok(BotMode::Webhook(Box::new(webhook_options(&url, &secret, &address)?)))
+            }
+            other => Err(anyhow::anyhow!("Unknown BOT_MODE '{}', expected '{}' or '{}'", other, POLLING_MODE, WEBHOOK_MODE)),
+        }
+    }
+}
+
+/// Собирает настройки webhook. Путь маршрута берется из WEBHOOK_URL.
+pub fn webhook_options(url: &str, secret: &str, address: &str) -> anyhow::Result<Options> {
+    let url = url.parse().map_err(|e| anyhow::anyhow!("Invalid WEBHOOK_URL '{}': {}", url, e))?;
+    let address: SocketAddr = address.parse().map_err(|e| anyhow::anyhow!("Invalid WEBHOOK_ADDRESS '{}': {}", address, e))?;
+
+    if !is_valid_secret_token(secret) {
+        return Err(anyhow::anyhow!(
+            "WEBHOOK_SECRET must be 1-{} characters of A-Z, a-z, 0-9, '_' or '-'",
+            MAX_SECRET_TOKEN_LENGTH
+        ));
+    }
+
+    Ok(Options::new(address, url).secret_token(secret.to_string()))
+}
+
+fn is_valid_secret_token(secret: &str) -> bool {
+    (1..=MAX_SECRET_TOKEN_LENGTH).contains(&secret.len())
+        && secret.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
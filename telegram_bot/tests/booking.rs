@@ -0,0 +1,196 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use core_logic::{
+    BroadcastMessageRecord, BroadcastStatus, BroadcastSummary, CreateSlotRequest, DeepLink, DeepLinkFlow, MessageStatus,
+};
+use sqlx::SqlitePool;
+use telegram_bot::callback_data::CallbackAction;
+use common::FakeTelegramApi;
+
+const USER_ID: i64 = 1001;
+const OTHER_USER_ID: i64 = 1002;
+const SIGN_UP_MESSAGE_ID: i64 = 50;
+
+async fn create_slot(pool: &SqlitePool, place: &str, max_users: u16) -> i64 {
+    let request = CreateSlotRequest {
+        start_time: Utc::now() + Duration::days(2),
+        place: place.to_string(),
+        max_users,
+    };
+    core_logic::db::create_slot(pool, request).await.unwrap().id
+}
+
+#[tokio::test]
+async fn books_slot_through_sign_up_flow() {
+    common::use_fake_user_api();
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    let codec = common::test_codec();
+    let slot_id = create_slot(&pool, "Аудитория 101", 2).await;
+    common::spawn_bot(&api, pool.clone(), codec.clone());
+
+    // «Записаться»: бот показывает слоты
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &codec.encode(&CallbackAction::SignUp))).await;
+    let slots = api.wait_for_call("editMessageText").await;
+    assert_eq!(slots.params["message_id"], SIGN_UP_MESSAGE_ID);
+    assert!(slots.params["reply_markup"]["inline_keyboard"][0][0]["text"].as_str().unwrap().contains("Аудитория 101"));
+    let book_data = common::button_data(&slots, 0);
+    assert_eq!(codec.decode(&book_data).unwrap(), CallbackAction::Book(slot_id));
+
+    // Выбор слота: бот просит подтвердить
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &book_data)).await;
+    let selected = api.wait_for_calls("editMessageText", 2).await.remove(1);
+    let confirm_data = common::button_data(&selected, 0);
+    assert_eq!(codec.decode(&confirm_data).unwrap(), CallbackAction::Confirm(slot_id));
+
+    // Подтверждение: запись сохранена, сценарий завершен
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &confirm_data)).await;
+    let confirmed = api.wait_for_calls("editMessageText", 3).await.remove(2);
+    let text = confirmed.params["text"].as_str().unwrap();
+    assert!(text.contains("Бронирование подтверждено"), "{}", text);
+    assert!(text.contains("Аудитория 101"));
+
+    let booking = core_logic::db::get_booking_by_telegram_id(&pool, USER_ID).await.unwrap().unwrap();
+    assert_eq!(booking.place, "Аудитория 101");
+    assert!(core_logic::db::get_dialogue_state(&pool, USER_ID).await.unwrap().is_none());
+    assert_eq!(api.calls("answerCallbackQuery").await.len(), 3);
+}
+
+#[tokio::test]
+async fn full_slot_offers_to_try_again() {
+    common::use_fake_user_api();
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    let codec = common::test_codec();
+    let slot_id = create_slot(&pool, "Аудитория 202", 1).await;
+    core_logic::db::create_or_update_booking(&pool, OTHER_USER_ID, Some(slot_id)).await.unwrap();
+    common::spawn_bot(&api, pool.clone(), codec.clone());
+
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &codec.encode(&CallbackAction::Confirm(slot_id)))).await;
+
+    let reply = api.wait_for_call("editMessageText").await;
+    assert!(reply.params["text"].as_str().unwrap().contains("Слот переполнен"));
+    assert_eq!(codec.decode(&common::button_data(&reply, 0)).unwrap(), CallbackAction::SignUp);
+    assert!(core_logic::db::get_booking_by_telegram_id(&pool, USER_ID).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn full_first_page_skips_to_free_slots() {
+    common::use_fake_user_api();
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    let codec = common::test_codec();
+    for place in ["Аудитория 1", "Аудитория 2", "Аудитория 3", "Аудитория 4"] {
+        create_slot(&pool, place, 1).await;
+    }
+    common::spawn_bot(&api, pool.clone(), codec.clone());
+
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &codec.encode(&CallbackAction::SignUp))).await;
+    let first = api.wait_for_call("editMessageText").await;
+    // Пока пользователь выбирал, слоты первой страницы заняли другие
+    for row in 0..3 {
+        let CallbackAction::Book(slot_id) = codec.decode(&common::button_data(&first, row)).unwrap() else {
+            panic!("expected a slot button");
+        };
+        core_logic::db::create_or_update_booking(&pool, OTHER_USER_ID + row as i64, Some(slot_id)).await.unwrap();
+    }
+
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &codec.encode(&CallbackAction::BackToFirstPage))).await;
+    let page = api.wait_for_calls("editMessageText", 2).await.remove(1);
+    let rows = page.params["reply_markup"]["inline_keyboard"].as_array().unwrap();
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0][0]["text"].as_str().unwrap().contains("Аудитория"));
+    assert!(matches!(codec.decode(&common::button_data(&page, 0)).unwrap(), CallbackAction::Book(_)));
+}
+
+#[tokio::test]
+async fn sign_up_deep_link_opens_slot_picker() {
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    let codec = common::test_codec();
+    let slot_id = create_slot(&pool, "Аудитория 303", 3).await;
+    for payload in ["vk_autumn", "tg_channel"] {
+        let link = DeepLink { payload: payload.to_string(), source: payload.to_string(), flow: DeepLinkFlow::SignUp, broadcast_id: None };
+        core_logic::db::create_deep_link(&pool, &link).await.unwrap();
+    }
+    common::spawn_bot(&api, pool.clone(), codec.clone());
+
+    // Незарегистрированный payload открывает сценарий по имени, но не попадает в статистику
+    api.push_update(common::text_message(USER_ID, "/start signup")).await;
+    let picker = api.wait_for_call("sendMessage").await;
+    assert_eq!(picker.params["chat_id"], USER_ID);
+    assert_eq!(codec.decode(&common::button_data(&picker, 0)).unwrap(), CallbackAction::Book(slot_id));
+
+    // Запись приписывается только последней ссылке перед ней
+    api.push_update(common::text_message(USER_ID, "/start vk_autumn")).await;
+    api.wait_for_calls("sendMessage", 2).await;
+    api.push_update(common::text_message(USER_ID, "/start tg_channel")).await;
+    api.wait_for_calls("sendMessage", 3).await;
+    core_logic::db::create_or_update_booking(&pool, USER_ID, Some(slot_id)).await.unwrap();
+
+    let stats = core_logic::db::get_deep_link_stats(&pool).await.unwrap();
+    let counts: Vec<_> = stats.iter().map(|link| (link.payload.as_str(), link.visitors, link.bookings)).collect();
+    assert_eq!(counts, vec![("tg_channel", 1, 1), ("vk_autumn", 1, 0)]);
+}
+
+#[tokio::test]
+async fn broadcast_sign_up_is_tracked_through_funnel() {
+    common::use_fake_user_api();
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    let codec = common::test_codec();
+    let slot_id = create_slot(&pool, "Аудитория 404", 2).await;
+    let broadcast_id = uuid::Uuid::new_v4();
+    let summary = BroadcastSummary {
+        id: broadcast_id.to_string(),
+        message: "Открыта запись".to_string(),
+        total_users: 1,
+        sent_count: 1,
+        failed_count: 0,
+        pending_count: 0,
+        status: BroadcastStatus::Completed,
+        created_at: Utc::now().naive_utc(),
+        started_at: None,
+        completed_at: None,
+        scheduled_at: None,
+    };
+    core_logic::db::create_broadcast_summary(&pool, &summary).await.unwrap();
+    let delivered = BroadcastMessageRecord {
+        id: 0,
+        broadcast_id: broadcast_id.to_string(),
+        telegram_id: USER_ID,
+        status: MessageStatus::Sent,
+        error: None,
+        sent_at: Some(Utc::now().naive_utc()),
+        retry_count: 0,
+        message_type: None,
+        created_at: Utc::now().naive_utc(),
+        edit_status: None,
+        edit_error: None,
+        variant: None,
+    };
+    core_logic::db::create_broadcast_message(&pool, &delivered).await.unwrap();
+    common::spawn_bot(&api, pool.clone(), codec.clone());
+
+    // id рассылки помещается в callback_data и восстанавливается при нажатии
+    let sign_up_data = codec.encode(&CallbackAction::BroadcastSignUp(broadcast_id));
+    assert!(sign_up_data.len() <= 64);
+    assert_eq!(codec.decode(&sign_up_data).unwrap(), CallbackAction::BroadcastSignUp(broadcast_id));
+
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &sign_up_data)).await;
+    let slots = api.wait_for_call("editMessageText").await;
+    let book_data = common::button_data(&slots, 0);
+    assert_eq!(codec.decode(&book_data).unwrap(), CallbackAction::Book(slot_id));
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &book_data)).await;
+    let selected = api.wait_for_calls("editMessageText", 2).await.remove(1);
+    api.push_update(common::callback_query(USER_ID, SIGN_UP_MESSAGE_ID, &common::button_data(&selected, 0))).await;
+    api.wait_for_calls("editMessageText", 3).await;
+
+    let booking = core_logic::db::get_booking_by_telegram_id(&pool, USER_ID).await.unwrap().unwrap();
+    assert_eq!(booking.place, "Аудитория 404");
+    let funnel = core_logic::db::get_broadcast_funnel(&pool, &broadcast_id.to_string()).await.unwrap().unwrap();
+    assert_eq!((funnel.delivered, funnel.clicked, funnel.slots_viewed, funnel.booked), (1, 1, 1, 1));
+    assert_eq!(funnel.conversion_rate, 1.0);
+    assert_eq!(core_logic::db::get_broadcast_funnel(&pool, "missing").await.unwrap().map(|f| f.booked), None);
+}
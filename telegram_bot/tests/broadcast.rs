@@ -0,0 +1,583 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use core_logic::formatting::BroadcastParseMode;
+use core_logic::keyboard::{BotAction, BroadcastKeyboard, ButtonKind, KeyboardButton};
+use core_logic::{
+    BroadcastEvent, BroadcastMessage, BroadcastMessageAction, BroadcastMessageRecord, BroadcastMessageType, BroadcastStatus,
+    BroadcastSummary, CreateBroadcastCommand, MediaGroup, MediaItem, MessageEditStatus, MessageOutcome, MessageStatus,
+};
+use sqlx::SqlitePool;
+use telegram_bot::broadcast;
+use telegram_bot::callback_data::CallbackAction;
+use telegram_bot::rate_limit::RateLimiter;
+use common::FakeTelegramApi;
+
+const BROADCAST_ID: &str = "test-broadcast";
+const USER_ID: i64 = 2001;
+
+/// Сообщение рассылки в очереди и его запись в БД со статусом pending
+async fn queued_message(pool: &SqlitePool, message_type: BroadcastMessageType, media_group: Option<MediaGroup>) -> BroadcastMessage {
+    let record = BroadcastMessageRecord {
+        id: 0,
+        broadcast_id: BROADCAST_ID.to_string(),
+        telegram_id: USER_ID,
+        status: MessageStatus::Pending,
+        error: None,
+        sent_at: None,
+        retry_count: 0,
+        message_type: Some(message_type.clone()),
+        created_at: Utc::now().naive_utc(),
+        edit_status: None,
+        edit_error: None,
+        variant: None,
+    };
+    core_logic::db::create_broadcast_message(pool, &record).await.unwrap();
+
+    BroadcastMessage {
+        telegram_id: USER_ID,
+        message: "Открыта запись на собеседования".to_string(),
+        broadcast_id: BROADCAST_ID.to_string(),
+        message_type: Some(message_type),
+        media_group,
+        created_at: Utc::now(),
+        keyboard: None,
+        parse_mode: BroadcastParseMode::Plain,
+        split_long_text: false,
+        is_test: false,
+        action: BroadcastMessageAction::Send,
+        telegram_message_ids: Vec::new(),
+    }
+}
+
+async fn message_record(pool: &SqlitePool) -> BroadcastMessageRecord {
+    core_logic::db::get_broadcast_messages(pool, BROADCAST_ID, None, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|record| record.telegram_id == USER_ID)
+        .unwrap()
+}
+
+async fn create_summary(pool: &SqlitePool, status: BroadcastStatus) {
+    let summary = BroadcastSummary {
+        id: BROADCAST_ID.to_string(),
+        message: "Открыта запись на собеседования".to_string(),
+        total_users: 1,
+        sent_count: 0,
+        failed_count: 0,
+        pending_count: 1,
+        status,
+        created_at: Utc::now().naive_utc(),
+        started_at: None,
+        completed_at: None,
+        scheduled_at: None,
+    };
+    core_logic::db::create_broadcast_summary(pool, &summary).await.unwrap();
+}
+
+fn test_limiter() -> RateLimiter {
+    RateLimiter::new(1000.0, 1000.0)
+}
+
+fn photo(file_id: &str, caption: Option<&str>) -> MediaItem {
+    MediaItem {
+        media_type: "photo".to_string(),
+        file_id: Some(file_id.to_string()),
+        file_path: None,
+        caption: caption.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn sends_sign_up_message_with_button() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let codec = common::test_codec();
+    let message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &codec, &test_limiter()).await.unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let call = api.wait_for_call("sendMessage").await;
+    assert_eq!(call.params["chat_id"], USER_ID);
+    assert_eq!(call.params["text"], "Открыта запись на собеседования");
+    assert_eq!(codec.decode(&common::button_data(&call, 0)).unwrap(), CallbackAction::SignUp);
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn sends_media_group_with_caption_on_first_item() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let media_group = MediaGroup {
+        media: vec![photo("photo-1", Some("Подпись")), photo("photo-2", Some("Не отправляется"))],
+    };
+    let message = queued_message(&pool, BroadcastMessageType::Custom, Some(media_group)).await;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let call = api.wait_for_call("sendMediaGroup").await;
+    let media = call.params["media"].as_array().unwrap();
+    assert_eq!(media.len(), 2);
+    assert_eq!(media[0]["media"], "photo-1");
+    assert_eq!(media[0]["caption"], "Подпись");
+    assert!(media[1].get("caption").is_none_or(|caption| caption.is_null()));
+    assert!(api.calls("sendMessage").await.is_empty());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn media_group_keyboard_follows_as_separate_message() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let codec = common::test_codec();
+    let media_group = MediaGroup {
+        media: vec![photo("photo-1", Some("Подпись"))],
+    };
+    let mut message = queued_message(&pool, BroadcastMessageType::Custom, Some(media_group)).await;
+    message.keyboard = Some(BroadcastKeyboard {
+        rows: vec![
+            vec![KeyboardButton {
+                text: "Подробнее".to_string(),
+                kind: ButtonKind::Url { url: "https://example.com/school".to_string() },
+            }],
+            vec![KeyboardButton::callback("Выбрать слот", BotAction::Book { slot_id: 7 })],
+        ],
+    });
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &codec, &test_limiter()).await.unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    api.wait_for_call("sendMediaGroup").await;
+    let call = api.wait_for_call("sendMessage").await;
+    let keyboard = &call.params["reply_markup"]["inline_keyboard"];
+    assert_eq!(keyboard[0][0]["url"], "https://example.com/school");
+    assert_eq!(codec.decode(&common::button_data(&call, 1)).unwrap(), CallbackAction::Book(7));
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn failed_keyboard_after_media_group_is_resent_alone_once() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let codec = common::test_codec();
+    let media_group = MediaGroup {
+        media: vec![photo("photo-1", Some("Подпись")), photo("photo-2", None)],
+    };
+    let message = queued_message(&pool, BroadcastMessageType::SignUp, Some(media_group)).await;
+    api.fail_method("sendMessage", 400, "Bad Request: internal error").await;
+
+    let first = broadcast::handle_message(message.clone(), &api.bot(), &pool, &codec, &test_limiter()).await.unwrap();
+    assert!(matches!(first, MessageOutcome::RetryAfter(_)));
+    let delivered = core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap();
+    assert_eq!(delivered.len(), 2);
+
+    api.recover_method("sendMessage").await;
+    let second = broadcast::handle_message(message.clone(), &api.bot(), &pool, &codec, &test_limiter()).await.unwrap();
+
+    assert_eq!(second, MessageOutcome::Processed);
+    assert_eq!(api.calls("sendMediaGroup").await.len(), 1);
+    let message_ids = core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap();
+    assert_eq!(message_ids.len(), 3);
+    assert_eq!(message_ids[..2], delivered[..]);
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+
+    // Повторная доставка после того, как ушли и файлы, и клавиатура, ничего не отправляет
+    let keyboard_calls = api.calls("sendMessage").await.len();
+    let third = broadcast::handle_message(message, &api.bot(), &pool, &codec, &test_limiter()).await.unwrap();
+    assert_eq!(third, MessageOutcome::Processed);
+    assert_eq!(api.calls("sendMediaGroup").await.len(), 1);
+    assert_eq!(api.calls("sendMessage").await.len(), keyboard_calls);
+    assert_eq!(core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap(), message_ids);
+}
+
+#[tokio::test]
+async fn long_plain_text_is_escaped_and_split_with_keyboard_on_last_part() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let mut message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+    let paragraph = format!("{}\n", "Собеседование <скоро> & ".repeat(100));
+    message.message = paragraph.repeat(2);
+    message.split_long_text = true;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let calls = api.calls("sendMessage").await;
+    assert_eq!(calls.len(), 2);
+    for call in &calls {
+        assert_eq!(call.params["parse_mode"], "HTML");
+        let text = call.params["text"].as_str().unwrap();
+        assert!(text.starts_with("Собеседование &lt;скоро&gt; &amp;"));
+        assert!(text.ends_with("&amp;"));
+    }
+    assert!(calls[0].params.get("reply_markup").is_none());
+    assert!(calls[1].params["reply_markup"]["inline_keyboard"].is_array());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn failed_part_is_resent_without_delivered_parts() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let mut message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+    message.message = format!("Первая {}\nВторая {}", "часть ".repeat(650), "часть ".repeat(50));
+    message.split_long_text = true;
+    api.fail_text("Вторая", 400, "Bad Request: internal error").await;
+
+    let first = broadcast::handle_message(message.clone(), &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    assert!(matches!(first, MessageOutcome::RetryAfter(_)));
+    assert_eq!(core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap().len(), 1);
+
+    api.recover_text("Вторая").await;
+    let second = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+
+    assert_eq!(second, MessageOutcome::Processed);
+    let texts: Vec<String> = api.calls("sendMessage").await.iter().map(|call| call.params["text"].as_str().unwrap().to_string()).collect();
+    assert_eq!(texts.iter().filter(|text| text.starts_with("Первая")).count(), 1);
+    assert_eq!(texts.iter().filter(|text| text.starts_with("Вторая")).count(), 2);
+    assert_eq!(core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap().len(), 2);
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn rendered_text_over_limit_is_not_sent() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let mut message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+    message.message = "Очень длинное имя ".repeat(300);
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+
+    assert!(matches!(outcome, MessageOutcome::DeadLetter(error) if error.contains("длиннее 4096")));
+    assert!(api.calls("sendMessage").await.is_empty());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Failed);
+}
+
+#[tokio::test]
+async fn test_message_leaves_broadcast_statistics_untouched() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    create_summary(&pool, BroadcastStatus::Draft).await;
+    let mut message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+    message.is_test = true;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let call = api.wait_for_call("sendMessage").await;
+    assert!(call.params["reply_markup"]["inline_keyboard"].is_array());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Pending);
+    let summary = core_logic::db::get_broadcast_summary(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    assert_eq!((summary.sent_count, summary.status), (0, BroadcastStatus::Draft));
+}
+
+#[tokio::test]
+async fn delivered_message_is_edited_in_place_with_its_keyboard() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+    let created: BroadcastEvent = serde_json::from_value(serde_json::json!({
+        "BroadcastCreated": {
+            "broadcast_id": BROADCAST_ID,
+            "message": message.message,
+            "target_users": [],
+            "message_type": "signup",
+            "media_group": null,
+            "created_at": message.created_at,
+        }
+    }))
+    .unwrap();
+    core_logic::db::save_broadcast_event(&pool, &created).await.unwrap();
+    broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    api.wait_for_call("sendMessage").await;
+
+    let edits = core_logic::db::handle_revise_broadcast(&pool, BROADCAST_ID, BroadcastMessageAction::Edit, Some("Исправлено, {{first_name|коллега}}"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edits.len(), 1);
+    let outcome = broadcast::handle_message(edits[0].clone(), &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let edit = api.wait_for_call("editMessageText").await;
+    // Фейковый API нумерует сообщения с 1000
+    assert_eq!(edit.params["message_id"], 1000);
+    assert_eq!(edit.params["text"], "Исправлено, коллега");
+    assert!(edit.params["reply_markup"]["inline_keyboard"].is_array());
+    let record = message_record(&pool).await;
+    assert_eq!((record.status, record.edit_status), (MessageStatus::Sent, Some(MessageEditStatus::Edited)));
+}
+
+#[tokio::test]
+async fn split_message_edit_follows_new_part_count() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let mut message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+    message.message = format!("Первая {}\nВторая {}", "часть ".repeat(650), "часть ".repeat(50));
+    message.split_long_text = true;
+    broadcast::handle_message(message.clone(), &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    let delivered = core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap();
+    assert_eq!(delivered, vec![1000, 1001]);
+
+    // Короткий текст умещается в одну часть: вторая удаляется, клавиатура переходит на первую
+    let shortened = BroadcastMessage {
+        message: "Исправлено".to_string(),
+        action: BroadcastMessageAction::Edit,
+        telegram_message_ids: delivered,
+        ..message.clone()
+    };
+    let outcome = broadcast::handle_message(shortened, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let edits = api.calls("editMessageText").await;
+    assert_eq!(edits.len(), 1);
+    assert_eq!((edits[0].params["message_id"].clone(), edits[0].params["text"].clone()), (1000.into(), "Исправлено".into()));
+    assert!(edits[0].params["reply_markup"]["inline_keyboard"].is_array());
+    let deletes = api.calls("deleteMessage").await;
+    assert_eq!(deletes.len(), 1);
+    assert_eq!(deletes[0].params["message_id"], 1001);
+    assert_eq!(core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap(), vec![1000]);
+
+    // Снова длинный текст: первая часть правится без клавиатуры, вторая досылается с ней
+    let lengthened = BroadcastMessage {
+        action: BroadcastMessageAction::Edit,
+        telegram_message_ids: vec![1000],
+        ..message
+    };
+    broadcast::handle_message(lengthened, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    let edits = api.calls("editMessageText").await;
+    assert_eq!(edits.len(), 2);
+    assert!(edits[1].params["text"].as_str().unwrap().starts_with("Первая"));
+    assert!(edits[1].params.get("reply_markup").is_none());
+    let sent = api.calls("sendMessage").await;
+    let resent = sent.last().unwrap();
+    assert!(resent.params["text"].as_str().unwrap().starts_with("Вторая"));
+    assert!(resent.params["reply_markup"]["inline_keyboard"].is_array());
+    assert_eq!(core_logic::db::get_broadcast_message_telegram_ids(&pool, BROADCAST_ID, USER_ID).await.unwrap(), vec![1000, 1002]);
+    assert_eq!(message_record(&pool).await.edit_status, Some(MessageEditStatus::Edited));
+}
+
+#[tokio::test]
+async fn blocked_user_is_marked_unreachable() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    api.fail_chat(USER_ID, 403, "Forbidden: bot was blocked by the user", None).await;
+    let message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    let record = message_record(&pool).await;
+    assert_eq!(record.status, MessageStatus::Failed);
+    assert!(record.error.unwrap().starts_with("blocked"));
+    assert!(core_logic::db::get_unreachable_telegram_ids(&pool).await.unwrap().contains(&USER_ID));
+}
+
+#[tokio::test]
+async fn retry_after_requeues_message() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    api.fail_chat(USER_ID, 429, "Too Many Requests: retry after 5", Some(5)).await;
+    let message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::RetryAfter(Duration::from_secs(5)));
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Pending);
+    assert!(core_logic::db::get_unreachable_telegram_ids(&pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn cancelled_broadcast_is_not_sent() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    create_summary(&pool, BroadcastStatus::Cancelled).await;
+    let message = queued_message(&pool, BroadcastMessageType::SignUp, None).await;
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    assert!(api.calls("sendMessage").await.is_empty());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Cancelled);
+    let summary = core_logic::db::get_broadcast_summary(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    assert_eq!(summary.status, BroadcastStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn paused_broadcast_defers_message() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    create_summary(&pool, BroadcastStatus::InProgress).await;
+    core_logic::db::handle_pause_broadcast(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    let message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+
+    let outcome = broadcast::handle_message(message.clone(), &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert!(matches!(outcome, MessageOutcome::RetryAfter(_)));
+    assert!(api.calls("sendMessage").await.is_empty());
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Pending);
+
+    // После возобновления сообщение уходит
+    core_logic::db::handle_resume_broadcast(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, MessageOutcome::Processed);
+    api.wait_for_call("sendMessage").await;
+    assert_eq!(message_record(&pool).await.status, MessageStatus::Sent);
+}
+
+#[tokio::test]
+async fn transient_error_is_retried_with_backoff() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    // 5xx teloxide ждет 10 с перед ответом, поэтому временную ошибку изображает неизвестная ошибка API
+    api.fail_chat(USER_ID, 400, "Bad Request: internal error", None).await;
+    let message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+
+    let first = broadcast::handle_message(message.clone(), &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+    let second = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert_eq!(first, MessageOutcome::RetryAfter(Duration::from_secs(5)));
+    assert_eq!(second, MessageOutcome::RetryAfter(Duration::from_secs(10)));
+    let record = message_record(&pool).await;
+    assert_eq!(record.status, MessageStatus::Retrying);
+    assert_eq!(record.retry_count, 2);
+}
+
+#[tokio::test]
+async fn exhausted_retries_go_to_dead_letter_queue() {
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    api.fail_chat(USER_ID, 400, "Bad Request: internal error", None).await;
+    let message = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+    for _ in 0..5 {
+        core_logic::db::record_broadcast_message_retry(&pool, BROADCAST_ID, USER_ID, "transient").await.unwrap();
+    }
+
+    let outcome = broadcast::handle_message(message, &api.bot(), &pool, &common::test_codec(), &test_limiter())
+        .await
+        .unwrap();
+
+    assert!(matches!(outcome, MessageOutcome::DeadLetter(error) if error.starts_with("transient")));
+    let record = message_record(&pool).await;
+    assert_eq!(record.status, MessageStatus::Failed);
+    assert_eq!(record.retry_count, 6);
+    assert!(core_logic::db::get_unreachable_telegram_ids(&pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn large_broadcast_waits_for_approval_by_another_responsible() {
+    const AUTHOR_ID: i64 = 3001;
+    const APPROVER_ID: i64 = 3002;
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    core_logic::db::set_user_role(&pool, AUTHOR_ID, 1).await.unwrap();
+    core_logic::db::set_user_role(&pool, APPROVER_ID, 1).await.unwrap();
+    let recipients: Vec<String> = (0..=core_logic::db::broadcast_approval_threshold()).map(|i| (10_000 + i).to_string()).collect();
+    let command: CreateBroadcastCommand = serde_json::from_value(serde_json::json!({
+        "message": "Открыта запись на собеседования",
+        "message_type": "custom",
+        "selected_external_users": recipients,
+        "media_group": null,
+        "created_by": AUTHOR_ID,
+    }))
+    .unwrap();
+
+    let (created, _) = core_logic::db::handle_create_broadcast(&pool, command).await.unwrap();
+
+    assert_eq!(created.status, BroadcastStatus::PendingApproval);
+    assert!(core_logic::db::get_due_scheduled_broadcasts(&pool, Utc::now()).await.unwrap().is_empty());
+    let summary = core_logic::db::get_broadcast_summary(&pool, &created.broadcast_id).await.unwrap().unwrap();
+    let notified = telegram_bot::admin::notify_broadcast_approvers(&api.bot(), &pool, &summary, Some(AUTHOR_ID)).await.unwrap();
+    let responsibles = core_logic::db::get_users(&pool).await.unwrap();
+    assert_eq!(notified, responsibles.len() - 1);
+    let chats: Vec<_> = api.calls("sendMessage").await.iter().map(|call| call.params["chat_id"].clone()).collect();
+    assert!(chats.contains(&APPROVER_ID.into()) && !chats.contains(&AUTHOR_ID.into()));
+
+    core_logic::db::handle_approve_broadcast(&pool, &created.broadcast_id, APPROVER_ID).await.unwrap().unwrap();
+    let due = core_logic::db::get_due_scheduled_broadcasts(&pool, Utc::now()).await.unwrap();
+    assert_eq!(due, vec![created.broadcast_id.clone()]);
+    let events = core_logic::db::get_broadcast_events(&pool, &created.broadcast_id).await.unwrap();
+    assert!(events.iter().any(|event| event.event_type == "BroadcastApproved"));
+}
+
+#[tokio::test]
+async fn last_processed_message_completes_broadcast_once() {
+    const BLOCKED_USER_ID: i64 = 2002;
+    let api = FakeTelegramApi::start().await;
+    let pool = Arc::new(common::test_pool().await);
+    let summary = BroadcastSummary {
+        id: BROADCAST_ID.to_string(),
+        message: "Открыта запись на собеседования".to_string(),
+        total_users: 2,
+        sent_count: 0,
+        failed_count: 0,
+        pending_count: 2,
+        status: BroadcastStatus::InProgress,
+        created_at: Utc::now().naive_utc(),
+        started_at: None,
+        completed_at: None,
+        scheduled_at: None,
+    };
+    core_logic::db::create_broadcast_summary(&pool, &summary).await.unwrap();
+    api.fail_chat(BLOCKED_USER_ID, 403, "Forbidden: bot was blocked by the user", None).await;
+    let delivered = queued_message(&pool, BroadcastMessageType::Custom, None).await;
+    let blocked = BroadcastMessage { telegram_id: BLOCKED_USER_ID, ..delivered.clone() };
+    let blocked_record = BroadcastMessageRecord { telegram_id: BLOCKED_USER_ID, ..message_record(&pool).await };
+    core_logic::db::create_broadcast_message(&pool, &blocked_record).await.unwrap();
+
+    broadcast::handle_message(delivered, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    let summary = core_logic::db::get_broadcast_summary(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    assert_eq!((summary.status, summary.sent_count, summary.pending_count), (BroadcastStatus::InProgress, 1, 1));
+
+    broadcast::handle_message(blocked, &api.bot(), &pool, &common::test_codec(), &test_limiter()).await.unwrap();
+    let summary = core_logic::db::get_broadcast_summary(&pool, BROADCAST_ID).await.unwrap().unwrap();
+    assert_eq!(summary.status, BroadcastStatus::Completed);
+    assert_eq!((summary.sent_count, summary.failed_count, summary.pending_count), (1, 1, 0));
+    assert!(summary.completed_at.is_some());
+
+    // Повторный пересчет не завершает рассылку второй раз
+    assert!(core_logic::db::update_broadcast_summary_from_messages(&pool, BROADCAST_ID).await.unwrap().is_none());
+    let event_types: Vec<String> = core_logic::db::get_broadcast_events(&pool, BROADCAST_ID)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|event| event.event_type)
+        .collect();
+    assert_eq!(event_types.iter().filter(|event_type| *event_type == "MessageSent").count(), 1);
+    assert_eq!(event_types.iter().filter(|event_type| *event_type == "MessageFailed").count(), 1);
+    assert_eq!(event_types.iter().filter(|event_type| *event_type == "BroadcastCompleted").count(), 1);
+
+    // Без автора итоги получают все ответственные
+    let notified = telegram_bot::admin::notify_broadcast_completed(&api.bot(), &pool, &summary).await.unwrap();
+    let responsibles = core_logic::db::get_users(&pool).await.unwrap();
+    assert_eq!(notified, responsibles.len());
+    let results = api.wait_for_calls("sendMessage", 1 + responsibles.len()).await;
+    assert!(results.iter().any(|call| call.params["text"].as_str().unwrap().contains("Рассылка завершена")));
+}
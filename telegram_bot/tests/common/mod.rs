@@ -0,0 +1,409 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::dispatching::UpdateHandler;
+use telegram_bot::callback_data::CallbackCodec;
+use telegram_bot::handlers;
+use telegram_bot::support::SupportInbox;
+use tokio::sync::Mutex;
+
+pub const BOT_ID: i64 = 12345;
+pub const BOT_TOKEN: &str = "12345:TEST_TOKEN";
+pub const BOT_USERNAME: &str = "test_bot";
+
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+// Сколько getUpdates ждет новых обновлений, прежде чем вернуть пустой список
+const GET_UPDATES_WAIT: Duration = Duration::from_millis(100);
+
+/// Вызов Bot API, который записал фейковый сервер
+#[derive(Debug, Clone)]
+pub struct ApiCall {
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Default)]
+struct FakeState {
+    calls: Mutex<Vec<ApiCall>>,
+    updates: Mutex<Vec<Value>>,
+    // Ошибки, которые Bot API вернет при отправке в конкретный чат
+    failures: Mutex<HashMap<i64, Value>>,
+    // Ошибки для всех вызовов метода и для отправок в тему форума
+    method_failures: Mutex<HashMap<String, Value>>,
+    thread_failures: Mutex<HashMap<i64, Value>>,
+    // Ошибки для сообщений, текст которых содержит фрагмент
+    text_failures: Mutex<HashMap<String, Value>>,
+    next_update_id: AtomicI64,
+    next_message_id: AtomicI64,
+}
+
+/// Фейковый Telegram Bot API: записывает вызовы, отвечает правдоподобными данными
+/// и отдает боту подложенные обновления через getUpdates
+#[derive(Clone)]
+pub struct FakeTelegramApi {
+    url: String,
+    state: Arc<FakeState>,
+}
+
+impl FakeTelegramApi {
+    pub async fn start() -> Self {
+        let state = Arc::new(FakeState {
+            next_update_id: AtomicI64::new(1),
+            next_message_id: AtomicI64::new(1000),
+            ..FakeState::default()
+        });
+        let app = Router::new()
+            .route("/{token}/{method}", post(handle_api_call))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { url, state }
+    }
+
+    /// Бот, который ходит в фейковый API вместо api.telegram.org
+    pub fn bot(&self) -> Bot {
+        Bot::new(BOT_TOKEN).set_api_url(self.url.parse().unwrap())
+    }
+
+    pub async fn calls(&self, method: &str) -> Vec<ApiCall> {
+        self.state.calls.lock().await.iter().filter(|call| call.method == method).cloned().collect()
+    }
+
+    /// Ждет первый вызов метода (обработчики работают асинхронно)
+    pub async fn wait_for_call(&self, method: &str) -> ApiCall {
+        self.wait_for_calls(method, 1).await.remove(0)
+    }
+
+    /// Ждет, пока метод будет вызван `count` раз, и возвращает все его вызовы
+    pub async fn wait_for_calls(&self, method: &str, count: usize) -> Vec<ApiCall> {
+        let deadline = tokio::time::Instant::now() + CALL_TIMEOUT;
+        loop {
+            let calls = self.calls(method).await;
+            if calls.len() >= count {
+                return calls;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "Bot API method {} was called {} times, expected {}",
+                method,
+                calls.len(),
+                count
+            );
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Подкладывает обновление, которое бот получит через getUpdates
+    pub async fn push_update(&self, mut update: Value) {
+        update["update_id"] = json!(self.state.next_update_id.fetch_add(1, Ordering::SeqCst));
+        self.state.updates.lock().await.push(update);
+    }
+
+    /// Все отправки в чат будут завершаться ошибкой Bot API
+    pub async fn fail_chat(&self, chat_id: i64, error_code: u16, description: &str, retry_after: Option<u32>) {
+        let mut error = json!({ "ok": false, "error_code": error_code, "description": description });
+        if let Some(retry_after) = retry_after {
+            error["parameters"] = json!({ "retry_after": retry_after });
+        }
+        self.state.failures.lock().await.insert(chat_id, error);
+    }
+
+    /// Все вызовы метода будут завершаться ошибкой Bot API
+    pub async fn fail_method(&self, method: &str, error_code: u16, description: &str) {
+        let error = json!({ "ok": false, "error_code": error_code, "description": description });
+        self.state.method_failures.lock().await.insert(method.to_string(), error);
+    }
+
+    /// Вызовы метода снова завершаются успешно
+    pub async fn recover_method(&self, method: &str) {
+        self.state.method_failures.lock().await.remove(method);
+    }
+
+    /// Отправки сообщений с фрагментом в тексте будут завершаться ошибкой Bot API
+    pub async fn fail_text(&self, fragment: &str, error_code: u16, description: &str) {
+        let error = json!({ "ok": false, "error_code": error_code, "description": description });
+        self.state.text_failures.lock().await.insert(fragment.to_string(), error);
+    }
+
+    /// Сообщения с фрагментом в тексте снова отправляются
+    pub async fn recover_text(&self, fragment: &str) {
+        self.state.text_failures.lock().await.remove(fragment);
+    }
+
+    /// Отправки в тему форума будут завершаться ошибкой Bot API (например, тему удалили)
+    pub async fn fail_thread(&self, thread_id: i64, error_code: u16, description: &str) {
+        let error = json!({ "ok": false, "error_code": error_code, "description": description });
+        self.state.thread_failures.lock().await.insert(thread_id, error);
+    }
+}
+
+async fn handle_api_call(
+    State(state): State<Arc<FakeState>>,
+    Path((_token, method)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<Value>) {
+    // teloxide пишет имена методов с заглавной буквы (SendMessage), Telegram их не различает
+    let method = lower_first_char(&method);
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let params = match content_type.split_once("boundary=") {
+        Some((_, boundary)) => multipart_params(&body, boundary),
+        None => serde_json::from_slice(&body).unwrap_or(Value::Null),
+    };
+
+    // Обновления отдаем отдельно и не записываем: бот опрашивает их постоянно
+    if method == "getUpdates" {
+        return (StatusCode::OK, Json(json!({ "ok": true, "result": pending_updates(&state, &params).await })));
+    }
+
+    state.calls.lock().await.push(ApiCall { method: method.clone(), params: params.clone() });
+
+    let chat_failure = match params["chat_id"].as_i64() {
+        Some(chat_id) => state.failures.lock().await.get(&chat_id).cloned(),
+        None => None,
+    };
+    let thread_failure = match params["message_thread_id"].as_i64() {
+        Some(thread_id) => state.thread_failures.lock().await.get(&thread_id).cloned(),
+        None => None,
+    };
+    let method_failure = state.method_failures.lock().await.get(&method).cloned();
+    let text_failure = match params["text"].as_str() {
+        Some(text) => state
+            .text_failures
+            .lock()
+            .await
+            .iter()
+            .find(|(fragment, _)| text.contains(fragment.as_str()))
+            .map(|(_, error)| error.clone()),
+        None => None,
+    };
+    if let Some(error) = chat_failure.or(thread_failure).or(method_failure).or(text_failure) {
+        let status = StatusCode::from_u16(error["error_code"].as_u64().unwrap_or(400) as u16).unwrap();
+        return (status, Json(error));
+    }
+
+    let result = match method.as_str() {
+        "getMe" => json!({
+            "id": BOT_ID,
+            "is_bot": true,
+            "first_name": "Test",
+            "username": BOT_USERNAME,
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+            "has_main_web_app": false,
+        }),
+        "sendMessage" => {
+            let message_id = state.next_message_id.fetch_add(1, Ordering::SeqCst);
+            bot_message(message_id, &params["chat_id"], &params["text"])
+        }
+        "forwardMessage" => {
+            let message_id = state.next_message_id.fetch_add(1, Ordering::SeqCst);
+            bot_message(message_id, &params["chat_id"], &json!("Пересланное сообщение"))
+        }
+        "copyMessage" => json!({ "message_id": state.next_message_id.fetch_add(1, Ordering::SeqCst) }),
+        "createForumTopic" => json!({
+            "message_thread_id": state.next_message_id.fetch_add(1, Ordering::SeqCst),
+            "name": params["name"],
+            "icon_color": 7322096,
+        }),
+        "editMessageText" => bot_message(params["message_id"].as_i64().unwrap_or(1), &params["chat_id"], &params["text"]),
+        "sendMediaGroup" => {
+            let media = params["media"].as_array().cloned().unwrap_or_default();
+            let messages: Vec<Value> = media
+                .iter()
+                .map(|item| {
+                    let message_id = state.next_message_id.fetch_add(1, Ordering::SeqCst);
+                    bot_message(message_id, &params["chat_id"], &item["caption"])
+                })
+                .collect();
+            json!(messages)
+        }
+        _ => json!(true),
+    };
+
+    (StatusCode::OK, Json(json!({ "ok": true, "result": result })))
+}
+
+/// Поля multipart-запроса (так teloxide отправляет медиа) в виде JSON-объекта
+fn multipart_params(body: &[u8], boundary: &str) -> Value {
+    let body = String::from_utf8_lossy(body);
+    let mut params = serde_json::Map::new();
+    for part in body.split(&format!("--{}", boundary)) {
+        let Some((headers, value)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let Some(name) = headers.split("name=\"").nth(1).and_then(|rest| rest.split('"').next()) else {
+            continue;
+        };
+        let value = value.trim_end_matches("\r\n");
+        let value = serde_json::from_str(value).unwrap_or_else(|_| json!(value));
+        params.insert(name.to_string(), value);
+    }
+    Value::Object(params)
+}
+
+/// Обновления начиная с offset. Если их нет, немного ждем, как long polling.
+async fn pending_updates(state: &FakeState, params: &Value) -> Vec<Value> {
+    let offset = params["offset"].as_i64().unwrap_or(0);
+    let deadline = tokio::time::Instant::now() + GET_UPDATES_WAIT;
+    loop {
+        let updates: Vec<Value> = state
+            .updates
+            .lock()
+            .await
+            .iter()
+            .filter(|update| update["update_id"].as_i64().unwrap_or(0) >= offset)
+            .cloned()
+            .collect();
+        if !updates.is_empty() || tokio::time::Instant::now() >= deadline {
+            return updates;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn lower_first_char(method: &str) -> String {
+    let mut chars = method.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn bot_message(message_id: i64, chat_id: &Value, text: &Value) -> Value {
+    json!({
+        "message_id": message_id,
+        "date": 0,
+        "chat": { "id": chat_id, "type": "private", "first_name": "Test" },
+        "from": { "id": BOT_ID, "is_bot": true, "first_name": "Test", "username": BOT_USERNAME },
+        "text": text,
+    })
+}
+
+fn user(user_id: i64) -> Value {
+    json!({ "id": user_id, "is_bot": false, "first_name": "Иван", "username": "ivan", "language_code": "ru" })
+}
+
+/// Обновление с текстовым сообщением пользователя в личном чате
+pub fn text_message(user_id: i64, text: &str) -> Value {
+    let mut message = json!({
+        "message_id": 10,
+        "date": 1760000000,
+        "chat": { "id": user_id, "type": "private", "first_name": "Иван", "username": "ivan" },
+        "from": user(user_id),
+        "text": text,
+    });
+    if let Some(command) = text.split_whitespace().next().filter(|word| word.starts_with('/')) {
+        message["entities"] = json!([{ "type": "bot_command", "offset": 0, "length": command.chars().count() }]);
+    }
+    json!({ "message": message })
+}
+
+/// Обновление с сообщением сотрудника в теме форума чата поддержки
+pub fn topic_message(chat_id: i64, thread_id: i64, staff_id: i64, text: &str) -> Value {
+    json!({
+        "message": {
+            "message_id": 20,
+            "message_thread_id": thread_id,
+            "is_topic_message": true,
+            "date": 1760000000,
+            "chat": { "id": chat_id, "type": "supergroup", "title": "Поддержка", "is_forum": true },
+            "from": user(staff_id),
+            "text": text,
+        }
+    })
+}
+
+/// Обновление с нажатием inline-кнопки под сообщением бота `message_id`
+pub fn callback_query(user_id: i64, message_id: i64, data: &str) -> Value {
+    json!({
+        "callback_query": {
+            "id": format!("callback-{}-{}", user_id, message_id),
+            "from": user(user_id),
+            "message": bot_message(message_id, &json!(user_id), &json!("Записаться на собеседование")),
+            "chat_instance": "-7018323481234567890",
+            "data": data,
+        }
+    })
+}
+
+/// callback_data кнопки из inline-клавиатуры вызова sendMessage/editMessageText
+pub fn button_data(call: &ApiCall, row: usize) -> String {
+    call.params["reply_markup"]["inline_keyboard"][row][0]["callback_data"]
+        .as_str()
+        .unwrap_or_else(|| panic!("No button in row {} of {:?}", row, call.params))
+        .to_string()
+}
+
+/// Запускает диспетчер бота в режиме polling поверх фейкового API
+pub fn spawn_bot(api: &FakeTelegramApi, pool: SqlitePool, codec: CallbackCodec) {
+    spawn_bot_with_support(api, pool, codec, SupportInbox::new(None));
+}
+
+/// Как spawn_bot, но обращения кандидатов пересылаются в чат сотрудников
+pub fn spawn_bot_with_support(api: &FakeTelegramApi, pool: SqlitePool, codec: CallbackCodec, inbox: SupportInbox) {
+    let schema: UpdateHandler<teloxide::RequestError> = handlers::schema();
+    let mut dispatcher = Dispatcher::builder(api.bot(), schema)
+        .dependencies(handlers::dependencies(Arc::new(pool), codec, inbox))
+        .build();
+    tokio::spawn(async move {
+        dispatcher.dispatch().await;
+    });
+}
+
+/// Фейковый API профилей пользователей (USER_API_URL): профилей нет, на все отвечает 404.
+/// Работает в отдельном потоке, чтобы переживать рантаймы отдельных тестов.
+pub fn use_fake_user_api() {
+    static USER_API_URL: OnceLock<String> = OnceLock::new();
+    USER_API_URL.get_or_init(|| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let app = Router::new().route("/user/{id}", get(|| async { StatusCode::NOT_FOUND }));
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                tx.send(format!("http://{}", listener.local_addr().unwrap())).unwrap();
+                axum::serve(listener, app).await.unwrap();
+            });
+        });
+        let url = rx.recv().unwrap();
+        // Переменная задается один раз до первого обращения к API профилей
+        unsafe { std::env::set_var("USER_API_URL", &url) };
+        url
+    });
+}
+
+/// SQLite в памяти с примененными миграциями
+pub async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    core_logic::db::run_migrations(&pool).await.unwrap();
+    pool
+}
+
+pub fn test_codec() -> CallbackCodec {
+    CallbackCodec::new(b"test_callback_secret", chrono::Duration::hours(1), chrono::Duration::hours(1))
+}
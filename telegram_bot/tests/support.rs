@@ -0,0 +1,67 @@
+mod common;
+
+use teloxide::types::ChatId;
+use telegram_bot::support::SupportInbox;
+use common::FakeTelegramApi;
+
+const CANDIDATE_ID: i64 = 4001;
+const STAFF_ID: i64 = 4002;
+const SUPPORT_CHAT_ID: i64 = -1001234567890;
+const DELETED_THREAD_ID: i32 = 77;
+
+#[tokio::test]
+async fn relays_candidate_question_and_staff_reply() {
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    common::spawn_bot_with_support(&api, pool.clone(), common::test_codec(), SupportInbox::new(Some(ChatId(SUPPORT_CHAT_ID))));
+
+    // Первое обращение: тема для кандидата, пересылка в нее и подтверждение кандидату
+    api.push_update(common::text_message(CANDIDATE_ID, "Можно перенести собеседование?")).await;
+    let forwarded = api.wait_for_call("forwardMessage").await;
+    let topic = api.wait_for_call("createForumTopic").await;
+    assert_eq!(topic.params["chat_id"], SUPPORT_CHAT_ID);
+    let thread_id = core_logic::db::get_support_thread(&pool, CANDIDATE_ID).await.unwrap().unwrap();
+    assert_eq!(forwarded.params["message_thread_id"], thread_id);
+    let received = api.wait_for_call("sendMessage").await;
+    assert_eq!(received.params["chat_id"], CANDIDATE_ID);
+
+    // Ответ сотрудника в теме копируется кандидату
+    api.push_update(common::topic_message(SUPPORT_CHAT_ID, thread_id as i64, STAFF_ID, "Да, напиши удобное время")).await;
+    let reply = api.wait_for_call("copyMessage").await;
+    assert_eq!(reply.params["chat_id"], CANDIDATE_ID);
+    assert_eq!(reply.params["from_chat_id"], SUPPORT_CHAT_ID);
+}
+
+#[tokio::test]
+async fn deleted_topic_is_recreated() {
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    core_logic::db::save_support_thread(&pool, CANDIDATE_ID, DELETED_THREAD_ID).await.unwrap();
+    api.fail_thread(DELETED_THREAD_ID as i64, 400, "Bad Request: message thread not found").await;
+    common::spawn_bot_with_support(&api, pool.clone(), common::test_codec(), SupportInbox::new(Some(ChatId(SUPPORT_CHAT_ID))));
+
+    api.push_update(common::text_message(CANDIDATE_ID, "Вы получили мое сообщение?")).await;
+
+    let forwards = api.wait_for_calls("forwardMessage", 2).await;
+    let thread_id = core_logic::db::get_support_thread(&pool, CANDIDATE_ID).await.unwrap().unwrap();
+    assert_ne!(thread_id, DELETED_THREAD_ID);
+    assert_eq!(forwards[1].params["message_thread_id"], thread_id);
+    assert_eq!(api.calls("createForumTopic").await.len(), 1);
+}
+
+#[tokio::test]
+async fn topics_are_not_retried_in_regular_group() {
+    const OTHER_CANDIDATE_ID: i64 = 4003;
+    let api = FakeTelegramApi::start().await;
+    let pool = common::test_pool().await;
+    api.fail_method("createForumTopic", 400, "Bad Request: the chat is not a forum").await;
+    common::spawn_bot_with_support(&api, pool.clone(), common::test_codec(), SupportInbox::new(Some(ChatId(SUPPORT_CHAT_ID))));
+
+    api.push_update(common::text_message(CANDIDATE_ID, "Вопрос")).await;
+    api.wait_for_call("forwardMessage").await;
+    api.push_update(common::text_message(OTHER_CANDIDATE_ID, "Еще вопрос")).await;
+
+    let forwards = api.wait_for_calls("forwardMessage", 2).await;
+    assert!(forwards.iter().all(|call| call.params["message_thread_id"].is_null()));
+    assert_eq!(api.calls("createForumTopic").await.len(), 1);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use teloxide::prelude::*;
+use teloxide::update_listeners::webhooks;
+use telegram_bot::{handlers, webhook};
+use telegram_bot::support::SupportInbox;
+use tower::ServiceExt;
+use common::FakeTelegramApi;
+
+const WEBHOOK_URL: &str = "https://bot.example.com/telegram/webhook";
+const WEBHOOK_PATH: &str = "/telegram/webhook";
+const WEBHOOK_SECRET: &str = "test_webhook_secret";
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+const HELP_COMMAND_UPDATE: &str = include_str!("fixtures/help_command.json");
+const TAMPERED_CALLBACK_UPDATE: &str = include_str!("fixtures/tampered_callback.json");
+
+/// Запускает диспетчер с webhook-листенером и возвращает его axum-маршрут
+async fn start_webhook_bot(api: &FakeTelegramApi) -> Router {
+    let pool = Arc::new(common::test_pool().await);
+    let options = webhook::webhook_options(WEBHOOK_URL, WEBHOOK_SECRET, "127.0.0.1:0").unwrap();
+    let (listener, _stop_flag, router) = webhooks::axum_no_setup(options);
+
+    let mut dispatcher = Dispatcher::builder(api.bot(), handlers::schema())
+        .dependencies(handlers::dependencies(pool, common::test_codec(), SupportInbox::new(None)))
+        .build();
+    tokio::spawn(async move {
+        dispatcher.dispatch_with_listener(listener, LoggingErrorHandler::new()).await;
+    });
+
+    router
+}
+
+/// Отправляет обновление в webhook. Пока диспетчер не начал слушать, маршрут отвечает 503.
+async fn post_update(router: &Router, secret: Option<&str>, update: &str) -> StatusCode {
+    for _ in 0..100 {
+        let mut request = Request::post(WEBHOOK_PATH).header("content-type", "application/json");
+        if let Some(secret) = secret {
+            request = request.header(SECRET_HEADER, secret);
+        }
+        let request = request.body(Body::from(update.to_string())).unwrap();
+
+        let status = router.clone().oneshot(request).await.unwrap().status();
+        if status != StatusCode::SERVICE_UNAVAILABLE {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    StatusCode::SERVICE_UNAVAILABLE
+}
+
+#[tokio::test]
+async fn rejects_updates_without_valid_secret() {
+    let api = FakeTelegramApi::start().await;
+    let router = start_webhook_bot(&api).await;
+
+    assert_eq!(post_update(&router, None, HELP_COMMAND_UPDATE).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(post_update(&router, Some("wrong_secret"), HELP_COMMAND_UPDATE).await, StatusCode::UNAUTHORIZED);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(api.calls("sendMessage").await.is_empty());
+}
+
+#[tokio::test]
+async fn answers_help_command_from_webhook() {
+    let api = FakeTelegramApi::start().await;
+    let router = start_webhook_bot(&api).await;
+
+    assert_eq!(post_update(&router, Some(WEBHOOK_SECRET), HELP_COMMAND_UPDATE).await, StatusCode::OK);
+
+    let call = api.wait_for_call("sendMessage").await;
+    assert_eq!(call.params["chat_id"], 1001);
+    assert!(call.params["text"].as_str().unwrap().contains("/review"));
+}
+
+#[tokio::test]
+async fn tampered_callback_gets_expired_button_alert() {
+    let api = FakeTelegramApi::start().await;
+    let router = start_webhook_bot(&api).await;
+
+    assert_eq!(post_update(&router, Some(WEBHOOK_SECRET), TAMPERED_CALLBACK_UPDATE).await, StatusCode::OK);
+
+    let call = api.wait_for_call("answerCallbackQuery").await;
+    assert_eq!(call.params["callback_query_id"], "4382bfdwdsb323b2d9");
+    assert_eq!(call.params["show_alert"], true);
+    assert!(call.params["text"].as_str().unwrap().contains("устарела"));
+    assert!(api.calls("editMessageText").await.is_empty());
+}